@@ -44,10 +44,14 @@ Time = outpost_ffi.types.Time.table
 Timer = outpost_ffi.types.Timer.table
 
 
+require('core.sandbox')
 require('core.userdata')
 require('core.extra')
 require('core.eval')
 require('core.timer')
+require('core.events')
+require('core.chat')
+require('core.async')
 local action = require('core.action')
 local command = require('core.command')
 local util = require('core.util')
@@ -116,7 +120,7 @@ action.use['bookshelf/1'] = function(c, s)
     if not ward.check(c, s:pos()) then return end
 
     local inv = c:pawn():inventory('main')
-    if inv:count('book') == 255 then
+    if inv:space_for('book') == 0 then
         return
     end
 
@@ -128,7 +132,7 @@ action.use['bookshelf/2'] = function(c, s)
     if not ward.check(c, s:pos()) then return end
 
     local inv = c:pawn():inventory('main')
-    if inv:count('book') == 255 then
+    if inv:space_for('book') == 0 then
         return
     end
 
@@ -164,11 +168,29 @@ function command.handler.where(client, args)
 end
 command.help.where = '/where: Show coordinates of your current position'
 
-local spawn_point = V3.new(32, 32, 0)
-PLANE_FOREST = 'Everfree Forest'
+function command.handler.time(client, args)
+    local w = World.get()
+    local part
+    if client:pawn():plane():is_day() then
+        part = 'daytime'
+    else
+        part = 'night'
+    end
+    client:send_message('Day ' .. (w:day() + 1) .. ' of ' .. w:season() .. ', ' .. part)
+end
+command.help.time = '/time: Show the current day, season, and time of day'
+
+local function spawn_point()
+    local plane = World.get():get_forest_plane():get()
+    return plane and plane:spawn_point() or V3.new(32, 32, 0)
+end
+
+function is_forest(plane)
+    return plane:stable_id() == plane:world():get_forest_plane()
+end
 
 function check_forest(client)
-    if client:pawn():plane():name() ~= PLANE_FOREST then
+    if not is_forest(client:pawn():plane()) then
         client:send_message("That doesn't work here.")
         return false
     else
@@ -177,9 +199,7 @@ function check_forest(client)
 end
 
 function command.handler.spawn(client, args)
-    --if not check_forest(client) then return end
-    --client:pawn():teleport(spawn_point)
-    client:pawn():teleport_stable_plane(client:world():get_forest_plane(), spawn_point)
+    client:pawn():teleport_stable_plane(client:world():get_forest_plane(), spawn_point())
 end
 command.help.spawn = '/spawn: Teleport to the spawn point'
 
@@ -196,21 +216,12 @@ command.help.sethome = {
 
 function command.handler.home(client, args)
     if not check_forest(client) then return end
-    local home = client:extra().home_pos or spawn_point
+    local home = client:extra().home_pos or spawn_point()
     client:pawn():teleport(home)
 end
 command.help.home = command.help.sethome
 
 
-no_op = function(...) end
-command.handler.ignore = no_op
-command.help.ignore = '/ignore <name>: Hide chat messages from named player'
-command.handler.unignore = no_op
-command.help.unignore = '/unignore <name>: Stop hiding chat messages from <name>'
-
-command.handler.count = no_op
-command.help.count = '/count: Show the number of players currently online'
-
 function command.handler.permit(c, arg)
     ward.permit(c, arg)
     c:send_message('Granted permission to ' .. arg)
@@ -226,6 +237,26 @@ function command.handler.revoke(c, arg)
 end
 command.help.revoke = "/revoke <name>: Revoke <name>'s permission to bypass your ward"
 
+function command.handler.trade(c, arg)
+    local other = client_by_name(arg)
+    if other == nil then
+        c:send_message('No such player: ' .. arg)
+        return
+    end
+
+    local pawn = c:pawn()
+    if pawn == nil then
+        c:send_message("Can't trade: you have no character")
+        return
+    end
+
+    local err = c:trade(other, pawn:inventory('main'))
+    if err ~= nil then
+        c:send_message("Can't trade: " .. err)
+    end
+end
+command.help.trade = "/trade <name>: Ask <name> to trade items, or accept <name>'s request"
+
 
 function client_by_name(s)
     local w = World.get()
@@ -301,8 +332,201 @@ function command.su_handler.tribe(client, args)
 end
 command.help.tribe = '/tribe [E|P|U|A]: Change the tribe of your character'
 
+function command.su_handler.rollback(client, args)
+    local name, radius, since, until_ = args:match('^([^ ]+) (%d+) (%d+) ?(%d*)$')
+    if name == nil then
+        client:send_message('Usage: ' .. command.help.rollback[1])
+        return
+    end
+    if until_ == '' then
+        until_ = 0
+    end
+
+    local pawn = client:pawn()
+    local center = pawn:pos():pixel_to_tile()
+    local r = radius + 0
+    local min = V3.new(center:x() - r, center:y() - r, 0)
+    local max = V3.new(center:x() + r + 1, center:y() + r + 1, 16)
+    local count, err = pawn:plane():rollback(name, min, max, since * 60, until_ * 60)
+    if count == nil then
+        client:send_message(err)
+    else
+        client:send_message('Undid ' .. count .. ' changes by ' .. name)
+    end
+end
+command.help.rollback = {
+    '/rollback <player> <radius> <minutes> [<until>]: Undo changes made by <player>',
+    'within <radius> tiles of you, between <minutes> and <until> minutes ago',
+}
+
+function command.su_handler.history(client, args)
+    local pawn = client:pawn()
+    local lines, err = pawn:plane():change_history(util.hit_tile(pawn))
+    if lines == nil then
+        client:send_message(err)
+    elseif #lines == 0 then
+        client:send_message('No recorded changes at that location')
+    else
+        for _, line in ipairs(lines) do
+            client:send_message(line)
+        end
+    end
+end
+command.help.history = '/history: Show who changed the tile in front of you'
+
+local LOG_UNITS = { m = 60, h = 60 * 60, d = 24 * 60 * 60 }
+
+function command.su_handler.log(client, args)
+    local name, count, unit = args:match('^(.*) (%d+)([mhd]?)$')
+    if name == nil then
+        name = args
+    end
+    if name == '' then
+        client:send_message('Usage: /log <player> [since]')
+        return
+    elseif name == '*' then
+        name = ''
+    end
+
+    local since = 24 * 60 * 60
+    if count ~= nil then
+        if unit == '' then
+            unit = 'h'
+        end
+        since = (count + 0) * LOG_UNITS[unit]
+    end
+
+    local lines, err = World.get():audit_log(name, since, 50)
+    if lines == nil then
+        client:send_message(err)
+    elseif #lines == 0 then
+        client:send_message('No matching log entries')
+    else
+        for _, line in ipairs(lines) do
+            client:send_message(line)
+        end
+    end
+end
+command.help.log = {
+    "/log <player> [since]: Show a player's recent chat, logins, kicks, and commands",
+    '<since> is like 30m, 2h, or 7d (default 1d).  Use * for all players.',
+}
+
+function command.su_handler.profile(client, args)
+    local profiler = outpost_ffi.profiler
+    if args == 'on' then
+        profiler.start()
+        client:send_message('Profiler started')
+    elseif args == 'off' then
+        profiler.stop()
+        client:send_message('Profiler stopped')
+    elseif args == 'reset' then
+        profiler.reset()
+        client:send_message('Profiler data cleared')
+    elseif args == '' or args == 'report' then
+        for line in profiler.report():gmatch('[^\n]+') do
+            client:send_message(line)
+        end
+    else
+        client:send_message('Usage: ' .. command.help.profile[1])
+    end
+end
+command.help.profile = {
+    '/profile [on|off|reset|report]: Control the script profiler, or show',
+    'time spent in callbacks, Rust functions, and Lua functions',
+}
+
+
+function command.su_handler.daylength(client, args)
+    local minutes, here = args:match('^(%d+) ?(%a*)$')
+    if minutes == nil or (here ~= '' and here ~= 'here') then
+        client:send_message('Usage: ' .. command.help.daylength[1])
+        return
+    end
+
+    local ms = minutes * 60 * 1000
+    local err
+    if here == 'here' then
+        local plane = client:pawn():plane()
+        if ms == 0 then
+            err = plane:clear_day_length()
+        else
+            err = plane:set_day_length(ms)
+        end
+    else
+        err = World.get():set_day_length(ms)
+    end
+    if err ~= nil then
+        client:send_message(err)
+    else
+        client:send_message('Day length updated')
+    end
+end
+command.help.daylength = {
+    '/daylength <minutes> [here]: Set the length of a day, for the world or',
+    'for your current plane only (0 with "here" returns to the world clock)',
+}
+
+function command.su_handler.vanish(client, args)
+    local pawn = client:pawn()
+    if pawn:visibility() == 'hidden' then
+        pawn:show()
+        client:send_message('You are now visible')
+    else
+        pawn:hide()
+        client:send_message('You are now hidden from other players')
+    end
+end
+command.help.vanish = '/vanish: Toggle whether other players can see you'
+
+function command.su_handler.spectate(client, args)
+    if args == '' then
+        client:follow_pawn()
+        client:send_message('Camera returned to your character')
+        return
+    end
+
+    local other = client_by_name(args)
+    if other == nil then
+        client:send_message('No such player: ' .. args)
+    elseif other:pawn() == nil then
+        client:send_message(args .. ' has no character to follow')
+    else
+        client:follow_entity(other:pawn())
+        client:send_message('Now following ' .. args)
+    end
+end
+command.help.spectate = {
+    "/spectate <player>: Move your camera to follow another player",
+    '/spectate: Return your camera to your character',
+}
+
+function command.su_handler.freecam(client, args)
+    if client:camera_mode() == 'free' then
+        client:follow_pawn()
+        client:send_message('Camera returned to your character')
+    else
+        local pawn = client:pawn()
+        client:set_camera_pos(pawn:plane(), pawn:pos())
+        client:send_message('Camera detached; movement keys now move the camera')
+    end
+end
+command.help.freecam = '/freecam: Toggle moving the camera independently of your character'
+
+function command.su_handler.closeinstance(client, args)
+    local p = client:pawn():plane():stable_id()
+    local err = World.get():close_instance(p)
+    if err ~= nil then
+        client:send_message("Can't close instance: " .. err)
+    else
+        client:send_message('Instance closed; it will be removed once it is empty')
+    end
+end
+command.help.closeinstance = '/closeinstance: Send everyone in your current instance to its return point'
+
 
 function outpost_ffi.callbacks.login(c)
+    c:set_chat_staff(c:extra().superuser == true)
     c:set_main_inventories(c:pawn():inventory('main'),
                            c:pawn():inventory('ability'))
 
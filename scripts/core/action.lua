@@ -1,9 +1,11 @@
 local outpost_ffi = require('outpost_ffi')
 local util = require('core.util')
+local async = require('core.async')
 local V3 = outpost_ffi.types.V3.table
 
 
--- Callback for user actions.
+-- Callback for user actions.  Responses to dialogs opened by async tasks (see
+-- core.async) go to the waiting task instead of the usual handler.
 
 local function noop(...) end
 
@@ -18,6 +20,10 @@ end
 
 local structure_use_handlers = {}
 function outpost_ffi.callbacks.interact(client, args)
+    if args ~= nil and async.resume_dialog(client, 'interact', args) then
+        return
+    end
+
     local s = util.hit_structure(client:pawn())
     if s ~= nil then
         local handler = get_or_noop(structure_use_handlers, s:template())
@@ -37,6 +43,10 @@ function outpost_ffi.callbacks.use_item(c, item_id, args)
         return
     end
 
+    if args ~= nil and async.resume_dialog(c, 'use_item/' .. item_id, args) then
+        return
+    end
+
     local handler = get_or_noop(item_use_handlers, item_type)
     handler(c, inv, args)
 end
@@ -53,6 +63,10 @@ function outpost_ffi.callbacks.use_ability(c, item_id, args)
         return
     end
 
+    if args ~= nil and async.resume_dialog(c, 'use_ability/' .. item_id, args) then
+        return
+    end
+
     local prefix = 'ability/'
     if item_type:sub(1, #prefix) ~= prefix then
         print('tried to use non-ability: ' .. item_type)
@@ -0,0 +1,189 @@
+local timer = require('core.timer')
+local events = require('core.events')
+
+
+-- Asynchronous tasks.  A task is a function running in a coroutine, which can
+-- suspend itself to wait for something to happen:
+--
+--  sleep(ms)                               Wait for `ms` milliseconds.
+--  wait_event(name, filter, timeout)       Wait for a world event (see
+--                                          core.events) for which
+--                                          `filter(...)` returns true, and
+--                                          return its arguments.
+--  interact_dialog(c, dialog_id, parts, timeout)
+--  use_item_dialog(c, item_id, dialog_id, parts, timeout)
+--  use_ability_dialog(c, item_id, dialog_id, parts, timeout)
+--                                          Show a dialog to client `c` and
+--                                          return the arguments it sends back.
+--
+-- Waits that time out, and dialogs the client never answers (or answers after
+-- disconnecting, or replaces with another dialog of the same kind), return
+-- nil.  Dialogs time out after five minutes by default.
+--
+-- Tasks are resumed from the same callbacks that drive the rest of the
+-- scripts: timers from `timeout`, world events from `world_event`, and dialog
+-- responses from `interact`, `use_item`, and `use_ability` (see core.action).
+-- A task must not yield from inside a `pcall`.  Tasks are lost when the
+-- scripts are reloaded.
+
+local DEFAULT_DIALOG_TIMEOUT = 5 * 60 * 1000
+
+local function resume(co, ...)
+    local ok, err = coroutine.resume(co, ...)
+    if not ok then
+        print('error in async task: ' .. debug.traceback(co, tostring(err)))
+    end
+end
+
+-- Start running `f(...)` as a task.  It runs until its first wait before
+-- `run` returns.
+local function run(f, ...)
+    local co = coroutine.create(f)
+    resume(co, ...)
+    return co
+end
+
+-- Wrap `f` so that each call runs it as a new task.  Useful for action
+-- handlers.
+local function handler(f)
+    return function(...)
+        run(f, ...)
+    end
+end
+
+-- Suspend the current task.  `setup(wake)` should arrange for `wake(...)` to
+-- be called later, and may return a cleanup function, which runs before the
+-- task is resumed.  Only the first call to `wake` has any effect.  Returns the
+-- arguments passed to `wake`.
+local function wait(setup)
+    local co = coroutine.running()
+    if co == nil then
+        error('cannot wait outside of an async task', 3)
+    end
+
+    local woken = false
+    local cleanup
+    cleanup = setup(function(...)
+        if woken then
+            return
+        end
+        woken = true
+        if cleanup ~= nil then
+            cleanup()
+        end
+        resume(co, ...)
+    end)
+    return coroutine.yield()
+end
+
+
+local function sleep(ms)
+    wait(function(wake)
+        local t = timer.set_timer(ms, function() wake() end)
+        return function() t:cancel() end
+    end)
+end
+
+local function wait_event(name, filter, timeout)
+    return wait(function(wake)
+        local h = events.subscribe(name, function(...)
+            if filter == nil or filter(...) then
+                wake(...)
+            end
+        end)
+        local t = nil
+        if timeout ~= nil then
+            t = timer.set_timer(timeout, function() wake() end)
+        end
+        return function()
+            events.unsubscribe(name, h)
+            if t ~= nil then
+                t:cancel()
+            end
+        end
+    end)
+end
+
+
+-- Maps each client ID to a table of pending dialogs, keyed by dialog kind.
+local dialog_waiters = {}
+
+local function wait_dialog(c, key, send, timeout)
+    local id = c:id()
+    local name = c:name()
+    return wait(function(wake)
+        local old = dialog_waiters[id] and dialog_waiters[id][key]
+        if old ~= nil then
+            old.wake()
+        end
+
+        local ws = dialog_waiters[id]
+        if ws == nil then
+            ws = {}
+            dialog_waiters[id] = ws
+        end
+
+        local waiter = { name = name, wake = wake }
+        ws[key] = waiter
+        send()
+
+        local t = timer.set_timer(timeout or DEFAULT_DIALOG_TIMEOUT, function() wake() end)
+        return function()
+            if ws[key] == waiter then
+                ws[key] = nil
+                if next(ws) == nil and dialog_waiters[id] == ws then
+                    dialog_waiters[id] = nil
+                end
+            end
+            t:cancel()
+        end
+    end)
+end
+
+local function interact_dialog(c, dialog_id, parts, timeout)
+    return wait_dialog(c, 'interact', function()
+        c:get_interact_args(dialog_id, parts)
+    end, timeout)
+end
+
+local function use_item_dialog(c, item_id, dialog_id, parts, timeout)
+    return wait_dialog(c, 'use_item/' .. item_id, function()
+        c:get_use_item_args(item_id, dialog_id, parts)
+    end, timeout)
+end
+
+local function use_ability_dialog(c, item_id, dialog_id, parts, timeout)
+    return wait_dialog(c, 'use_ability/' .. item_id, function()
+        c:get_use_ability_args(item_id, dialog_id, parts)
+    end, timeout)
+end
+
+-- Deliver a dialog response from client `c`.  Returns true if a task was
+-- waiting for it.
+local function resume_dialog(c, key, args)
+    local ws = dialog_waiters[c:id()]
+    local waiter = ws and ws[key]
+    if waiter == nil then
+        return false
+    end
+    if waiter.name ~= c:name() then
+        -- The client that opened the dialog is gone, and its ID was reused.
+        waiter.wake()
+        return false
+    end
+    waiter.wake(args)
+    return true
+end
+
+
+return {
+    run = run,
+    handler = handler,
+    wait = wait,
+    sleep = sleep,
+    wait_event = wait_event,
+    interact_dialog = interact_dialog,
+    use_item_dialog = use_item_dialog,
+    use_ability_dialog = use_ability_dialog,
+    resume_dialog = resume_dialog,
+}
@@ -0,0 +1,51 @@
+local outpost_ffi = require('outpost_ffi')
+
+
+-- Chat filters.  Each filter is called as `f(client, channel, msg)` before a
+-- message typed by a player is sent.  `channel` is one of 'global', 'local',
+-- 'party', 'staff', or 'whisper'.  A filter can return a string to replace
+-- the message, `false` to drop it, or `nil` to leave it unchanged.  Filters
+-- run in the order they were added.
+
+local filters = {}
+
+local function add_filter(f)
+    filters[#filters + 1] = f
+    return f
+end
+
+local function remove_filter(f)
+    for i, g in ipairs(filters) do
+        if g == f then
+            table.remove(filters, i)
+            return
+        end
+    end
+end
+
+-- Run `msg` through all filters.  Returns the new message, or `nil` if a
+-- filter dropped it.
+local function filter(client, channel, msg)
+    for _, f in ipairs(filters) do
+        local ok, result = pcall(f, client, channel, msg)
+        if not ok then
+            print('error in chat filter: ' .. tostring(result))
+        elseif result == false then
+            return nil
+        elseif result ~= nil then
+            msg = result
+        end
+    end
+    return msg
+end
+
+function outpost_ffi.callbacks.chat_filter(client, channel, msg)
+    return filter(client, channel, msg)
+end
+
+
+return {
+    add_filter = add_filter,
+    remove_filter = remove_filter,
+    filter = filter,
+}
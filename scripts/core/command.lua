@@ -20,7 +20,9 @@ function outpost_ffi.callbacks.command(client, msg)
     local handler = nil
     if client:extra().superuser then
         handler = super_command_handlers[command]
-        if handler == nil then
+        if handler ~= nil then
+            client:record_command(msg)
+        else
             handler = command_handlers[command]
         end
     else
@@ -1,21 +1,24 @@
 local outpost_ffi = require('outpost_ffi')
 
+-- REPL code runs against the trusted global environment, captured here rather
+-- than taken from whatever thread happens to be running the callback.
+local real_env = _G
+
 
 function outpost_ffi.callbacks.eval(w, code)
-    func, err = loadstring(code, '<repl>')
+    local func, err = loadstring(code, '<repl>')
 
     if not func then
         return 'error parsing code: ' .. err .. '\n >>> '
     end
 
     local out_buf = ''
-    local real_env = getfenv(0)
     local eval_env = {
         _real_env = real_env,
         print = function(...)
-            s = ''
+            local s = ''
             for i = 1, select('#', ...) do
-                x = select(i, ...)
+                local x = select(i, ...)
                 s = s .. tostring(x) .. '\t'
             end
             out_buf = out_buf .. s .. '\n'
@@ -25,7 +28,7 @@ function outpost_ffi.callbacks.eval(w, code)
     setmetatable(eval_env, { __index = real_env })
     setfenv(func, eval_env)
 
-    ok, msg = pcall(func)
+    local ok, msg = pcall(func)
 
     if ok then
         if msg ~= nil then
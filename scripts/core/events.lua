@@ -0,0 +1,139 @@
+local outpost_ffi = require('outpost_ffi')
+
+
+-- Subscriptions to world events.  The server collects events while it
+-- processes a request and delivers them in a batch once it's done, so
+-- handlers are free to modify the world.  Available events and the arguments
+-- passed to their handlers:
+--
+--  structure_create(s)                     A structure was placed.
+--  structure_destroy(id, plane, min, max)  A structure was removed.  `id` is
+--                                          the old structure ID (a number),
+--                                          and `min` and `max` are the
+--                                          corners of its old bounds.
+--  structure_replace(s)                    A structure's template changed.
+--  inventory_update(i, item_id, old, new)  The count of an item changed.
+--  entity_motion(e)                        An entity started a new motion.
+--  terrain_chunk_create(plane, cpos)       A new terrain chunk was generated.
+--  dawn(day)                               The sun started to rise.  `day` is
+--                                          the world clock's day count.
+--  dusk(day)                               The sun started to set.
+--
+-- Objects that are loaded or unloaded with their chunk don't generate events.
+
+local handlers = {}
+
+-- The server only queues events that have at least one handler, so it needs
+-- to be told the number of handlers whenever it changes.  Scripts subscribe
+-- to some events while they're being loaded, before the server can be
+-- called, so the counts are only reported once the server has asked for them
+-- through the `world_event_sync` callback.
+local synced = false
+
+local function report(name)
+    if synced then
+        local hs = handlers[name]
+        World.get():set_event_subscribers(name, hs ~= nil and #hs or 0)
+    end
+end
+
+local function subscribe(name, f)
+    local hs = handlers[name]
+    if hs == nil then
+        hs = {}
+        handlers[name] = hs
+    end
+    hs[#hs + 1] = f
+    report(name)
+    return f
+end
+
+local function unsubscribe(name, f)
+    local hs = handlers[name]
+    if hs == nil then
+        return
+    end
+    for i, h in ipairs(hs) do
+        if h == f then
+            table.remove(hs, i)
+            report(name)
+            return
+        end
+    end
+end
+
+function outpost_ffi.callbacks.world_event_sync()
+    synced = true
+    for name, _ in pairs(handlers) do
+        report(name)
+    end
+end
+
+function outpost_ffi.callbacks.world_event(name, ...)
+    local hs = handlers[name]
+    if hs == nil then
+        return
+    end
+    -- Copy the list, so handlers can unsubscribe while it's being traversed.
+    local copy = {}
+    for i, h in ipairs(hs) do
+        copy[i] = h
+    end
+    for _, h in ipairs(copy) do
+        local ok, err = pcall(h, ...)
+        if not ok then
+            print('error in ' .. name .. ' handler: ' .. tostring(err))
+        end
+    end
+end
+
+
+-- Call `f(e)` whenever an entity on plane `pid` enters the region between
+-- `min` (inclusive) and `max` (exclusive).  Positions are checked each time an
+-- entity starts a new motion.  Returns a handle for use with
+-- `unsubscribe_region`.
+local function on_region_enter(pid, min, max, f)
+    local inside = {}
+    return subscribe('entity_motion', function(e)
+        local id = e:id()
+        local p = e:plane()
+        local pos = e:pos()
+
+        local now_inside = p ~= nil and p:id() == pid and
+            pos:x() >= min:x() and pos:x() < max:x() and
+            pos:y() >= min:y() and pos:y() < max:y() and
+            pos:z() >= min:z() and pos:z() < max:z()
+
+        if now_inside and not inside[id] then
+            inside[id] = true
+            f(e)
+        elseif not now_inside then
+            inside[id] = nil
+        end
+    end)
+end
+
+local function unsubscribe_region(handle)
+    unsubscribe('entity_motion', handle)
+end
+
+
+-- Call `f(day)` each time the sun starts to rise or set, according to the
+-- world clock.  Planes with their own day length don't affect these events.
+local function on_dawn(f)
+    return subscribe('dawn', f)
+end
+
+local function on_dusk(f)
+    return subscribe('dusk', f)
+end
+
+
+return {
+    subscribe = subscribe,
+    unsubscribe = unsubscribe,
+    on_region_enter = on_region_enter,
+    unsubscribe_region = unsubscribe_region,
+    on_dawn = on_dawn,
+    on_dusk = on_dusk,
+}
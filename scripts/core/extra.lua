@@ -102,33 +102,69 @@ function outpost_ffi.types.Structure.table.extra(self)
 end
 
 
--- Callbacks used when structures are loaded/unloaded
+-- Callbacks used when objects are loaded/unloaded.  Only structures have unload
+-- hooks, and only structures with the `has_save_hooks` flag set get their hooks
+-- called.  World, entity, and plane load hooks are called for every object.
 
+local world_hooks = {}
+local entity_hooks = {}
+local plane_hooks = {}
 local structure_hooks = {}
 
+local function run_hooks(hooks_list, name, e, id)
+    for _, hooks in ipairs(hooks_list) do
+        if hooks[name] ~= nil then
+            hooks[name](e, id)
+        end
+    end
+end
+
+local function register_world_hooks(hooks)
+    world_hooks[#world_hooks + 1] = hooks
+end
+
+local function register_entity_hooks(hooks)
+    entity_hooks[#entity_hooks + 1] = hooks
+end
+
+local function register_plane_hooks(hooks)
+    plane_hooks[#plane_hooks + 1] = hooks
+end
+
 local function register_structure_hooks(hooks)
     structure_hooks[#structure_hooks + 1] = hooks
 end
 
+function outpost_ffi.callbacks.world_load()
+    run_hooks(world_hooks, 'load', world_extra)
+end
+
+function outpost_ffi.callbacks.entity_load(id)
+    if entity_extra[id] ~= nil then
+        run_hooks(entity_hooks, 'load', entity_extra[id], id)
+    end
+end
+
+function outpost_ffi.callbacks.plane_load(id)
+    if plane_extra[id] ~= nil then
+        run_hooks(plane_hooks, 'load', plane_extra[id], id)
+    end
+end
+
 function outpost_ffi.callbacks.structure_load(id)
     print('load', id)
-    for _, hooks in ipairs(structure_hooks) do
-        if hooks.load ~= nil then
-            hooks.load(get_or_create(structure_extra, id), id)
-        end
-    end
+    run_hooks(structure_hooks, 'load', get_or_create(structure_extra, id), id)
 end
 
 function outpost_ffi.callbacks.structure_unload(id)
     print('unload', id)
-    for _, hooks in ipairs(structure_hooks) do
-        if hooks.unload ~= nil then
-            hooks.unload(get_or_create(structure_extra, id), id)
-        end
-    end
+    run_hooks(structure_hooks, 'unload', get_or_create(structure_extra, id), id)
 end
 
 
 return {
+    register_world_hooks = register_world_hooks,
+    register_entity_hooks = register_entity_hooks,
+    register_plane_hooks = register_plane_hooks,
     register_structure_hooks = register_structure_hooks,
 }
@@ -0,0 +1,206 @@
+-- Isolation for third-party mods.  Modules belonging to the trusted mods (the
+-- core library and the base game) run with the normal global environment.
+-- Everything else is loaded into a restricted environment built from an
+-- explicit list of safe globals.  Mods can't reach `outpost_ffi` or the
+-- trusted modules, except for the read-only views listed in `MOD_API`.
+
+local TRUSTED = {
+    core = true,
+    outpost = true,
+    loader = true,
+    bootstrap = true,
+}
+
+local SAFE_GLOBALS = {
+    'assert', 'error', 'ipairs', 'next', 'pairs', 'pcall', 'print', 'rawequal',
+    'rawget', 'rawset', 'select', 'setmetatable', 'tonumber', 'tostring',
+    'type', 'unpack', 'xpcall',
+}
+
+-- Libraries that mods get their own copies of, so changes to them stay within
+-- the mod.
+local SAFE_LIBRARIES = {
+    'coroutine', 'math', 'string', 'table',
+}
+
+-- Game types available as globals.
+local SAFE_TYPES = {
+    'V3', 'V2', 'World', 'ExtraArg', 'Time', 'Timer',
+}
+
+-- Trusted modules that mods may require.  Mods get a read-only view of each
+-- one, so they can add handlers to its tables but can't replace its functions.
+local MOD_API = {
+    ['core.action'] = true,
+    ['core.command'] = true,
+    ['core.util'] = true,
+    ['outpost.lib.tools'] = true,
+    ['outpost.lib.ward'] = true,
+}
+
+local real_G = _G
+local real_require = require
+local real_os = os
+local real_debug = debug
+local real_getfenv = getfenv
+local real_getmetatable = getmetatable
+
+local script_dir = package.path:match('^(.*)/%?%.lua$') or '.'
+
+local safe_os = {
+    time = real_os.time,
+    clock = real_os.clock,
+    date = real_os.date,
+    difftime = real_os.difftime,
+}
+
+-- Environments created by `make_env`.
+local mod_envs = setmetatable({}, { __mode = 'k' })
+
+local function read_only(t)
+    return setmetatable({}, {
+        __index = t,
+        __newindex = function(_, k)
+            error("can't modify " .. tostring(k), 2)
+        end,
+        __metatable = false,
+    })
+end
+
+local function copy(t)
+    local result = {}
+    for k, v in pairs(t) do
+        result[k] = v
+    end
+    return result
+end
+
+-- The metatables of userdata and strings are shared with trusted code, so only
+-- the metatables of tables are visible to mods.
+local function safe_getmetatable(x)
+    if type(x) ~= 'table' then
+        return nil
+    end
+    return real_getmetatable(x)
+end
+
+local function mod_of(name)
+    return name:match('^([^.]*)')
+end
+
+local function find_module(name)
+    local rel = name:gsub('%.', '/')
+    for pat in package.path:gmatch('[^;]+') do
+        local path = pat:gsub('%?', rel)
+        local f = io.open(path, 'r')
+        if f ~= nil then
+            f:close()
+            return path
+        end
+    end
+    return nil
+end
+
+local api_views = {}
+
+local function safe_require(name)
+    if MOD_API[name] then
+        if api_views[name] == nil then
+            api_views[name] = read_only(real_require(name))
+        end
+        return api_views[name]
+    end
+
+    -- Only the mods' own script files can be loaded.  This excludes the
+    -- built-in libraries and `outpost_ffi`, which have no file.
+    if TRUSTED[mod_of(name)] or find_module(name) == nil then
+        error('module ' .. name .. ' is not available to mods', 2)
+    end
+    return real_require(name)
+end
+
+local function make_env()
+    local env = {
+        os = copy(safe_os),
+        require = safe_require,
+        getmetatable = safe_getmetatable,
+    }
+    for _, k in ipairs(SAFE_GLOBALS) do
+        env[k] = real_G[k]
+    end
+    for _, k in ipairs(SAFE_LIBRARIES) do
+        env[k] = copy(real_G[k])
+    end
+    for _, k in ipairs(SAFE_TYPES) do
+        env[k] = read_only(real_G[k])
+    end
+    env._G = env
+
+    mod_envs[env] = true
+    return env
+end
+
+local function sandboxed_loader(name)
+    if TRUSTED[mod_of(name)] then
+        return nil
+    end
+
+    local path = find_module(name)
+    if path == nil then
+        return '\n\tno mod file for ' .. name
+    end
+
+    local chunk, err = loadfile(path)
+    if chunk == nil then
+        error(err, 2)
+    end
+    setfenv(chunk, make_env())
+    return chunk
+end
+
+table.insert(package.loaders, 2, sandboxed_loader)
+
+
+-- Find the mod responsible for the innermost stack frame that doesn't belong
+-- to the core library.
+local function blame_mod()
+    local prefix = '@' .. script_dir .. '/'
+    local level = 2
+    while true do
+        local info = real_debug.getinfo(level, 'S')
+        if info == nil then
+            return nil
+        end
+
+        local src = info.source
+        if src:sub(1, #prefix) == prefix then
+            local mod = src:sub(#prefix + 1):match('^([^/.]*)')
+            if mod ~= 'core' and mod ~= 'bootstrap' then
+                return mod
+            end
+        end
+
+        level = level + 1
+    end
+end
+
+function outpost_ffi.callbacks.error_handler(msg)
+    local mod = blame_mod()
+    if mod ~= nil then
+        msg = tostring(msg) .. ' (in mod ' .. mod .. ')'
+    end
+    return real_debug.traceback(msg, 2)
+end
+
+
+-- Callbacks are only set by trusted code.  Mods have no way to reach
+-- `outpost_ffi.callbacks`, but refuse the write anyway if one finds a way.
+local callbacks_meta = real_getmetatable(outpost_ffi.callbacks)
+local set_callback = callbacks_meta.__newindex
+callbacks_meta.__newindex = function(t, k, v)
+    if mod_envs[real_getfenv(2)] then
+        error("mods can't set callback " .. tostring(k), 2)
+    end
+    set_callback(t, k, v)
+end
+callbacks_meta.__metatable = false
@@ -1,4 +1,5 @@
 local outpost_ffi = require('outpost_ffi')
+local extra = require('core.extra')
 
 
 local timers = {}
@@ -64,8 +65,172 @@ function outpost_ffi.callbacks.timeout(slot)
     t:callback()
 end
 
+-- Called before the script state is replaced during a reload.  Pending timers
+-- would otherwise fire into the new state, which doesn't know about them.
+function outpost_ffi.callbacks.cancel_all_timers()
+    for _, t in pairs(timers) do
+        t:cancel()
+    end
+end
+
+
+
+-- Persistent timers are saved along with the object that owns them (the World,
+-- an Entity, a Plane, or a Structure), and are re-armed when the owner is loaded
+-- again, so they survive unloading and server restarts.  Closures can't be saved,
+-- so instead each timer has a name, which is also the key of its handler in
+-- `persistent_handler`.  When the timer fires, the handler is called as
+-- `handler(owner, args)`.  `args` may contain only values that can be saved in
+-- `extra` data.  Each owner can have at most one pending timer with a given name.
+
+local persistent_handler = {}
+
+-- Maps each saved timer record to the in-memory timer that will fire it.
+local live_timers = setmetatable({}, { __mode = 'k' })
+
+local owner_kinds = {
+    World = {
+        id = function(w) return 0 end,
+        get = function(id) return World.get() end,
+    },
+    Entity = {
+        id = function(e) return e:id() end,
+        get = function(id) return World.get():get_entity(id) end,
+    },
+    Plane = {
+        id = function(p) return p:id() end,
+        get = function(id) return World.get():get_plane(id) end,
+    },
+    Structure = {
+        id = function(s) return s:id() end,
+        get = function(id) return World.get():get_structure(id) end,
+    },
+}
+
+local function owner_kind(owner)
+    local mt = getmetatable(owner)
+    for name, _ in pairs(owner_kinds) do
+        if rawequal(mt, outpost_ffi.types[name].metatable) then
+            return name
+        end
+    end
+    error('persistent timers are not supported on ' .. tostring(owner))
+end
+
+local function take_record(kind, owner, name)
+    local e = owner:extra()
+    local timers = e.persistent_timers
+    if timers == nil then
+        return nil
+    end
+
+    local record = timers[name]
+    timers[name] = nil
+    if next(timers) == nil then
+        e.persistent_timers = nil
+        if kind == 'Structure' then
+            owner:set_has_save_hooks(false)
+        end
+    end
+    return record
+end
+
+local function arm_persistent(kind, id, name, record)
+    live_timers[record] = set_timer_at(record.when, function()
+        live_timers[record] = nil
+
+        local owner = owner_kinds[kind].get(id)
+        if owner == nil then
+            return
+        end
+        local timers = owner:extra().persistent_timers
+        if timers == nil or not rawequal(timers[name], record) then
+            -- The owner was unloaded, and the ID now refers to something else.
+            return
+        end
+        take_record(kind, owner, name)
+
+        local h = persistent_handler[name]
+        if h == nil then
+            print('no handler for persistent timer ' .. name)
+            return
+        end
+        h(owner, record.args)
+    end)
+end
+
+local function cancel_live(record)
+    local t = live_timers[record]
+    if t ~= nil then
+        live_timers[record] = nil
+        t:cancel()
+    end
+end
+
+local function cancel_persistent(owner, name)
+    local record = take_record(owner_kind(owner), owner, name)
+    if record ~= nil then
+        cancel_live(record)
+    end
+end
+
+local function set_persistent_timer_at(owner, name, when, args)
+    local kind = owner_kind(owner)
+    cancel_persistent(owner, name)
+
+    local e = owner:extra()
+    if e.persistent_timers == nil then
+        e.persistent_timers = {}
+        if kind == 'Structure' then
+            owner:set_has_save_hooks(true)
+        end
+    end
+
+    local record = {
+        when = when,
+        args = args or {},
+    }
+    e.persistent_timers[name] = record
+    arm_persistent(kind, owner_kinds[kind].id(owner), name, record)
+end
+
+local function set_persistent_timer(owner, name, delay, args)
+    set_persistent_timer_at(owner, name, Time.now() + delay, args)
+end
+
+local function rearm_all(kind)
+    return function(e, id)
+        if e.persistent_timers == nil then
+            return
+        end
+        for name, record in pairs(e.persistent_timers) do
+            arm_persistent(kind, id or 0, name, record)
+        end
+    end
+end
+
+extra.register_world_hooks({ load = rearm_all('World') })
+extra.register_entity_hooks({ load = rearm_all('Entity') })
+extra.register_plane_hooks({ load = rearm_all('Plane') })
+extra.register_structure_hooks({
+    load = rearm_all('Structure'),
+    unload = function(e, id)
+        if e.persistent_timers == nil then
+            return
+        end
+        for _, record in pairs(e.persistent_timers) do
+            cancel_live(record)
+        end
+    end,
+})
+
 
 return {
     set_timer = set_timer,
     set_timer_at = set_timer_at,
+
+    set_persistent_timer = set_persistent_timer,
+    set_persistent_timer_at = set_persistent_timer_at,
+    cancel_persistent_timer = cancel_persistent,
+    persistent_handler = persistent_handler,
 }
@@ -9,7 +9,19 @@ local function hit_structure(entity)
     return entity:world():find_structure_at_point(entity:plane(), hit_tile(entity))
 end
 
+-- Check that `c`'s pawn is still on plane `plane` and facing tile `tile`.
+-- Used after a dialog, since the pawn can move while the dialog is open.
+local function still_facing(c, plane, tile)
+    local pawn = c:pawn()
+    if pawn == nil or pawn:plane() ~= plane then
+        return false
+    end
+    local cur = hit_tile(pawn)
+    return cur:x() == tile:x() and cur:y() == tile:y() and cur:z() == tile:z()
+end
+
 return {
     hit_tile = hit_tile,
     hit_structure = hit_structure,
+    still_facing = still_facing,
 }
@@ -0,0 +1,158 @@
+local chat = require('core.chat')
+local command = require('core.command')
+
+
+function command.handler.count(client, args)
+    local count = World.get():client_count()
+    local s = ''
+    if count ~= 1 then
+        s = 's'
+    end
+    client:send_message(count .. ' player' .. s .. ' online')
+end
+command.help.count = '/count: Show the number of players online'
+
+
+-- Send `msg` on a channel, after running it through the chat filters.
+local function send(client, channel, msg)
+    if msg == '' then
+        return
+    end
+    msg = chat.filter(client, channel, msg)
+    if msg == nil then
+        return
+    end
+    local err = client:chat(channel, msg)
+    if err ~= nil then
+        client:send_message(err)
+    end
+end
+
+function command.handler.g(client, args)
+    send(client, 'global', args)
+end
+command.help.g = '/g <message>: Send a message to all players'
+
+function command.handler.l(client, args)
+    send(client, 'local', args)
+end
+command.help.l = '/l <message>: Send a message to nearby players'
+
+function command.handler.p(client, args)
+    send(client, 'party', args)
+end
+command.help.p = '/p <message>: Send a message to your party'
+
+function command.su_handler.s(client, args)
+    send(client, 'staff', args)
+end
+command.help.s = '/s <message>: Send a message to other staff members'
+
+function command.handler.channel(client, args)
+    if args == '' then
+        client:send_message('You are talking on the ' .. client:chat_channel() .. ' channel')
+        return
+    end
+
+    local err = client:set_chat_channel(args)
+    if err ~= nil then
+        client:send_message("Can't switch channels: " .. err)
+    else
+        client:send_message('You are now talking on the ' .. args .. ' channel')
+    end
+end
+command.help.channel = {
+    '/channel <name>: Send your messages to <name> by default',
+    'Channels: global, local, party',
+    '/channel: Show your current channel',
+}
+
+
+function command.handler.msg(client, args)
+    local name, msg = args:match('([^ ]+) (.*)')
+    if name == nil then
+        client:send_message('Usage: /msg <name> <message>')
+        return
+    end
+
+    msg = chat.filter(client, 'whisper', msg)
+    if msg == nil then
+        return
+    end
+    client:extra().last_whisper = name
+    client:whisper(name, msg)
+end
+command.help.msg = '/msg <name> <message>: Send a private message to <name>'
+command.handler.w = command.handler.msg
+command.help.w = '/w <name> <message>: Same as /msg'
+
+function command.handler.r(client, args)
+    local name = client:extra().last_whisper
+    if name == nil then
+        client:send_message('You have not sent any private messages yet')
+        return
+    end
+    command.handler.msg(client, name .. ' ' .. args)
+end
+command.help.r = '/r <message>: Send a private message to the last person you messaged'
+
+
+function command.handler.ignore(client, args)
+    if args == '' then
+        local names = client:ignored()
+        if #names == 0 then
+            client:send_message('You are not ignoring anyone')
+        else
+            client:send_message('Ignoring: ' .. table.concat(names, ', '))
+        end
+        return
+    end
+
+    local err = client:ignore(args)
+    if err ~= nil then
+        client:send_message("Can't ignore " .. args .. ': ' .. err)
+    else
+        client:send_message('Ignoring ' .. args)
+    end
+end
+command.help.ignore = {
+    "/ignore <name>: Hide <name>'s chat messages",
+    '/ignore: List the players you are ignoring',
+}
+
+function command.handler.unignore(client, args)
+    local err = client:unignore(args)
+    if err ~= nil then
+        client:send_message("Can't unignore " .. args .. ': ' .. err)
+    else
+        client:send_message('No longer ignoring ' .. args)
+    end
+end
+command.help.unignore = "/unignore <name>: Show <name>'s chat messages again"
+
+
+function command.su_handler.mute(client, args)
+    local name, mins = args:match('([^ ]+) ([%d]+)')
+    if name == nil then
+        client:send_message('Usage: /mute <name> <minutes>')
+        return
+    end
+
+    local err = World.get():mute(name, mins * 60000)
+    if err ~= nil then
+        client:send_message("Can't mute " .. name .. ': ' .. err)
+    else
+        client:send_message('Muted ' .. name .. ' for ' .. mins .. ' minutes')
+    end
+end
+command.help.mute = '/mute <name> <minutes>: Prevent <name> from chatting'
+
+function command.su_handler.unmute(client, args)
+    local err = World.get():unmute(args)
+    if err ~= nil then
+        client:send_message("Can't unmute " .. args .. ': ' .. err)
+    else
+        client:send_message('Unmuted ' .. args)
+    end
+end
+command.help.unmute = '/unmute <name>: Allow <name> to chat again'
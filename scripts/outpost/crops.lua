@@ -22,6 +22,20 @@ local function set_crop_timer(s, steps)
     s:set_timer_at(when)
 end
 
+-- Crops only grow during the day, as seen on the crop's own plane.  If a stage
+-- would finish at night, hold it until dawn and push back the rest of the
+-- schedule to match.  Returns true if the stage was delayed.
+local function wait_for_day(s)
+    local p = s:plane()
+    if p:is_day() then
+        return false
+    end
+    local dawn = p:next_dawn()
+    s:extra().start_time = s:extra().start_time + (dawn - Time.now())
+    s:set_timer_at(dawn)
+    return true
+end
+
 
 local function mk_crop(name)
     action.use_item[name] = function(c, inv)
@@ -45,16 +59,19 @@ local function mk_crop(name)
     end
 
     timer.handler[name .. '/0'] = function(s)
+        if wait_for_day(s) then return end
         s:replace(name .. '/1')
         set_crop_timer(s, 2)
     end
 
     timer.handler[name .. '/1'] = function(s)
+        if wait_for_day(s) then return end
         s:replace(name .. '/2')
         set_crop_timer(s, 3)
     end
 
     timer.handler[name .. '/2'] = function(s)
+        if wait_for_day(s) then return end
         s:replace(name .. '/3')
     end
 
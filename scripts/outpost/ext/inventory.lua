@@ -1,11 +1,28 @@
 local outpost_ffi = require('outpost_ffi')
 
+-- Slot limits for newly created inventories.  Inventories not listed here
+-- (and any created before limits existed) have no limit.
+local ENTITY_SLOTS = {
+    main = 30,
+    outbox = 10,
+}
+
+local STRUCTURE_SLOTS = {
+    chest = 20,
+    barrel = 10,
+    cabinets = 15,
+}
+
 function outpost_ffi.types.Entity.table.inventory(e, name)
     local extra = e:extra()
     local k = 'inventory_' .. name
     if extra[k] == nil then
         local i, err = e:world():create_inventory()
         i:attach_to_entity(e)
+        local slots = ENTITY_SLOTS[name]
+        if slots ~= nil then
+            i:set_limits(slots, 0, 0)
+        end
         extra[k] = i
     end
     return extra[k]
@@ -17,7 +34,46 @@ function outpost_ffi.types.Structure.table.inventory(s, name)
     if extra[k] == nil then
         local i, err = s:world():create_inventory()
         i:attach_to_structure(s)
+        local slots = STRUCTURE_SLOTS[s:template()]
+        if name == 'contents' and slots ~= nil then
+            i:set_limits(slots, 0, 0)
+        end
         extra[k] = i
     end
     return extra[k]
 end
+
+-- Schematics record structure inventories by the name they are registered
+-- under in the structure's extras, and `paste_schematic` registers the
+-- restored inventories under the same names.
+local INVENTORY_PREFIX = 'inventory_'
+
+local save_schematic = outpost_ffi.types.Plane.table.save_schematic
+function outpost_ffi.types.Plane.table.save_schematic(p, name, min, max, with_items)
+    local names = ExtraArg.map()
+    if with_items then
+        for _, s in ipairs(p:structures_in_region(min, max)) do
+            for k, v in pairs(s:extra()) do
+                if k:sub(1, #INVENTORY_PREFIX) == INVENTORY_PREFIX then
+                    names:set(v:id(), ExtraArg.str(k:sub(#INVENTORY_PREFIX + 1)))
+                end
+            end
+        end
+    end
+    return save_schematic(p, name, min, max, with_items or false, names)
+end
+
+local paste_schematic = outpost_ffi.types.Plane.table.paste_schematic
+function outpost_ffi.types.Plane.table.paste_schematic(p, name, pos, rotation)
+    local structures, names, invs, err = paste_schematic(p, name, pos, rotation or 0)
+    if structures == nil then
+        return nil, err
+    end
+    for j, s in ipairs(structures) do
+        local extra = s:extra()
+        for k, inv_name in ipairs(names[j]) do
+            extra[INVENTORY_PREFIX .. inv_name] = invs[j][k]
+        end
+    end
+    return structures
+end
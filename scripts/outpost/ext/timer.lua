@@ -5,29 +5,14 @@ local timer = require('core.timer')
 
 local handlers = {}
 
-local function mk_callback(sid)
-    return function(t)
-        local s = World.get():get_structure(sid)
-        if s == nil then
-            -- Structure ID is no longer valid.
-            return
-        end
-        local template = s:template()
-
-        if not rawequal(t, s:extra().pending_timer) then
-            -- Structure ID no longer refers to the same structure.
-            return
-        end
-
-        s:extra().pending_timer = nil
-        s:set_has_save_hooks(false)
-
-        local h = handlers[s:template()]
-        if h == nil then
-            return
-        end
-        h(s)
+-- Structure timers are persistent timers named 'structure', which dispatch to
+-- the handler for the structure's current template.
+timer.persistent_handler.structure = function(s, args)
+    local h = handlers[s:template()]
+    if h == nil then
+        return
     end
+    h(s)
 end
 
 function outpost_ffi.types.Structure.table.set_timer(s, delay)
@@ -35,39 +20,27 @@ function outpost_ffi.types.Structure.table.set_timer(s, delay)
 end
 
 function outpost_ffi.types.Structure.table.set_timer_at(s, when)
-    s:extra().pending_timer = timer.set_timer_at(when, mk_callback(s:id()))
-    s:set_has_save_hooks(true)
+    timer.set_persistent_timer_at(s, 'structure', when)
 end
 
 function outpost_ffi.types.Structure.table.cancel_timer(s)
-    local t = s:extra().pending_timer
-    if t == nil then
-        return
-    end
-    s:extra().pending_timer = nil
-    s:set_has_save_hooks(false)
-    s:cancel()
+    timer.cancel_persistent_timer(s, 'structure')
 end
 
 
-function pre_unload(e, id)
-    if e.pending_timer ~= nil then
-        local t = e.pending_timer
-        e.pending_timer = {
-            when = t.when,
-        }
-        t:cancel()
-    end
-end
-
-function post_load(e, id)
+-- Older saves store a structure's pending timer as `pending_timer = { when = ... }`.
+local function post_load(e, id)
     if e.pending_timer ~= nil then
         local when = e.pending_timer.when
-        e.pending_timer = timer.set_timer_at(when, mk_callback(id))
+        e.pending_timer = nil
+        local s = World.get():get_structure(id)
+        if s ~= nil then
+            s:set_timer_at(when)
+        end
     end
 end
 
-extra.register_structure_hooks({ unload = pre_unload, load = post_load })
+extra.register_structure_hooks({ load = post_load })
 
 
 return {
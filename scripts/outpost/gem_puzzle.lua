@@ -82,7 +82,7 @@ local function handle_use_normal(c, s)
     local item_name = ITEM_PREFIX .. color
 
     local inv = c:pawn():inventory('main')
-    if inv:count(item_name) == 255 then
+    if inv:space_for(item_name) == 0 then
         return
     end
     inv:update(item_name, 1)
@@ -20,7 +20,7 @@ local function place_structure(world, inv, plane, pos, item_name, template_name)
 end
 
 local function take_structure(s, inv, item_name)
-    if inv:count(item_name) == 255 then
+    if inv:space_for(item_name) == 0 then
         return
     end
 
@@ -113,8 +113,17 @@ return {
     revoke = function(c, name) return revoke(c:stable_id(), name) end,
 
     check = function(c, pos)
+        local plane = c:pawn():plane()
+        if not plane:build_allowed() then
+            if c:extra().superuser then
+                return true
+            end
+            c:send_message("You can't build here.")
+            return false
+        end
+
         -- There are no wards outside the forest.
-        if c:pawn():plane():name() ~= PLANE_FOREST then
+        if not is_forest(plane) then
             return true
         end
 
@@ -0,0 +1,85 @@
+local action = require('core.action')
+local command = require('core.command')
+
+
+-- Items placed in a player's outbox are attached to the next letter they send.
+local function outbox(client)
+    return client:pawn():inventory('outbox')
+end
+
+local function open_mailbox(client)
+    local main = client:pawn():inventory('main')
+    local mailbox = client:mailbox()
+    if mailbox == nil then
+        client:send_message("Can't open mailbox: no mailbox")
+        return
+    end
+    client:open_container(main, mailbox)
+end
+
+local function list_mail(client)
+    local letters, err = client:mail()
+    if letters == nil then
+        client:send_message("Can't read mail: " .. err)
+        return
+    end
+    if #letters == 0 then
+        client:send_message('You have no mail')
+        return
+    end
+    for i, l in ipairs(letters) do
+        client:send_message(i .. '. ' .. l)
+    end
+end
+
+function command.handler.mail(client, args)
+    local cmd, rest = args:match('^([^ ]*) *(.*)$')
+    local err
+
+    if cmd == '' then
+        list_mail(client)
+        return
+    elseif cmd == 'send' then
+        local to, body = rest:match('^([^ ]+) *(.*)$')
+        if to == nil then
+            client:send_message('Usage: /mail send <name> <message>')
+            return
+        end
+        err = client:send_mail_items(to, body, outbox(client))
+        if err == nil then
+            client:send_message('Mail sent to ' .. to)
+        end
+    elseif cmd == 'delete' then
+        local index = tonumber(rest)
+        if index == nil then
+            client:send_message('Usage: /mail delete <number>')
+            return
+        end
+        err = client:delete_mail(index)
+    elseif cmd == 'box' then
+        open_mailbox(client)
+        return
+    elseif cmd == 'outbox' then
+        client:open_container(client:pawn():inventory('main'), outbox(client))
+        return
+    else
+        client:send_message('Unknown mail command: ' .. cmd)
+        return
+    end
+
+    if err ~= nil then
+        client:send_message("Can't " .. cmd .. ' mail: ' .. err)
+    end
+end
+command.help.mail = {
+    '/mail: List your letters',
+    '/mail send <name> <message>: Send a letter, with the contents of your outbox',
+    '/mail delete <number>: Delete a letter',
+    '/mail box: Collect items mailed to you',
+    '/mail outbox: Put items in your outbox, to send with your next letter',
+}
+
+-- Mailbox structures give access to the same mailbox as `/mail box`.
+function action.use.mailbox(c, s)
+    open_mailbox(c)
+end
@@ -0,0 +1,85 @@
+local command = require('core.command')
+
+
+local function list_members(client)
+    local members = client:party_members()
+    if #members == 0 then
+        client:send_message('You are not in a party')
+        return
+    end
+
+    local leader = client:party_leader()
+    local names = {}
+    for i, m in ipairs(members) do
+        if m:id() == leader:id() then
+            names[i] = m:name() .. ' (leader)'
+        else
+            names[i] = m:name()
+        end
+    end
+    client:send_message('Party: ' .. table.concat(names, ', '))
+end
+
+-- Run a party operation that takes another player as its argument.
+local function with_player(client, name, f)
+    local other = client_by_name(name)
+    if other == nil then
+        client:send_message('No such player: ' .. name)
+        return
+    end
+    return f(other)
+end
+
+function command.handler.party(client, args)
+    local cmd, rest = args:match('^([^ ]*) *(.*)$')
+    local err
+
+    if cmd == '' then
+        list_members(client)
+        return
+    elseif cmd == 'invite' then
+        err = with_player(client, rest, function(other)
+            return client:party_invite(other)
+        end)
+    elseif cmd == 'accept' then
+        err = client:party_accept()
+    elseif cmd == 'decline' then
+        err = client:party_decline()
+    elseif cmd == 'leave' then
+        err = client:party_leave()
+    elseif cmd == 'leader' then
+        err = with_player(client, rest, function(other)
+            return client:party_set_leader(other)
+        end)
+    else
+        client:send_message('Unknown party command: ' .. cmd)
+        return
+    end
+
+    if err ~= nil then
+        client:send_message("Can't " .. cmd .. ': ' .. err)
+    end
+end
+command.help.party = {
+    '/party: List the members of your party',
+    '/party invite <name>: Invite <name> to your party',
+    '/party accept, /party decline: Answer an invitation',
+    '/party leave: Leave your party',
+    '/party leader <name>: Make <name> the party leader',
+}
+
+function command.su_handler.partydungeons(client, args)
+    local extra = World.get():extra()
+    if args == 'on' then
+        extra.party_dungeons = true
+    elseif args == 'off' then
+        extra.party_dungeons = nil
+    end
+
+    if extra.party_dungeons then
+        client:send_message('Each party gets its own instance of a dungeon')
+    else
+        client:send_message('All players share the same dungeons')
+    end
+end
+command.help.partydungeons = '/partydungeons on|off: Give each party its own dungeon instances'
@@ -1,4 +1,6 @@
 local action = require('core.action')
+local async = require('core.async')
+local util = require('core.util')
 local structure_items = require('outpost.lib.structure_items')
 local tools = require('outpost.lib.tools')
 
@@ -8,16 +10,21 @@ function action.use.sign(c, s)
     c:send_message('Sign: ' .. s:extra().message)
 end
 
-function action.use_item.sign(c, inv, args)
-    if args == nil then
-        local id = c:world():item_name_to_id('sign')
-        local parts = ExtraArg.map()
-        c:get_use_item_args(id, SIGN_TEXT_DIALOG_ID, parts)
-    else
-        local s = structure_items.use_item(c, inv, 'sign', 'sign')
+action.use_item.sign = async.handler(function(c, inv)
+    local plane = c:pawn():plane()
+    local tile = util.hit_tile(c:pawn())
+    local id = c:world():item_name_to_id('sign')
+    local args = async.use_item_dialog(c, id, SIGN_TEXT_DIALOG_ID, ExtraArg.map())
+    -- Place the sign where the player was facing when they opened the dialog.
+    if args == nil or not util.still_facing(c, plane, tile) then
+        return
+    end
+
+    local s = structure_items.use_item(c, inv, 'sign', 'sign')
+    if s ~= nil then
         s:extra().message = args:get('msg'):as_str()
     end
-end
+end)
 
 function tools.handler.axe.sign(c, s, inv)
     structure_items.use_structure(c, s, 'sign')
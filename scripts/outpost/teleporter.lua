@@ -1,4 +1,6 @@
 local action = require('core.action')
+local async = require('core.async')
+local util = require('core.util')
 local structure_items = require('outpost.lib.structure_items')
 local tools = require('outpost.lib.tools')
 
@@ -6,53 +8,65 @@ local tools = require('outpost.lib.tools')
 local TELEPORT_SETUP_DIALOG_ID = 1
 local TELEPORT_DEST_DIALOG_ID = 2
 
-function action.use.teleporter(c, s, args)
-    if args == nil then
-        local dests = ExtraArg.list()
-        for k,_ in pairs(net_get_table(s:extra().network)) do
-            dests:push(ExtraArg.str(k))
-        end
+action.use.teleporter = async.handler(function(c, s)
+    local network = s:extra().network
+    local dests = ExtraArg.list()
+    for k,_ in pairs(net_get_table(network)) do
+        dests:push(ExtraArg.str(k))
+    end
 
-        local parts = ExtraArg.map()
-        parts:set('dests', dests)
+    local parts = ExtraArg.map()
+    parts:set('dests', dests)
 
-        c:get_interact_args(TELEPORT_DEST_DIALOG_ID, parts)
-    else
-        local network = s:extra().network
-        local dest_name = args:get('dest'):as_str()
+    local args = async.interact_dialog(c, TELEPORT_DEST_DIALOG_ID, parts)
+    if args == nil then
+        return
+    end
 
-        local dest_pos = net_get_dest(network, dest_name)
+    -- The pawn may have walked away, or the teleporter may be gone, by the
+    -- time the dialog is answered.
+    local pawn = c:pawn()
+    if pawn == nil or util.hit_structure(pawn) ~= s then
+        return
+    end
+    network = s:extra().network
+
+    local dest_name = args:get('dest'):as_str()
+    local dest_pos = net_get_dest(network, dest_name)
+    if dest_pos ~= nil then
         c:pawn():teleport(dest_pos)
     end
-end
+end)
 
-function action.use_item.teleporter(c, inv, args)
+action.use_item.teleporter = async.handler(function(c, inv)
     if not check_forest(c) then return end
 
-    if args == nil then
-        local id = c:world():item_name_to_id('teleporter')
-        local parts = ExtraArg.map()
-        c:get_use_item_args(id, TELEPORT_SETUP_DIALOG_ID, parts)
-    else
-        local name = args:get('name'):as_str()
-        local network = args:get('network'):as_str()
+    local plane = c:pawn():plane()
+    local tile = util.hit_tile(c:pawn())
+    local id = c:world():item_name_to_id('teleporter')
+    local args = async.use_item_dialog(c, id, TELEPORT_SETUP_DIALOG_ID, ExtraArg.map())
+    if args == nil or not util.still_facing(c, plane, tile) then
+        return
+    end
 
-        ok, err = net_register(network, name, c:pawn():pos())
-        if not ok then
-            c:send_message(err)
-            return
-        end
+    local name = args:get('name'):as_str()
+    local network = args:get('network'):as_str()
 
-        local s = structure_items.use_item(c, inv, 'teleporter', 'teleporter')
-        if s == nil then
-            net_deregister(network, name)
-            return
-        end
+    local ok, err = net_register(network, name, c:pawn():pos())
+    if not ok then
+        c:send_message(err)
+        return
+    end
 
-        s:extra().name = name
-        s:extra().network = network
+    local s = structure_items.use_item(c, inv, 'teleporter', 'teleporter')
+    if s == nil then
+        net_deregister(network, name)
+        return
     end
-end
+
+    s:extra().name = name
+    s:extra().network = network
+end)
 
 function tools.handler.pick.teleporter(c, s, inv)
     -- `s` will be destroyed if `use_structure` succeeds, so get its name and
@@ -115,15 +129,46 @@ function net_get_dest(net, name)
 end
 
 
-function action.use.dungeon_entrance(c, s)
+-- Party dungeons are destroyed after being empty for this many seconds.
+local PARTY_DUNGEON_TTL = 10 * 60
+
+-- Get the dungeon plane that `c` should enter through entrance `s`.  When
+-- party dungeons are enabled (see `/partydungeons`), each party gets its own
+-- instance of the dungeon, which goes away once the party has left it.
+local function dungeon_plane(c, s)
+    if World.get():extra().party_dungeons and c:party_leader() ~= nil then
+        local key = 'dungeon:' .. s:stable_id():id()
+        local target = c:party_instance(key)
+        if target == nil then
+            local pawn = c:pawn()
+            local err
+            target, err = World.get():create_generated_instance('dungeon', 'Dungeon',
+                PARTY_DUNGEON_TTL, pawn:plane():stable_id(), pawn:pos())
+            if target == nil then
+                c:send_message("Can't create dungeon: " .. err)
+                return nil
+            end
+            target:get():extra().exit_pos = pawn:pos()
+            c:set_party_instance(key, target)
+        end
+        return target
+    end
+
     if s:extra().target_plane == nil then
         local p = s:world():create_plane('Dungeon')
         p:extra().exit_pos = c:pawn():pos()
         s:extra().target_plane = p:stable_id()
     end
+    return s:extra().target_plane
+end
 
+function action.use.dungeon_entrance(c, s)
+    local target = dungeon_plane(c, s)
+    if target == nil then
+        return
+    end
     local entrance_pos = V3.new(128, 128, 12) * V3.new(32, 32, 32)
-    c:pawn():teleport_stable_plane(s:extra().target_plane, entrance_pos)
+    c:pawn():teleport_stable_plane(target, entrance_pos)
 end
 
 
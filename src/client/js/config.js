@@ -34,6 +34,7 @@ var DEFAULT_CONFIG = {
         38: 'move_up',      // ArrowUp
         40: 'move_down',    // ArrowDown
         16: 'run',          // Shift
+        81: 'jump',         // Q
 
         65: 'interact',     // A
         83: 'use_ability',  // S
@@ -77,6 +78,8 @@ var DEFAULT_CONFIG = {
         32: 'cancel',       // Space
         13: 'select',       // Enter
         65: 'select',       // A
+        76: 'trade_lock',   // L
+        67: 'trade_confirm', // C
 
         49: 'set_hotbar_1',
         50: 'set_hotbar_2',
@@ -3,6 +3,7 @@ function ItemDef_(id, info) {
     this.id = id;
     this.name = info['name'];
     this.ui_name = info['ui_name'] || info['name'];
+    this.stack_size = info['stack_size'] || 255;
     this.tile_x = id & 0x1f;
     this.tile_y = id >> 5;
 }
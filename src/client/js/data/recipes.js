@@ -3,9 +3,10 @@ function RecipeDef_(id, info) {
     this.id = id;
     this.name = info['name'];
     this.ui_name = info['ui_name'] || info['name'];
-    this.station = info['station'];
+    this.stations = info['stations'] || [info['station']];
     this.inputs = info['inputs'];
     this.outputs = info['outputs'];
+    this.time = info['time'] || 0;
 }
 
 // Closure compiler doesn't like having static items on functions.
@@ -10,6 +10,8 @@ function Motion(pos) {
     this.start_time = 0;
     this.end_time = 1;
     this.anim_id = 0;
+    // Vertical arc for jumps and falls.  See libphysics::arc_offset.
+    this.arc = 0;
 }
 exports.Motion = Motion;
 
@@ -17,7 +19,12 @@ Motion.prototype.position = function(now) {
     var dur = this.end_time - this.start_time;
     var delta = Math.max(0, Math.min(dur, now - this.start_time));
     var offset = this.end_pos.sub(this.start_pos);
-    return this.start_pos.add(offset.mulScalar(delta).divScalar(dur));
+    var pos = this.start_pos.add(offset.mulScalar(delta).divScalar(dur));
+    if (this.arc != 0) {
+        // NB: keep this in sync with physics/lib.rs  arc_offset
+        pos.z += Math.floor(this.arc * delta * (dur - delta) / (dur * dur));
+    }
+    return pos;
 }
 
 Motion.fromForecast = function(forecast, offset) {
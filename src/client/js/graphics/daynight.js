@@ -14,6 +14,8 @@ function DayNight(assets) {
     this.night_end = info['night_end'] - day_start;
 
     this.active = true;
+    // Color to use when the cycle is not active.
+    this.ambient_color = [0, 0, 0];
     this.base_time = 0;
     this.cycle_ms = 24000;
 }
@@ -35,7 +37,7 @@ DayNight.prototype._phaseTime = function(time) {
 
 DayNight.prototype.getAmbientColor = function(now) {
     if (!this.active) {
-        return [0, 0, 0];
+        return this.ambient_color;
     }
 
     var pt = this._phaseTime((now - this.base_time) * CYCLE_LENGTH / this.cycle_ms);
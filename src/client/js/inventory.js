@@ -1,3 +1,4 @@
+var ItemDef = require('data/items').ItemDef;
 var chain = require('util/misc').chain;
 
 
@@ -7,8 +8,8 @@ function InventoryTracker(conn) {
     this.conn = conn;
 
     var this_ = this;
-    this.conn.onInventoryUpdate = function(inventory_id, updates) {
-        this_._handleUpdate(inventory_id, updates);
+    this.conn.onInventoryUpdate = function(inventory_id, slots, updates) {
+        this_._handleUpdate(inventory_id, slots, updates);
     };
 }
 exports.InventoryTracker = InventoryTracker;
@@ -24,9 +25,10 @@ InventoryTracker.prototype.reset = function() {
     this.inventories = {};
 };
 
-InventoryTracker.prototype._handleUpdate = function(inventory_id, updates) {
+InventoryTracker.prototype._handleUpdate = function(inventory_id, slots, updates) {
     var inv = this.inventories[inventory_id];
     if (inv != null) {
+        inv._slots = slots;
         inv._update(updates);
     } else {
         console.assert(false, 'received unexpected update for inventory', inventory_id);
@@ -64,6 +66,8 @@ function InventoryData(owner, id) {
     this._owner = owner;
     this._id = id;
     this._contents = {};
+    // Maximum number of stacks, or 0 for no limit.
+    this._slots = 0;
     this._handlers = [];
     this._ref_count = 1;
 }
@@ -132,6 +136,23 @@ Inventory.prototype.count = function(item_id) {
     return this._data()._contents[item_id] || 0;
 };
 
+Inventory.prototype.slotCount = function() {
+    return this._data()._slots;
+};
+
+// Number of slots taken up by the contents.  The client doesn't know which
+// items are unique instances, so those are counted as if they stacked.
+Inventory.prototype.usedSlots = function() {
+    var contents = this._data()._contents;
+    var ids = Object.getOwnPropertyNames(contents);
+    var used = 0;
+    for (var i = 0; i < ids.length; ++i) {
+        var stack_size = ItemDef.by_id[+ids[i]].stack_size;
+        used += Math.ceil(contents[ids[i]] / stack_size);
+    }
+    return used;
+};
+
 Inventory.prototype.itemIds = function() {
     var ids = Object.getOwnPropertyNames(this._data()._contents);
     // Convert all to numbers.
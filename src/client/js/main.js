@@ -30,6 +30,7 @@ var Banner = require('ui/banner').Banner;
 var ChatWindow = require('ui/chat').ChatWindow;
 var InventoryUI = require('ui/inventory').InventoryUI;
 var ContainerUI = require('ui/inventory').ContainerUI;
+var TradeUI = require('ui/inventory').TradeUI;
 var CraftingUI = require('ui/crafting').CraftingUI;
 var Iframe = require('ui/iframe').Iframe;
 var KeyDisplay = require('ui/keydisplay').KeyDisplay;
@@ -41,6 +42,7 @@ var KeybindingEditor = require('ui/keybinding').KeybindingEditor;
 var widget = require('ui/widget');
 var ErrorList = require('ui/errorlist').ErrorList;
 var InventoryUpdateList = require('ui/invupdate').InventoryUpdateList;
+var PartyPanel = require('ui/party').PartyPanel;
 var Hotbar = require('ui/hotbar').Hotbar;
 var DIALOG_TYPES = require('ui/dialogs').DIALOG_TYPES;
 
@@ -116,6 +118,7 @@ var keyboard;
 var chat;
 var error_list;
 var inv_update_list;
+var party_panel;
 var music_test;
 var hotbar;
 
@@ -128,6 +131,9 @@ var assets;
 
 var entities;
 var player_entity;
+// Entity the camera follows, or -1 if it follows `camera_motion` instead.
+var camera_entity;
+var camera_motion;
 var structures;
 
 var chunks;
@@ -148,6 +154,9 @@ var inv_tracker;
 
 var item_inv;
 var ability_inv;
+// The open crafting dialog, if any.
+var crafting_ui = null;
+var trade_ui = null;
 
 
 // Top-level initialization function
@@ -177,6 +186,7 @@ function init() {
     dialog = new Dialog(keyboard);
     chat = new ChatWindow();
     inv_update_list = new InventoryUpdateList();
+    party_panel = new PartyPanel();
     music_test = new MusicTest();
     hotbar = new Hotbar();
 
@@ -191,6 +201,8 @@ function init() {
 
     entities = {};
     player_entity = -1;
+    camera_entity = -1;
+    camera_motion = null;
     structures = {};
 
     chunks = buildArray(LOCAL_SIZE * LOCAL_SIZE, function() { return new Chunk(); });
@@ -230,7 +242,9 @@ function init() {
                 inv_tracker = new InventoryTracker(conn);
 
                 maybeRegister(info, function() {
-                    conn.sendLogin(Config.login_name.get(), Config.login_secret.get());
+                    // The server uses the screen size to decide how much of the world to send.
+                    conn.sendLogin(Config.login_name.get(), Config.login_secret.get(),
+                            canvas.virtualWidth, canvas.virtualHeight);
 
                     // Show "Loading World..." banner.
                     handleSyncStatus(net.SYNC_LOADING);
@@ -328,12 +342,19 @@ function openConn(info, next) {
     conn.onStructureGone = handleStructureGone;
     conn.onMainInventory = handleMainInventory;
     conn.onAbilityInventory = handleAbilityInventory;
-    conn.onPlaneFlags = handlePlaneFlags;
+    conn.onPlaneProps = handlePlaneProps;
     conn.onGetInteractArgs = handleGetInteractArgs;
     conn.onGetUseItemArgs = handleGetUseItemArgs;
     conn.onGetUseAbilityArgs = handleGetUseAbilityArgs;
     conn.onSyncStatus = handleSyncStatus;
     conn.onStructureReplace = handleStructureReplace;
+    conn.onStructureCrafting = handleStructureCrafting;
+    conn.onTradeStatus = handleTradeStatus;
+    conn.onTradeClosed = handleTradeClosed;
+    conn.onDayNightCycle = handleDayNightCycle;
+    conn.onCameraUpdate = handleCameraUpdate;
+    conn.onPartyUpdate = handlePartyUpdate;
+    conn.onPartyMarkers = handlePartyMarkers;
 }
 
 function maybeRegister(info, next) {
@@ -395,6 +416,7 @@ function buildUI() {
     document.body.appendChild(hotbar.dom);
     document.body.appendChild(chat.container);
     document.body.appendChild(inv_update_list.container);
+    document.body.appendChild(party_panel.container);
     document.body.appendChild(banner.container);
     document.body.appendChild(dialog.container);
     document.body.appendChild(debug.container);
@@ -533,6 +555,7 @@ var INPUT_RIGHT =   0x0002;
 var INPUT_UP =      0x0004;
 var INPUT_DOWN =    0x0008;
 var INPUT_RUN =     0x0010;
+var INPUT_JUMP =    0x0020;
 
 var ACTION_USE =        1;
 var ACTION_INVENTORY =  2;
@@ -640,6 +663,9 @@ function setupKeyHandler() {
                 case 'use_ability':
                     conn.sendUseAbility(time, hotbar.getAbility());
                     break;
+                case 'jump':
+                    updateWalkDir(INPUT_JUMP);
+                    break;
 
                 default:
                     if (binding != null && binding.startsWith('hotbar_')) {
@@ -657,8 +683,8 @@ function setupKeyHandler() {
         }
     });
 
-    function updateWalkDir() {
-        var bits = 0;
+    function updateWalkDir(extra_bits) {
+        var bits = extra_bits || 0;
         var target_velocity = new Vec(0, 0, 0);
 
         if (dirs_held['move_left']) {
@@ -689,7 +715,9 @@ function setupKeyHandler() {
         var arrival = timing.nextArrival() + Config.input_delay.get();
         conn.sendInput(timing.encodeSend(arrival), bits);
 
-        if (player_entity != null && entities[player_entity] != null) {
+        // With a free camera, input moves the camera rather than the pawn.
+        if (camera_motion == null &&
+                player_entity != null && entities[player_entity] != null) {
             var pony = entities[player_entity];
             prediction.predict(arrival, pony, target_velocity);
         }
@@ -727,6 +755,12 @@ function handleClose(evt, reason) {
 
 function handleInit(entity_id, now, cycle_base, cycle_ms) {
     player_entity = entity_id;
+    camera_entity = entity_id;
+    camera_motion = null;
+    handleDayNightCycle(now, cycle_base, cycle_ms);
+}
+
+function handleDayNightCycle(now, cycle_base, cycle_ms) {
     var pst_now = timing.decodeRecv(now);
     day_night.base_time = pst_now - cycle_base;
     day_night.cycle_ms = cycle_ms;
@@ -754,8 +788,20 @@ function handleEntityUpdate(id, motion, anim) {
         return;
     }
 
+    var m = decodeMotion(motion);
+    m.anim_id = anim;
+
+    if (id != player_entity) {
+        entities[id].queueMotion(m);
+    } else {
+        prediction.receivedMotion(m, entities[id]);
+    }
+}
+
+function decodeMotion(motion) {
     var m = new Motion(motion.start_pos);
     m.end_pos = motion.end_pos;
+    m.arc = motion.arc;
 
     var now = timing.visibleNow();
     m.start_time = timing.decodeRecv(motion.start_time);
@@ -766,16 +812,27 @@ function handleEntityUpdate(id, motion, anim) {
     if (m.end_time < m.start_time) {
         m.end_time += 0x10000;
     }
+    return m;
+}
 
-    m.anim_id = anim;
-
-    if (id != player_entity) {
-        entities[id].queueMotion(m);
+function handleCameraUpdate(id, motion) {
+    if (id != 0xffffffff) {
+        camera_entity = id;
+        camera_motion = null;
     } else {
-        prediction.receivedMotion(m, entities[id]);
+        camera_entity = -1;
+        camera_motion = decodeMotion(motion);
     }
 }
 
+function handlePartyUpdate(members) {
+    party_panel.setMembers(members);
+}
+
+function handlePartyMarkers(markers) {
+    party_panel.setMarkers(markers);
+}
+
 function handleUnloadChunk(idx) {
     chunkLoaded[idx] = false;
 }
@@ -799,6 +856,44 @@ function handleOpenDialog(idx, args) {
             inv1.unsubscribe();
             inv2.unsubscribe();
         };
+    } else if (idx == 2) {
+        var main_inv = inv_tracker.subscribe(args[0]);
+        var own_inv = inv_tracker.subscribe(args[1]);
+        var other_inv = inv_tracker.subscribe(args[2]);
+
+        var ui = new TradeUI(main_inv, own_inv, other_inv);
+        dialog.show(ui);
+        trade_ui = ui;
+        ui.ontransfer = function(from_inventory, to_inventory, item_id, amount) {
+            conn.sendMoveItem(from_inventory, to_inventory, item_id, amount);
+        };
+        ui.onaction = function(action) {
+            conn.sendTradeAction(action);
+        };
+
+        ui.oncancel = function() {
+            // The server replies with TradeClosed, which closes the dialog.
+            conn.sendTradeAction(0);
+        };
+        ui.close = function() {
+            dialog.hide();
+            main_inv.unsubscribe();
+            own_inv.unsubscribe();
+            other_inv.unsubscribe();
+            trade_ui = null;
+        };
+    }
+}
+
+function handleTradeStatus(own_state, other_state) {
+    if (trade_ui != null) {
+        trade_ui.setStatus(own_state, other_state);
+    }
+}
+
+function handleTradeClosed() {
+    if (trade_ui != null) {
+        trade_ui.close();
     }
 }
 
@@ -808,6 +903,11 @@ function handleOpenCrafting(station_type, station_id, inventory_id) {
     var ui = new CraftingUI(station_type, station_id, inv);
     dialog.show(ui);
 
+    if (structures[station_id] != null) {
+        ui.setCraftingJobs(structures[station_id].crafting_jobs);
+    }
+    crafting_ui = ui;
+
     ui.onaction = function(station_id, inventory_id, recipe_id, count) {
         conn.sendCraftRecipe(station_id, inventory_id, recipe_id, count);
     };
@@ -815,9 +915,25 @@ function handleOpenCrafting(station_type, station_id, inventory_id) {
     ui.oncancel = function() {
         dialog.hide();
         inv.unsubscribe();
+        ui.close();
+        crafting_ui = null;
     };
 }
 
+function handleStructureCrafting(id, jobs) {
+    var now = Date.now();
+    for (var i = 0; i < jobs.length; ++i) {
+        jobs[i].received = now;
+    }
+
+    if (structures[id] != null) {
+        structures[id].crafting_jobs = jobs;
+    }
+    if (crafting_ui != null && crafting_ui.station_id == id) {
+        crafting_ui.setCraftingJobs(jobs);
+    }
+}
+
 function handleChatUpdate(msg) {
     chat.addMessage(msg);
 }
@@ -876,8 +992,10 @@ function handleStructureReplace(id, template_id) {
     if (structures[id] != null) {
         var now = timing.visibleNow();
         var pos = structures[id].pos.mulScalar(TILE_SIZE);
+        var jobs = structures[id].crafting_jobs;
         handleStructureGone(id, now);
         handleStructureAppear(id, template_id, pos.x, pos.y, pos.z);
+        structures[id].crafting_jobs = jobs;
     }
 }
 
@@ -900,8 +1018,17 @@ function handleAbilityInventory(iid) {
     hotbar.attachAbilities(ability_inv.clone());
 }
 
-function handlePlaneFlags(flags) {
-    day_night.active = (flags == 0);
+var LIGHTING_DARK = 1;
+var PLANE_FLAG_DAY_NIGHT = 0x01;
+
+function handlePlaneProps(lighting, flags, ambient_color) {
+    if (lighting == LIGHTING_DARK) {
+        day_night.active = false;
+        day_night.ambient_color = [0, 0, 0];
+    } else {
+        day_night.active = (flags & PLANE_FLAG_DAY_NIGHT) != 0;
+        day_night.ambient_color = ambient_color;
+    }
 }
 
 function handleGetInteractArgs(dialog_id, parts) {
@@ -965,6 +1092,9 @@ function resetAll() {
         handleEntityGone(id, now);
     });
     player_entity = -1;
+    camera_entity = -1;
+    camera_motion = null;
+    party_panel.setMembers([]);
 
     Object.getOwnPropertyNames(structures).forEach(function(id) {
         handleStructureGone(id, now);
@@ -1012,6 +1142,26 @@ function localSprite(now, entity, camera_mid) {
     return sprite;
 }
 
+// Like `localSprite`, but for a free camera's motion.
+function localCamera(now, motion) {
+    var local_px = CHUNK_SIZE * TILE_SIZE * LOCAL_SIZE;
+    var min = (local_px / 2)|0;
+    var max = min + local_px;
+
+    var pos = motion.position(now);
+    if (pos.x < min) {
+        motion.translate(new Vec(local_px, 0, 0));
+    } else if (pos.x >= max) {
+        motion.translate(new Vec(-local_px, 0, 0));
+    }
+
+    if (pos.y < min) {
+        motion.translate(new Vec(0, local_px, 0));
+    } else if (pos.y >= max) {
+        motion.translate(new Vec(0, -local_px, 0));
+    }
+}
+
 function checkLocalSprite(sprite, camera_mid) {
     var local_px = CHUNK_SIZE * TILE_SIZE * LOCAL_SIZE;
     if (camera_mid == null) {
@@ -1102,10 +1252,19 @@ function frame(ac, client_now) {
 
         // Make sure the camera remains within the middle of the local space.
         localSprite(predict_now, pony, null);
+        debug.updateMotions(pony, timing);
+    }
+
+    if (camera_entity == player_entity && pony != null) {
         // TODO: another hacky offset
         pos = pony.position(predict_now).add(new Vec(16, 16, 0));
-
-        debug.updateMotions(pony, timing);
+    } else if (camera_entity >= 0 && entities[camera_entity] != null) {
+        var target = entities[camera_entity];
+        localSprite(now, target, null);
+        pos = target.position(now).add(new Vec(16, 16, 0));
+    } else if (camera_motion != null) {
+        localCamera(now, camera_motion);
+        pos = camera_motion.position(now);
     }
     debug.updatePos(pos);
 
@@ -19,6 +19,8 @@ var OP_OPEN_INVENTORY =         0x000f;
 var OP_INTERACT_WITH_ARGS =     0x0010;
 var OP_USE_ITEM_WITH_ARGS =     0x0011;
 var OP_USE_ABILITY_WITH_ARGS =  0x0012;
+var OP_TRADE_ACTION =           0x0013;
+var OP_LOGIN_WITH_SCREEN =      0x0014;
 
 var OP_TERRAIN_CHUNK =          0x8001;
 var OP_PLAYER_MOTION =          0x8002;
@@ -38,12 +40,19 @@ var OP_STRUCTURE_APPEAR =       0x800f;
 var OP_STRUCTURE_GONE =         0x8010;
 var OP_MAIN_INVENTORY =         0x8011;
 var OP_ABILITY_INVENTORY =      0x8012;
-var OP_PLANE_FLAGS =            0x8013;
+var OP_PLANE_PROPS =            0x8013;
 var OP_GET_INTERACT_ARGS =      0x8014;
 var OP_GET_USE_ITEM_ARGS =      0x8015;
 var OP_GET_USE_ABILITY_ARGS =   0x8016;
 var OP_SYNC_STATUS =            0x8017;
 var OP_STRUCTURE_REPLACE =      0x8018;
+var OP_STRUCTURE_CRAFTING =     0x8019;
+var OP_TRADE_STATUS =           0x801a;
+var OP_TRADE_CLOSED =           0x801b;
+var OP_DAY_NIGHT_CYCLE =        0x801c;
+var OP_CAMERA_UPDATE =          0x801d;
+var OP_PARTY_UPDATE =           0x801e;
+var OP_PARTY_MARKERS =          0x801f;
 
 exports.SYNC_LOADING = 0;
 exports.SYNC_OK = 1;
@@ -81,12 +90,19 @@ function Connection(url) {
     this.onStructureGone = null;
     this.onMainInventory = null;
     this.onAbilityInventory = null;
-    this.onPlaneFlags = null;
+    this.onPlaneProps = null;
     this.onGetInteractArgs = null;
     this.onGetUseItemArgs = null;
     this.onGetUseAbilityArgs = null;
     this.onSyncStatus = null;
     this.onStructureReplace = null;
+    this.onStructureCrafting = null;
+    this.onTradeStatus = null;
+    this.onTradeClosed = null;
+    this.onDayNightCycle = null;
+    this.onCameraUpdate = null;
+    this.onPartyUpdate = null;
+    this.onPartyMarkers = null;
 }
 exports.Connection = Connection;
 
@@ -124,6 +140,12 @@ Connection.prototype._handleMessage = function(evt) {
         return result;
     }
 
+    function getI32() {
+        var result = view.getInt32(offset, true);
+        offset += 4;
+        return result;
+    }
+
     function getString() {
         var len = get16();
         var result = decodeUtf8(new Uint8Array(view.buffer, offset, len));
@@ -184,11 +206,13 @@ Connection.prototype._handleMessage = function(evt) {
                 var end_y =         get16();
                 var end_z =         get16();
                 var end_time =      get16();
+                var arc =           get16();
                 var motion = {
                     start_pos:  new Vec(start_x, start_y, start_z),
                     start_time: start_time,
                     end_pos:    new Vec(end_x, end_y, end_z),
                     end_time:   end_time,
+                    arc:        arc,
                 };
                 this.onPlayerMotion(id, motion);
             }
@@ -213,12 +237,14 @@ Connection.prototype._handleMessage = function(evt) {
                 var end_y =         get16();
                 var end_z =         get16();
                 var end_time =      get16();
+                var arc =           get16();
                 var anim =          get16();
                 var motion = {
                     start_pos:  new Vec(start_x, start_y, start_z),
                     start_time: start_time,
                     end_pos:    new Vec(end_x, end_y, end_z),
                     end_time:   end_time,
+                    arc:        arc,
                 };
                 this.onEntityUpdate(id, motion, anim);
             }
@@ -261,19 +287,20 @@ Connection.prototype._handleMessage = function(evt) {
         case OP_INVENTORY_UPDATE:
             if (this.onInventoryUpdate != null) {
                 var inventory_id = get32();
+                var slots = get16();
                 var len = get16();
                 var updates = [];
                 for (var i = 0; i < len; ++i) {
                     var item_id = get16();
-                    var old_count = get8();
-                    var new_count = get8();
+                    var old_count = get16();
+                    var new_count = get16();
                     updates.push({
                         id: item_id,
                         old_count: old_count,
                         new_count: new_count,
                     });
                 }
-                this.onInventoryUpdate(inventory_id, updates);
+                this.onInventoryUpdate(inventory_id, slots, updates);
             };
             break;
 
@@ -350,10 +377,14 @@ Connection.prototype._handleMessage = function(evt) {
             }
             break;
 
-        case OP_PLANE_FLAGS:
-            if (this.onPlaneFlags != null) {
-                var flags = get32();
-                this.onPlaneFlags(flags);
+        case OP_PLANE_PROPS:
+            if (this.onPlaneProps != null) {
+                var lighting = get8();
+                var flags = get8();
+                var r = get8();
+                var g = get8();
+                var b = get8();
+                this.onPlaneProps(lighting, flags, [r, g, b]);
             }
             break;
 
@@ -398,6 +429,111 @@ Connection.prototype._handleMessage = function(evt) {
             }
             break;
 
+        case OP_STRUCTURE_CRAFTING:
+            if (this.onStructureCrafting != null) {
+                var structure_id = get32();
+                var len = get16();
+                var jobs = [];
+                for (var i = 0; i < len; ++i) {
+                    var recipe_id = get16();
+                    var count = get16();
+                    var remaining = get32();
+                    var total = get32();
+                    jobs.push({
+                        recipe_id: recipe_id,
+                        count: count,
+                        remaining: remaining,
+                        total: total,
+                    });
+                }
+                this.onStructureCrafting(structure_id, jobs);
+            }
+            break;
+
+        case OP_TRADE_STATUS:
+            if (this.onTradeStatus != null) {
+                var own_state = get8();
+                var other_state = get8();
+                this.onTradeStatus(own_state, other_state);
+            }
+            break;
+
+        case OP_TRADE_CLOSED:
+            if (this.onTradeClosed != null) {
+                this.onTradeClosed();
+            }
+            break;
+
+        case OP_DAY_NIGHT_CYCLE:
+            if (this.onDayNightCycle != null) {
+                var now = get16();
+                var cycle_base = get32();
+                var cycle_ms = get32();
+                this.onDayNightCycle(now, cycle_base, cycle_ms);
+            }
+            break;
+
+        case OP_CAMERA_UPDATE:
+            if (this.onCameraUpdate != null) {
+                var id =            get32();
+                var start_x =       get16();
+                var start_y =       get16();
+                var start_z =       get16();
+                var start_time =    get16();
+                var end_x =         get16();
+                var end_y =         get16();
+                var end_z =         get16();
+                var end_time =      get16();
+                var arc =           get16();
+                var motion = {
+                    start_pos:  new Vec(start_x, start_y, start_z),
+                    start_time: start_time,
+                    end_pos:    new Vec(end_x, end_y, end_z),
+                    end_time:   end_time,
+                    arc:        arc,
+                };
+                this.onCameraUpdate(id, motion);
+            }
+            break;
+
+        case OP_PARTY_UPDATE:
+            if (this.onPartyUpdate != null) {
+                var len = get16();
+                var members = [];
+                for (var i = 0; i < len; ++i) {
+                    var id = get16();
+                    var leader = get8();
+                    var name = getString();
+                    members.push({
+                        id: id,
+                        leader: leader != 0,
+                        name: name,
+                    });
+                }
+                this.onPartyUpdate(members);
+            }
+            break;
+
+        case OP_PARTY_MARKERS:
+            if (this.onPartyMarkers != null) {
+                var len = get16();
+                var markers = [];
+                for (var i = 0; i < len; ++i) {
+                    var id = get16();
+                    var same_plane = get8();
+                    var dx = getI32();
+                    var dy = getI32();
+                    markers.push({
+                        id: id,
+                        same_plane: same_plane != 0,
+                        dx: dx,
+                        dy: dy,
+                    });
+                }
+                this.onPartyMarkers(markers);
+            }
+            break;
+
         default:
             console.assert(false, 'received invalid opcode:', opcode.toString(16));
             break;
@@ -514,13 +650,15 @@ Connection.prototype.sendInput = function(time, input) {
     this.socket.send(msg.done());
 };
 
-Connection.prototype.sendLogin = function(name, secret) {
+Connection.prototype.sendLogin = function(name, secret, width, height) {
     var msg = MESSAGE_BUILDER.reset();
 
-    msg.put16(OP_LOGIN);
+    msg.put16(OP_LOGIN_WITH_SCREEN);
     for (var i = 0; i < 4; ++i) {
         msg.put32(secret[i]);
     }
+    msg.put16(width);
+    msg.put16(height);
     msg.putString(name);
 
     this.socket.send(msg.done());
@@ -638,3 +776,9 @@ Connection.prototype.sendUseAbilityWithArgs = function(time, item_id, args) {
     this.socket.send(msg.done());
 };
 
+Connection.prototype.sendTradeAction = function(action) {
+    var msg = MESSAGE_BUILDER.reset();
+    msg.put16(OP_TRADE_ACTION);
+    msg.put8(action);
+    this.socket.send(msg.done());
+};
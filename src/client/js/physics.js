@@ -140,6 +140,7 @@ Prediction.prototype.receivedMotion = function(m, entity) {
 function motions_equal(m1, m2) {
     // TODO: include end once client physics is un-broken
     return m1.start_time == m2.start_time &&
+           m1.arc == m2.arc &&
            vecs_equal(m1.start_pos, m2.start_pos);
 }
 
@@ -7,5 +7,7 @@ function Structure(pos, template, render_index) {
     this.template = template;
 
     this.render_index = render_index;
+    // Timed crafting jobs queued at this structure.
+    this.crafting_jobs = [];
 }
 exports.Structure = Structure;
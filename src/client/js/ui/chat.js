@@ -67,12 +67,6 @@ ChatWindow.prototype.addMessage = function(msg) {
     }
 };
 
-ChatWindow.prototype.addIgnore = function(name) {
-    var ignores = Config.ignores.get();
-    ignores['<' + name + '>'] = true;
-    Config.ignores.save();
-};
-
 ChatWindow.prototype.removeIgnore = function(name) {
     var ignores = Config.ignores.get();
     delete ignores['<' + name + '>'];
@@ -118,23 +112,20 @@ ChatWindow.prototype.finishTyping = function(keyboard, conn, send) {
     keyboard.popHandler();
 
     var msg = this._entry.value;
-    var handled = false;
     if (msg[0] == '/') {
         var idx = msg.indexOf(' ');
         if (idx != -1) {
             var cmd = msg.substring(1, idx);
             var arg = msg.substring(idx + 1);
-            if (cmd == 'ignore') {
-                this.addIgnore(arg);
-                handled = true;
-            } else if (cmd == 'unignore') {
+            // Ignore lists are kept on the server now.  Clear out any old
+            // local entry, so the server's list is the only one that applies.
+            if (cmd == 'unignore') {
                 this.removeIgnore(arg);
-                handled = true;
             }
         }
     }
 
-    if (send && !handled && msg != '') {
+    if (send && msg != '') {
         conn.sendChat(msg);
     }
 
@@ -19,6 +19,9 @@ function CraftingUI(station_type, station_id, inv) {
     this.output_div = util.element('div', ['recipe-item-list']);
     this.arrow_div = util.element('div', ['recipe-item-arrow']);
     this.arrow_div.innerHTML = '&dArr;';
+    this.progress_div = util.element('div', ['crafting-progress']);
+    this.jobs = [];
+    this.progress_timer = null;
 
     var parts = util.templateParts('crafting', {
         'item_list': this.item_list.dom,
@@ -26,6 +29,7 @@ function CraftingUI(station_type, station_id, inv) {
         'input_list': this.input_div,
         'output_list': this.output_div,
         'arrow': this.arrow_div,
+        'progress': this.progress_div,
     });
 
     var this_ = this;
@@ -63,6 +67,61 @@ CraftingUI.prototype._craft = function(mag) {
     }
 };
 
+// Show the timed crafting jobs queued at this station.  Each job has the fields
+// sent in a StructureCrafting message, plus `received`, the local time when
+// the message arrived.
+CraftingUI.prototype.setCraftingJobs = function(jobs) {
+    this.jobs = jobs;
+
+    if (jobs.length > 0 && this.progress_timer == null) {
+        var this_ = this;
+        this.progress_timer = window.setInterval(function() {
+            this_._updateProgress();
+        }, 250);
+    } else if (jobs.length == 0 && this.progress_timer != null) {
+        window.clearInterval(this.progress_timer);
+        this.progress_timer = null;
+    }
+
+    this._updateProgress();
+};
+
+CraftingUI.prototype._updateProgress = function() {
+    var now = Date.now();
+    var job = null;
+    var queued = 0;
+    for (var i = 0; i < this.jobs.length; ++i) {
+        var j = this.jobs[i];
+        var end = j.received + j.remaining;
+        if (end <= now) {
+            continue;
+        }
+        if (job == null) {
+            job = j;
+        } else {
+            ++queued;
+        }
+    }
+
+    if (job == null) {
+        this.progress_div.textContent = '';
+        return;
+    }
+
+    var recipe = RecipeDef.by_id[job.recipe_id];
+    var left = job.received + job.remaining - now;
+    var percent = Math.floor(100 * (job.total - left) / job.total);
+    var text = 'Crafting ' + recipe.ui_name + ' x' + job.count + ': ' + percent + '%';
+    if (queued > 0) {
+        text += ' (' + queued + ' more queued)';
+    }
+    this.progress_div.textContent = text;
+};
+
+CraftingUI.prototype.close = function() {
+    this.setCraftingJobs([]);
+};
+
 CraftingUI.prototype._updateRecipeDisplay = function() {
     while (this.input_div.firstChild) {
         this.input_div.removeChild(this.input_div.firstChild);
@@ -111,7 +170,7 @@ function RecipeList(station_type, inv) {
     var recipe_items = [];
     for (var i = 0; i < RecipeDef.by_id.length; ++i) {
         var recipe = RecipeDef.by_id[i];
-        if (recipe != null && recipe.station == station_type) {
+        if (recipe != null && recipe.stations.indexOf(station_type) != -1) {
             var row = new RecipeRow(i, recipe.ui_name);
             recipe_items.push(row);
             list_div.appendChild(row.dom);
@@ -11,7 +11,7 @@ function InventoryUI(inv, title) {
     this.list = new ItemList(inv);
     this.list.dom.classList.add('active');
 
-    var dom = fromTemplate('inventory', { 'item_list': this.list.dom });
+    var dom = fromTemplate('inventory', { 'item_list': listColumn(this.list, inv) });
     if (title != null) {
         dom.getElementsByClassName('title')[0].textContent = title;
     }
@@ -50,8 +50,8 @@ function ContainerUI(inv1, inv2) {
     this.lists = [new ItemList(inv1), new ItemList(inv2)];
 
     var dom = fromTemplate('container', {
-        'item_list1': this.lists[0].dom,
-        'item_list2': this.lists[1].dom,
+        'item_list1': listColumn(this.lists[0], inv1),
+        'item_list2': listColumn(this.lists[1], inv2),
     });
     var container = new widget.SimpleList(dom, this.lists, ['move_left', 'move_right']);
 
@@ -88,6 +88,71 @@ ContainerUI.prototype._transfer = function(evt, fromIndex) {
 };
 
 
+var TRADE_STATE_NAMES = ['Open', 'Locked', 'Confirmed'];
+var TRADE_OPEN = 0;
+
+var TRADE_ACTION_LOCK = 1;
+var TRADE_ACTION_UNLOCK = 2;
+var TRADE_ACTION_CONFIRM = 3;
+
+/** @constructor */
+function TradeUI(main_inv, own_inv, other_inv) {
+    this.lists = [new ItemList(main_inv), new ItemList(own_inv)];
+    this.other_list = new ItemList(other_inv);
+    this.status = util.element('div', ['trade-status']);
+
+    var dom = fromTemplate('trade', {
+        'item_list1': listColumn(this.lists[0], main_inv),
+        'item_list2': listColumn(this.lists[1], own_inv),
+        'item_list3': listColumn(this.other_list, other_inv),
+        'status': this.status,
+    });
+    var container = new widget.SimpleList(dom, this.lists, ['move_left', 'move_right']);
+
+    widget.Form.call(this, container);
+
+    var this_ = this;
+    widget.hookKey(this.lists[0], 'select', function(evt) { this_._transfer(evt, 0) });
+    widget.hookKey(this.lists[1], 'select', function(evt) { this_._transfer(evt, 1) });
+    this.ontransfer = null;
+    this.onaction = null;
+
+    this.setStatus(TRADE_OPEN, TRADE_OPEN);
+}
+TradeUI.prototype = Object.create(widget.Form.prototype);
+TradeUI.prototype.constructor = TradeUI;
+exports.TradeUI = TradeUI;
+
+TradeUI.prototype._transfer = ContainerUI.prototype._transfer;
+
+TradeUI.prototype.onkey = function(evt) {
+    if (widget.Form.prototype.onkey.call(this, evt)) {
+        return true;
+    }
+
+    var binding = evt.uiKeyName();
+    if (binding == 'trade_lock') {
+        if (evt.down && this.onaction != null) {
+            var locked = this.own_state != TRADE_OPEN;
+            this.onaction(locked ? TRADE_ACTION_UNLOCK : TRADE_ACTION_LOCK);
+        }
+        return true;
+    } else if (binding == 'trade_confirm') {
+        if (evt.down && this.onaction != null) {
+            this.onaction(TRADE_ACTION_CONFIRM);
+        }
+        return true;
+    }
+};
+
+TradeUI.prototype.setStatus = function(own_state, other_state) {
+    this.own_state = own_state;
+    this.other_state = other_state;
+    this.status.textContent = 'You: ' + TRADE_STATE_NAMES[own_state] +
+        ' \u2013 Them: ' + TRADE_STATE_NAMES[other_state];
+};
+
+
 /** @constructor */
 function ItemList(inv) {
     widget.DynamicList.call(this, util.element('div', ['item-list']));
@@ -147,6 +212,40 @@ ItemList.prototype.updateItems = function(updates) {
 };
 
 
+/** @constructor */
+function SlotCounter(inv) {
+    this.dom = util.element('div', ['item-slots']);
+    this.inv = inv;
+    this.refresh();
+
+    var this_ = this;
+    inv.onUpdate(function(updates) {
+        this_.refresh();
+    });
+}
+exports.SlotCounter = SlotCounter;
+
+SlotCounter.prototype.refresh = function() {
+    var total = this.inv.slotCount();
+    if (total == 0) {
+        // No slot limit.
+        this.dom.classList.add('hidden');
+        return;
+    }
+    this.dom.classList.remove('hidden');
+    this.dom.textContent = 'Slots: ' + this.inv.usedSlots() + '/' + total;
+};
+
+// Stack an item list on top of a count of the used and total slots of its
+// inventory.
+function listColumn(list, inv) {
+    var col = util.element('div', ['container-vert']);
+    col.appendChild(list.dom);
+    col.appendChild(new SlotCounter(inv).dom);
+    return col;
+}
+
+
 /** @constructor */
 function ItemRow(id, qty, name, icon_x, icon_y) {
     var parts = util.templateParts('item-row');
@@ -11,6 +11,7 @@ var KEYBIND_NAMES = [
     ['Left', 'move_left'],
     ['Right', 'move_right'],
     ['Run', 'run'],
+    ['Jump', 'jump'],
 
     ['Actions', null],
     ['Interact', 'interact'],
@@ -0,0 +1,65 @@
+var util = require('util/misc');
+var TILE_SIZE = require('data/chunk').TILE_SIZE;
+
+
+// Arrows pointing east, southeast, south, ..., northeast, matching the order
+// of angles measured clockwise from the positive x axis (y points down).
+var ARROWS = ['\u2192', '\u2198', '\u2193', '\u2199',
+              '\u2190', '\u2196', '\u2191', '\u2197'];
+
+
+/** @constructor */
+function PartyPanel() {
+    this.container = util.element('div', ['party-panel', 'hidden']);
+    this.rows = {};
+}
+exports.PartyPanel = PartyPanel;
+
+// Replace the list of party members.  An empty list means the player is not
+// in a party.
+PartyPanel.prototype.setMembers = function(members) {
+    this.container.innerHTML = '';
+    this.rows = {};
+
+    if (members.length == 0) {
+        this.container.classList.add('hidden');
+        return;
+    }
+    this.container.classList.remove('hidden');
+
+    for (var i = 0; i < members.length; ++i) {
+        var m = members[i];
+        var row = util.element('div', ['party-member'], this.container);
+        var name = util.element('span', ['party-name', 'text=' + m.name], row);
+        if (m.leader) {
+            name.classList.add('party-leader');
+        }
+        var dir = util.element('span', ['party-direction'], row);
+        this.rows[m.id] = dir;
+    }
+};
+
+// Update the direction and distance shown for each teammate.
+PartyPanel.prototype.setMarkers = function(markers) {
+    for (var i = 0; i < markers.length; ++i) {
+        var m = markers[i];
+        var dir = this.rows[m.id];
+        if (dir == null) {
+            continue;
+        }
+
+        if (!m.same_plane) {
+            dir.textContent = 'elsewhere';
+            continue;
+        }
+
+        var dist = Math.round(Math.sqrt(m.dx * m.dx + m.dy * m.dy) / TILE_SIZE);
+        if (dist == 0) {
+            dir.textContent = 'here';
+            continue;
+        }
+        var angle = Math.atan2(m.dy, m.dx);
+        var idx = (Math.round(angle / (Math.PI / 4)) + 8) % 8;
+        dir.textContent = ARROWS[idx] + ' ' + dist;
+    }
+};
@@ -3,18 +3,22 @@ from PIL import Image
 from outpost_data.core.builder2.base import *
 from outpost_data.core.builder2.structure import StructureBuilder, StructurePrototype
 from outpost_data.core.consts import *
-from outpost_data.core.item import ItemDef
+from outpost_data.core.item import ItemDef, DEFAULT_STACK_SIZE
 
 
 class ItemPrototype(PrototypeBase):
     KIND = 'item'
-    FIELDS = ('display_name', 'icon')
+    FIELDS = ('display_name', 'icon', 'stack_size', 'weight', 'volume')
 
     def instantiate(self):
         self.name = self.require('name') or '_%x' % id(self)
         display_name = self.require('display_name', default=self.name)
         icon = raw_image(self.require('icon'))
-        return ItemDef(self.name, display_name, icon)
+        stack_size = self.stack_size if self.stack_size is not None else DEFAULT_STACK_SIZE
+        return ItemDef(self.name, display_name, icon,
+                stack_size=stack_size,
+                weight=self.weight or 0,
+                volume=self.volume or 0)
 
 def make_structure_icon(orig):
     w, h = orig.size
@@ -27,6 +31,9 @@ class ItemBuilder(BuilderBase):
     PROTO_CLASS = ItemPrototype
 
     display_name = dict_modifier('display_name')
+    stack_size = dict_modifier('stack_size')
+    weight = dict_modifier('weight')
+    volume = dict_modifier('volume')
 
     @dict_setter
     def icon(self, icon):
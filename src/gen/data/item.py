@@ -2,12 +2,21 @@ from outpost_data.core import util
 from outpost_data.core.consts import *
 
 
+DEFAULT_STACK_SIZE = 255
+
 class ItemDef(object):
-    def __init__(self, name, ui_name, image):
+    def __init__(self, name, ui_name, image,
+            stack_size=DEFAULT_STACK_SIZE, weight=0, volume=0):
         self.name = name
         self.ui_name = ui_name
         self.image = image
 
+        # Number of items that fit in one inventory slot.
+        self.stack_size = stack_size
+        # Per-item contribution toward inventory weight and volume limits.
+        self.weight = weight
+        self.volume = volume
+
         self.id = None
 
 
@@ -25,6 +34,7 @@ def build_client_json(items):
         return {
                 'name': i.name,
                 'ui_name': i.ui_name,
+                'stack_size': i.stack_size,
                 }
     return list(convert(i) for i in items)
 
@@ -32,5 +42,8 @@ def build_server_json(items):
     def convert(i):
         return {
                 'name': i.name,
+                'stack_size': i.stack_size,
+                'weight': i.weight,
+                'volume': i.volume,
                 }
     return list(convert(i) for i in items)
@@ -3,15 +3,21 @@ from .util import err
 
 
 class RecipeDef(object):
-    def __init__(self, name, ui_name, station, inputs, outputs):
+    def __init__(self, name, ui_name, station, inputs, outputs, time=0):
+        """`station` is either a single structure name or a list of names, any of
+        which can be used to craft this recipe.  `time` is the crafting duration
+        in milliseconds, or 0 for instant crafting."""
         self.name = name
         self.ui_name = ui_name
-        self.station_name = station
+        if isinstance(station, str):
+            station = (station,)
+        self.station_names = tuple(station)
         self.input_names = tuple(inputs.items())
         self.output_names = tuple(outputs.items())
+        self.time = time
 
         self.id = None
-        self.station_id = None
+        self.station_ids = None
         self.input_ids = None
         self.output_ids = None
 
@@ -28,18 +34,23 @@ def resolve_item_ids(recipes, item_id_map):
         r.output_ids = tuple((go(r.name, k), v) for k,v in r.output_names)
 
 def resolve_structure_ids(recipes, structure_id_map):
+    def go(recipe_name, name):
+        id = structure_id_map.get(name)
+        if id is None:
+            err('recipe %r: no such structure: %r' % (recipe_name, name))
+        return id
+
     for r in recipes:
-        r.station_id = structure_id_map.get(r.station_name)
-        if r.station_id is None:
-            err('recipe %r: no such structure: %r' % (r.name, r.station_name))
+        r.station_ids = tuple(go(r.name, s) for s in r.station_names)
 
 def build_client_json(recipes):
     def convert(r):
         return {
                 'ui_name': r.ui_name,
-                'station': r.station_id,
+                'stations': r.station_ids,
                 'inputs': r.input_ids,
                 'outputs': r.output_ids,
+                'time': r.time,
                 }
     return list(convert(r) for r in recipes)
 
@@ -47,8 +58,9 @@ def build_server_json(recipes):
     def convert(r):
         return {
                 'name': r.name,
-                'station': r.station_id,
+                'stations': r.station_ids,
                 'inputs': r.input_ids,
                 'outputs': r.output_ids,
+                'time': r.time,
                 }
     return list(convert(r) for r in recipes)
@@ -11,8 +11,9 @@
 #[cfg(not(asmjs))] #[macro_use] extern crate log;
 
 use core::prelude::*;
+use core::cmp;
 
-use v3::{Vn, V3, scalar};
+use v3::{Vn, V3, Axis, scalar};
 
 
 pub mod v3;
@@ -34,6 +35,12 @@ pub const CHUNK_BITS: usize = 4;
 pub const CHUNK_SIZE: i32 = 1 << CHUNK_BITS;    // 16
 pub const CHUNK_MASK: i32 = CHUNK_SIZE - 1;
 
+/// Downward acceleration of falling and jumping entities, in pixels per second squared.
+pub const GRAVITY: i32 = 2048;
+/// Height of a jump, in pixels.  This is less than `TILE_SIZE`, so jumping can't be used to
+/// climb onto a higher layer.
+pub const JUMP_HEIGHT: i32 = 24;
+
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(u8)]
@@ -117,6 +124,122 @@ pub fn collide<S: ShapeSource>(chunk: &S, pos: V3, size: V3, velocity: V3) -> (V
 }
 
 
+/// Compute the vertical offset from the straight-line path at time `t` of a motion lasting
+/// `dur`.  The offset is a parabola that is zero at both endpoints and has a peak of `arc / 4` at
+/// the midpoint.
+///
+/// NB: keep this in sync with client/js/entity.js  Motion.position
+pub fn arc_offset(arc: i32, t: i32, dur: i32) -> i32 {
+    if dur <= 0 {
+        return 0;
+    }
+    let t = t as i64;
+    let dur = dur as i64;
+    (arc as i64 * t * (dur - t) / (dur * dur)) as i32
+}
+
+/// Time (in milliseconds) it takes to fall `dist` pixels, starting from rest.
+pub fn fall_time(dist: i32) -> i32 {
+    isqrt(2000000 * dist as i64 / GRAVITY as i64) as i32
+}
+
+/// The `arc` value for a motion lasting `dur` milliseconds under `GRAVITY`.  A motion whose
+/// vertical component follows this arc is a proper ballistic trajectory.
+pub fn ballistic_arc(dur: i32) -> i32 {
+    (GRAVITY as i64 * dur as i64 * dur as i64 / 2000000) as i32
+}
+
+fn isqrt(x: i64) -> i64 {
+    if x <= 0 {
+        return 0;
+    }
+    let mut r = x;
+    let mut next = (r + 1) / 2;
+    while next < r {
+        r = next;
+        next = (r + x / r) / 2;
+    }
+    r
+}
+
+
+/// Check if the entity at `pos` is unsupported, and if so, compute its fall to the nearest
+/// surface below.  Returns the landing position, the duration, and the arc of the fall.
+pub fn fall<S: ShapeSource>(chunk: &S, pos: V3, size: V3) -> Option<(V3, i32, i32)> {
+    use walk::GroundStep;
+    let step = GroundStep::new(size);
+
+    if step.check_support(chunk, pos) {
+        return None;
+    }
+
+    let end_pos = step.find_landing(chunk, pos);
+    let drop = pos.z - end_pos.z;
+    if drop <= 0 {
+        return None;
+    }
+
+    let dur = fall_time(drop);
+    Some((end_pos, dur, cmp::min(ballistic_arc(dur), drop)))
+}
+
+/// Try to walk off a ledge.  This is meant to be called when `collide` reports that the entity
+/// can't make any progress in the direction of `velocity`.  If the entity is standing at the
+/// edge of a drop, returns the landing position, duration, and arc of a motion that carries it
+/// over the edge and down to the surface below.
+pub fn step_off<S: ShapeSource>(chunk: &S, pos: V3, size: V3, velocity: V3)
+                                -> Option<(V3, i32, i32)> {
+    use walk::GroundStep;
+    let step = GroundStep::new(size);
+
+    let speed = velocity.abs().max();
+    if speed == 0 {
+        return None;
+    }
+
+    let edge_pos = match step.check_ledge(chunk, pos, velocity.signum()) {
+        Some(x) => x,
+        None => return None,
+    };
+    let end_pos = step.find_landing(chunk, edge_pos);
+    let drop = pos.z - end_pos.z;
+    if drop <= 0 {
+        return None;
+    }
+
+    // The entity keeps its walking speed while crossing the edge, so a short drop may take
+    // longer than a free fall would.  In that case the arc is limited to `drop`, which means the
+    // entity starts moving downward as soon as it leaves the edge.
+    let walk_time = (edge_pos - pos).abs().max() * 1000 / speed;
+    let dur = cmp::max(fall_time(drop), walk_time);
+    Some((end_pos, dur, cmp::min(ballistic_arc(dur), drop)))
+}
+
+/// Compute a jump starting from `pos`.  The entity rises `JUMP_HEIGHT` pixels and keeps moving
+/// at `velocity` while in the air, landing either at its starting altitude or on some surface
+/// below.  If the path or the landing spot is obstructed, the entity jumps in place instead.
+/// Returns the landing position, duration, and arc.
+pub fn jump<S: ShapeSource>(chunk: &S, pos: V3, size: V3, velocity: V3) -> (V3, i32, i32) {
+    use walk::GroundStep;
+    let step = GroundStep::new(size);
+
+    let rise_time = fall_time(JUMP_HEIGHT);
+    let offset = velocity.with(Axis::Z, 0) * scalar(2 * rise_time) / scalar(1000);
+
+    let mut end_pos = pos;
+    if offset != scalar(0) && step.check_arc_clear(chunk, pos, pos + offset, JUMP_HEIGHT) {
+        let landing = step.find_landing(chunk, pos + offset);
+        if step.check_full_support(chunk, landing) {
+            end_pos = landing;
+        }
+    }
+
+    let drop = pos.z - end_pos.z;
+    let dur = rise_time + fall_time(JUMP_HEIGHT + drop);
+    (end_pos, dur, ballistic_arc(dur))
+}
+
+
 trait StepCallback {
     fn adjust_offset<S: ShapeSource>(&self, chunk: &S, pos: V3, dir: V3) -> V3;
 }
@@ -300,6 +300,121 @@ impl GroundStep {
 
         scalar(0)
     }
+
+    /// Count the tiles under the entity's footprint that can support it, either by providing
+    /// a floor at exactly `pos.z` or by having a ramp or solid surface whose top is at `pos.z`.
+    /// Returns the number of supporting tiles and the total number of tiles in the footprint.
+    fn count_support<S: ShapeSource>(&self, chunk: &S, pos: V3) -> (i32, i32) {
+        let bounds = Region::new(pos, pos + self.size);
+        let bounds_tiles = bounds.div_round(TILE_SIZE);
+        let z = bounds_tiles.min.z;
+        let on_floor = pos.z == z * TILE_SIZE;
+
+        let mut supported = 0;
+        let mut total = 0;
+
+        for tile_pos in bounds_tiles.reduce().points() {
+            total += 1;
+
+            let tile_base_px = tile_pos * scalar(TILE_SIZE);
+            let tile_bounds = Region::new(tile_base_px, tile_base_px + scalar(TILE_SIZE));
+            let overlap = bounds.reduce().intersect(tile_bounds);
+
+            let shape = chunk.get_shape(tile_pos.extend(z));
+            if shape == Shape::Floor && on_floor {
+                supported += 1;
+                continue;
+            }
+            if shape.is_ramp() &&
+               max_altitude(shape, overlap - tile_base_px) + z * TILE_SIZE == pos.z {
+                supported += 1;
+                continue;
+            }
+
+            if on_floor && shape == Shape::Empty && z > 0 {
+                let shape_below = chunk.get_shape(tile_pos.extend(z - 1));
+                if shape_below == Shape::Solid ||
+                   (shape_below.is_ramp() &&
+                    max_altitude(shape_below, overlap - tile_base_px) == TILE_SIZE) {
+                    supported += 1;
+                }
+            }
+        }
+
+        (supported, total)
+    }
+
+    /// Check if anything under the entity's footprint keeps it from falling.  The bottom of the
+    /// chunk (`pos.z == 0`) always counts as support.
+    pub fn check_support<S: ShapeSource>(&self, chunk: &S, pos: V3) -> bool {
+        if pos.z <= 0 {
+            return true;
+        }
+        let (supported, _) = self.count_support(chunk, pos);
+        supported > 0
+    }
+
+    /// Check if the entity's entire footprint is supported, so that it can stand at `pos`
+    /// without hanging over an edge.
+    pub fn check_full_support<S: ShapeSource>(&self, chunk: &S, pos: V3) -> bool {
+        if pos.z <= 0 {
+            return true;
+        }
+        let (supported, total) = self.count_support(chunk, pos);
+        supported == total
+    }
+
+    /// Find the position where an entity dropping straight down from `pos` comes to rest.
+    pub fn find_landing<S: ShapeSource>(&self, chunk: &S, pos: V3) -> V3 {
+        let down = V3::new(0, 0, -1);
+        let mut pos = pos;
+        while !self.check_support(chunk, pos) {
+            if self.check_blocked(chunk, pos + down, down).intersects(BLOCKED) {
+                break;
+            }
+            pos = pos + down;
+        }
+        pos
+    }
+
+    /// Check if the entity at `pos` can step off a ledge by moving its full width in direction
+    /// `dir`.  On success, returns the position just past the edge, which the entity will fall
+    /// from.
+    pub fn check_ledge<S: ShapeSource>(&self, chunk: &S, pos: V3, dir: V3) -> Option<V3> {
+        let dir = dir.with(Axis::Z, 0);
+        if dir == scalar(0) {
+            return None;
+        }
+
+        let edge_pos = pos + dir * self.size.with(Axis::Z, 0);
+        if self.check_blocked(chunk, edge_pos, dir).intersects(BLOCKED) {
+            return None;
+        }
+        if self.check_support(chunk, edge_pos) {
+            // There's something to stand on, so this isn't a ledge.  Whatever stopped the entity
+            // from walking here, it wasn't missing floor.
+            return None;
+        }
+        Some(edge_pos)
+    }
+
+    /// Check that the entity can travel through the air from `start` to `end`, rising up to
+    /// `height` pixels above its starting altitude along the way.
+    pub fn check_arc_clear<S: ShapeSource>(&self, chunk: &S, start: V3, end: V3,
+                                           height: i32) -> bool {
+        let offset = end - start;
+        let dir = offset.signum();
+        // Sample at half-tile intervals, which is fine enough to catch any single-tile obstacle.
+        let steps = offset.abs().max() / (TILE_SIZE / 2) + 1;
+        for i in 1 .. steps + 1 {
+            let pos = start + offset * scalar(i) / scalar(steps);
+            if self.check_blocked(chunk, pos, dir).intersects(BLOCKED) ||
+               self.check_blocked(chunk, pos + V3::new(0, 0, height), dir).intersects(BLOCKED) {
+                return false;
+            }
+        }
+        true
+    }
 }
 
 fn apply_collision_planar(dir: V3, c: Collision) -> V3 {
@@ -169,9 +169,15 @@ impl BlockData {
 }
 
 
+/// Stack size used for items that don't specify one.
+pub const DEFAULT_STACK_SIZE: u16 = 255;
+
 pub struct ItemData {
     names: Vec<String>,
     name_to_id: HashMap<String, ItemId>,
+    stack_sizes: Vec<u16>,
+    weights: Vec<u32>,
+    volumes: Vec<u32>,
 }
 
 impl ItemData {
@@ -181,18 +187,35 @@ impl ItemData {
 
         let mut names = Vec::with_capacity(items.len());
         let mut name_to_id = HashMap::new();
+        let mut stack_sizes = Vec::with_capacity(items.len());
+        let mut weights = Vec::with_capacity(items.len());
+        let mut volumes = Vec::with_capacity(items.len());
 
         for (i, item) in items.iter().enumerate() {
             let name = get_convert!(item, "name", as_string,
                                     "for item {}", i);
+            // Capacity-related properties are optional.
+            let stack_size = item.find("stack_size").and_then(|j| j.as_u64())
+                                 .map_or(DEFAULT_STACK_SIZE, |x| x as u16);
+            if stack_size == 0 {
+                return fail!("invalid stack size 0 for item {} ({})", i, name);
+            }
+            let weight = item.find("weight").and_then(|j| j.as_u64()).unwrap_or(0);
+            let volume = item.find("volume").and_then(|j| j.as_u64()).unwrap_or(0);
 
             names.push(name.to_owned());
             name_to_id.insert(name.to_owned(), i as ItemId);
+            stack_sizes.push(stack_size);
+            weights.push(weight as u32);
+            volumes.push(volume as u32);
         }
 
         Ok(ItemData {
             names: names,
             name_to_id: name_to_id,
+            stack_sizes: stack_sizes,
+            weights: weights,
+            volumes: volumes,
         })
     }
 
@@ -211,6 +234,19 @@ impl ItemData {
     pub fn find_id(&self, name: &str) -> Option<ItemId> {
         self.name_to_id.get(name).map(|&x| x)
     }
+
+    /// Maximum number of items of this kind that fit in a single inventory slot.
+    pub fn stack_size(&self, id: ItemId) -> u16 {
+        self.stack_sizes.get(id as usize).map_or(DEFAULT_STACK_SIZE, |&x| x)
+    }
+
+    pub fn weight(&self, id: ItemId) -> u32 {
+        self.weights.get(id as usize).map_or(0, |&x| x)
+    }
+
+    pub fn volume(&self, id: ItemId) -> u32 {
+        self.volumes.get(id as usize).map_or(0, |&x| x)
+    }
 }
 
 
@@ -218,7 +254,16 @@ pub struct Recipe {
     pub name: String,
     pub inputs: HashMap<ItemId, u8>,
     pub outputs: HashMap<ItemId, u8>,
-    pub station: Option<TemplateId>,
+    /// Structure templates where this recipe can be crafted.  If empty, any station will do.
+    pub stations: Vec<TemplateId>,
+    /// Time to craft one batch, in milliseconds.  Zero means crafting is instant.
+    pub time: u32,
+}
+
+impl Recipe {
+    pub fn allows_station(&self, template_id: TemplateId) -> bool {
+        self.stations.len() == 0 || self.stations.contains(&template_id)
+    }
 }
 
 pub struct RecipeData {
@@ -237,11 +282,24 @@ impl RecipeData {
         for (i, recipe) in recipes_json.iter().enumerate() {
             let name = get_convert!(recipe, "name", as_string,
                                     "for recipe {}", i);
-            let station = match find_convert!(recipe, "station", as_i64,
-                                              "for recipe {}", i) {
-                Ok(station) => Some(station as TemplateId),
-                Err(_) => None,
+            let stations = match recipe.find("stations").and_then(|j| j.as_array()) {
+                Some(list) => {
+                    let mut stations = Vec::with_capacity(list.len());
+                    for (j, station) in list.iter().enumerate() {
+                        let station = expect!(station.as_i64(),
+                                              "failed to convert recipe {} station {}", i, j);
+                        stations.push(station as TemplateId);
+                    }
+                    stations
+                },
+                // Older data files list only a single station.
+                None => match find_convert!(recipe, "station", as_i64,
+                                            "for recipe {}", i) {
+                    Ok(station) => vec![station as TemplateId],
+                    Err(_) => Vec::new(),
+                },
             };
+            let time = recipe.find("time").and_then(|j| j.as_u64()).unwrap_or(0) as u32;
 
             fn build_map(list: &[Json], what: &str, i: usize) -> Result<HashMap<ItemId, u8>, ParseError> {
                 let mut map = HashMap::new();
@@ -272,7 +330,8 @@ impl RecipeData {
                 name: name.to_owned(),
                 inputs: inputs,
                 outputs: outputs,
-                station: station,
+                stations: stations,
+                time: time,
             });
             name_to_id.insert(name.to_owned(), i as RecipeId); 
         }
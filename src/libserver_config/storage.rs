@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 use std::fmt::Debug;
-use std::fs::{self, File};
+use std::fs::{self, File, OpenOptions};
 use std::io;
 use std::path::{Path, PathBuf};
 
@@ -23,11 +23,19 @@ const SAVE_DIR: &'static str = "save";
 const CLIENT_DIR: &'static str = "clients";
 const PLANE_DIR: &'static str = "planes";
 const SUMMARY_DIR: &'static str = "summary";
+const SCHEMATIC_DIR: &'static str = "schematics";
 const TERRAIN_CHUNK_DIR: &'static str = "terrain_chunks";
+const MAIL_DIR: &'static str = "mail";
 const WORLD_FILE_NAME: &'static str = "world.dat";
 const MISC_FILE_NAME: &'static str = "misc.dat";
 const AUTH_DB_FILE_NAME: &'static str = "auth.sqlite";
+const AUDIT_DB_FILE_NAME: &'static str = "audit.sqlite";
 const RESTART_FILE_NAME: &'static str = "restart.dat";
+const JOURNAL_FILE_NAME: &'static str = "journal.dat";
+const CLOCK_FILE_NAME: &'static str = "clock.json";
+const CHAT_FILE_NAME: &'static str = "chat.json";
+const INSTANCES_FILE_NAME: &'static str = "instances.json";
+const CONFIG_FILE_NAME: &'static str = "config.json";
 
 pub struct Storage {
     base: PathBuf,
@@ -39,6 +47,8 @@ impl Storage {
         fs::create_dir_all(base.join(SAVE_DIR).join(CLIENT_DIR)).unwrap();
         fs::create_dir_all(base.join(SAVE_DIR).join(PLANE_DIR)).unwrap();
         fs::create_dir_all(base.join(SAVE_DIR).join(TERRAIN_CHUNK_DIR)).unwrap();
+        fs::create_dir_all(base.join(SAVE_DIR).join(SCHEMATIC_DIR)).unwrap();
+        fs::create_dir_all(base.join(SAVE_DIR).join(MAIL_DIR)).unwrap();
 
         Storage {
             base: base,
@@ -83,6 +93,14 @@ impl Storage {
         self.base.join(SCRIPT_DIR)
     }
 
+    pub fn config_path(&self) -> PathBuf {
+        self.base.join(CONFIG_FILE_NAME)
+    }
+
+    pub fn open_config_file(&self) -> Option<File> {
+        try_open_file(self.config_path())
+    }
+
     pub fn world_path(&self) -> PathBuf {
         self.base.join(SAVE_DIR).join(WORLD_FILE_NAME)
     }
@@ -95,12 +113,22 @@ impl Storage {
         self.base.join(SAVE_DIR).join(AUTH_DB_FILE_NAME)
     }
 
+    pub fn audit_db_path(&self) -> PathBuf {
+        self.base.join(SAVE_DIR).join(AUDIT_DB_FILE_NAME)
+    }
+
     pub fn client_path(&self, name: &str) -> PathBuf {
         self.base.join(SAVE_DIR).join(CLIENT_DIR)
             .join(&*sanitize(name))
             .with_extension("client")
     }
 
+    pub fn mail_path(&self, name: &str) -> PathBuf {
+        self.base.join(SAVE_DIR).join(MAIL_DIR)
+            .join(&*sanitize(name))
+            .with_extension("json")
+    }
+
     pub fn plane_path(&self, stable_pid: Stable<PlaneId>) -> PathBuf {
         self.base.join(SAVE_DIR).join(PLANE_DIR)
             .join(format!("{:x}", stable_pid.unwrap()))
@@ -117,12 +145,38 @@ impl Storage {
         self.base.join(SAVE_DIR).join(RESTART_FILE_NAME)
     }
 
+    pub fn journal_path(&self) -> PathBuf {
+        self.base.join(SAVE_DIR).join(JOURNAL_FILE_NAME)
+    }
+
+    pub fn clock_path(&self) -> PathBuf {
+        self.base.join(SAVE_DIR).join(CLOCK_FILE_NAME)
+    }
+
+    pub fn chat_path(&self) -> PathBuf {
+        self.base.join(SAVE_DIR).join(CHAT_FILE_NAME)
+    }
+
+    pub fn instances_path(&self) -> PathBuf {
+        self.base.join(SAVE_DIR).join(INSTANCES_FILE_NAME)
+    }
+
+    pub fn schematic_path(&self, name: &str) -> PathBuf {
+        self.base.join(SAVE_DIR).join(SCHEMATIC_DIR)
+            .join(&*sanitize(name))
+            .with_extension("schematic")
+    }
+
+    pub fn summary_dir_path(&self, stable_pid: Stable<PlaneId>) -> PathBuf {
+        self.base.join(SAVE_DIR).join(SUMMARY_DIR)
+            .join(format!("{:x}", stable_pid.unwrap()))
+    }
+
     pub fn summary_file_path(&self,
                              name: &str,
                              stable_pid: Stable<PlaneId>,
                              cpos: V2) -> PathBuf {
-        self.base.join(SAVE_DIR).join(SUMMARY_DIR)
-            .join(format!("{:x}", stable_pid.unwrap()))
+        self.summary_dir_path(stable_pid)
             .join(name)
             .join(format!("{},{}.dat", cpos.x, cpos.y))
     }
@@ -140,6 +194,10 @@ impl Storage {
         try_open_file(self.client_path(name))
     }
 
+    pub fn open_mail_file(&self, name: &str) -> Option<File> {
+        try_open_file(self.mail_path(name))
+    }
+
     pub fn open_plane_file(&self, stable_pid: Stable<PlaneId>) -> Option<File> {
         try_open_file(self.plane_path(stable_pid))
     }
@@ -152,6 +210,26 @@ impl Storage {
         try_open_file(self.restart_file_path())
     }
 
+    pub fn open_journal_file(&self) -> Option<File> {
+        try_open_file(self.journal_path())
+    }
+
+    pub fn open_clock_file(&self) -> Option<File> {
+        try_open_file(self.clock_path())
+    }
+
+    pub fn open_chat_file(&self) -> Option<File> {
+        try_open_file(self.chat_path())
+    }
+
+    pub fn open_instances_file(&self) -> Option<File> {
+        try_open_file(self.instances_path())
+    }
+
+    pub fn open_schematic_file(&self, name: &str) -> Option<File> {
+        try_open_file(self.schematic_path(name))
+    }
+
     pub fn open_summary_file(&self,
                              name: &str,
                              stable_pid: Stable<PlaneId>,
@@ -172,6 +250,10 @@ impl Storage {
         File::create(self.client_path(name)).unwrap()
     }
 
+    pub fn create_mail_file(&self, name: &str) -> File {
+        File::create(self.mail_path(name)).unwrap()
+    }
+
     pub fn create_plane_file(&self, stable_pid: Stable<PlaneId>) -> File {
         File::create(self.plane_path(stable_pid)).unwrap()
     }
@@ -188,6 +270,30 @@ impl Storage {
         fs::remove_file(self.restart_file_path()).unwrap()
     }
 
+    pub fn create_journal_file(&self) -> File {
+        File::create(self.journal_path()).unwrap()
+    }
+
+    pub fn append_journal_file(&self) -> File {
+        OpenOptions::new().append(true).create(true).open(self.journal_path()).unwrap()
+    }
+
+    pub fn create_clock_file(&self) -> File {
+        File::create(self.clock_path()).unwrap()
+    }
+
+    pub fn create_chat_file(&self) -> File {
+        File::create(self.chat_path()).unwrap()
+    }
+
+    pub fn create_instances_file(&self) -> File {
+        File::create(self.instances_path()).unwrap()
+    }
+
+    pub fn create_schematic_file(&self, name: &str) -> File {
+        File::create(self.schematic_path(name)).unwrap()
+    }
+
     pub fn create_summary_file(&self,
                                name: &str,
                                stable_pid: Stable<PlaneId>,
@@ -196,6 +302,29 @@ impl Storage {
         fs::create_dir_all(path.parent().unwrap()).unwrap();
         File::create(path).unwrap()
     }
+
+
+    pub fn remove_mail_file(&self, name: &str) {
+        try_remove(self.mail_path(name))
+    }
+
+    pub fn remove_plane_file(&self, stable_pid: Stable<PlaneId>) {
+        try_remove(self.plane_path(stable_pid))
+    }
+
+    pub fn remove_terrain_chunk_file(&self, stable_tcid: Stable<TerrainChunkId>) {
+        try_remove(self.terrain_chunk_path(stable_tcid))
+    }
+
+    /// Remove all terrain generator summaries for a plane.
+    pub fn remove_summary_files(&self, stable_pid: Stable<PlaneId>) {
+        let path = self.summary_dir_path(stable_pid);
+        match fs::remove_dir_all(&path) {
+            Ok(()) => {},
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => warn!("error removing {:?}: {}", path, e),
+        }
+    }
 }
 
 fn char_legal(c: char) -> bool {
@@ -239,6 +368,14 @@ fn sanitize(s: &str) -> Cow<str> {
     }
 }
 
+fn try_remove<P: AsRef<Path>+Debug>(path: P) {
+    match fs::remove_file(&path) {
+        Ok(()) => {},
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+        Err(e) => warn!("error removing {:?}: {}", path, e),
+    }
+}
+
 fn try_open_file<P: AsRef<Path>+Debug>(path: P) -> Option<File> {
     match File::open(path) {
         Ok(f) => Some(f),
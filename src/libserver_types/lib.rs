@@ -99,6 +99,51 @@ pub const STABLE_PLANE_FOREST: Stable<PlaneId> = const_Stable!(2);
 pub const CONTROL_WIRE_ID: WireId = WireId(0);
 
 
+// Terrain generation
+
+/// The terrain generator used to fill in new chunks of a plane.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GeneratorKind {
+    /// Generate nothing.  Chunks are left empty, for planes built entirely by scripts.
+    Empty = 0,
+    Forest = 1,
+    Dungeon = 2,
+}
+
+impl GeneratorKind {
+    pub fn from_primitive(i: u8) -> Option<GeneratorKind> {
+        use self::GeneratorKind::*;
+        let k = match i {
+            0 => Empty,
+            1 => Forest,
+            2 => Dungeon,
+            _ => return None,
+        };
+        Some(k)
+    }
+
+    pub fn from_name(s: &str) -> Option<GeneratorKind> {
+        use self::GeneratorKind::*;
+        let k = match s {
+            "empty" => Empty,
+            "forest" => Forest,
+            "dungeon" => Dungeon,
+            _ => return None,
+        };
+        Some(k)
+    }
+
+    pub fn name(&self) -> &'static str {
+        use self::GeneratorKind::*;
+        match *self {
+            Empty => "empty",
+            Forest => "forest",
+            Dungeon => "dungeon",
+        }
+    }
+}
+
+
 // Time and space
 
 pub type LocalTime = u16;
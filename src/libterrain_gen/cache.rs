@@ -89,6 +89,18 @@ impl<'d, T: Summary> Cache<'d, T> {
 
     // No explicit `unload` - data is unloaded automatically in LRU fashion.
 
+    /// Discard all cached data for a plane, without writing it to disk.  Used when the plane is
+    /// destroyed.
+    pub fn forget_plane(&mut self, pid: Stable<PlaneId>) {
+        let keys = self.cache.iter()
+                       .map(|(&key, _)| key)
+                       .filter(|&(p, _)| p == pid)
+                       .collect::<Vec<_>>();
+        for key in keys {
+            self.cache.remove(&key);
+        }
+    }
+
     pub fn get(&self, pid: Stable<PlaneId>, cpos: V2) -> &T {
         &self.cache[&(pid, cpos)].data
     }
@@ -1,3 +1,4 @@
+use std::mem;
 use rand::Rng;
 
 use libserver_types::*;
@@ -38,6 +39,18 @@ impl<'d> Provider<'d> {
         }
     }
 
+    /// Discard cached summaries for a plane that is being destroyed.
+    pub fn forget_plane(&mut self, pid: Stable<PlaneId>) {
+        self.cache.forget_plane(pid);
+        self.super_cache.forget_plane(pid);
+    }
+
+    /// Replace the random number generator, returning the old one.  Used to generate chunks of
+    /// planes that have their own seed.
+    pub fn swap_rng(&mut self, rng: StdRng) -> StdRng {
+        mem::replace(&mut self.rng, rng)
+    }
+
     fn get_super_heightmap(&mut self,
                            pid: Stable<PlaneId>,
                            scpos: V2) -> &[u8] {
@@ -13,7 +13,11 @@ use dungeon::Provider as DungeonProvider;
 
 
 pub enum Command {
-    Generate(Stable<PlaneId>, V2),
+    /// Generate a chunk of the plane using the given generator and seed.  A seed of zero means
+    /// the plane has no seed of its own, and uses the worker's shared random number generator.
+    Generate(Stable<PlaneId>, V2, GeneratorKind, u32),
+    /// Discard all summary data for a destroyed plane, both cached and on disk.
+    ForgetPlane(Stable<PlaneId>),
 }
 
 pub type Response = (Stable<PlaneId>, V2, GenChunk);
@@ -27,16 +31,20 @@ pub fn run(data: &Data,
     for cmd in recv.iter() {
         use self::Command::*;
         match cmd {
-            Generate(pid, cpos) => {
-                let gc = w.generate_forest_chunk(pid, cpos);
+            Generate(pid, cpos, kind, seed) => {
+                let gc = w.generate_chunk(pid, cpos, kind, seed);
                 send.send((pid, cpos, gc)).unwrap();
             },
+            ForgetPlane(pid) => {
+                w.forget_plane(pid);
+            },
         }
     }
 }
 
 
 struct Worker<'d> {
+    storage: &'d Storage,
     forest: ForestProvider<'d>,
     dungeon: DungeonProvider<'d>,
 }
@@ -49,23 +57,61 @@ impl<'d> Worker<'d> {
                                                       0x00012345]);
 
         Worker {
+            storage: storage,
             forest: ForestProvider::new(data, storage, rng.gen()),
             dungeon: DungeonProvider::new(data, storage, rng.gen()),
         }
     }
 
-    pub fn generate_forest_chunk(&mut self, pid: Stable<PlaneId>, cpos: V2) -> GenChunk {
+    pub fn generate_chunk(&mut self,
+                          pid: Stable<PlaneId>,
+                          cpos: V2,
+                          kind: GeneratorKind,
+                          seed: u32) -> GenChunk {
         let start = now();
-        let gc =
-            if pid == STABLE_PLANE_FOREST {
-                self.forest.generate(pid, cpos)
-            } else {
-                self.dungeon.generate(pid, cpos)
-            };
+        let gc = match kind {
+            GeneratorKind::Empty => GenChunk::new(),
+            GeneratorKind::Forest => {
+                if seed != 0 {
+                    let old = self.forest.swap_rng(seeded_rng(seed, cpos));
+                    let gc = self.forest.generate(pid, cpos);
+                    self.forest.swap_rng(old);
+                    gc
+                } else {
+                    self.forest.generate(pid, cpos)
+                }
+            },
+            GeneratorKind::Dungeon => {
+                if seed != 0 {
+                    let old = self.dungeon.swap_rng(seeded_rng(seed, cpos));
+                    let gc = self.dungeon.generate(pid, cpos);
+                    self.dungeon.swap_rng(old);
+                    gc
+                } else {
+                    self.dungeon.generate(pid, cpos)
+                }
+            },
+        };
         let end = now();
-        info!("generated {} {:?} in {} ms", pid.unwrap(), cpos, end - start);
+        info!("generated {} {:?} ({}) in {} ms", pid.unwrap(), cpos, kind.name(), end - start);
         gc
     }
+
+    pub fn forget_plane(&mut self, pid: Stable<PlaneId>) {
+        self.forest.forget_plane(pid);
+        self.dungeon.forget_plane(pid);
+        self.storage.remove_summary_files(pid);
+    }
+}
+
+
+/// Build a random number generator for chunk `cpos` of a plane with the given seed.
+fn seeded_rng(seed: u32, cpos: V2) -> StdRng {
+    // The last word is never zero, so the seed is valid for XorShiftRng.
+    SeedableRng::from_seed([seed,
+                            cpos.x as u32,
+                            cpos.y as u32,
+                            0x9e3779b9])
 }
 
 
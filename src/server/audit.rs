@@ -0,0 +1,166 @@
+//! Append-only audit log for moderators.  Records chat lines, logins and logouts, registration
+//! attempts, kicks, superuser commands, and mail in `save/audit.sqlite`, next to the auth
+//! database.  Entries are timestamped with the real-world time, in milliseconds since the Unix
+//! epoch, and are only ever removed by `prune`.
+//!
+//! New entries are buffered in memory and written to the database in a single transaction by
+//! `flush`, which runs periodically and at shutdown.
+
+use std::mem;
+use std::path::Path;
+use std::result;
+
+use rusqlite::{SqliteConnection, SqliteError};
+use rusqlite::types::ToSql;
+
+use types::*;
+use util::now;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Chat,
+    Login,
+    Logout,
+    Register,
+    Kick,
+    Command,
+    Mail,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        use self::Kind::*;
+        match *self {
+            Chat => "chat",
+            Login => "login",
+            Logout => "logout",
+            Register => "register",
+            Kick => "kick",
+            Command => "command",
+            Mail => "mail",
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub time: Time,
+    pub kind: String,
+    pub name: String,
+    pub detail: String,
+}
+
+
+pub struct AuditLog {
+    conn: SqliteConnection,
+    /// Entries that haven't been written to the database yet.
+    pending: Vec<(Time, Kind, String, String)>,
+}
+
+impl AuditLog {
+    pub fn new<P: AsRef<Path>>(db_path: &P) -> Result<AuditLog> {
+        let conn = try!(SqliteConnection::open(db_path));
+        try!(conn.execute("CREATE TABLE IF NOT EXISTS audit (
+                           time      INTEGER NOT NULL,
+                           kind      TEXT NOT NULL,
+                           name      TEXT NOT NULL,
+                           detail    TEXT NOT NULL
+                           )", &[]));
+        try!(conn.execute("CREATE INDEX IF NOT EXISTS audit_name_time
+                           ON audit (name, time)", &[]));
+        try!(conn.execute("CREATE INDEX IF NOT EXISTS audit_time
+                           ON audit (time)", &[]));
+        Ok(AuditLog {
+            conn: conn,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Record an event involving the player `name`.  `name` is empty for events with no known
+    /// player, such as a kick before login.  The entry is written on the next `flush`.
+    pub fn record(&mut self, kind: Kind, name: &str, detail: &str) {
+        self.pending.push((now(), kind, name.to_owned(), detail.to_owned()));
+    }
+
+    /// Write all buffered entries to the database.  If the write fails, the entries stay buffered
+    /// and the next `flush` tries again.
+    pub fn flush(&mut self) {
+        if self.pending.len() == 0 {
+            return;
+        }
+        let pending = mem::replace(&mut self.pending, Vec::new());
+        if let Err(e) = self.write(&pending) {
+            warn!("error writing {} audit log entries: {}", pending.len(), e);
+            self.pending = pending;
+        }
+    }
+
+    fn write(&self, entries: &[(Time, Kind, String, String)]) -> Result<()> {
+        let tx = try!(self.conn.transaction());
+        {
+            let mut stmt = try!(self.conn.prepare("INSERT INTO audit (time, kind, name, detail)
+                                                   VALUES ($1, $2, $3, $4)"));
+            for &(time, kind, ref name, ref detail) in entries {
+                let name: &str = name;
+                let detail: &str = detail;
+                try!(stmt.execute(&[&time as &ToSql,
+                                    &kind.name() as &ToSql,
+                                    &name as &ToSql,
+                                    &detail as &ToSql]));
+            }
+        }
+        tx.commit()
+    }
+
+    /// Get up to `limit` of the most recent entries recorded at or after `since`, oldest first.
+    /// If `name` is `None`, entries for all players are returned.
+    pub fn query(&mut self,
+                 name: Option<&str>,
+                 since: Time,
+                 limit: u32) -> Result<Vec<Entry>> {
+        // Include entries that haven't been written yet.
+        self.flush();
+
+        let limit = limit as i64;
+        let mut stmt;
+        let rows = match name {
+            Some(ref name) => {
+                stmt = try!(self.conn.prepare("SELECT time, kind, name, detail FROM audit
+                                               WHERE name = $1 AND time >= $2
+                                               ORDER BY time DESC LIMIT $3"));
+                try!(stmt.query(&[name as &ToSql, &since as &ToSql, &limit as &ToSql]))
+            },
+            None => {
+                stmt = try!(self.conn.prepare("SELECT time, kind, name, detail FROM audit
+                                               WHERE time >= $1
+                                               ORDER BY time DESC LIMIT $2"));
+                try!(stmt.query(&[&since as &ToSql, &limit as &ToSql]))
+            },
+        };
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let row = try!(row);
+            entries.push(Entry {
+                time: row.get(0),
+                kind: row.get(1),
+                name: row.get(2),
+                detail: row.get(3),
+            });
+        }
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Remove all entries recorded before `before`.  Returns the number of entries removed.
+    pub fn prune(&mut self, before: Time) -> Result<usize> {
+        let count = try!(self.conn.execute("DELETE FROM audit WHERE time < $1",
+                                           &[&before as &ToSql]));
+        Ok(count as usize)
+    }
+}
+
+
+pub type Result<T> = result::Result<T, SqliteError>;
@@ -62,6 +62,17 @@ impl Auth {
         }
         Ok(false)
     }
+
+    /// Check whether an account named `name` has been registered.
+    pub fn is_registered(&self, name: &str) -> Result<bool> {
+        let mut stmt = try!(self.conn.prepare("SELECT 1 FROM auth WHERE name = $1"));
+
+        for row in try!(stmt.query(&[&name as &ToSql])) {
+            try!(row);
+            return Ok(true);
+        }
+        Ok(false)
+    }
 }
 
 
@@ -22,7 +22,7 @@ macro_rules! part2 {
         part2!(vision, VisionHooks, $($x)*);
     };
     (VisionHooks, $($x:tt)*) => {
-        part2!(world, messages, $($x)*);
+        part2!(world, messages, extra, $($x)*);
     };
 
     // Hidden WorldFragment.  Changes through this fragment will be propagated to server data
@@ -172,6 +172,7 @@ impl_slice! {
     EngineRef::as_hidden_world_fragment -> HiddenWorldFragment;
     EngineRef::as_hidden_vision_fragment -> HiddenVisionFragment;
     HiddenWorldHooks::as_hidden_vision_fragment -> HiddenVisionFragment;
+    HiddenWorldHooks::as_hidden_world_fragment -> HiddenWorldFragment;
 }
 
 
@@ -4,6 +4,7 @@ use std::sync::mpsc::{Sender, Receiver};
 
 use types::*;
 
+use audit::{self, AuditLog};
 use auth::{Auth, Secret};
 use cache::TerrainCache;
 use chunks::Chunks;
@@ -21,7 +22,7 @@ use storage::Storage;
 use terrain_gen::{TerrainGen, TerrainGenEvent};
 use terrain_gen::Fragment as TerrainGen_Fragment;
 use timer::{Timer, TimerEvent};
-use vision::Vision;
+use vision::{self, Vision, ViewShape};
 use world::World;
 
 use self::split::EngineRef;
@@ -45,6 +46,7 @@ pub struct Engine<'d> {
     pub physics: Physics<'d>,
     pub vision: Vision,
     pub auth: Auth,
+    pub audit: AuditLog,
     pub chunks: Chunks<'d>,
     pub cache: TerrainCache,
     pub terrain_gen: TerrainGen<'d>,
@@ -77,6 +79,7 @@ impl<'d> Engine<'d> {
             physics: Physics::new(data),
             vision: Vision::new(),
             auth: Auth::new(&storage.auth_db_path()).unwrap(),
+            audit: AuditLog::new(&storage.audit_db_path()).unwrap(),
             chunks: Chunks::new(storage),
             cache: TerrainCache::new(),
             terrain_gen: TerrainGen::new(data, storage),
@@ -185,6 +188,13 @@ impl<'d> Engine<'d> {
                     return HandlerResult::Restart;
                 }
             },
+
+            ReloadScripts => {
+                match ScriptEngine::reload(self) {
+                    Ok(()) => info!("reloaded scripts"),
+                    Err(e) => warn!("failed to reload scripts: {}", e.description()),
+                }
+            },
         }
         HandlerResult::Continue
     }
@@ -195,19 +205,30 @@ impl<'d> Engine<'d> {
         use messages::WireEvent::*;
         use messages::WireResponse::*;
         match evt {
-            Login(name, secret) => {
+            Login(name, secret, screen) => {
                 match self.auth.login(&*name, &secret) {
                     Ok(true) => {
-                        warn_on_err!(logic::client::login(self.as_ref(), wire_id, &*name));
+                        // Clients using the old `Login` message don't report their screen size.
+                        let view_size =
+                            if screen == scalar(0) { vision::DEFAULT_VIEW_SIZE }
+                            else { ViewShape::size_for_screen(screen) };
+                        warn_on_err!(logic::client::login(self.as_ref(),
+                                                          wire_id,
+                                                          &*name,
+                                                          view_size));
                     },
                     Ok(false) => {
                         info!("{:?}: login as {} failed: bad name/secret",
                               wire_id, name);
+                        self.audit.record(audit::Kind::Login, &name,
+                                          &format!("{:?}: failed: bad name/secret", wire_id));
                         self.kick_wire(wire_id, "login failed")
                     },
                     Err(e) => {
                         info!("{:?}: login as {} failed: auth error: {}",
                               wire_id, name, e.description());
+                        self.audit.record(audit::Kind::Login, &name,
+                                          &format!("{:?}: failed: auth error", wire_id));
                         self.kick_wire(wire_id, "login failed")
                     },
                 }
@@ -240,32 +261,62 @@ impl<'d> Engine<'d> {
             },
 
             MoveItem(from_iid, to_iid, item_id, count) => {
-                warn_on_err!(logic::items::move_items(self.as_ref(),
-                                                      from_iid, to_iid, item_id, count));
+                let result = logic::journal::with_actor(self.as_ref(), cid, |eng| {
+                    logic::items::move_items(eng, cid, from_iid, to_iid, item_id, count)
+                });
+                if let Err(e) = result {
+                    // Let the player know why nothing happened.
+                    let msg = format!("***\tCan't move items: {}", e.description());
+                    self.messages.send_client(cid, ClientResponse::ChatUpdate(msg));
+                }
             },
 
             CraftRecipe(station_sid, iid, recipe_id, count) => {
-                warn_on_err!(logic::items::craft_recipe(self.as_ref(),
-                                                        station_sid, iid, recipe_id, count));
+                let result = logic::journal::with_actor(self.as_ref(), cid, |eng| {
+                    logic::items::craft_recipe(eng, cid, station_sid, iid, recipe_id, count)
+                });
+                if let Err(e) = result {
+                    let msg = format!("***\tCan't craft: {}", e.description());
+                    self.messages.send_client(cid, ClientResponse::ChatUpdate(msg));
+                }
             },
 
             Chat(msg) => {
-                logic::input::chat(self.as_ref(), cid, msg);
+                logic::journal::with_actor(self.as_ref(), cid,
+                                           |eng| logic::input::chat(eng, cid, msg));
             },
 
             Interact(time, args) => {
-                self.timer.schedule(time,
-                                    move |eng| logic::input::interact(eng, cid, args));
+                self.timer.schedule(time, move |eng| {
+                    logic::journal::with_actor(eng, cid,
+                                               |eng| logic::input::interact(eng, cid, args))
+                });
             },
 
             UseItem(time, item_id, args) => {
-                self.timer.schedule(time,
-                                    move |eng| logic::input::use_item(eng, cid, item_id, args));
+                self.timer.schedule(time, move |eng| {
+                    logic::journal::with_actor(eng, cid, |eng| {
+                        logic::input::use_item(eng, cid, item_id, args)
+                    })
+                });
             },
 
             UseAbility(time, item_id, args) => {
-                self.timer.schedule(time,
-                                    move |eng| logic::input::use_ability(eng, cid, item_id, args));
+                self.timer.schedule(time, move |eng| {
+                    logic::journal::with_actor(eng, cid, |eng| {
+                        logic::input::use_ability(eng, cid, item_id, args)
+                    })
+                });
+            },
+
+            TradeAction(action) => {
+                let result = logic::journal::with_actor(self.as_ref(), cid, |eng| {
+                    logic::trade::action(eng, cid, action)
+                });
+                if let Err(e) = result {
+                    let msg = format!("***\tCan't trade: {}", e.description());
+                    self.messages.send_client(cid, ClientResponse::ChatUpdate(msg));
+                }
             },
 
             BadRequest => {
@@ -290,13 +341,23 @@ impl<'d> Engine<'d> {
         let wire_id = self.messages.client_to_wire(cid)
                 .expect("missing WireId for existing client");
 
-        self.messages.send_client(cid, ClientResponse::KickReason(msg.into()));
+        let msg = msg.into();
+        let name = self.world.get_client(cid).map_or(String::new(), |c| c.name().to_owned());
+        self.audit.record(audit::Kind::Kick, &name, &format!("{:?}: {}", wire_id, msg));
+
+        self.messages.send_client(cid, ClientResponse::KickReason(msg));
         self.cleanup_client(cid);
         self.messages.send_control(ControlResponse::WireClosed(wire_id));
     }
 
     pub fn kick_wire<'a, S: Into<String>>(&mut self, wire_id: WireId, msg: S) {
-        self.messages.send_wire(wire_id, WireResponse::KickReason(msg.into()));
+        let msg = msg.into();
+        let name = self.messages.wire_to_client(wire_id)
+                       .and_then(|cid| self.world.get_client(cid))
+                       .map_or(String::new(), |c| c.name().to_owned());
+        self.audit.record(audit::Kind::Kick, &name, &format!("{:?}: {}", wire_id, msg));
+
+        self.messages.send_wire(wire_id, WireResponse::KickReason(msg));
         self.cleanup_wire(wire_id);
         self.messages.send_control(ControlResponse::WireClosed(wire_id));
     }
@@ -313,32 +374,37 @@ impl<'d> Engine<'d> {
                    secret: Secret,
                    appearance: u32) -> (u32, String) {
         if let Err(msg) = name_valid(&*name) {
+            self.audit.record(audit::Kind::Register, &name,
+                              &format!("{:?}: failed: {}", wire_id, msg));
             return (1, String::from(msg));
         }
 
-        match self.auth.register(&*name, &secret) {
+        let (result, detail) = match self.auth.register(&*name, &secret) {
             Ok(true) => {
                 info!("{:?}: registered as {}", wire_id, name);
                 match logic::client::register(self.as_ref(), &*name, appearance) {
-                    Ok(()) => (0, String::new()),
+                    Ok(()) => ((0, String::new()), "ok"),
                     Err(e) => {
                         warn!("{:?}: error registering as {}: {}",
                               wire_id, name, e.description());
-                        (2, String::from("An internal error occurred."))
+                        ((2, String::from("An internal error occurred.")),
+                         "failed: internal error")
                     }
                 }
             },
             Ok(false) => {
                 info!("{:?}: registration as {} failed: name is in use",
                       wire_id, name);
-                (1, String::from("That name is already in use."))
+                ((1, String::from("That name is already in use.")), "failed: name is in use")
             },
             Err(e) => {
                 info!("{:?}: registration as {} failed: database error: {}",
                       wire_id, name, e.description());
-                (2, String::from("An internal error occurred."))
+                ((2, String::from("An internal error occurred.")), "failed: database error")
             }
-        }
+        };
+        self.audit.record(audit::Kind::Register, &name, &format!("{:?}: {}", wire_id, detail));
+        result
     }
 
     pub fn now(&self) -> Time {
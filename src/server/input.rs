@@ -9,6 +9,7 @@ bitflags! {
         const INPUT_UP =        0x0004,
         const INPUT_DOWN =      0x0008,
         const INPUT_RUN =       0x0010,
+        const INPUT_JUMP =      0x0020,
     }
 }
 
@@ -0,0 +1,88 @@
+//! Audit log upkeep and queries.  See `audit` for the log itself.
+
+use types::*;
+use util::StrResult;
+use util::now;
+
+use audit::{Kind, Entry};
+use engine::split::EngineRef;
+
+
+/// Interval between removals of expired entries.
+const PRUNE_INTERVAL: Time = 24 * 60 * 60 * 1000;
+
+/// Interval between writes of buffered entries to the database.
+const FLUSH_INTERVAL: Time = 10 * 1000;
+
+
+pub fn record(mut eng: EngineRef, kind: Kind, name: &str, detail: &str) {
+    eng.borrow().unwrap().audit.record(kind, name, detail);
+}
+
+/// Write buffered entries to the database.
+pub fn flush(mut eng: EngineRef) {
+    eng.borrow().unwrap().audit.flush();
+}
+
+/// Schedule the next periodic flush of buffered entries.
+pub fn schedule_flush(mut eng: EngineRef) {
+    let when = eng.now() + FLUSH_INTERVAL;
+    eng.timer_mut().schedule(when, |mut eng| {
+        flush(eng.borrow());
+        schedule_flush(eng);
+    });
+}
+
+/// Remove entries older than the configured retention period, then schedule the next pruning.
+pub fn prune(mut eng: EngineRef) {
+    let days = eng.extra().config.audit_retention_days;
+    if days > 0 {
+        let before = now() - days as Time * 24 * 60 * 60 * 1000;
+        match eng.borrow().unwrap().audit.prune(before) {
+            Ok(0) => {},
+            Ok(count) => info!("pruned {} audit log entries", count),
+            Err(e) => warn!("error pruning audit log: {}", e),
+        }
+    }
+
+    let when = eng.now() + PRUNE_INTERVAL;
+    eng.timer_mut().schedule(when, |eng| prune(eng));
+}
+
+/// Get the entries for player `name` (or for everyone, if `None`) from the last `since`
+/// milliseconds, formatted for display.
+pub fn query(mut eng: EngineRef,
+             name: Option<&str>,
+             since: Time,
+             limit: u32) -> StrResult<Vec<String>> {
+    let now = now();
+    let entries = match eng.borrow().unwrap().audit.query(name, now - since, limit) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("error reading audit log: {}", e);
+            fail!("error reading audit log");
+        },
+    };
+    Ok(entries.iter().map(|e| format_entry(e, now)).collect())
+}
+
+/// Format the real-world time elapsed between `time` and `now`, such as `5m` or `2h10m`.
+pub fn format_age(time: Time, now: Time) -> String {
+    let mins = (now - time) / 60000;
+    if mins < 60 {
+        format!("{}m", mins)
+    } else if mins < 24 * 60 {
+        format!("{}h{}m", mins / 60, mins % 60)
+    } else {
+        format!("{}d{}h", mins / (24 * 60), mins / 60 % 24)
+    }
+}
+
+fn format_entry(e: &Entry, now: Time) -> String {
+    let age = format_age(e.time, now);
+    if e.name.len() > 0 {
+        format!("{} ago: {} {}: {}", age, e.kind, e.name, e.detail)
+    } else {
+        format!("{} ago: {}: {}", age, e.kind, e.detail)
+    }
+}
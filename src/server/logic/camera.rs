@@ -0,0 +1,165 @@
+//! Client cameras.  The camera decides which part of the world a client can see: vision and chunk
+//! loading follow the camera rather than the pawn.  By default the camera follows the client's
+//! pawn, but it can instead follow another entity, for moderators and spectators, or move freely
+//! around a plane under the control of the client's movement keys.
+
+use types::*;
+use util::StrResult;
+
+use engine::split::EngineRef;
+use logic;
+use logic::extra::Extra;
+use messages::ClientResponse;
+use physics;
+use world::{self, Motion};
+use world::object::*;
+
+
+/// Length of a free camera movement.  The camera stops after this long, unless the client sends
+/// more input first.
+const FREE_MOVE_DURATION: Duration = 60000;
+
+
+#[derive(Clone, Debug)]
+pub enum Camera {
+    /// Follow the client's pawn.
+    Pawn,
+    /// Follow another entity.
+    Entity(EntityId),
+    /// Move freely around a plane.
+    Free(Stable<PlaneId>, Motion),
+}
+
+pub fn camera(extra: &Extra, cid: ClientId) -> Camera {
+    extra.client_camera.get(&cid).cloned().unwrap_or(Camera::Pawn)
+}
+
+/// Get the plane and motion that the client's camera is following.  Clients with no pawn and no
+/// other camera target see the origin of Limbo.
+pub fn focus(w: &world::World,
+             extra: &Extra,
+             cid: ClientId) -> Option<(Stable<PlaneId>, Motion)> {
+    let c = unwrap_or!(w.get_client(cid), return None);
+    let target = match extra.client_camera.get(&cid) {
+        Some(&Camera::Free(stable_pid, ref m)) => return Some((stable_pid, m.clone())),
+        Some(&Camera::Entity(eid)) => w.get_entity(eid).or_else(|| c.pawn()),
+        Some(&Camera::Pawn) | None => c.pawn(),
+    };
+    match target {
+        Some(e) => Some((e.stable_plane_id(), e.motion().clone())),
+        None => Some((STABLE_PLANE_LIMBO, Motion::fixed(scalar(0)))),
+    }
+}
+
+/// Build the message telling the client what to center its display on.
+pub fn camera_message(w: &world::World, extra: &Extra, cid: ClientId) -> ClientResponse {
+    let pawn_id = w.get_client(cid).and_then(|c| c.pawn_id());
+    match camera(extra, cid) {
+        Camera::Pawn => ClientResponse::CameraUpdate(pawn_id, Motion::fixed(scalar(0))),
+        Camera::Entity(eid) =>
+            if w.get_entity(eid).is_some() {
+                ClientResponse::CameraUpdate(Some(eid), Motion::fixed(scalar(0)))
+            } else {
+                ClientResponse::CameraUpdate(pawn_id, Motion::fixed(scalar(0)))
+            },
+        Camera::Free(_, m) => ClientResponse::CameraUpdate(None, m),
+    }
+}
+
+
+pub fn follow_pawn(mut eng: EngineRef, cid: ClientId) -> StrResult<()> {
+    unwrap!(eng.world().get_client(cid));
+    eng.extra_mut().client_camera.remove(&cid);
+    refresh(eng, cid);
+    Ok(())
+}
+
+pub fn follow_entity(mut eng: EngineRef, cid: ClientId, eid: EntityId) -> StrResult<()> {
+    let pawn_id = unwrap!(eng.world().get_client(cid)).pawn_id();
+    unwrap!(eng.world().get_entity(eid));
+    if pawn_id == Some(eid) {
+        eng.extra_mut().client_camera.remove(&cid);
+    } else {
+        eng.extra_mut().client_camera.insert(cid, Camera::Entity(eid));
+    }
+    refresh(eng, cid);
+    Ok(())
+}
+
+/// Detach the camera from any entity and place it at `pos` on plane `pid`.  The client's movement
+/// keys will move the camera instead of the pawn.
+pub fn set_free(mut eng: EngineRef, cid: ClientId, pid: PlaneId, pos: V3) -> StrResult<()> {
+    let now = eng.now();
+    let pawn_id = unwrap!(eng.world().get_client(cid)).pawn_id();
+    let stable_pid = unwrap!(eng.as_hidden_world_fragment().get_plane_mut(pid)).stable_id();
+
+    // Stop the pawn, in case the client was holding down a movement key.
+    if let Some(eid) = pawn_id {
+        warn_on_err!(physics::Fragment::set_velocity(
+                &mut eng.as_physics_fragment(), now, eid, scalar(0)));
+    }
+
+    let camera = Camera::Free(stable_pid, Motion::stationary(pos, now));
+    eng.extra_mut().client_camera.insert(cid, camera);
+    refresh(eng, cid);
+    Ok(())
+}
+
+/// Handle movement input for a client with a free camera.  Returns `false` if the camera is not
+/// free, meaning the input should move the pawn instead.
+pub fn input(mut eng: EngineRef, cid: ClientId, velocity: V3) -> bool {
+    let now = eng.now();
+    let (stable_pid, motion) = match eng.extra().client_camera.get(&cid) {
+        Some(&Camera::Free(stable_pid, ref m)) => (stable_pid, m.clone()),
+        _ => return false,
+    };
+
+    let start = motion.pos(now);
+    let motion =
+        if velocity == scalar(0) {
+            Motion::stationary(start, now)
+        } else {
+            let dist = velocity * scalar(FREE_MOVE_DURATION as i32) / scalar(1000);
+            Motion {
+                start_time: now,
+                duration: FREE_MOVE_DURATION,
+                start_pos: start,
+                end_pos: start + dist,
+                arc: 0,
+            }
+        };
+    eng.extra_mut().client_camera.insert(cid, Camera::Free(stable_pid, motion.clone()));
+
+    let msg = ClientResponse::CameraUpdate(None, motion.clone());
+    eng.messages().send_client(cid, msg);
+    // TODO: using `with_hooks` here is gross, move schedule_view_update somewhere better
+    {
+        use world::fragment::Fragment;
+        eng.as_world_fragment().with_hooks(|h| h.schedule_client_view_update(cid, &motion));
+    }
+    true
+}
+
+/// Return the cameras following an entity to their clients' pawns, and get the affected
+/// clients.  Called when the entity is destroyed.  The caller is responsible for calling
+/// `follow_pawn` on each client afterward, to update its view.
+pub fn release_entity(extra: &mut Extra, eid: EntityId) -> Vec<ClientId> {
+    let cids = extra.client_camera.iter()
+                    .filter(|&(_, camera)| match *camera {
+                        Camera::Entity(target) => target == eid,
+                        _ => false,
+                    })
+                    .map(|(&cid, _)| cid)
+                    .collect::<Vec<_>>();
+    for cid in &cids {
+        extra.client_camera.remove(cid);
+    }
+    cids
+}
+
+/// Tell the client about its new camera, and move its view to match.
+fn refresh(eng: EngineRef, cid: ClientId) {
+    let msg = camera_message(eng.world(), eng.extra(), cid);
+    eng.messages().send_client(cid, msg);
+    logic::client::update_view(eng, cid);
+}
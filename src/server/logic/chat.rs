@@ -0,0 +1,407 @@
+//! Chat: channels, private whispers, ignore lists, mutes, and message history.
+//!
+//! Each client has a current channel, which receives its plain chat messages.  Messages on the
+//! `global` channel go to everyone, `local` messages go to players within `chat_local_range` tiles
+//! on the same plane, and `staff` messages go to staff members only.  Messages on the `party`
+//! channel go to the sender's party.  Whispers go to a single player, named by the sender.
+//!
+//! Messages typed by players pass through the script chat filters first, which can rewrite or
+//! drop them.  Messages sent by scripts are assumed to be filtered already.
+//!
+//! Ignore lists and mutes are keyed by player name, so they persist across logins.  They are saved
+//! in `save/chat.json`, along with the most recent global messages, which are replayed to clients
+//! when they log in.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+use rustc_serialize::json;
+
+use libphysics::TILE_SIZE;
+use types::*;
+use util::StrResult;
+
+use audit;
+use engine::split::EngineRef;
+use logic;
+use messages::ClientResponse;
+use script;
+use world::object::*;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    Global,
+    Local,
+    Party,
+    Staff,
+}
+
+impl Channel {
+    pub fn from_name(s: &str) -> Option<Channel> {
+        use self::Channel::*;
+        let c = match s {
+            "global" => Global,
+            "local" => Local,
+            "party" => Party,
+            "staff" => Staff,
+            _ => return None,
+        };
+        Some(c)
+    }
+
+    pub fn name(&self) -> &'static str {
+        use self::Channel::*;
+        match *self {
+            Global => "global",
+            Local => "local",
+            Party => "party",
+            Staff => "staff",
+        }
+    }
+}
+
+
+pub struct Chat {
+    /// Current channel of each client.  Clients not listed use the global channel.
+    channels: HashMap<ClientId, Channel>,
+    /// Clients who can use the staff channel.
+    staff: HashSet<ClientId>,
+    /// Names of the players each player is ignoring, by player name.
+    ignores: HashMap<String, HashSet<String>>,
+    /// Time when each muted player's mute expires, by player name.
+    mutes: HashMap<String, Time>,
+    /// Recent global messages, oldest first.
+    history: VecDeque<String>,
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct SavedChat {
+    ignores: Vec<(String, Vec<String>)>,
+    mutes: Vec<(String, Time)>,
+    history: Vec<String>,
+}
+
+impl Chat {
+    pub fn new() -> Chat {
+        Chat {
+            channels: HashMap::new(),
+            staff: HashSet::new(),
+            ignores: HashMap::new(),
+            mutes: HashMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn channel(&self, cid: ClientId) -> Channel {
+        self.channels.get(&cid).cloned().unwrap_or(Channel::Global)
+    }
+
+    pub fn is_staff(&self, cid: ClientId) -> bool {
+        self.staff.contains(&cid)
+    }
+
+    pub fn set_staff(&mut self, cid: ClientId, staff: bool) {
+        if staff {
+            self.staff.insert(cid);
+        } else {
+            self.staff.remove(&cid);
+            if self.channel(cid) == Channel::Staff {
+                self.channels.remove(&cid);
+            }
+        }
+    }
+
+    pub fn is_ignoring(&self, name: &str, other: &str) -> bool {
+        self.ignores.get(name).map_or(false, |names| names.contains(other))
+    }
+
+    /// Get the names of the players `name` is ignoring, in sorted order.
+    pub fn ignored(&self, name: &str) -> Vec<String> {
+        let mut names = self.ignores.get(name)
+                            .map_or(Vec::new(), |names| names.iter().cloned().collect());
+        names.sort();
+        names
+    }
+
+    /// Get the time when a player's mute expires, or `None` if the player is not muted.
+    pub fn mute_expiry(&self, name: &str, now: Time) -> Option<Time> {
+        self.mutes.get(name).cloned().and_then(|t| if t > now { Some(t) } else { None })
+    }
+
+    /// Forget a client's channel and staff status.  Called on logout.
+    pub fn remove_client(&mut self, cid: ClientId) {
+        self.channels.remove(&cid);
+        self.staff.remove(&cid);
+    }
+}
+
+
+pub fn load(mut eng: EngineRef) {
+    let mut file = unwrap_or!(eng.storage().open_chat_file());
+    let mut s = String::new();
+    if let Err(e) = file.read_to_string(&mut s) {
+        warn!("error reading chat state: {}", e);
+        return;
+    }
+    let saved = match json::decode::<SavedChat>(&s) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("bad chat state: {}", e);
+            return;
+        },
+    };
+
+    let chat = &mut eng.extra_mut().chat;
+    chat.ignores = saved.ignores.into_iter()
+                        .map(|(name, names)| (name, names.into_iter().collect()))
+                        .collect();
+    chat.mutes = saved.mutes.into_iter().collect();
+    chat.history = saved.history.into_iter().collect();
+}
+
+pub fn save(eng: EngineRef) {
+    let now = eng.now();
+    let saved = {
+        let chat = &eng.extra().chat;
+        SavedChat {
+            ignores: chat.ignores.iter()
+                         .filter(|&(_, names)| names.len() > 0)
+                         .map(|(name, names)| (name.clone(), names.iter().cloned().collect()))
+                         .collect(),
+            mutes: chat.mutes.iter()
+                       .filter(|&(_, &t)| t > now)
+                       .map(|(name, &t)| (name.clone(), t))
+                       .collect(),
+            history: chat.history.iter().cloned().collect(),
+        }
+    };
+    let s = unwrap_or!(json::encode(&saved).ok(),
+                       { warn!("error encoding chat state"); return });
+    let mut file = eng.storage().create_chat_file();
+    warn_on_err!(file.write_all(s.as_bytes()));
+}
+
+
+/// Send a server notice to a single client.
+pub fn notice(eng: &EngineRef, cid: ClientId, msg: &str) {
+    eng.messages().send_client(cid, ClientResponse::ChatUpdate(format!("***\t{}", msg)));
+}
+
+/// Replay the recent global messages to a client.  Called on login.
+pub fn send_history(eng: EngineRef, cid: ClientId) {
+    for line in &eng.extra().chat.history {
+        eng.messages().send_client(cid, ClientResponse::ChatUpdate(line.clone()));
+    }
+}
+
+/// Handle a plain chat message typed by a player: run the script chat filters, then send it on
+/// the player's current channel.
+pub fn chat(mut eng: EngineRef, cid: ClientId, msg: String) {
+    let channel = eng.extra().chat.channel(cid);
+    let result = script::ScriptEngine::cb_chat_filter(eng.borrow().unwrap(),
+                                                      cid,
+                                                      channel.name(),
+                                                      &msg);
+    let msg = match result {
+        Ok(Some(msg)) => msg,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("{}", e.msg);
+            msg
+        },
+    };
+    send(eng, cid, channel, &msg);
+}
+
+/// Check that a player may send `msg`.  Tells the player why not, if the message is rejected.
+fn check_message(eng: &EngineRef, cid: ClientId, name: &str, msg: &str) -> bool {
+    let max_len = eng.extra().config.chat_max_len;
+    if msg.chars().count() > max_len {
+        notice(eng, cid, &format!("Message is too long (the limit is {} characters)", max_len));
+        return false;
+    }
+
+    if let Some(expiry) = eng.extra().chat.mute_expiry(name, eng.now()) {
+        let mins = (expiry - eng.now() + 59999) / 60000;
+        notice(eng, cid, &format!("You are muted for another {} minute{}",
+                                  mins,
+                                  if mins != 1 { "s" } else { "" }));
+        return false;
+    }
+
+    true
+}
+
+/// Send a message from client `cid` on a channel.  Problems, like an oversized message or a
+/// channel the client can't use, are reported to the client.
+pub fn send(mut eng: EngineRef, cid: ClientId, channel: Channel, msg: &str) {
+    let name = unwrap_or!(eng.world().get_client(cid)).name().to_owned();
+    if !check_message(&eng, cid, &name, msg) {
+        return;
+    }
+
+    let recipients = match channel {
+        Channel::Global =>
+            eng.world().clients().map(|c| c.id()).collect::<Vec<_>>(),
+
+        Channel::Local => {
+            let now = eng.now();
+            let (pid, pos) = match eng.world().client(cid).pawn() {
+                Some(e) => (e.plane_id(), e.pos(now)),
+                None => {
+                    notice(&eng, cid, "You have no character to speak with");
+                    return;
+                },
+            };
+            let range = eng.extra().config.chat_local_range as i64 * TILE_SIZE as i64;
+            eng.world().clients().filter(|c| {
+                let e = unwrap_or!(c.pawn(), return false);
+                let d = e.pos(now) - pos;
+                e.plane_id() == pid &&
+                    d.x as i64 * d.x as i64 + d.y as i64 * d.y as i64 <= range * range
+            }).map(|c| c.id()).collect()
+        },
+
+        Channel::Party => match party_members(&eng, cid) {
+            Some(cids) => cids,
+            None => {
+                notice(&eng, cid, "You are not in a party");
+                return;
+            },
+        },
+
+        Channel::Staff => {
+            if !eng.extra().chat.is_staff(cid) {
+                notice(&eng, cid, "Only staff can use the staff channel");
+                return;
+            }
+            eng.extra().chat.staff.iter().cloned().collect()
+        },
+    };
+
+    let line =
+        if channel == Channel::Global {
+            format!("<{}>\t{}", name, msg)
+        } else {
+            format!("[{}] <{}>\t{}", channel.name(), name, msg)
+        };
+    deliver(&eng, &name, &recipients, &line);
+    logic::audit::record(eng.borrow(), audit::Kind::Chat, &name,
+                         &format!("[{}] {}", channel.name(), msg));
+
+    if channel == Channel::Global {
+        let len = eng.extra().config.chat_history_len;
+        let history = &mut eng.extra_mut().chat.history;
+        history.push_back(line);
+        while history.len() > len {
+            history.pop_front();
+        }
+    }
+}
+
+/// Send a line to each recipient, except those who are ignoring `sender`.
+fn deliver(eng: &EngineRef, sender: &str, recipients: &[ClientId], line: &str) {
+    for &cid in recipients {
+        let c = unwrap_or!(eng.world().get_client(cid), continue);
+        if eng.extra().chat.is_ignoring(c.name(), sender) {
+            continue;
+        }
+        eng.messages().send_client(cid, ClientResponse::ChatUpdate(line.to_owned()));
+    }
+}
+
+/// Get the clients in the same party as `cid`, or `None` if it's not in a party.
+fn party_members(eng: &EngineRef, cid: ClientId) -> Option<Vec<ClientId>> {
+    eng.extra().parties.client_party(cid).map(|p| p.members.clone())
+}
+
+fn find_client(eng: &EngineRef, name: &str) -> Option<ClientId> {
+    eng.world().clients().find(|c| c.name() == name).map(|c| c.id())
+}
+
+/// Send a private message from client `cid` to the player named `target`.
+pub fn whisper(mut eng: EngineRef, cid: ClientId, target: &str, msg: &str) {
+    let name = unwrap_or!(eng.world().get_client(cid)).name().to_owned();
+    if !check_message(&eng, cid, &name, msg) {
+        return;
+    }
+
+    let target_cid = match find_client(&eng, target) {
+        Some(x) => x,
+        None => {
+            notice(&eng, cid, &format!("No such player: {}", target));
+            return;
+        },
+    };
+
+    deliver(&eng, &name, &[target_cid], &format!("[from] <{}>\t{}", name, msg));
+    let echo = format!("[to] <{}>\t{}", target, msg);
+    eng.messages().send_client(cid, ClientResponse::ChatUpdate(echo));
+    logic::audit::record(eng.borrow(), audit::Kind::Chat, &name,
+                         &format!("[whisper to {}] {}", target, msg));
+}
+
+
+pub fn set_channel(mut eng: EngineRef, cid: ClientId, channel: Channel) -> StrResult<()> {
+    unwrap!(eng.world().get_client(cid));
+    if channel == Channel::Staff && !eng.extra().chat.is_staff(cid) {
+        fail!("only staff can use the staff channel");
+    }
+    eng.extra_mut().chat.channels.insert(cid, channel);
+    Ok(())
+}
+
+pub fn ignore(mut eng: EngineRef, cid: ClientId, other: &str) -> StrResult<()> {
+    let name = unwrap!(eng.world().get_client(cid)).name().to_owned();
+    if name == other {
+        fail!("can't ignore yourself");
+    }
+    eng.extra_mut().chat.ignores.entry(name).or_insert_with(HashSet::new)
+       .insert(other.to_owned());
+    save(eng);
+    Ok(())
+}
+
+pub fn unignore(mut eng: EngineRef, cid: ClientId, other: &str) -> StrResult<()> {
+    let name = unwrap!(eng.world().get_client(cid)).name().to_owned();
+    let removed = match eng.extra_mut().chat.ignores.get_mut(&name) {
+        Some(names) => names.remove(other),
+        None => false,
+    };
+    if !removed {
+        fail!("not ignoring that player");
+    }
+    save(eng);
+    Ok(())
+}
+
+/// Prevent the player named `name` from chatting for `duration` milliseconds.  Replaces any
+/// existing mute.
+pub fn mute(mut eng: EngineRef, name: &str, duration: u32) -> StrResult<()> {
+    if duration == 0 {
+        fail!("mute duration must be positive");
+    }
+    let expiry = eng.now() + duration as Time;
+    eng.extra_mut().chat.mutes.insert(name.to_owned(), expiry);
+    save(eng.borrow());
+
+    if let Some(cid) = find_client(&eng, name) {
+        let mins = (duration as Time + 59999) / 60000;
+        notice(&eng, cid, &format!("You have been muted for {} minute{}",
+                                   mins,
+                                   if mins != 1 { "s" } else { "" }));
+    }
+    Ok(())
+}
+
+pub fn unmute(mut eng: EngineRef, name: &str) -> StrResult<()> {
+    if eng.extra_mut().chat.mutes.remove(name).is_none() {
+        fail!("that player is not muted");
+    }
+    save(eng.borrow());
+
+    if let Some(cid) = find_client(&eng, name) {
+        notice(&eng, cid, "You are no longer muted");
+    }
+    Ok(())
+}
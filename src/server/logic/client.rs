@@ -1,32 +1,49 @@
 use std::borrow::ToOwned;
+use std::cmp;
 
 use types::*;
 use util::SmallVec;
 
+use audit;
 use chunks;
 use engine::glue::*;
 use engine::split::EngineRef;
 use logic;
 use messages::{ClientResponse, SyncKind};
+use logic::extra::Extra;
 use script;
 use world;
 use world::object::*;
 use world::save::{self, ObjectReader, ObjectWriter};
-use vision::{self, vision_region};
+use vision::{self, ViewShape};
 
 
-const DAY_NIGHT_CYCLE_TICKS: u32 = 24_000;
-const DAY_NIGHT_CYCLE_MS: u32 = 24 * 60 * 1000;
+/// Get the spawn point of a plane, falling back on a fixed position if the plane is not loaded or
+/// has no spawn point.
+pub fn default_spawn_point(w: &world::World, stable_pid: Stable<PlaneId>) -> V3 {
+    w.transient_plane_id(stable_pid)
+     .and_then(|pid| w.plane(pid).props().spawn_point)
+     .unwrap_or(V3::new(32, 32, 0))
+}
+
+/// Get the shape of a client's view, falling back on the default size if none was negotiated.
+pub fn view_shape(extra: &Extra, cid: ClientId) -> ViewShape {
+    match extra.client_view_shape.get(&cid) {
+        Some(&shape) => shape,
+        None => ViewShape::new(vision::DEFAULT_VIEW_SIZE, extra.config.entity_view_radius),
+    }
+}
 
 pub fn register(mut eng: EngineRef, name: &str, appearance: u32) -> save::Result<()> {
     let pawn_id;
     let cid;
 
+    let pid = STABLE_PLANE_FOREST;
+    let pos = default_spawn_point(eng.world(), pid);
+
     {
         let mut eng = eng.as_hidden_world_fragment();
 
-        let pos = V3::new(32, 32, 0);
-        let pid = STABLE_PLANE_FOREST;
         pawn_id = try!(world::Fragment::create_entity(&mut eng, pid, pos, 2, appearance)).id();
 
         cid = {
@@ -49,7 +66,12 @@ pub fn register(mut eng: EngineRef, name: &str, appearance: u32) -> save::Result
     Ok(())
 }
 
-pub fn login(mut eng: EngineRef, wire_id: WireId, name: &str) -> save::Result<()> {
+/// Log in a client.  `view_size` is the size of the view the client requested, in chunks.  The
+/// server may give it a smaller view.
+pub fn login(mut eng: EngineRef,
+             wire_id: WireId,
+             name: &str,
+             view_size: V2) -> save::Result<()> {
     let now = eng.now();
 
     if let Some(old_cid) = eng.messages().name_to_client(name) {
@@ -67,6 +89,29 @@ pub fn login(mut eng: EngineRef, wire_id: WireId, name: &str) -> save::Result<()
             fail!("client file not found");
         };
 
+    // A client who logged out inside an instance that has since been destroyed goes to the
+    // instance's return point.  If the instance has been forgotten too, they go to the spawn
+    // point instead.
+    let opt_pawn = eng.world().client(cid).pawn_id();
+    let opt_return = opt_pawn.and_then(|eid| {
+        let stable_pid = eng.world().entity(eid).stable_plane_id();
+        if let Some(point) = eng.extra().instances.retired_return_point(stable_pid) {
+            return Some(point);
+        }
+        if eng.world().transient_plane_id(stable_pid).is_none() &&
+           eng.storage().open_plane_file(stable_pid).is_none() {
+            return Some((STABLE_PLANE_FOREST,
+                         default_spawn_point(eng.world(), STABLE_PLANE_FOREST)));
+        }
+        None
+    });
+    if let (Some(eid), Some((stable_pid, pos))) = (opt_pawn, opt_return) {
+        let mut wf = eng.as_hidden_world_fragment();
+        let mut e = wf.entity_mut(eid);
+        warn_on_err!(e.set_stable_plane_id(stable_pid));
+        e.set_motion(world::Motion::stationary(pos, now));
+    }
+
     // Tell Vision about the client's entity (or entities).
     {
         let mut eids = SmallVec::new();
@@ -86,16 +131,21 @@ pub fn login(mut eng: EngineRef, wire_id: WireId, name: &str) -> save::Result<()
         }
     }
 
-    // Load the chunks the client can currently see.
-    let (pawn_stable_pid, center) = match eng.world().client(cid).pawn() {
-        Some(e) => (e.stable_plane_id(), e.pos(now)),
-        None => (STABLE_PLANE_LIMBO, scalar(0)),
+    // Choose the client's view size.
+    let shape = {
+        let config = &eng.extra().config;
+        let size = view_size.zip(config.max_view_size, |a, b| cmp::max(1, cmp::min(a, b)));
+        ViewShape::new(size, config.entity_view_radius)
     };
-    let region = vision::vision_region(center);
+    eng.extra_mut().client_view_shape.insert(cid, shape);
 
-    let pawn_pid = chunks::Fragment::get_plane_id(&mut eng.as_chunks_fragment(), pawn_stable_pid);
-    for cpos in region.points() {
-        logic::chunks::load_chunk(eng.borrow(), pawn_pid, cpos);
+    // Load the chunks the client can currently see.
+    let (stable_pid, motion) = logic::camera::focus(eng.world(), eng.extra(), cid).unwrap();
+    let view = shape.view(motion.pos(now));
+
+    let pid = chunks::Fragment::get_plane_id(&mut eng.as_chunks_fragment(), stable_pid);
+    for cpos in view.terrain.points() {
+        logic::chunks::load_chunk(eng.borrow(), pid, cpos);
     }
 
     // Set up the client to receive messages.
@@ -105,20 +155,42 @@ pub fn login(mut eng: EngineRef, wire_id: WireId, name: &str) -> save::Result<()
 
     // Send the client's startup messages.
     let opt_eid = eng.world().client(cid).pawn_id();
-    let cycle_base = (now % DAY_NIGHT_CYCLE_MS as Time) as u32;
+    let (cycle_base, cycle_ms) = {
+        let cycle = eng.extra().clock.cycle(stable_pid.unwrap());
+        (cycle.ms_of_day(now), cycle.day_ms)
+    };
     eng.messages_mut().send_client(cid, ClientResponse::Init(opt_eid,
                                                              now,
                                                              cycle_base,
-                                                             DAY_NIGHT_CYCLE_MS));
+                                                             cycle_ms));
 
-    vision::Fragment::add_client(&mut eng.as_vision_fragment(), cid, pawn_pid, region);
+    vision::Fragment::add_client(&mut eng.as_vision_fragment(), cid, pid, view);
+    logic::chat::send_history(eng.borrow(), cid);
+    logic::mail::login(eng.borrow(), cid);
     warn_on_err!(script::ScriptEngine::cb_login(eng.borrow().unwrap(), cid));
     eng.messages().send_client(cid, ClientResponse::SyncStatus(SyncKind::Ok));
 
+    logic::audit::record(eng.borrow(), audit::Kind::Login, name, &format!("{:?}", wire_id));
+
     Ok(())
 }
 
 pub fn logout(mut eng: EngineRef, cid: ClientId) -> save::Result<()> {
+    // Return any offered items, and put away the mailbox, before the client's inventories are
+    // saved.
+    logic::trade::cancel_client(eng.borrow(), cid);
+    logic::mail::logout(eng.borrow(), cid);
+    logic::party::remove_client(eng.borrow(), cid);
+
+    let name = eng.world().get_client(cid).map(|c| c.name().to_owned());
+    if let Some(name) = name {
+        let detail = match eng.messages().client_to_wire(cid) {
+            Some(wire_id) => format!("{:?}", wire_id),
+            None => String::new(),
+        };
+        logic::audit::record(eng.borrow(), audit::Kind::Logout, &name, &detail);
+    }
+
     eng.messages_mut().remove_client(cid);
 
     let old_region = eng.vision().client_view_area(cid);
@@ -129,6 +201,9 @@ pub fn logout(mut eng: EngineRef, cid: ClientId) -> save::Result<()> {
             logic::chunks::unload_chunk(eng.borrow(), old_pid, cpos);
         }
     }
+    eng.extra_mut().client_view_shape.remove(&cid);
+    eng.extra_mut().client_camera.remove(&cid);
+    eng.extra_mut().chat.remove_client(cid);
 
     {
         let (h, eng) = eng.borrow().0.split_off();
@@ -138,7 +213,7 @@ pub fn logout(mut eng: EngineRef, cid: ClientId) -> save::Result<()> {
         let mut sw = ObjectWriter::new(file, h);
         try!(sw.save_client(&c));
     }
-    try!(world::Fragment::destroy_client(&mut eng.as_world_fragment(), cid));
+    try!(world::Fragment::unload_client(&mut eng.as_world_fragment(), cid));
     Ok(())
 }
 
@@ -148,20 +223,14 @@ pub fn update_view(mut eng: EngineRef, cid: ClientId) {
     let old_region = unwrap_or!(eng.vision().client_view_area(cid));
     let old_pid = unwrap_or!(eng.vision().client_view_plane(cid));
 
-    let (new_stable_pid, new_region, pawn_id) = {
-        // TODO: warn on None? - may indicate inconsistency between World and Vision
-        let client = unwrap_or!(eng.world().get_client(cid));
-
-        // TODO: make sure return is the right thing to do on None
-        let pawn = unwrap_or!(client.pawn());
-
-        (pawn.stable_plane_id(),
-         vision::vision_region(pawn.pos(now)),
-         pawn.id())
-    };
+    let shape = view_shape(eng.extra(), cid);
+    // TODO: warn on None? - may indicate inconsistency between World and Vision
+    let (new_stable_pid, motion) = unwrap_or!(logic::camera::focus(eng.world(), eng.extra(), cid));
+    let new_view = shape.view(motion.pos(now));
     let new_pid = chunks::Fragment::get_plane_id(&mut eng.as_chunks_fragment(), new_stable_pid);
 
     let plane_change = new_pid != old_pid;
+    let new_region = new_view.terrain;
 
     // un/load_chunk use HiddenWorldFragment, so do the calls in this specific order to make sure
     // the chunks being un/loaded are actually not in the client's vision.
@@ -170,7 +239,7 @@ pub fn update_view(mut eng: EngineRef, cid: ClientId) {
         logic::chunks::load_chunk(eng.borrow(), new_pid, cpos);
     }
 
-    vision::Fragment::set_client_view(&mut eng.as_vision_fragment(), cid, new_pid, new_region);
+    vision::Fragment::set_client_view(&mut eng.as_vision_fragment(), cid, new_pid, new_view);
 
     for cpos in old_region.points().filter(|&p| !new_region.contains(p) || plane_change) {
         logic::chunks::unload_chunk(eng.borrow(), old_pid, cpos);
@@ -181,6 +250,6 @@ pub fn update_view(mut eng: EngineRef, cid: ClientId) {
     // TODO: using `with_hooks` here is gross, move schedule_view_update somewhere better
     {
         use world::fragment::Fragment;
-        eng.as_world_fragment().with_hooks(|h| h.schedule_view_update(pawn_id));
+        eng.as_world_fragment().with_hooks(|h| h.schedule_client_view_update(cid, &motion));
     }
 }
@@ -0,0 +1,294 @@
+//! World clock: the day/night cycle and the calendar.
+//!
+//! Time of day is measured in ticks, with `CYCLE_TICKS` ticks in each day, matching the client's
+//! `day_night` data.  The real-time length of a day can be configured for the whole world, and
+//! overridden for individual planes.  The world clock also counts days and seasons, and notifies
+//! scripts at dawn and dusk through the `dawn` and `dusk` world events.  Planes with their own
+//! cycle don't produce events.
+//!
+//! Changing the length of a day keeps the current day count and time of day, so clients see the
+//! sky change speed rather than jump.  The configuration is saved in `save/clock.json` whenever it
+//! changes.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use rustc_serialize::json;
+
+use types::*;
+use util::IntrusiveStableId;
+use util::StrResult;
+
+use engine::split::EngineRef;
+use logic;
+use logic::events::WorldEvent;
+use messages::ClientResponse;
+use timer;
+use world::object::*;
+
+
+/// Number of ticks in a full day.
+pub const CYCLE_TICKS: u32 = 24000;
+/// Tick at which the sun starts to rise.  Matches `night_end` in `gen_day_night.py`.
+pub const DAWN_TICK: u32 = 22000;
+/// Tick at which the sun starts to set.  Matches `day_end` in `gen_day_night.py`.
+pub const DUSK_TICK: u32 = 14000;
+
+pub const DEFAULT_DAY_MS: u32 = 24 * 60 * 1000;
+pub const DEFAULT_SEASON_DAYS: u32 = 7;
+
+pub const SEASONS: [&'static str; 4] = ["spring", "summer", "autumn", "winter"];
+
+
+/// A day/night cycle.
+#[derive(Clone, Copy, Debug, RustcEncodable, RustcDecodable)]
+pub struct Cycle {
+    /// Length of a full day, in milliseconds.
+    pub day_ms: u32,
+    /// World time at the start of day zero.
+    pub epoch: Time,
+}
+
+impl Cycle {
+    pub fn new(day_ms: u32) -> Cycle {
+        Cycle {
+            day_ms: day_ms,
+            epoch: 0,
+        }
+    }
+
+    /// Number of full days since the epoch.
+    pub fn day(&self, now: Time) -> i64 {
+        let elapsed = now - self.epoch;
+        let len = self.day_ms as Time;
+        if elapsed >= 0 {
+            elapsed / len
+        } else {
+            (elapsed - len + 1) / len
+        }
+    }
+
+    /// Milliseconds since the start of the current day.
+    pub fn ms_of_day(&self, now: Time) -> u32 {
+        (now - self.epoch - self.day(now) * self.day_ms as Time) as u32
+    }
+
+    /// Current time of day, in ticks.
+    pub fn tick(&self, now: Time) -> u32 {
+        (self.ms_of_day(now) as u64 * CYCLE_TICKS as u64 / self.day_ms as u64) as u32
+    }
+
+    pub fn is_day(&self, now: Time) -> bool {
+        let tick = self.tick(now);
+        tick < DUSK_TICK || tick >= DAWN_TICK
+    }
+
+    /// The first time after `now` when the cycle reaches `tick`.
+    pub fn next_tick(&self, now: Time, tick: u32) -> Time {
+        let day_start = now - self.ms_of_day(now) as Time;
+        // Round up, so that `self.tick(when) == tick`.
+        let offset = (tick as u64 * self.day_ms as u64 + CYCLE_TICKS as u64 - 1) /
+                     CYCLE_TICKS as u64;
+        let when = day_start + offset as Time;
+        if when > now {
+            when
+        } else {
+            when + self.day_ms as Time
+        }
+    }
+
+    /// Change the length of a day, keeping the current day count and time of day.
+    pub fn set_day_ms(&mut self, now: Time, day_ms: u32) {
+        let day = self.day(now);
+        let ms = self.ms_of_day(now) as u64 * day_ms as u64 / self.day_ms as u64;
+        self.day_ms = day_ms;
+        self.epoch = now - ms as Time - day * day_ms as Time;
+    }
+}
+
+
+pub struct Clock {
+    pub world: Cycle,
+    /// Number of days in each season.
+    pub season_days: u32,
+    /// Planes with their own day/night cycle, by stable plane ID.
+    pub overrides: HashMap<StableId, Cycle>,
+    /// Timer for the next `dawn` or `dusk` event.
+    timer: Option<timer::Cookie>,
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct SavedClock {
+    world: Cycle,
+    season_days: u32,
+    overrides: Vec<(StableId, Cycle)>,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            world: Cycle::new(DEFAULT_DAY_MS),
+            season_days: DEFAULT_SEASON_DAYS,
+            overrides: HashMap::new(),
+            timer: None,
+        }
+    }
+
+    /// Get the cycle that applies to a plane.
+    pub fn cycle(&self, stable_pid: StableId) -> &Cycle {
+        self.overrides.get(&stable_pid).unwrap_or(&self.world)
+    }
+
+    /// Index of the current season in `SEASONS`.
+    pub fn season(&self, now: Time) -> usize {
+        let day = self.world.day(now);
+        let len = self.season_days as i64 * SEASONS.len() as i64;
+        let day_of_year = ((day % len) + len) % len;
+        (day_of_year / self.season_days as i64) as usize
+    }
+}
+
+
+/// Read the clock configuration and schedule the first dawn or dusk event.
+pub fn load(mut eng: EngineRef) {
+    if let Some(mut file) = eng.storage().open_clock_file() {
+        let mut s = String::new();
+        match file.read_to_string(&mut s) {
+            Ok(_) => match json::decode::<SavedClock>(&s) {
+                Ok(saved) => {
+                    let clock = &mut eng.extra_mut().clock;
+                    clock.world = saved.world;
+                    clock.season_days = saved.season_days;
+                    clock.overrides = saved.overrides.into_iter().collect();
+                },
+                Err(e) => warn!("bad clock configuration: {}", e),
+            },
+            Err(e) => warn!("error reading clock configuration: {}", e),
+        }
+    }
+    schedule_event(eng);
+}
+
+fn save(eng: EngineRef) {
+    let saved = {
+        let clock = &eng.extra().clock;
+        SavedClock {
+            world: clock.world,
+            season_days: clock.season_days,
+            overrides: clock.overrides.iter().map(|(&k, &v)| (k, v)).collect(),
+        }
+    };
+    let s = unwrap_or!(json::encode(&saved).ok(),
+                       { warn!("error encoding clock configuration"); return });
+    let mut file = eng.storage().create_clock_file();
+    warn_on_err!(file.write_all(s.as_bytes()));
+}
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Event {
+    Dawn,
+    Dusk,
+}
+
+fn schedule_event(mut eng: EngineRef) {
+    if let Some(cookie) = eng.extra_mut().clock.timer.take() {
+        eng.timer_mut().cancel(cookie);
+    }
+
+    let now = eng.now();
+    let (when, evt) = {
+        let world = &eng.extra().clock.world;
+        let dawn = world.next_tick(now, DAWN_TICK);
+        let dusk = world.next_tick(now, DUSK_TICK);
+        if dawn < dusk { (dawn, Event::Dawn) } else { (dusk, Event::Dusk) }
+    };
+    let cookie = eng.timer_mut().schedule(when, move |eng| fire_event(eng, evt));
+    eng.extra_mut().clock.timer = Some(cookie);
+}
+
+fn fire_event(mut eng: EngineRef, evt: Event) {
+    eng.extra_mut().clock.timer = None;
+
+    let now = eng.now();
+    let day = eng.extra().clock.world.day(now) as u32;
+    let evt = match evt {
+        Event::Dawn => WorldEvent::Dawn(day),
+        Event::Dusk => WorldEvent::Dusk(day),
+    };
+    // Timer callbacks don't run inside a world operation, so the event can be delivered now.
+    eng.extra_mut().world_events.push(evt);
+    logic::events::dispatch(eng.borrow());
+
+    schedule_event(eng);
+}
+
+
+/// Get the stable ID of the plane containing the client's pawn, or limbo if it has no pawn.
+fn client_plane(eng: &EngineRef, cid: ClientId) -> StableId {
+    match eng.world().client(cid).pawn() {
+        Some(e) => e.stable_plane_id().unwrap(),
+        None => STABLE_PLANE_LIMBO.unwrap(),
+    }
+}
+
+/// Build the message describing the cycle of a plane.
+pub fn cycle_message(clock: &Clock, stable_pid: StableId, now: Time) -> ClientResponse {
+    let cycle = clock.cycle(stable_pid);
+    ClientResponse::DayNightCycle(now, cycle.ms_of_day(now), cycle.day_ms)
+}
+
+/// Send the current cycle to every client.
+fn send_all(eng: EngineRef) {
+    let now = eng.now();
+    for c in eng.world().clients() {
+        let stable_pid = client_plane(&eng, c.id());
+        let msg = cycle_message(&eng.extra().clock, stable_pid, now);
+        eng.messages().send_client(c.id(), msg);
+    }
+}
+
+
+pub fn set_day_length(mut eng: EngineRef, day_ms: u32) -> StrResult<()> {
+    if day_ms == 0 {
+        fail!("day length must be positive");
+    }
+    let now = eng.now();
+    eng.extra_mut().clock.world.set_day_ms(now, day_ms);
+    save(eng.borrow());
+    schedule_event(eng.borrow());
+    send_all(eng);
+    Ok(())
+}
+
+pub fn set_season_length(mut eng: EngineRef, days: u32) -> StrResult<()> {
+    if days == 0 {
+        fail!("season length must be positive");
+    }
+    eng.extra_mut().clock.season_days = days;
+    save(eng);
+    Ok(())
+}
+
+/// Give a plane its own day length, or return it to the world cycle if `day_ms` is `None`.  A new
+/// override starts at the world's current time of day.
+pub fn set_plane_day_length(mut eng: EngineRef,
+                            pid: PlaneId,
+                            day_ms: Option<u32>) -> StrResult<()> {
+    let stable_pid = unwrap!(eng.world().get_plane(pid)).get_stable_id();
+    let now = eng.now();
+    match day_ms {
+        Some(0) => fail!("day length must be positive"),
+        Some(day_ms) => {
+            let clock = &mut eng.extra_mut().clock;
+            let mut cycle = *clock.cycle(stable_pid);
+            cycle.set_day_ms(now, day_ms);
+            clock.overrides.insert(stable_pid, cycle);
+        },
+        None => {
+            eng.extra_mut().clock.overrides.remove(&stable_pid);
+        },
+    }
+    save(eng.borrow());
+    send_all(eng);
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Server configuration, read from `config.json` at startup.  Every setting is optional, and the
+//! file itself may be missing.
+
+use std::cmp;
+use std::io::Read;
+use rustc_serialize::json;
+
+use types::*;
+
+use engine::split::EngineRef;
+
+
+/// Largest view the client can display without confusing chunks and entities on opposite edges.
+/// The client keeps an 8x8 ring of chunks, centered on the camera.
+pub const DEFAULT_MAX_VIEW_SIZE: V2 = V2 { x: 7, y: 7 };
+pub const DEFAULT_ENTITY_VIEW_RADIUS: i32 = 3;
+/// Largest entity view radius that fits in the client's 8x8 ring of chunks.  Like
+/// `DEFAULT_MAX_VIEW_SIZE`, this leaves a 7x7 area around the pawn's chunk.
+pub const MAX_ENTITY_VIEW_RADIUS: i32 = 3;
+pub const DEFAULT_CHAT_MAX_LEN: usize = 400;
+pub const DEFAULT_CHAT_HISTORY_LEN: usize = 50;
+pub const DEFAULT_CHAT_LOCAL_RANGE: i32 = 32;
+pub const DEFAULT_AUDIT_RETENTION_DAYS: u32 = 90;
+
+
+pub struct Config {
+    /// Upper limit on the view size a client can request, in chunks.
+    pub max_view_size: V2,
+    /// Radius, in chunks, in which clients can see entities outside their terrain view.
+    pub entity_view_radius: i32,
+    /// Longest chat message a client can send, in characters.
+    pub chat_max_len: usize,
+    /// Number of global chat messages to keep and send to clients on login.
+    pub chat_history_len: usize,
+    /// Distance, in tiles, that local chat messages can be heard.
+    pub chat_local_range: i32,
+    /// Number of days to keep audit log entries.  Zero means entries are kept forever.
+    pub audit_retention_days: u32,
+}
+
+#[derive(RustcDecodable)]
+struct RawConfig {
+    max_view_size: Option<(i32, i32)>,
+    entity_view_radius: Option<i32>,
+    chat_max_len: Option<usize>,
+    chat_history_len: Option<usize>,
+    chat_local_range: Option<i32>,
+    audit_retention_days: Option<u32>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            max_view_size: DEFAULT_MAX_VIEW_SIZE,
+            entity_view_radius: DEFAULT_ENTITY_VIEW_RADIUS,
+            chat_max_len: DEFAULT_CHAT_MAX_LEN,
+            chat_history_len: DEFAULT_CHAT_HISTORY_LEN,
+            chat_local_range: DEFAULT_CHAT_LOCAL_RANGE,
+            audit_retention_days: DEFAULT_AUDIT_RETENTION_DAYS,
+        }
+    }
+}
+
+
+pub fn load(mut eng: EngineRef) {
+    let mut file = unwrap_or!(eng.storage().open_config_file());
+    let mut s = String::new();
+    if let Err(e) = file.read_to_string(&mut s) {
+        warn!("error reading server configuration: {}", e);
+        return;
+    }
+    let raw = match json::decode::<RawConfig>(&s) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("bad server configuration: {}", e);
+            return;
+        },
+    };
+
+    let config = &mut eng.extra_mut().config;
+    if let Some((x, y)) = raw.max_view_size {
+        config.max_view_size = V2::new(cmp::max(x, 1), cmp::max(y, 1));
+    }
+    if let Some(r) = raw.entity_view_radius {
+        let clamped = cmp::max(0, cmp::min(r, MAX_ENTITY_VIEW_RADIUS));
+        if clamped != r {
+            warn!("entity_view_radius {} is out of range; using {}", r, clamped);
+        }
+        config.entity_view_radius = clamped;
+    }
+    if let Some(len) = raw.chat_max_len {
+        config.chat_max_len = len;
+    }
+    if let Some(len) = raw.chat_history_len {
+        config.chat_history_len = len;
+    }
+    if let Some(range) = raw.chat_local_range {
+        config.chat_local_range = range;
+    }
+    if let Some(days) = raw.audit_retention_days {
+        config.audit_retention_days = days;
+    }
+}
@@ -0,0 +1,140 @@
+//! World events delivered to scripts.
+//!
+//! World hooks run while a `world::Fragment` is borrowed, so calling into scripts directly from a
+//! hook would let script code re-enter the `World` in the middle of an operation.  Instead, the
+//! hooks queue events here, and a timer delivers the whole batch to scripts once the current
+//! operation has finished.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use types::*;
+
+use engine::split::EngineRef;
+use script::ScriptEngine;
+
+
+#[derive(Clone, Copy, Debug)]
+pub enum WorldEvent {
+    StructureCreate(StructureId),
+    StructureDestroy(StructureId, PlaneId, Region),
+    StructureReplace(StructureId),
+    InventoryUpdate(InventoryId, ItemId, u16, u16),
+    EntityMotion(EntityId),
+    TerrainChunkCreate(PlaneId, V2),
+    /// The sun started to rise on the given day of the world clock.
+    Dawn(u32),
+    /// The sun started to set on the given day of the world clock.
+    Dusk(u32),
+}
+
+impl WorldEvent {
+    /// The name scripts use to subscribe to this kind of event.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            WorldEvent::StructureCreate(..) => "structure_create",
+            WorldEvent::StructureDestroy(..) => "structure_destroy",
+            WorldEvent::StructureReplace(..) => "structure_replace",
+            WorldEvent::InventoryUpdate(..) => "inventory_update",
+            WorldEvent::EntityMotion(..) => "entity_motion",
+            WorldEvent::TerrainChunkCreate(..) => "terrain_chunk_create",
+            WorldEvent::Dawn(..) => "dawn",
+            WorldEvent::Dusk(..) => "dusk",
+        }
+    }
+}
+
+pub struct WorldEvents {
+    queue: Vec<WorldEvent>,
+    /// Entities that already have an `EntityMotion` event in the queue.  Scripts only need to
+    /// see the final motion of each entity in a batch.
+    moved_entities: HashSet<EntityId>,
+    /// Number of script handlers for each event name.  Events without handlers are never
+    /// queued, so common changes like entity motion don't call into scripts for nothing.
+    subscribers: HashMap<String, u32>,
+}
+
+impl WorldEvents {
+    pub fn new() -> WorldEvents {
+        WorldEvents {
+            queue: Vec::new(),
+            moved_entities: HashSet::new(),
+            subscribers: HashMap::new(),
+        }
+    }
+
+    pub fn set_subscribers(&mut self, name: &str, count: u32) {
+        if count == 0 {
+            self.subscribers.remove(name);
+        } else {
+            self.subscribers.insert(name.to_owned(), count);
+        }
+    }
+
+    /// Forget all subscriber counts.  Used when the script state is replaced.
+    pub fn clear_subscribers(&mut self) {
+        self.subscribers.clear();
+    }
+
+    /// Add an event to the queue.  Returns `true` if the queue was previously empty, meaning the
+    /// caller must schedule a call to `dispatch`.  Events that no script handles are dropped.
+    pub fn push(&mut self, evt: WorldEvent) -> bool {
+        if !self.subscribers.contains_key(evt.name()) {
+            return false;
+        }
+
+        let was_empty = self.queue.is_empty();
+
+        match evt {
+            WorldEvent::EntityMotion(eid) => {
+                if !self.moved_entities.insert(eid) {
+                    return false;
+                }
+            },
+            WorldEvent::InventoryUpdate(iid, item_id, _, new_count) => {
+                // Merge consecutive updates to the same item.
+                if let Some(&mut WorldEvent::InventoryUpdate(last_iid, last_item_id, _,
+                                                             ref mut last_count)) =
+                        self.queue.last_mut() {
+                    if last_iid == iid && last_item_id == item_id {
+                        *last_count = new_count;
+                        return false;
+                    }
+                }
+            },
+            _ => {},
+        }
+
+        self.queue.push(evt);
+        was_empty
+    }
+
+    fn take(&mut self) -> Vec<WorldEvent> {
+        self.moved_entities.clear();
+        mem::replace(&mut self.queue, Vec::new())
+    }
+}
+
+
+/// Deliver all queued events to scripts.  Events about objects that have been destroyed since the
+/// event was queued are dropped.
+pub fn dispatch(mut eng: EngineRef) {
+    let events = eng.extra_mut().world_events.take();
+    let events = events.into_iter().filter(|evt| {
+        let w = eng.world();
+        match *evt {
+            WorldEvent::StructureCreate(sid) |
+            WorldEvent::StructureReplace(sid) => w.get_structure(sid).is_some(),
+            WorldEvent::StructureDestroy(..) => true,
+            WorldEvent::InventoryUpdate(iid, _, _, _) => w.get_inventory(iid).is_some(),
+            WorldEvent::EntityMotion(eid) => w.get_entity(eid).is_some(),
+            WorldEvent::TerrainChunkCreate(pid, _) => w.get_plane(pid).is_some(),
+            WorldEvent::Dawn(_) |
+            WorldEvent::Dusk(_) => true,
+        }
+    }).collect::<Vec<_>>();
+
+    if events.len() > 0 {
+        ScriptEngine::cb_world_events(eng.unwrap(), &events);
+    }
+}
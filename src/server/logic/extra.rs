@@ -2,19 +2,54 @@ use std::collections::HashMap;
 
 use types::*;
 
+use logic::camera::Camera;
+use logic::chat::Chat;
+use logic::clock::Clock;
+use logic::config::Config;
+use logic::events::WorldEvents;
+use logic::instance::Instances;
+use logic::journal::Journal;
+use logic::mail::Mail;
+use logic::party::Parties;
+use logic::trade::Trades;
 use timer;
+use vision::ViewShape;
 
 
 pub struct Extra {
     pub client_view_update_timer: HashMap<ClientId, timer::Cookie>,
+    pub client_view_shape: HashMap<ClientId, ViewShape>,
+    pub client_camera: HashMap<ClientId, Camera>,
     pub entity_physics_update_timer: HashMap<EntityId, timer::Cookie>,
+    pub structure_crafting_timer: HashMap<StructureId, timer::Cookie>,
+    pub trades: Trades,
+    pub world_events: WorldEvents,
+    pub journal: Journal,
+    pub clock: Clock,
+    pub chat: Chat,
+    pub parties: Parties,
+    pub instances: Instances,
+    pub mail: Mail,
+    pub config: Config,
 }
 
 impl Extra {
     pub fn new() -> Extra {
         Extra {
             client_view_update_timer: HashMap::new(),
+            client_view_shape: HashMap::new(),
+            client_camera: HashMap::new(),
             entity_physics_update_timer: HashMap::new(),
+            structure_crafting_timer: HashMap::new(),
+            trades: Trades::new(),
+            world_events: WorldEvents::new(),
+            journal: Journal::new(),
+            clock: Clock::new(),
+            chat: Chat::new(),
+            parties: Parties::new(),
+            instances: Instances::new(),
+            mail: Mail::new(),
+            config: Config::new(),
         }
     }
 }
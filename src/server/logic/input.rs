@@ -1,8 +1,8 @@
 use types::*;
 
 use engine::split::EngineRef;
-use input::{InputBits};
-use messages::ClientResponse;
+use input::{InputBits, INPUT_JUMP};
+use logic;
 use msg::ExtraArg;
 use physics;
 use script;
@@ -14,9 +14,15 @@ pub fn input(mut eng: EngineRef, cid: ClientId, input: InputBits) {
     let now = eng.now();
 
     let target_velocity = input.to_velocity();
+    if logic::camera::input(eng.borrow(), cid, target_velocity) {
+        return;
+    }
     if let Some(eid) = eng.world().get_client(cid).and_then(|c| c.pawn_id()) {
         warn_on_err!(physics::Fragment::set_velocity(
                 &mut eng.as_physics_fragment(), now, eid, target_velocity));
+        if input.contains(INPUT_JUMP) {
+            warn_on_err!(physics::Fragment::jump(&mut eng.as_physics_fragment(), now, eid));
+        }
     }
 }
 
@@ -43,25 +49,10 @@ pub fn unsubscribe_inventory(mut eng: EngineRef, cid: ClientId, iid: InventoryId
 
 
 
-pub fn chat(mut eng: EngineRef, cid: ClientId, msg: String) {
-    // TODO: move this into a script
-    if &*msg == "/count" {
-        let count = eng.messages().clients_len();
-        let msg_out = format!("***\t{} player{} online",
-                              count,
-                              if count != 1 { "s" } else { "" });
-        eng.messages_mut().send_client(cid, ClientResponse::ChatUpdate(msg_out));
-    } else if msg.starts_with("/") {
+pub fn chat(eng: EngineRef, cid: ClientId, msg: String) {
+    if msg.starts_with("/") {
         warn_on_err!(script::ScriptEngine::cb_chat_command(eng.unwrap(), cid, &*msg));
     } else {
-        if msg.len() > 400 {
-            warn!("{:?}: bad request: chat message too long ({})", cid, msg.len());
-            return;
-        }
-
-        let msg_out = format!("<{}>\t{}",
-                              eng.world().client(cid).name(),
-                              msg);
-        eng.messages_mut().broadcast_clients(ClientResponse::ChatUpdate(msg_out));
+        logic::chat::chat(eng, cid, msg);
     }
 }
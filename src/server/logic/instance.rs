@@ -0,0 +1,402 @@
+//! Instanced planes: temporary planes, created on demand and destroyed once they have been empty
+//! for a while.
+//!
+//! An instance copies the properties (including the terrain generator and seed) of a template
+//! plane, or is created directly from a terrain generator.  Each instance has a time-to-live and a
+//! return point.  Once no client has been inside the instance for longer than its TTL, the
+//! instance is destroyed: entities left inside it, or waiting in limbo to enter it, are moved to
+//! the return point, and its plane file, terrain chunk files, and terrain generator summaries are
+//! deleted.
+//!
+//! Destroyed instances are remembered along with their return points for `RETIRED_TTL`, so that
+//! players who logged out inside an instance are moved to its return point when they log back in.
+//! An instance may return to another instance, so when an instance is destroyed, the instances
+//! (live or destroyed) that return to it are given its return point instead.  The instance table
+//! is saved in `save/instances.json`.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+use rustc_serialize::json;
+
+use types::*;
+use util::StrResult;
+
+use chunks;
+use engine::glue::*;
+use engine::split::EngineRef;
+use logic;
+use terrain_gen;
+use timer;
+use world;
+use world::{Fragment, PlaneProps};
+use world::object::*;
+
+
+/// Interval between checks for expired instances.
+const CHECK_INTERVAL: Time = 60 * 1000;
+
+/// How long to remember the return points of destroyed instances.
+const RETIRED_TTL: Time = 90 * 24 * 60 * 60 * 1000;
+
+
+pub struct Instance {
+    pub return_plane: Stable<PlaneId>,
+    pub return_pos: V3,
+    pub ttl: Time,
+    /// Time when the last client left the instance, or `None` if a client is inside.
+    pub empty_since: Option<Time>,
+}
+
+pub struct Instances {
+    instances: HashMap<Stable<PlaneId>, Instance>,
+    /// Return points of destroyed instances, and the time each was destroyed.
+    retired: HashMap<Stable<PlaneId>, (Stable<PlaneId>, V3, Time)>,
+    check_timer: Option<timer::Cookie>,
+}
+
+impl Instances {
+    pub fn new() -> Instances {
+        Instances {
+            instances: HashMap::new(),
+            retired: HashMap::new(),
+            check_timer: None,
+        }
+    }
+
+    pub fn is_instance(&self, stable_pid: Stable<PlaneId>) -> bool {
+        self.instances.contains_key(&stable_pid)
+    }
+
+    /// Check if `stable_pid` is a destroyed instance.
+    pub fn is_retired(&self, stable_pid: Stable<PlaneId>) -> bool {
+        self.retired.contains_key(&stable_pid)
+    }
+
+    /// Get the return point of a destroyed instance.
+    pub fn retired_return_point(&self,
+                                stable_pid: Stable<PlaneId>) -> Option<(Stable<PlaneId>, V3)> {
+        let &(return_plane, return_pos, _) = unwrap_or!(self.retired.get(&stable_pid),
+                                                        return None);
+        Some(self.follow_retired(return_plane, return_pos))
+    }
+
+    /// Follow a return point through any destroyed instances to one on a plane that still
+    /// exists.  `retire` keeps return points from pointing at destroyed instances, so this is only
+    /// a safeguard against a damaged instance table.
+    fn follow_retired(&self,
+                      return_plane: Stable<PlaneId>,
+                      return_pos: V3) -> (Stable<PlaneId>, V3) {
+        let mut cur = (return_plane, return_pos);
+        // Bound the walk in case the saved table contains a cycle.
+        for _ in 0 .. self.retired.len() {
+            match self.retired.get(&cur.0) {
+                Some(&(plane, pos, _)) => cur = (plane, pos),
+                None => break,
+            }
+        }
+        cur
+    }
+
+    /// Record that instance `stable_pid` was destroyed at `now`, and send anything that would
+    /// have returned to it to its return point instead.
+    fn retire(&mut self,
+              stable_pid: Stable<PlaneId>,
+              return_plane: Stable<PlaneId>,
+              return_pos: V3,
+              now: Time) {
+        for inst in self.instances.values_mut() {
+            if inst.return_plane == stable_pid {
+                inst.return_plane = return_plane;
+                inst.return_pos = return_pos;
+            }
+        }
+        for entry in self.retired.values_mut() {
+            if entry.0 == stable_pid {
+                entry.0 = return_plane;
+                entry.1 = return_pos;
+            }
+        }
+        self.retired.insert(stable_pid, (return_plane, return_pos, now));
+    }
+
+    /// Forget destroyed instances whose return points have been kept for `RETIRED_TTL`.
+    fn prune_retired(&mut self, now: Time) {
+        let expired = self.retired.iter()
+                          .filter(|&(_, &(_, _, time))| now >= time + RETIRED_TTL)
+                          .map(|(&id, _)| id)
+                          .collect::<Vec<_>>();
+        for id in expired {
+            self.retired.remove(&id);
+        }
+    }
+}
+
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct SavedInstance {
+    plane: StableId,
+    return_plane: StableId,
+    return_pos: (i32, i32, i32),
+    ttl: Time,
+    empty_since: Option<Time>,
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct SavedInstances {
+    instances: Vec<SavedInstance>,
+    retired: Vec<(StableId, StableId, (i32, i32, i32), Time)>,
+}
+
+pub fn load(mut eng: EngineRef) {
+    {
+        let mut file = unwrap_or!(eng.storage().open_instances_file());
+        let mut s = String::new();
+        if let Err(e) = file.read_to_string(&mut s) {
+            warn!("error reading instance table: {}", e);
+            return;
+        }
+        let saved = match json::decode::<SavedInstances>(&s) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("bad instance table: {}", e);
+                return;
+            },
+        };
+
+        let instances = &mut eng.extra_mut().instances;
+        for i in saved.instances {
+            let (x, y, z) = i.return_pos;
+            instances.instances.insert(Stable::new(i.plane), Instance {
+                return_plane: Stable::new(i.return_plane),
+                return_pos: V3::new(x, y, z),
+                ttl: i.ttl,
+                empty_since: i.empty_since,
+            });
+        }
+        for (plane, return_plane, (x, y, z), time) in saved.retired {
+            instances.retired.insert(Stable::new(plane),
+                                     (Stable::new(return_plane), V3::new(x, y, z), time));
+        }
+    }
+    let now = eng.now();
+    eng.extra_mut().instances.prune_retired(now);
+    schedule_check(eng);
+}
+
+pub fn save(eng: EngineRef) {
+    let saved = {
+        let instances = &eng.extra().instances;
+        SavedInstances {
+            instances: instances.instances.iter().map(|(&id, i)| SavedInstance {
+                plane: id.unwrap(),
+                return_plane: i.return_plane.unwrap(),
+                return_pos: (i.return_pos.x, i.return_pos.y, i.return_pos.z),
+                ttl: i.ttl,
+                empty_since: i.empty_since,
+            }).collect(),
+            retired: instances.retired.iter().map(|(&id, &(return_plane, pos, time))| {
+                (id.unwrap(), return_plane.unwrap(), (pos.x, pos.y, pos.z), time)
+            }).collect(),
+        }
+    };
+    let s = unwrap_or!(json::encode(&saved).ok(),
+                       { warn!("error encoding instance table"); return });
+    let mut file = eng.storage().create_instances_file();
+    warn_on_err!(file.write_all(s.as_bytes()));
+}
+
+
+/// Create an instance with the given properties.  The instance is destroyed once it has been
+/// empty for `ttl` milliseconds.  Anything left inside is then moved to `return_pos` on
+/// `return_plane`.  If `return_plane` is itself an instance and is destroyed first, its own
+/// return point is used instead.
+pub fn create(mut eng: EngineRef,
+              name: String,
+              props: PlaneProps,
+              ttl: Time,
+              return_plane: Stable<PlaneId>,
+              return_pos: V3) -> StrResult<Stable<PlaneId>> {
+    if eng.extra().instances.is_retired(return_plane) {
+        fail!("the return plane no longer exists");
+    }
+
+    let stable_pid = {
+        let mut wf = eng.as_world_fragment();
+        let mut p = try!(wf.create_plane(name));
+        p.set_props(props);
+        p.stable_id()
+    };
+
+    let now = eng.now();
+    eng.extra_mut().instances.instances.insert(stable_pid, Instance {
+        return_plane: return_plane,
+        return_pos: return_pos,
+        ttl: ttl,
+        empty_since: Some(now),
+    });
+    save(eng.borrow());
+    schedule_check(eng);
+    Ok(stable_pid)
+}
+
+/// Send everything inside an instance to its return point, and destroy the instance at the next
+/// check.
+pub fn close(mut eng: EngineRef, stable_pid: Stable<PlaneId>) -> StrResult<()> {
+    {
+        let inst = unwrap!(eng.extra_mut().instances.instances.get_mut(&stable_pid),
+                           "not an instance");
+        inst.ttl = 0;
+    }
+    evict(eng, stable_pid)
+}
+
+/// Move every entity in an instance, or waiting in limbo to enter it, to its return point.  Fails
+/// if any of them couldn't be moved.
+fn evict(mut eng: EngineRef, stable_pid: Stable<PlaneId>) -> StrResult<()> {
+    let (return_plane, return_pos) = {
+        let instances = &eng.extra().instances;
+        let inst = unwrap!(instances.instances.get(&stable_pid), "not an instance");
+        instances.follow_retired(inst.return_plane, inst.return_pos)
+    };
+
+    let eids = eng.world().entities()
+                  .filter(|e| e.stable_plane_id() == stable_pid)
+                  .map(|e| e.id())
+                  .collect::<Vec<_>>();
+    let mut failed = 0;
+    for eid in eids {
+        let result = logic::world::teleport_entity_stable_plane(eng.as_world_fragment(),
+                                                                eid,
+                                                                return_plane,
+                                                                return_pos);
+        if let Err(e) = result {
+            warn!("can't move {:?} out of instance {:?}: {}", eid, stable_pid, e.msg);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        fail!("{} entities could not be moved out of the instance", failed);
+    }
+    Ok(())
+}
+
+/// Destroy an instance and delete its files.  The instance must not have any terrain chunks
+/// loaded.  If anything inside can't be moved to the return point, the instance is left alone so
+/// the next check can try again.
+fn destroy(mut eng: EngineRef, stable_pid: Stable<PlaneId>) -> StrResult<()> {
+    let (return_plane, return_pos) = {
+        let instances = &eng.extra().instances;
+        let inst = unwrap!(instances.instances.get(&stable_pid), "not an instance");
+        instances.follow_retired(inst.return_plane, inst.return_pos)
+    };
+
+    // Load the plane, if necessary, to find its saved terrain chunks.  This also brings its limbo
+    // entities onto the plane.
+    let loaded_pid = eng.world().transient_plane_id(stable_pid);
+    let saved = eng.storage().open_plane_file(stable_pid).is_some();
+    let opt_pid =
+        if loaded_pid.is_some() {
+            loaded_pid
+        } else if saved {
+            Some(chunks::Fragment::get_plane_id(&mut eng.as_chunks_fragment(), stable_pid))
+        } else {
+            None
+        };
+
+    if let Some(pid) = opt_pid {
+        if eng.world().plane(pid).loaded_terrain_chunk_count() > 0 {
+            fail!("instance still has terrain loaded");
+        }
+    }
+
+    try!(evict(eng.borrow(), stable_pid));
+
+    if let Some(pid) = opt_pid {
+        let stable_tcids = eng.world().plane(pid).saved_terrain_chunk_ids();
+        try!(world::Fragment::destroy_plane(&mut eng.as_hidden_world_fragment(), pid));
+        for stable_tcid in stable_tcids {
+            eng.storage().remove_terrain_chunk_file(stable_tcid);
+        }
+    }
+    eng.storage().remove_plane_file(stable_pid);
+    terrain_gen::Fragment::forget_plane(&mut eng.as_terrain_gen_fragment(), stable_pid);
+
+    eng.extra_mut().clock.overrides.remove(&stable_pid.unwrap());
+    logic::party::forget_instance(eng.borrow(), stable_pid);
+    let now = eng.now();
+    {
+        let instances = &mut eng.extra_mut().instances;
+        instances.instances.remove(&stable_pid);
+        instances.retire(stable_pid, return_plane, return_pos, now);
+    }
+    info!("destroyed instance {:?}", stable_pid);
+    Ok(())
+}
+
+
+fn schedule_check(mut eng: EngineRef) {
+    if eng.extra().instances.check_timer.is_some() ||
+       eng.extra().instances.instances.len() == 0 {
+        return;
+    }
+    let when = eng.now() + CHECK_INTERVAL;
+    let cookie = eng.timer_mut().schedule(when, |eng| check(eng));
+    eng.extra_mut().instances.check_timer = Some(cookie);
+}
+
+/// Update the occupancy of each instance, and destroy those that have been empty for too long.
+fn check(mut eng: EngineRef) {
+    eng.extra_mut().instances.check_timer = None;
+    let now = eng.now();
+
+    // An instance is occupied if a client's pawn or camera is inside it, or if anything else is
+    // keeping its terrain loaded.
+    let mut occupied = HashSet::new();
+    for c in eng.world().clients() {
+        if let Some(e) = c.pawn() {
+            occupied.insert(e.stable_plane_id());
+        }
+        // The camera may be following another entity, or moving freely.
+        if let Some((stable_pid, _)) = logic::camera::focus(eng.world(), eng.extra(), c.id()) {
+            occupied.insert(stable_pid);
+        }
+    }
+    let ids = eng.extra().instances.instances.keys().cloned().collect::<Vec<_>>();
+    for stable_pid in ids {
+        let pid = unwrap_or!(eng.world().transient_plane_id(stable_pid), continue);
+        if eng.world().plane(pid).loaded_terrain_chunk_count() > 0 {
+            occupied.insert(stable_pid);
+        }
+    }
+
+    let mut expired = Vec::new();
+    for (&stable_pid, inst) in eng.extra_mut().instances.instances.iter_mut() {
+        if occupied.contains(&stable_pid) {
+            inst.empty_since = None;
+            continue;
+        }
+        let empty_since = match inst.empty_since {
+            Some(t) => t,
+            None => {
+                inst.empty_since = Some(now);
+                now
+            },
+        };
+        if now >= empty_since + inst.ttl {
+            expired.push(stable_pid);
+        }
+    }
+
+    let retired_count = eng.extra().instances.retired.len();
+    eng.extra_mut().instances.prune_retired(now);
+    let pruned = eng.extra().instances.retired.len() != retired_count;
+
+    if expired.len() > 0 || pruned {
+        for stable_pid in expired {
+            warn_on_err!(destroy(eng.borrow(), stable_pid));
+        }
+        save(eng.borrow());
+    }
+    schedule_check(eng);
+}
@@ -1,12 +1,16 @@
 use std::cmp;
-use std::u8;
 
+use libphysics::TILE_SIZE;
 use types::*;
 use util::StrResult;
 
+use data::Recipe;
+
+use engine::glue::WorldFragment;
 use engine::split::EngineRef;
+use logic;
 use messages::{ClientResponse, Dialog};
-use world;
+use world::{self, CraftingJob};
 use world::object::*;
 use vision;
 
@@ -51,6 +55,9 @@ pub fn open_crafting(mut eng: EngineRef,
         s.template_id()
     };
 
+    // Deliver any finished jobs that were waiting for the client's inventory to be loaded.
+    try!(finish_crafting(eng.borrow(), sid));
+
     let dialog = Dialog::Crafting(template_id, sid, iid);
     eng.messages_mut().send_client(cid, ClientResponse::OpenDialog(dialog));
     vision::Fragment::subscribe_inventory(&mut eng.as_vision_fragment(), cid, iid);
@@ -77,61 +84,233 @@ pub fn set_main_inventories(mut eng: EngineRef,
 
 
 pub fn move_items(mut eng: EngineRef,
+                  cid: ClientId,
                   from_iid: InventoryId,
                   to_iid: InventoryId,
                   item_id: ItemId,
                   count: u16) -> StrResult<()> {
-    let real_count = {
+    try!(logic::trade::check_move(&eng, cid, from_iid, to_iid));
+    try!(logic::mail::check_move(&eng, cid, from_iid, to_iid));
+
+    let (real_count, keys) = {
         let world = eng.world();
         let i1 = unwrap!(world.get_inventory(from_iid));
         let i2 = unwrap!(world.get_inventory(to_iid));
-        let count1 = i1.count(item_id);
-        let count2 = i2.count(item_id);
-        cmp::min(cmp::min(count1 as u16, (u8::MAX - count2) as u16), count) as i16
+        let (plain, keys) = i1.split_move(item_id, count);
+        if plain == 0 && keys.len() == 0 {
+            fail!("no items to move");
+        }
+        if plain > 0 {
+            // Report the reason if not even one item fits.  Otherwise, move as many as possible.
+            try!(i2.check_space(item_id, 1));
+        }
+        (cmp::min(plain, i2.space_for(item_id)) as i32, keys)
     };
     if real_count > 0 {
         // OK: inventory IDs have already been checked.
-        world::Fragment::inventory_mut(&mut eng.as_world_fragment(), from_iid)
-             .update(item_id, -real_count);
-        world::Fragment::inventory_mut(&mut eng.as_world_fragment(), to_iid)
-             .update(item_id, real_count);
+        try!(world::Fragment::inventory_mut(&mut eng.as_world_fragment(), from_iid)
+                  .update(item_id, -real_count));
+        try!(world::Fragment::inventory_mut(&mut eng.as_world_fragment(), to_iid)
+                  .update(item_id, real_count));
+    }
+
+    // Instances move one at a time, keeping their metadata, until one doesn't fit.
+    let mut moved_any = real_count > 0;
+    for key in keys {
+        let result = world::Fragment::inventory_mut(&mut eng.as_world_fragment(), from_iid)
+                         .move_instance(key, to_iid);
+        match result {
+            Ok(_) => moved_any = true,
+            Err(e) => {
+                if !moved_any {
+                    return Err(e);
+                }
+                break;
+            },
+        }
     }
     Ok(())
 }
 
+/// How far (in pixels) a client's pawn can be from the edge of a crafting station.
+const CRAFTING_REACH: i32 = 2 * TILE_SIZE;
+
 pub fn craft_recipe(mut eng: EngineRef,
+                    cid: ClientId,
                     station_sid: StructureId,
                     iid: InventoryId,
                     recipe_id: RecipeId,
                     count: u16) -> StrResult<()> {
+    let now = eng.now();
     let recipe = unwrap!(eng.world().data().recipes.get_recipe(recipe_id));
 
-    let _ = station_sid; // TODO
+    {
+        let world = eng.world();
+        let c = unwrap!(world.get_client(cid));
+        let pawn = unwrap!(c.pawn(), "client has no pawn");
+        let s = unwrap!(world.get_structure(station_sid), "crafting station does not exist");
+
+        if !recipe.allows_station(s.template_id()) {
+            fail!("recipe can't be crafted at this station");
+        }
+
+        let bounds = s.bounds();
+        let bounds_px = Region::new(bounds.min * scalar(TILE_SIZE),
+                                    bounds.max * scalar(TILE_SIZE));
+        if pawn.plane_id() != s.plane_id() ||
+           !bounds_px.expand(scalar(CRAFTING_REACH)).contains_inclusive(pawn.pos(now)) {
+            fail!("crafting station is out of reach");
+        }
+    }
+
+    // Deliver any finished jobs first, in case they are waiting on this inventory.
+    try!(finish_crafting(eng.borrow(), station_sid));
+
     let mut wf = eng.as_world_fragment();
+
+    if recipe.time > 0 {
+        return queue_crafting(&mut wf, station_sid, iid, recipe_id, count, now);
+    }
+
     let mut i = unwrap!(world::Fragment::get_inventory_mut(&mut wf, iid));
 
     let real_count = {
-        let mut count = count as u8;
-
-        for (&item_id, &num_required) in recipe.inputs.iter() {
-            count = cmp::min(count, i.count(item_id) / num_required);
-        }
+        let mut count = i.batches_available(&recipe.inputs, count);
 
+        // NB: This ignores the space freed up by consuming the inputs, so crafting in a nearly
+        // full inventory may produce fewer items than expected.
         for (&item_id, &num_produced) in recipe.outputs.iter() {
-            count = cmp::min(count, (u8::MAX - i.count(item_id)) / num_produced);
+            count = cmp::min(count, i.space_for(item_id) / num_produced as u16);
         }
 
-        count as i16
+        count as i32
     };
 
     if real_count > 0 {
         for (&item_id, &num_required) in recipe.inputs.iter() {
-            i.update(item_id, -real_count * num_required as i16);
+            try!(i.update(item_id, -real_count * num_required as i32));
         }
 
         for (&item_id, &num_produced) in recipe.outputs.iter() {
-            i.update(item_id, real_count * num_produced as i16);
+            try!(i.update(item_id, real_count * num_produced as i32));
+        }
+    }
+    Ok(())
+}
+
+/// Consume the inputs for up to `count` batches of a timed recipe, and queue a job at the
+/// station to produce the outputs.  Jobs at the same station run one after another.
+fn queue_crafting(wf: &mut WorldFragment,
+                  station_sid: StructureId,
+                  iid: InventoryId,
+                  recipe_id: RecipeId,
+                  count: u16,
+                  now: Time) -> StrResult<()> {
+    let recipe = unwrap!(wf.world().data().recipes.get_recipe(recipe_id));
+
+    let (real_count, stable_iid) = {
+        let mut i = unwrap!(world::Fragment::get_inventory_mut(wf, iid));
+
+        let count = i.batches_available(&recipe.inputs, count);
+        if count == 0 {
+            return Ok(());
+        }
+
+        for (&item_id, &num_required) in recipe.inputs.iter() {
+            try!(i.update(item_id, -(count as i32) * num_required as i32));
+        }
+        (count, i.stable_id())
+    };
+
+    let mut s = unwrap!(world::Fragment::get_structure_mut(wf, station_sid));
+    let start_time = s.crafting_jobs().iter()
+                      .map(|j| j.end_time)
+                      .fold(now, |a, b| cmp::max(a, b));
+    s.push_crafting_job(CraftingJob {
+        recipe_id: recipe_id,
+        count: real_count,
+        inventory: stable_iid,
+        start_time: start_time,
+        end_time: start_time + recipe.time as Time * real_count as Time,
+    });
+    Ok(())
+}
+
+/// Remove all crafting jobs that deliver to `stable_iid`, which has been destroyed.  Their inputs
+/// are lost.  Returns the stations that had jobs removed.
+pub fn drop_crafting_jobs<'d, F>(wf: &mut F,
+                                 stable_iid: Stable<InventoryId>) -> Vec<StructureId>
+        where F: world::Fragment<'d> {
+    let sids = wf.world().structures()
+                 .filter(|s| s.crafting_jobs().iter().any(|j| j.inventory == stable_iid))
+                 .map(|s| s.id())
+                 .collect::<Vec<_>>();
+    for &sid in &sids {
+        loop {
+            let idx = {
+                let s = wf.world().structure(sid);
+                unwrap_or!(s.crafting_jobs().iter().position(|j| j.inventory == stable_iid),
+                           break)
+            };
+            warn_on_err!(world::Fragment::structure_mut(wf, sid).remove_crafting_job(idx));
         }
     }
+    sids
+}
+
+/// Deliver the outputs of all finished crafting jobs at `sid`.  Jobs whose output inventory is
+/// not currently loaded, or doesn't have room for all of the outputs, stay in the queue until the
+/// next attempt, which happens when the station is loaded, opened, or used for crafting.  Jobs
+/// whose output inventory was destroyed are removed by `drop_crafting_jobs`.
+pub fn finish_crafting(mut eng: EngineRef, sid: StructureId) -> StrResult<()> {
+    let now = eng.now();
+    let mut wf = eng.as_world_fragment();
+
+    let mut start = 0;
+    loop {
+        let next = {
+            let world = wf.world();
+            let s = unwrap!(world.get_structure(sid));
+            s.crafting_jobs().iter().enumerate().skip(start)
+             .filter(|&(_, j)| j.end_time <= now)
+             .filter_map(|(idx, j)| world.transient_inventory_id(j.inventory).map(|iid| (idx, iid)))
+             .next()
+        };
+        let (idx, iid) = unwrap_or!(next, break);
+
+        let (recipe_id, count) = {
+            let job = &wf.world().structure(sid).crafting_jobs()[idx];
+            (job.recipe_id, job.count)
+        };
+        let recipe = unwrap!(wf.world().data().recipes.get_recipe(recipe_id));
+        if deliver_outputs(&mut wf, iid, recipe, count) {
+            try!(world::Fragment::structure_mut(&mut wf, sid).remove_crafting_job(idx));
+        } else {
+            start = idx + 1;
+        }
+    }
+
+    world::Fragment::with_hooks(&mut wf, |h| h.schedule_crafting_update(sid, false));
     Ok(())
 }
+
+/// Add the outputs of `count` batches of `recipe` to `iid`.  Returns `false` without adding
+/// anything if they don't all fit.
+fn deliver_outputs(wf: &mut WorldFragment,
+                   iid: InventoryId,
+                   recipe: &Recipe,
+                   count: u16) -> bool {
+    let mut i = world::Fragment::inventory_mut(wf, iid);
+    let mut added = Vec::with_capacity(recipe.outputs.len());
+    for (&item_id, &num_produced) in recipe.outputs.iter() {
+        let n = count as i32 * num_produced as i32;
+        if i.update(item_id, n).is_err() {
+            for (item_id, n) in added {
+                warn_on_err!(i.update(item_id, -n));
+            }
+            return false;
+        }
+        added.push((item_id, n));
+    }
+    true
+}
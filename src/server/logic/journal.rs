@@ -0,0 +1,538 @@
+//! Journal of world edits made by players, for finding and undoing griefing.
+//!
+//! Changes made while handling a client's request are recorded along with the client's name and
+//! the time: block changes, structure creation, destruction, and replacement, and changes to
+//! inventory contents.  Changes made by the world itself (timers, terrain generation, chunk
+//! loading and unloading) are not recorded.  Entries refer to planes and inventories by stable ID
+//! and to blocks, templates, and items by name, so they stay meaningful across restarts.
+//!
+//! The most recent `MAX_ENTRIES` entries are kept in memory.  New entries are appended to
+//! `save/journal.dat`, one JSON object per line, and the file is rewritten from memory once it
+//! grows past twice that many entries, or after a rollback, which marks existing entries as
+//! undone.
+//!
+//! Rolling back an entry only undoes it if the world still matches the entry's result, so later
+//! changes by other players are left alone.  The contents of destroyed structures are not
+//! recorded, so a restored structure comes back empty.  Inventories carried by characters have no
+//! location, so changes to them are only undone as the other side of a transfer with an inventory
+//! in the rolled back region.
+
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::mem;
+use rustc_serialize::json;
+
+use libphysics::CHUNK_SIZE;
+use types::*;
+use util::StrResult;
+
+use engine::split::EngineRef;
+use logic;
+use world::{self, World, InventoryAttachment};
+use world::object::*;
+
+
+/// Maximum number of entries to keep.
+pub const MAX_ENTRIES: usize = 200000;
+
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct Entry {
+    pub time: Time,
+    pub actor: String,
+    pub change: Change,
+    /// Set once the entry has been rolled back, so it isn't undone twice.
+    pub undone: bool,
+}
+
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub enum Change {
+    /// Plane, position, old block name, new block name.
+    Block(StableId, (i32, i32, i32), String, String),
+    /// Plane, position, template name.
+    StructureCreate(StableId, (i32, i32, i32), String),
+    /// Plane, position, template name.
+    StructureDestroy(StableId, (i32, i32, i32), String),
+    /// Plane, position, old template name, new template name.
+    StructureReplace(StableId, (i32, i32, i32), String, String),
+    /// Inventory, plane and position of the structure it's attached to (if any), item name, and
+    /// the change in count.
+    Inventory(StableId, Option<(StableId, (i32, i32, i32))>, String, i32),
+}
+
+impl Change {
+    /// The plane and position where the change happened.  Inventory changes happen at their
+    /// structure, or nowhere in particular for inventories carried by characters.
+    fn location(&self) -> Option<(StableId, V3)> {
+        match *self {
+            Change::Block(pid, pos, _, _) |
+            Change::StructureCreate(pid, pos, _) |
+            Change::StructureDestroy(pid, pos, _) |
+            Change::StructureReplace(pid, pos, _, _) => Some((pid, to_v3(pos))),
+            Change::Inventory(_, loc, _, _) => loc.map(|(pid, pos)| (pid, to_v3(pos))),
+        }
+    }
+}
+
+fn to_v3((x, y, z): (i32, i32, i32)) -> V3 {
+    V3::new(x, y, z)
+}
+
+fn from_v3(v: V3) -> (i32, i32, i32) {
+    (v.x, v.y, v.z)
+}
+
+
+pub struct Journal {
+    entries: VecDeque<Entry>,
+    /// Entries that haven't been written to the journal file yet.
+    pending: Vec<Entry>,
+    /// Number of entries in the journal file.
+    file_len: usize,
+    /// Name of the client whose request is currently being handled.
+    actor: Option<String>,
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal {
+            entries: VecDeque::new(),
+            pending: Vec::new(),
+            file_len: 0,
+            actor: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.actor.is_some()
+    }
+
+    /// Record a change made by the current actor.  Returns `true` if there were previously no
+    /// pending entries, meaning the caller must schedule a call to `flush`.
+    pub fn record(&mut self, time: Time, change: Change) -> bool {
+        let actor = unwrap_or!(self.actor.clone(), return false);
+        let entry = Entry {
+            time: time,
+            actor: actor,
+            change: change,
+            undone: false,
+        };
+
+        self.push(entry.clone());
+        self.pending.push(entry);
+        self.pending.len() == 1
+    }
+
+    fn push(&mut self, entry: Entry) {
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+
+/// Run `f` with changes attributed to client `cid`.
+pub fn with_actor<F, R>(mut eng: EngineRef, cid: ClientId, f: F) -> R
+        where F: FnOnce(EngineRef) -> R {
+    let name = eng.world().get_client(cid).map(|c| c.name().to_owned());
+    let old_actor = mem::replace(&mut eng.extra_mut().journal.actor, name);
+    let result = f(eng.borrow());
+    eng.extra_mut().journal.actor = old_actor;
+    result
+}
+
+/// Run `f` with no actor, so its changes aren't recorded.
+fn without_actor<F, R>(mut eng: EngineRef, f: F) -> R
+        where F: FnOnce(EngineRef) -> R {
+    let old_actor = mem::replace(&mut eng.extra_mut().journal.actor, None);
+    let result = f(eng.borrow());
+    eng.extra_mut().journal.actor = old_actor;
+    result
+}
+
+
+pub fn block_change<'d, WF>(wf: &mut WF,
+                            pid: PlaneId,
+                            pos: V3,
+                            old: BlockId,
+                            new: BlockId) -> Option<Change>
+        where WF: world::Fragment<'d> {
+    let stable_pid = unwrap_or!(wf.get_plane_mut(pid), return None).stable_id();
+    let bd = &wf.world().data().block_data;
+    Some(Change::Block(stable_pid.unwrap(),
+                       from_v3(pos),
+                       bd.name(old).to_owned(),
+                       bd.name(new).to_owned()))
+}
+
+pub fn structure_create<'d, WF>(wf: &mut WF, sid: StructureId) -> Option<Change>
+        where WF: world::Fragment<'d> {
+    let (pid, pos, tid) = {
+        let s = unwrap_or!(wf.world().get_structure(sid), return None);
+        (s.plane_id(), s.pos(), s.template_id())
+    };
+    let stable_pid = unwrap_or!(wf.get_plane_mut(pid), return None).stable_id();
+    let t = wf.world().data().structure_templates.template(tid);
+    Some(Change::StructureCreate(stable_pid.unwrap(), from_v3(pos), t.name.clone()))
+}
+
+pub fn structure_destroy<'d, WF>(wf: &mut WF,
+                                 pid: PlaneId,
+                                 tid: TemplateId,
+                                 bounds: Region) -> Option<Change>
+        where WF: world::Fragment<'d> {
+    let stable_pid = unwrap_or!(wf.get_plane_mut(pid), return None).stable_id();
+    let t = wf.world().data().structure_templates.template(tid);
+    Some(Change::StructureDestroy(stable_pid.unwrap(), from_v3(bounds.min), t.name.clone()))
+}
+
+pub fn structure_replace<'d, WF>(wf: &mut WF,
+                                 sid: StructureId,
+                                 old_tid: TemplateId) -> Option<Change>
+        where WF: world::Fragment<'d> {
+    let (pid, pos, tid) = {
+        let s = unwrap_or!(wf.world().get_structure(sid), return None);
+        (s.plane_id(), s.pos(), s.template_id())
+    };
+    let stable_pid = unwrap_or!(wf.get_plane_mut(pid), return None).stable_id();
+    let templates = &wf.world().data().structure_templates;
+    Some(Change::StructureReplace(stable_pid.unwrap(),
+                                  from_v3(pos),
+                                  templates.template(old_tid).name.clone(),
+                                  templates.template(tid).name.clone()))
+}
+
+pub fn inventory_update<'d, WF>(wf: &mut WF,
+                                iid: InventoryId,
+                                item_id: ItemId,
+                                old_count: u16,
+                                new_count: u16) -> Option<Change>
+        where WF: world::Fragment<'d> {
+    let attachment = unwrap_or!(wf.world().get_inventory(iid), return None).attachment();
+    let location = match attachment {
+        InventoryAttachment::Structure(sid) => {
+            let (pid, pos) = {
+                let s = unwrap_or!(wf.world().get_structure(sid), return None);
+                (s.plane_id(), s.pos())
+            };
+            let stable_pid = unwrap_or!(wf.get_plane_mut(pid), return None).stable_id();
+            Some((stable_pid.unwrap(), from_v3(pos)))
+        },
+        _ => None,
+    };
+    let stable_iid = wf.inventory_mut(iid).stable_id();
+    let name = wf.world().data().item_data.name(item_id).to_owned();
+    Some(Change::Inventory(stable_iid.unwrap(),
+                           location,
+                           name,
+                           new_count as i32 - old_count as i32))
+}
+
+
+/// Read the journal file into memory.
+pub fn load(mut eng: EngineRef) {
+    let file = unwrap_or!(eng.storage().open_journal_file());
+    let mut count = 0;
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("error reading journal: {}", e);
+                break;
+            },
+        };
+        count += 1;
+        match json::decode(&line) {
+            Ok(entry) => eng.extra_mut().journal.push(entry),
+            Err(e) => warn!("bad journal entry: {}", e),
+        }
+    }
+    eng.extra_mut().journal.file_len = count;
+}
+
+/// Write pending entries to the journal file.  If the file has grown too large, it is rewritten
+/// with only the entries kept in memory.
+pub fn flush(mut eng: EngineRef) {
+    let pending = mem::replace(&mut eng.extra_mut().journal.pending, Vec::new());
+    if pending.len() == 0 {
+        return;
+    }
+
+    let file_len = eng.extra().journal.file_len + pending.len();
+    if file_len > 2 * MAX_ENTRIES {
+        rewrite(eng);
+        return;
+    }
+
+    let buf = encode_entries(pending.iter());
+    let mut file = eng.storage().append_journal_file();
+    if let Err(e) = file.write_all(buf.as_bytes()) {
+        warn!("error writing journal: {}", e);
+    }
+    eng.extra_mut().journal.file_len = file_len;
+}
+
+/// Replace the journal file with the entries kept in memory, including any pending ones.
+fn rewrite(mut eng: EngineRef) {
+    eng.extra_mut().journal.pending.clear();
+    let buf = encode_entries(eng.extra().journal.entries.iter());
+    let mut file = eng.storage().create_journal_file();
+    if let Err(e) = file.write_all(buf.as_bytes()) {
+        warn!("error writing journal: {}", e);
+    }
+    let file_len = eng.extra().journal.entries.len();
+    eng.extra_mut().journal.file_len = file_len;
+}
+
+fn encode_entries<'a, I: Iterator<Item=&'a Entry>>(entries: I) -> String {
+    let mut buf = String::new();
+    for entry in entries {
+        buf.push_str(&unwrap_or!(json::encode(entry).ok(), continue));
+        buf.push('\n');
+    }
+    buf
+}
+
+
+/// Undo the changes made by `actor` inside `region` between `start` and `end`, newest first.
+/// When an inventory change in `region` was one side of a transfer, the other side is undone along
+/// with it, wherever that inventory is, and neither is undone unless both can be.  Changes to
+/// inventories without a location are not undone on their own.  Returns the number of changes
+/// that were undone.  The undo itself is not recorded, even when the rollback is requested by a
+/// client.
+pub fn rollback(mut eng: EngineRef,
+                actor: &str,
+                pid: PlaneId,
+                region: Region,
+                start: Time,
+                end: Time) -> StrResult<usize> {
+    let stable_pid = unwrap!(eng.as_hidden_world_fragment().get_plane_mut(pid)).stable_id();
+
+    let changes = {
+        let entries = &eng.extra().journal.entries;
+        let mut changes = Vec::new();
+        // Entries already chosen, either on their own or as the other side of a transfer.
+        let mut used = HashSet::new();
+        for (idx, entry) in entries.iter().enumerate().rev() {
+            if used.contains(&idx) || entry.undone || entry.actor != actor ||
+               entry.time < start || entry.time > end {
+                continue;
+            }
+            let (entry_pid, pos) = unwrap_or!(entry.change.location(), continue);
+            if entry_pid != stable_pid.unwrap() || !region.contains(pos) {
+                continue;
+            }
+            used.insert(idx);
+            let partner = transfer_partner(entries, idx, &used);
+            if let Some(other) = partner {
+                used.insert(other);
+            }
+            changes.push((idx, partner));
+        }
+        changes.into_iter().map(|(idx, partner)| {
+            (idx, entries[idx].change.clone(), partner.map(|i| (i, entries[i].change.clone())))
+        }).collect::<Vec<_>>()
+    };
+
+    // Nothing is recorded while there's no actor, so the entry indices stay valid.
+    let count = without_actor(eng.borrow(), |mut eng| {
+        let mut count = 0;
+        for &(idx, ref change, ref partner) in &changes {
+            let result = match *partner {
+                None => undo(eng.borrow(), pid, change),
+                Some((_, ref other)) => undo_transfer(eng.borrow(), pid, change, other),
+            };
+            match result {
+                Ok(true) => {
+                    eng.extra_mut().journal.entries[idx].undone = true;
+                    count += 1;
+                    if let Some((other_idx, _)) = *partner {
+                        eng.extra_mut().journal.entries[other_idx].undone = true;
+                        count += 1;
+                    }
+                },
+                Ok(false) => {},
+                Err(e) => info!("rollback: can't undo {:?}: {}", change, e.description()),
+            }
+        }
+        count
+    });
+
+    if count > 0 {
+        rewrite(eng);
+    }
+    Ok(count)
+}
+
+/// Find the entry recording the other side of a transfer involving the inventory change at `idx`:
+/// an adjacent change by the same actor at the same time, moving the same item the opposite way.
+fn transfer_partner(entries: &VecDeque<Entry>,
+                    idx: usize,
+                    used: &HashSet<usize>) -> Option<usize> {
+    let entry = &entries[idx];
+    let (item, delta) = match entry.change {
+        Change::Inventory(_, _, ref item, delta) => (item, delta),
+        _ => return None,
+    };
+
+    let candidates = [idx.checked_sub(1), Some(idx + 1)];
+    for &other_idx in candidates.iter() {
+        let other_idx = unwrap_or!(other_idx, continue);
+        let other = unwrap_or!(entries.get(other_idx), continue);
+        if used.contains(&other_idx) || other.undone ||
+           other.actor != entry.actor || other.time != entry.time {
+            continue;
+        }
+        match other.change {
+            Change::Inventory(_, _, ref other_item, other_delta)
+                    if other_item == item && other_delta == -delta => return Some(other_idx),
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Undo both sides of a transfer, or neither.  The side that took items in is undone first, so
+/// the items are never in both inventories at once.
+fn undo_transfer(mut eng: EngineRef,
+                 pid: PlaneId,
+                 a: &Change,
+                 b: &Change) -> StrResult<bool> {
+    let (first, second) = match *a {
+        Change::Inventory(_, _, _, delta) if delta > 0 => (a, b),
+        _ => (b, a),
+    };
+    if !try!(undo(eng.borrow(), pid, first)) {
+        return Ok(false);
+    }
+    match undo(eng.borrow(), pid, second) {
+        Ok(true) => Ok(true),
+        result => {
+            // Put the first side back as it was.
+            if let Change::Inventory(stable_iid, loc, ref item, delta) = *first {
+                let redo = Change::Inventory(stable_iid, loc, item.clone(), -delta);
+                warn_on_err!(undo(eng.borrow(), pid, &redo));
+            }
+            result
+        },
+    }
+}
+
+fn undo(mut eng: EngineRef, pid: PlaneId, change: &Change) -> StrResult<bool> {
+    let mut wf = eng.as_world_fragment();
+    let data = wf.world().data();
+    match *change {
+        Change::Block(_, pos, ref old, ref new) => {
+            let pos = to_v3(pos);
+            let old_id = unwrap!(data.block_data.find_id(old));
+            let new_id = unwrap!(data.block_data.find_id(new));
+            if block_at(wf.world(), pid, pos) != Some(new_id) {
+                return Ok(false);
+            }
+            logic::terrain::set_block(&mut wf, pid, pos, old_id)
+        },
+
+        Change::StructureCreate(_, pos, ref template) => {
+            let tid = unwrap!(data.structure_templates.find_id(template));
+            let sid = unwrap_or!(structure_at(wf.world(), pid, to_v3(pos), tid),
+                                 return Ok(false));
+            try!(wf.destroy_structure(sid));
+            Ok(true)
+        },
+
+        Change::StructureDestroy(_, pos, ref template) => {
+            let tid = unwrap!(data.structure_templates.find_id(template));
+            try!(wf.create_structure(pid, to_v3(pos), tid));
+            Ok(true)
+        },
+
+        Change::StructureReplace(_, pos, ref old, ref new) => {
+            let old_tid = unwrap!(data.structure_templates.find_id(old));
+            let new_tid = unwrap!(data.structure_templates.find_id(new));
+            let sid = unwrap_or!(structure_at(wf.world(), pid, to_v3(pos), new_tid),
+                                 return Ok(false));
+            try!(wf.structure_mut(sid).set_template_id(old_tid));
+            Ok(true)
+        },
+
+        Change::Inventory(stable_iid, _, ref item, delta) => {
+            let item_id = unwrap!(data.item_data.find_id(item));
+            let iid = unwrap_or!(wf.world().transient_inventory_id(Stable::new(stable_iid)),
+                                 return Ok(false));
+            Ok(wf.inventory_mut(iid).update(item_id, -delta).is_ok())
+        },
+    }
+}
+
+fn block_at(w: &World, pid: PlaneId, pos: V3) -> Option<BlockId> {
+    let cpos = pos.reduce().div_floor(scalar(CHUNK_SIZE));
+    let tc = unwrap_or!(w.get_plane(pid).and_then(|p| p.get_terrain_chunk(cpos)),
+                        return None);
+    Some(tc.block(tc.bounds().index(pos)))
+}
+
+fn structure_at(w: &World, pid: PlaneId, pos: V3, tid: TemplateId) -> Option<StructureId> {
+    let cpos = pos.reduce().div_floor(scalar(CHUNK_SIZE));
+    w.chunk_structures(pid, cpos)
+     .find(|s| s.pos() == pos && s.template_id() == tid)
+     .map(|s| s.id())
+}
+
+
+/// Describe the recorded changes to the tile at `pos`, oldest first.
+pub fn history(mut eng: EngineRef, pid: PlaneId, pos: V3) -> StrResult<Vec<String>> {
+    let stable_pid = unwrap!(eng.as_hidden_world_fragment().get_plane_mut(pid)).stable_id();
+    let now = eng.now();
+    let data = eng.world().data();
+
+    let mut lines = Vec::new();
+    for entry in eng.extra().journal.entries.iter() {
+        let (entry_pid, entry_pos) = unwrap_or!(entry.change.location(), continue);
+        if entry_pid != stable_pid.unwrap() {
+            continue;
+        }
+
+        let desc = match entry.change {
+            Change::Block(_, _, ref old, ref new) => {
+                if entry_pos != pos {
+                    continue;
+                }
+                format!("changed {} to {}", old, new)
+            },
+            Change::StructureCreate(_, _, ref template) |
+            Change::StructureDestroy(_, _, ref template) |
+            Change::StructureReplace(_, _, _, ref template) => {
+                let tid = unwrap_or!(data.structure_templates.find_id(template), continue);
+                let size = data.structure_templates.template(tid).size;
+                if !Region::new(entry_pos, entry_pos + size).contains(pos) {
+                    continue;
+                }
+                match entry.change {
+                    Change::StructureCreate(..) => format!("placed {}", template),
+                    Change::StructureDestroy(..) => format!("removed {}", template),
+                    Change::StructureReplace(_, _, ref old, _) =>
+                        format!("changed {} to {}", old, template),
+                    _ => unreachable!(),
+                }
+            },
+            Change::Inventory(_, _, ref item, delta) => {
+                if entry_pos != pos {
+                    continue;
+                }
+                if delta > 0 {
+                    format!("added {} {}", delta, item)
+                } else {
+                    format!("took {} {}", -delta, item)
+                }
+            },
+        };
+
+        lines.push(format!("{} min ago: {} {}{}",
+                           (now - entry.time) / 60000,
+                           entry.actor,
+                           desc,
+                           if entry.undone { " (undone)" } else { "" }));
+    }
+    Ok(lines)
+}
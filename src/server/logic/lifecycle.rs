@@ -8,13 +8,26 @@ use engine::glue::*;
 use engine::split::EngineRef;
 use logic;
 use messages::{ClientResponse, SyncKind};
+use script::ScriptEngine;
+use vision;
 use wire::{WireWriter, WireReader};
-use world::Fragment;
+use world::{Fragment, PlaneProps};
 use world::object::*;
 use world::save::{ObjectReader, ObjectWriter};
 
 
+/// Version of the restart file layout.  The file starts with a message on the control wire
+/// holding the version, followed by one record per client.  Files written before the version
+/// was added have no version message and hold only the client name.
+///
+/// Version 1 records hold the view size and name.  Version 2 adds the client's party and
+/// whether they lead it.
+const RESTART_FILE_VERSION: u16 = 2;
+
+
 pub fn start_up(mut eng: EngineRef) {
+    logic::config::load(eng.borrow());
+
     let world_time =
         if let Some(mut file) = eng.storage().open_misc_file() {
             file.read_bytes().unwrap()
@@ -46,9 +59,20 @@ pub fn start_up(mut eng: EngineRef) {
         sr.load_plane(&mut eng.as_save_read_fragment()).unwrap();
     } else {
         let name = "Everfree Forest".to_owned();
-        let stable_pid = eng.as_hidden_world_fragment().create_plane(name).unwrap().stable_id();
-        assert!(stable_pid == STABLE_PLANE_FOREST);
+        let mut wf = eng.as_hidden_world_fragment();
+        let mut p = wf.create_plane(name).unwrap();
+        p.set_props(PlaneProps::forest());
+        assert!(p.stable_id() == STABLE_PLANE_FOREST);
     }
+
+    logic::journal::load(eng.borrow());
+    logic::clock::load(eng.borrow());
+    logic::chat::load(eng.borrow());
+    logic::instance::load(eng.borrow());
+    logic::audit::prune(eng.borrow());
+    logic::audit::schedule_flush(eng.borrow());
+
+    ScriptEngine::cb_world_event_sync(eng.unwrap());
 }
 
 
@@ -78,6 +102,11 @@ pub fn shut_down(mut eng: EngineRef) {
         let mut file = eng.storage().create_misc_file();
         warn_on_err!(file.write_bytes(eng.now()));
     }
+
+    logic::journal::flush(eng.borrow());
+    logic::audit::flush(eng.borrow());
+    logic::chat::save(eng.borrow());
+    logic::instance::save(eng.borrow());
 }
 
 
@@ -90,6 +119,8 @@ pub fn pre_restart(eng: EngineRef) {
         info!("recording clients to file...");
         let file = eng.storage().create_restart_file();
         let mut ww = WireWriter::new(file);
+        ww.write_msg(CONTROL_WIRE_ID, RESTART_FILE_VERSION).unwrap();
+        let parties = logic::party::restart_info(&eng);
         for c in eng.world().clients() {
             let wire_id = match eng.messages().client_to_wire(c.id()) {
                 Some(x) => x,
@@ -98,7 +129,10 @@ pub fn pre_restart(eng: EngineRef) {
                     continue;
                 },
             };
-            ww.write_msg(wire_id, c.name()).unwrap();
+            let size = logic::client::view_shape(eng.extra(), c.id()).size;
+            let (party, leader) = parties.get(&c.id()).cloned().unwrap_or((0, false));
+            ww.write_msg(wire_id, (size.x as u8, size.y as u8,
+                                   party, leader as u8, c.name())).unwrap();
         }
     }
 }
@@ -107,10 +141,40 @@ pub fn post_restart(mut eng: EngineRef, file: File) {
     info!("retrieving clients from file...");
 
     let mut wr = WireReader::new(file);
+    let mut party_members = Vec::new();
+    let mut version = 0;
     while let Ok(wire_id) = wr.read_header() {
-        let name = wr.read::<String>().unwrap();
-        warn_on_err!(logic::client::login(eng.borrow(), wire_id, &name));
+        if wire_id == CONTROL_WIRE_ID {
+            version = unwrap_or!(wr.read::<u16>().ok(), break);
+            if version == 0 || version > RESTART_FILE_VERSION {
+                warn!("unsupported restart file version {}", version);
+                break;
+            }
+            continue;
+        }
+
+        let result = match version {
+            0 => wr.read::<String>().map(|name| {
+                let size = vision::DEFAULT_VIEW_SIZE;
+                (size.x as u8, size.y as u8, 0, 0, name)
+            }),
+            1 => wr.read::<(u8, u8, String)>().map(|(x, y, name)| (x, y, 0, 0, name)),
+            _ => wr.read::<(u8, u8, u16, u8, String)>(),
+        };
+        let (x, y, party, leader, name) = match result {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("bad restart record for {:?}: {}", wire_id, e);
+                continue;
+            },
+        };
+        let view_size = V2::new(x as i32, y as i32);
+        warn_on_err!(logic::client::login(eng.borrow(), wire_id, &name, view_size));
+        if let Some(cid) = eng.messages().name_to_client(&name) {
+            party_members.push((cid, party, leader != 0));
+        }
     }
+    logic::party::restore(eng.borrow(), party_members);
 
     let msg = ClientResponse::ChatUpdate("***\tServer restarted".to_owned());
     eng.messages().broadcast_clients(msg);
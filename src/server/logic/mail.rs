@@ -0,0 +1,393 @@
+//! Player mail: letters, optionally with items attached, that can be sent to any registered
+//! player, whether or not they are logged in.
+//!
+//! Each player's letters are stored in `save/mail/<name>.json`, separately from the player's
+//! client file, so mail can be delivered without loading the recipient.  The attachment is the
+//! entire contents of an inventory, which is taken from the sender in a single step: either all
+//! the items are sent, or none are.  Unique items can't be mailed, and can't be put in a
+//! mailbox by scripts either.
+//!
+//! While a player is logged in, they have a mailbox inventory, attached to their client.  On
+//! login, the items of all newly delivered letters are placed in the mailbox, and mail sent while
+//! the player is online goes there directly.  Players can take items out of their mailbox, but
+//! can't put anything in.  Items still in the mailbox on logout go back into the mail file, and
+//! return on the next login.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::mem;
+
+use rustc_serialize::json;
+
+use types::*;
+use util::StrResult;
+use util::now;
+
+use audit;
+use engine::split::EngineRef;
+use logic;
+use logic::chat::notice;
+use world::{self, InventoryAttachment};
+use world::object::*;
+
+
+/// Largest number of letters a player can have at once.
+pub const MAX_LETTERS: usize = 100;
+
+/// Maximum length of a letter, in bytes.
+pub const MAX_BODY_LEN: usize = 1000;
+
+
+pub struct Mail {
+    /// Mailbox inventory of each logged-in client.
+    mailboxes: HashMap<ClientId, InventoryId>,
+}
+
+impl Mail {
+    pub fn new() -> Mail {
+        Mail {
+            mailboxes: HashMap::new(),
+        }
+    }
+
+    pub fn mailbox(&self, cid: ClientId) -> Option<InventoryId> {
+        self.mailboxes.get(&cid).cloned()
+    }
+
+    /// Find the client who owns the mailbox inventory `iid`, if any.
+    pub fn mailbox_owner(&self, iid: InventoryId) -> Option<ClientId> {
+        self.mailboxes.iter()
+            .filter(|&(_, &mailbox_iid)| mailbox_iid == iid)
+            .map(|(&cid, _)| cid)
+            .next()
+    }
+}
+
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct Letter {
+    pub from: String,
+    /// Real-world time when the letter was sent, in milliseconds since the Unix epoch.
+    pub sent: Time,
+    pub body: String,
+    /// Names and counts of the attached items.
+    pub items: Vec<(String, u16)>,
+    /// Whether the attached items have been placed in the recipient's mailbox inventory.
+    pub delivered: bool,
+}
+
+#[derive(Default, RustcEncodable, RustcDecodable)]
+struct SavedMailbox {
+    letters: Vec<Letter>,
+    /// Items that were left in the mailbox inventory when its owner logged out.
+    held: Vec<(String, u16)>,
+}
+
+fn load_mailbox(eng: &EngineRef, name: &str) -> StrResult<SavedMailbox> {
+    let mut file = unwrap_or!(eng.storage().open_mail_file(name),
+                              return Ok(SavedMailbox::default()));
+    let mut s = String::new();
+    if let Err(e) = file.read_to_string(&mut s) {
+        warn!("error reading mail for {}: {}", name, e);
+        fail!("error reading mailbox");
+    }
+    match json::decode::<SavedMailbox>(&s) {
+        Ok(x) => Ok(x),
+        Err(e) => {
+            warn!("bad mail file for {}: {}", name, e);
+            fail!("error reading mailbox");
+        },
+    }
+}
+
+fn save_mailbox(eng: &EngineRef, name: &str, mailbox: &SavedMailbox) -> StrResult<()> {
+    if mailbox.letters.len() == 0 && mailbox.held.len() == 0 {
+        eng.storage().remove_mail_file(name);
+        return Ok(());
+    }
+    let s = match json::encode(mailbox) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("error encoding mail for {}: {}", name, e);
+            fail!("error saving mailbox");
+        },
+    };
+    let mut file = eng.storage().create_mail_file(name);
+    if let Err(e) = file.write_all(s.as_bytes()) {
+        warn!("error writing mail for {}: {}", name, e);
+        fail!("error saving mailbox");
+    }
+    Ok(())
+}
+
+
+/// Send a letter from `from` to the player named `to`.  If `attach_iid` is given, the entire
+/// contents of that inventory are taken and attached to the letter.  If the letter can't be
+/// saved, the items are put back.
+pub fn send(mut eng: EngineRef,
+            from: &str,
+            to: &str,
+            body: &str,
+            attach_iid: Option<InventoryId>) -> StrResult<()> {
+    if body.len() > MAX_BODY_LEN {
+        fail!("letter is too long");
+    }
+    match eng.auth().is_registered(to) {
+        Ok(true) => {},
+        Ok(false) => fail!("no such player"),
+        Err(e) => {
+            warn!("error looking up mail recipient {}: {}", to, e);
+            fail!("couldn't look up the recipient");
+        },
+    }
+    let online_cid = eng.messages().name_to_client(to);
+
+    let mut mailbox = try!(load_mailbox(&eng, to));
+    if mailbox.letters.len() >= MAX_LETTERS {
+        fail!("recipient's mailbox is full");
+    }
+
+    let items = match attach_iid {
+        Some(iid) => try!(take_contents(&mut eng, iid)),
+        None => Vec::new(),
+    };
+    if body.len() == 0 && items.len() == 0 {
+        fail!("nothing to send");
+    }
+
+    let mailbox_iid = online_cid.and_then(|cid| eng.extra().mail.mailbox(cid));
+    mailbox.letters.push(Letter {
+        from: from.to_owned(),
+        sent: now(),
+        body: body.to_owned(),
+        items: items.clone(),
+        delivered: mailbox_iid.is_some(),
+    });
+    let added = match mailbox_iid {
+        Some(iid) => {
+            let (added, rest) = add_items(&mut eng, iid, &items);
+            mailbox.held.extend(rest.into_iter());
+            added
+        },
+        None => Vec::new(),
+    };
+
+    if let Err(e) = save_mailbox(&eng, to, &mailbox) {
+        // Undo the delivery and give the items back, so nothing is lost or duplicated.
+        if let Some(iid) = mailbox_iid {
+            remove_items(&mut eng, iid, &added);
+        }
+        if let Some(iid) = attach_iid {
+            let (_, lost) = add_items(&mut eng, iid, &items);
+            if lost.len() > 0 {
+                warn!("lost mail items of {}:{}", from, format_items(&lost));
+            }
+        }
+        return Err(e);
+    }
+
+    if let Some(cid) = online_cid {
+        notice(&eng, cid, &format!("New mail from {}", from));
+    }
+    let detail = format!("to {}: {}{}", to, body, format_items(&items));
+    logic::audit::record(eng, audit::Kind::Mail, from, &detail);
+    Ok(())
+}
+
+/// Remove all items from `iid`, returning their names and counts.  Fails without removing
+/// anything if the inventory holds unique items.
+fn take_contents(eng: &mut EngineRef, iid: InventoryId) -> StrResult<Vec<(String, u16)>> {
+    if eng.extra().trades.is_offer(iid) || eng.extra().mail.mailbox_owner(iid).is_some() {
+        fail!("can't send items from that inventory");
+    }
+
+    let (counts, names) = {
+        let world = eng.world();
+        let item_data = &world.data().item_data;
+        let i = unwrap!(world.get_inventory(iid));
+        if i.instances().len() > 0 {
+            fail!("unique items can't be mailed");
+        }
+        let counts = i.contents().iter()
+                      .map(|(&item_id, &count)| (item_id, count))
+                      .collect::<Vec<_>>();
+        let names = counts.iter()
+                          .map(|&(item_id, count)| (item_data.name(item_id).to_owned(), count))
+                          .collect::<Vec<_>>();
+        (counts, names)
+    };
+
+    let mut wf = eng.as_world_fragment();
+    for (item_id, count) in counts {
+        // OK: the inventory ID has already been checked.
+        try!(world::Fragment::inventory_mut(&mut wf, iid).update(item_id, -(count as i32)));
+    }
+    Ok(names)
+}
+
+/// Add items to an inventory by name.  Returns the items that were added and the items that
+/// didn't fit.  Items that no longer exist in the item data are dropped.
+fn add_items(eng: &mut EngineRef,
+             iid: InventoryId,
+             items: &[(String, u16)]) -> (Vec<(String, u16)>, Vec<(String, u16)>) {
+    let mut added_items = Vec::new();
+    let mut rest = Vec::new();
+    for &(ref name, count) in items {
+        let item_id = unwrap_or!(eng.world().data().item_data.find_id(name), {
+            warn!("dropping {} mailed {}: no such item", count, name);
+            continue;
+        });
+        let mut wf = eng.as_world_fragment();
+        let mut i = unwrap_or!(world::Fragment::get_inventory_mut(&mut wf, iid), {
+            rest.push((name.clone(), count));
+            continue;
+        });
+        let space = cmp::min(count, i.space_for(item_id));
+        let added = if space > 0 && i.update(item_id, space as i32).is_ok() { space } else { 0 };
+        if added > 0 {
+            added_items.push((name.clone(), added));
+        }
+        if added < count {
+            rest.push((name.clone(), count - added));
+        }
+    }
+    (added_items, rest)
+}
+
+/// Remove items from an inventory by name.
+fn remove_items(eng: &mut EngineRef, iid: InventoryId, items: &[(String, u16)]) {
+    for &(ref name, count) in items {
+        let item_id = unwrap_or!(eng.world().data().item_data.find_id(name), continue);
+        let mut wf = eng.as_world_fragment();
+        let mut i = unwrap_or!(world::Fragment::get_inventory_mut(&mut wf, iid), return);
+        warn_on_err!(i.update(item_id, -(count as i32)));
+    }
+}
+
+fn format_items(items: &[(String, u16)]) -> String {
+    if items.len() == 0 {
+        return String::new();
+    }
+    let parts = items.iter()
+                     .map(|&(ref name, count)| format!("{} {}", count, name))
+                     .collect::<Vec<_>>();
+    format!(" [{}]", parts.join(", "))
+}
+
+
+/// Create a client's mailbox inventory and deliver any new mail into it.  Called on login.
+pub fn login(mut eng: EngineRef, cid: ClientId) {
+    let name = eng.world().client(cid).name().to_owned();
+    let mut mailbox = unwrap_or!(load_mailbox(&eng, &name).ok());
+
+    let iid = {
+        let mut wf = eng.as_world_fragment();
+        let mut i = match world::Fragment::create_inventory(&mut wf) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("error creating mailbox for {}: {}", name, e);
+                return;
+            },
+        };
+        warn_on_err!(i.set_attachment(InventoryAttachment::Client(cid)));
+        i.id()
+    };
+    eng.extra_mut().mail.mailboxes.insert(cid, iid);
+
+    let mut items = mem::replace(&mut mailbox.held, Vec::new());
+    let mut new_letters = 0;
+    for l in mailbox.letters.iter_mut().filter(|l| !l.delivered) {
+        items.extend(l.items.iter().cloned());
+        l.delivered = true;
+        new_letters += 1;
+    }
+    if new_letters == 0 && items.len() == 0 {
+        return;
+    }
+
+    let (added, rest) = add_items(&mut eng, iid, &items);
+    mailbox.held = rest;
+    if save_mailbox(&eng, &name, &mailbox).is_err() {
+        // The file still lists the items as undelivered, so take them back out.
+        remove_items(&mut eng, iid, &added);
+        return;
+    }
+
+    if new_letters == 1 {
+        notice(&eng, cid, "You have 1 new letter");
+    } else if new_letters > 1 {
+        notice(&eng, cid, &format!("You have {} new letters", new_letters));
+    }
+}
+
+/// Put the contents of a client's mailbox inventory back into their mail file, and destroy the
+/// inventory.  Called on logout, before the client is saved.
+pub fn logout(mut eng: EngineRef, cid: ClientId) {
+    let iid = unwrap_or!(eng.extra_mut().mail.mailboxes.remove(&cid));
+    let name = eng.world().client(cid).name().to_owned();
+
+    let held = {
+        let world = eng.world();
+        let item_data = &world.data().item_data;
+        // The mailbox never holds unique items, so only the plain stacks need to be kept.
+        unwrap_or!(world.get_inventory(iid)).contents().iter()
+            .map(|(&item_id, &count)| (item_data.name(item_id).to_owned(), count))
+            .collect::<Vec<_>>()
+    };
+
+    if held.len() > 0 {
+        let result = load_mailbox(&eng, &name).and_then(|mut mailbox| {
+            mailbox.held.extend(held.iter().cloned());
+            save_mailbox(&eng, &name, &mailbox)
+        });
+        if result.is_err() {
+            warn!("lost mailbox contents of {}:{}", name, format_items(&held));
+        }
+    }
+    warn_on_err!(world::Fragment::destroy_inventory(&mut eng.as_world_fragment(), iid));
+}
+
+
+/// Check whether items can be moved between `from_iid` and `to_iid` by `cid`.  Players can only
+/// take items out of their own mailbox.
+pub fn check_move(eng: &EngineRef,
+                  cid: ClientId,
+                  from_iid: InventoryId,
+                  to_iid: InventoryId) -> StrResult<()> {
+    let mail = &eng.extra().mail;
+    if mail.mailbox_owner(to_iid).is_some() {
+        fail!("can't put items in a mailbox");
+    }
+    if let Some(owner) = mail.mailbox_owner(from_iid) {
+        if owner != cid {
+            fail!("that is not your mailbox");
+        }
+    }
+    Ok(())
+}
+
+
+/// Get the letters of player `name`, oldest first, formatted for display.
+pub fn list(eng: EngineRef, name: &str) -> StrResult<Vec<String>> {
+    let mailbox = try!(load_mailbox(&eng, name));
+    let now = now();
+    Ok(mailbox.letters.iter().map(|l| {
+        format!("From {}, {} ago: {}{}",
+                l.from, logic::audit::format_age(l.sent, now), l.body, format_items(&l.items))
+    }).collect())
+}
+
+/// Delete the letter at `index` (counting from zero, oldest first) from the mail of `name`.  The
+/// letter's items stay wherever they were delivered.
+pub fn delete(eng: EngineRef, name: &str, index: usize) -> StrResult<()> {
+    let mut mailbox = try!(load_mailbox(&eng, name));
+    if index >= mailbox.letters.len() {
+        fail!("no such letter");
+    }
+    if !mailbox.letters[index].delivered {
+        fail!("letter has not been delivered yet");
+    }
+    mailbox.letters.remove(index);
+    save_mailbox(&eng, name, &mailbox)
+}
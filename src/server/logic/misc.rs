@@ -32,12 +32,17 @@ pub fn clear_block_interior<'d, F>(wf: &mut F,
         where F: world::Fragment<'d> {
     try!(update_block_interior(wf, pid, center, base, false));
 
-    {
+    let old_center = {
         let mut p = wf.plane_mut(pid);
         let cpos = center.reduce().div_floor(scalar(CHUNK_SIZE));
         let mut tc = p.terrain_chunk_mut(cpos);
         let idx = tc.bounds().index(center);
+        let old_center = tc.blocks()[idx];
         tc.blocks_mut()[idx] = new_center;
+        old_center
+    };
+    if old_center != new_center {
+        wf.with_hooks(|h| h.on_block_change(pid, center, old_center, new_center));
     }
 
     let update_region = Region::new(center - V3::new(1, 1, 0),
@@ -140,6 +145,7 @@ fn update_block_interior<'d, F>(wf: &mut F,
         }
     }
 
+    let mut changes = Vec::with_capacity(updates.len());
     {
         let mut p = wf.plane_mut(pid);
 
@@ -147,11 +153,19 @@ fn update_block_interior<'d, F>(wf: &mut F,
             if let Some(block_id) = updates[update_region.index(pos)] {
                 let cpos = pos.reduce().div_floor(scalar(CHUNK_SIZE));
                 let mut tc = p.terrain_chunk_mut(cpos);
-                tc.blocks_mut()[tc.bounds().index(pos)] = block_id;
+                let idx = tc.bounds().index(pos);
+                changes.push((pos, tc.blocks()[idx], block_id));
+                tc.blocks_mut()[idx] = block_id;
             }
         }
     }
 
+    for &(pos, old, new) in &changes {
+        if old != new {
+            wf.with_hooks(|h| h.on_block_change(pid, pos, old, new));
+        }
+    }
+
     Ok(())
 }
 
@@ -264,53 +278,63 @@ pub fn set_cave_single<'a, 'd, F>(p: &mut ObjectRefMut<'a, 'd, world::Plane, F>,
                                   pos: V3,
                                   set_corners: [bool; 4]) -> world::OpResult<bool>
         where F: world::Fragment<'d> {
-    let block_data = &p.world().data().block_data;
-    let mut tc = unwrap!(p.get_terrain_chunk_mut(pos.reduce().div_floor(scalar(CHUNK_SIZE))));
-    let idx = tc.bounds().index(pos);
-    let old_name = block_data.name(tc.blocks()[idx]);
-
-    let mut cave_key_str = "";
-    let mut floor_type = "";
-    {
-        // Match against the pattern: cave/<key>/z0/<floor_type>
-        // We `return Ok(false)` if this fails anywhere, since that just means the player is trying
-        // to mine the wrong type of block.
-        let mut iter = old_name.split("/");
-        const BAIL: world::OpResult<bool> = Ok(false);
-        if unwrap_or!(iter.next(), return BAIL) != "cave" {
-            return BAIL;
+    let pid = p.id();
+    let changes = {
+        let block_data = &p.world().data().block_data;
+        let mut tc = unwrap!(p.get_terrain_chunk_mut(pos.reduce().div_floor(scalar(CHUNK_SIZE))));
+        let idx = tc.bounds().index(pos);
+        let old_name = block_data.name(tc.blocks()[idx]);
+
+        let mut cave_key_str = "";
+        let mut floor_type = "";
+        {
+            // Match against the pattern: cave/<key>/z0/<floor_type>
+            // We `return Ok(false)` if this fails anywhere, since that just means the player is
+            // trying to mine the wrong type of block.
+            let mut iter = old_name.split("/");
+            const BAIL: world::OpResult<bool> = Ok(false);
+            if unwrap_or!(iter.next(), return BAIL) != "cave" {
+                return BAIL;
+            }
+            cave_key_str = unwrap_or!(iter.next(), return BAIL);
+            if unwrap_or!(iter.next(), return BAIL) != "z0" {
+                return BAIL;
+            }
+            floor_type = unwrap_or!(iter.next(), return BAIL);
+            if iter.next().is_some() {
+                return BAIL;
+            }
         }
-        cave_key_str = unwrap_or!(iter.next(), return BAIL);
-        if unwrap_or!(iter.next(), return BAIL) != "z0" {
-            return BAIL;
+        // After this, we use `unwrap!` because the only blocks matching the pattern should be
+        // ones with a properly constructed name.
+
+        let old_key: u8 = unwrap!(FromStr::from_str(cave_key_str).ok());
+        let mut mul = 1;
+        let mut new_key = 0;
+        for &set in &set_corners {
+            let old_val = old_key / mul % 3;
+            let new_val = if set && old_val == 0 { 2 } else { old_val };
+            new_key += new_val * mul;
+            mul *= 3;
         }
-        floor_type = unwrap_or!(iter.next(), return BAIL);
-        if iter.next().is_some() {
-            return BAIL;
+
+        if new_key == old_key {
+            return Ok(false);
         }
-    }
-    // After this, we use `unwrap!` because the only blocks matching the pattern should be ones
-    // with a properly constructed name.
-
-    let old_key: u8 = unwrap!(FromStr::from_str(cave_key_str).ok());
-    let mut mul = 1;
-    let mut new_key = 0;
-    for &set in &set_corners {
-        let old_val = old_key / mul % 3;
-        let new_val = if set && old_val == 0 { 2 } else { old_val };
-        new_key += new_val * mul;
-        mul *= 3;
-    }
 
-    if new_key == old_key {
-        return Ok(false);
+        let z0_idx = idx;
+        let z1_idx = tc.bounds().index(pos + V3::new(0, 0, 1));
+        let new_z0 = unwrap!(block_data.find_id(&format!("cave/{}/z0/{}", new_key, floor_type)));
+        let new_z1 = unwrap!(block_data.find_id(&format!("cave/{}/z1", new_key)));
+        let changes = [(pos, tc.blocks()[z0_idx], new_z0),
+                       (pos + V3::new(0, 0, 1), tc.blocks()[z1_idx], new_z1)];
+        tc.blocks_mut()[z0_idx] = new_z0;
+        tc.blocks_mut()[z1_idx] = new_z1;
+        changes
+    };
+
+    for &(pos, old, new) in &changes {
+        p.fragment_mut().with_hooks(|h| h.on_block_change(pid, pos, old, new));
     }
-
-    let z0_idx = idx;
-    let z1_idx = tc.bounds().index(pos + V3::new(0, 0, 1));
-    tc.blocks_mut()[z0_idx] = unwrap!(block_data.find_id(
-            &format!("cave/{}/z0/{}", new_key, floor_type)));
-    tc.blocks_mut()[z1_idx] = unwrap!(block_data.find_id(
-            &format!("cave/{}/z1", new_key)));
     Ok(true)
 }
@@ -6,4 +6,17 @@ pub mod lifecycle;
 pub mod vision;
 pub mod world;
 pub mod misc;
+pub mod trade;
 pub mod extra;
+pub mod events;
+pub mod terrain;
+pub mod schematic;
+pub mod journal;
+pub mod clock;
+pub mod config;
+pub mod camera;
+pub mod chat;
+pub mod party;
+pub mod instance;
+pub mod audit;
+pub mod mail;
@@ -0,0 +1,387 @@
+//! Parties: groups of players who adventure together.
+//!
+//! A party forms when a player accepts another player's invitation, with the inviter as leader.
+//! Only the leader can invite more players.  When the leader leaves, the longest-standing member
+//! takes over, and a party with only one member left is disbanded.
+//!
+//! Members can talk on the `party` chat channel, and periodically receive the positions of their
+//! teammates, even those far outside their view.  Scripts can also record per-party instances of
+//! planes, so that each party gets its own copy of a dungeon.
+//!
+//! Parties are not saved with the world, but they survive a server restart through the restart
+//! file.  Instanced planes are forgotten on restart.
+
+use std::collections::HashMap;
+
+use types::*;
+use util::StrResult;
+
+use engine::split::EngineRef;
+use logic;
+use logic::chat::notice;
+use messages::ClientResponse;
+use timer;
+use world::object::*;
+
+
+/// Largest number of players in one party.
+pub const MAX_PARTY_SIZE: usize = 8;
+
+/// Interval between updates of teammate positions.
+const MARKER_INTERVAL: Time = 1000;
+
+
+pub type PartyId = u32;
+
+pub struct Party {
+    pub leader: ClientId,
+    /// Members of the party, including the leader, in the order they joined.
+    pub members: Vec<ClientId>,
+    /// Planes instanced for this party, keyed by a script-defined name.
+    pub instances: HashMap<String, Stable<PlaneId>>,
+}
+
+pub struct Parties {
+    parties: HashMap<PartyId, Party>,
+    client_party: HashMap<ClientId, PartyId>,
+    /// Pending invitations, mapping the invited client to the one who invited it.
+    invites: HashMap<ClientId, ClientId>,
+    next_id: PartyId,
+    marker_timer: Option<timer::Cookie>,
+}
+
+impl Parties {
+    pub fn new() -> Parties {
+        Parties {
+            parties: HashMap::new(),
+            client_party: HashMap::new(),
+            invites: HashMap::new(),
+            next_id: 1,
+            marker_timer: None,
+        }
+    }
+
+    pub fn party_id(&self, cid: ClientId) -> Option<PartyId> {
+        self.client_party.get(&cid).cloned()
+    }
+
+    pub fn client_party(&self, cid: ClientId) -> Option<&Party> {
+        self.party_id(cid).and_then(|id| self.parties.get(&id))
+    }
+
+    fn client_party_mut(&mut self, cid: ClientId) -> Option<&mut Party> {
+        let id = unwrap_or!(self.party_id(cid), return None);
+        self.parties.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> ::std::collections::hash_map::Iter<PartyId, Party> {
+        self.parties.iter()
+    }
+
+    /// Get the client who invited `cid` to a party, if any.
+    pub fn invitation(&self, cid: ClientId) -> Option<ClientId> {
+        self.invites.get(&cid).cloned()
+    }
+
+    fn create(&mut self, leader: ClientId, members: Vec<ClientId>) -> PartyId {
+        let id = self.next_id;
+        self.next_id += 1;
+        for &cid in &members {
+            self.client_party.insert(cid, id);
+        }
+        self.parties.insert(id, Party {
+            leader: leader,
+            members: members,
+            instances: HashMap::new(),
+        });
+        id
+    }
+}
+
+
+fn client_name(eng: &EngineRef, cid: ClientId) -> String {
+    eng.world().get_client(cid).map_or(String::new(), |c| c.name().to_owned())
+}
+
+/// Send a notice to every member of a party.
+fn notify_party(eng: &EngineRef, id: PartyId, msg: &str) {
+    let party = unwrap_or!(eng.extra().parties.parties.get(&id));
+    for &cid in &party.members {
+        notice(eng, cid, msg);
+    }
+}
+
+/// Send the current member list to every member of a party.
+fn send_roster(eng: &EngineRef, id: PartyId) {
+    let party = unwrap_or!(eng.extra().parties.parties.get(&id));
+    let roster = party.members.iter()
+                      .map(|&cid| (cid, cid == party.leader, client_name(eng, cid)))
+                      .collect::<Vec<_>>();
+    for &cid in &party.members {
+        eng.messages().send_client(cid, ClientResponse::PartyUpdate(roster.clone()));
+    }
+}
+
+
+pub fn invite(mut eng: EngineRef, cid: ClientId, target: ClientId) -> StrResult<()> {
+    unwrap!(eng.world().get_client(cid));
+    unwrap!(eng.world().get_client(target));
+    if cid == target {
+        fail!("can't invite yourself");
+    }
+    if eng.extra().parties.party_id(target).is_some() {
+        fail!("that player is already in a party");
+    }
+    if let Some(party) = eng.extra().parties.client_party(cid) {
+        if party.leader != cid {
+            fail!("only the party leader can invite players");
+        }
+        if party.members.len() >= MAX_PARTY_SIZE {
+            fail!("the party is full");
+        }
+    }
+
+    eng.extra_mut().parties.invites.insert(target, cid);
+    let name = client_name(&eng, cid);
+    notice(&eng, target, &format!("{} invited you to a party.  \
+                                   Type /party accept to join.", name));
+    notice(&eng, cid, &format!("Invited {} to your party", client_name(&eng, target)));
+    Ok(())
+}
+
+pub fn accept(mut eng: EngineRef, cid: ClientId) -> StrResult<()> {
+    let inviter = unwrap!(eng.extra_mut().parties.invites.remove(&cid),
+                          "no pending invitation");
+    unwrap!(eng.world().get_client(inviter), "the inviting player has left");
+    if eng.extra().parties.party_id(cid).is_some() {
+        fail!("already in a party");
+    }
+
+    let id = match eng.extra().parties.party_id(inviter) {
+        Some(id) => {
+            let party = &eng.extra().parties.parties[&id];
+            if party.leader != inviter {
+                fail!("the inviting player is no longer the party leader");
+            }
+            if party.members.len() >= MAX_PARTY_SIZE {
+                fail!("the party is full");
+            }
+            Some(id)
+        },
+        None => None,
+    };
+
+    let id = match id {
+        Some(id) => {
+            let parties = &mut eng.extra_mut().parties;
+            parties.client_party.insert(cid, id);
+            parties.parties.get_mut(&id).unwrap().members.push(cid);
+            id
+        },
+        None => eng.extra_mut().parties.create(inviter, vec![inviter, cid]),
+    };
+
+    let name = client_name(&eng, cid);
+    notify_party(&eng, id, &format!("{} joined the party", name));
+    send_roster(&eng, id);
+    schedule_markers(eng);
+    Ok(())
+}
+
+pub fn decline(mut eng: EngineRef, cid: ClientId) -> StrResult<()> {
+    let inviter = unwrap!(eng.extra_mut().parties.invites.remove(&cid),
+                          "no pending invitation");
+    if eng.world().get_client(inviter).is_some() {
+        let name = client_name(&eng, cid);
+        notice(&eng, inviter, &format!("{} declined your invitation", name));
+    }
+    Ok(())
+}
+
+pub fn leave(mut eng: EngineRef, cid: ClientId) -> StrResult<()> {
+    let id = unwrap!(eng.extra().parties.party_id(cid), "not in a party");
+    let name = client_name(&eng, cid);
+
+    let (new_leader, disband) = {
+        let parties = &mut eng.extra_mut().parties;
+        parties.client_party.remove(&cid);
+        let party = parties.parties.get_mut(&id).unwrap();
+        party.members.retain(|&m| m != cid);
+        let new_leader =
+            if party.leader == cid {
+                party.leader = party.members[0];
+                Some(party.leader)
+            } else {
+                None
+            };
+        (new_leader, party.members.len() < 2)
+    };
+
+    eng.messages().send_client(cid, ClientResponse::PartyUpdate(Vec::new()));
+    notify_party(&eng, id, &format!("{} left the party", name));
+
+    if disband {
+        let party = eng.extra_mut().parties.parties.remove(&id).unwrap();
+        for &m in &party.members {
+            eng.extra_mut().parties.client_party.remove(&m);
+            notice(&eng, m, "The party has disbanded");
+            eng.messages().send_client(m, ClientResponse::PartyUpdate(Vec::new()));
+        }
+    } else {
+        if let Some(leader) = new_leader {
+            let leader_name = client_name(&eng, leader);
+            notify_party(&eng, id, &format!("{} is now the party leader", leader_name));
+        }
+        send_roster(&eng, id);
+    }
+    Ok(())
+}
+
+pub fn set_leader(mut eng: EngineRef, cid: ClientId, target: ClientId) -> StrResult<()> {
+    let id = {
+        let parties = &mut eng.extra_mut().parties;
+        let id = unwrap!(parties.party_id(cid), "not in a party");
+        if parties.party_id(target) != Some(id) {
+            fail!("that player is not in your party");
+        }
+        let party = parties.client_party_mut(cid).unwrap();
+        if party.leader != cid {
+            fail!("only the party leader can choose a new leader");
+        }
+        party.leader = target;
+        id
+    };
+
+    let name = client_name(&eng, target);
+    notify_party(&eng, id, &format!("{} is now the party leader", name));
+    send_roster(&eng, id);
+    Ok(())
+}
+
+/// Remove a client from its party and cancel its invitations.  Called on logout.
+pub fn remove_client(mut eng: EngineRef, cid: ClientId) {
+    {
+        let invites = &mut eng.extra_mut().parties.invites;
+        invites.remove(&cid);
+        let pending = invites.iter()
+                             .filter(|&(_, &inviter)| inviter == cid)
+                             .map(|(&invitee, _)| invitee)
+                             .collect::<Vec<_>>();
+        for invitee in pending {
+            invites.remove(&invitee);
+        }
+    }
+
+    if eng.extra().parties.party_id(cid).is_some() {
+        warn_on_err!(leave(eng, cid));
+    }
+}
+
+
+pub fn instance(extra: &logic::extra::Extra,
+                cid: ClientId,
+                key: &str) -> Option<Stable<PlaneId>> {
+    extra.parties.client_party(cid).and_then(|p| p.instances.get(key).cloned())
+}
+
+pub fn set_instance(mut eng: EngineRef,
+                    cid: ClientId,
+                    key: &str,
+                    stable_pid: Stable<PlaneId>) -> StrResult<()> {
+    let party = unwrap!(eng.extra_mut().parties.client_party_mut(cid), "not in a party");
+    party.instances.insert(key.to_owned(), stable_pid);
+    Ok(())
+}
+
+
+/// Forget a destroyed instance in every party that was using it.
+pub fn forget_instance(mut eng: EngineRef, stable_pid: Stable<PlaneId>) {
+    for party in eng.extra_mut().parties.parties.values_mut() {
+        let keys = party.instances.iter()
+                        .filter(|&(_, &id)| id == stable_pid)
+                        .map(|(k, _)| k.clone())
+                        .collect::<Vec<_>>();
+        for k in keys {
+            party.instances.remove(&k);
+        }
+    }
+}
+
+
+/// Record the parties to the restart file.  Returns, for each client, the index of its party
+/// (starting from 1, with 0 for no party) and whether it is the leader.
+pub fn restart_info(eng: &EngineRef) -> HashMap<ClientId, (u16, bool)> {
+    let mut info = HashMap::new();
+    for (i, (_, party)) in eng.extra().parties.iter().enumerate() {
+        for &cid in &party.members {
+            info.insert(cid, (i as u16 + 1, cid == party.leader));
+        }
+    }
+    info
+}
+
+/// Rebuild the parties recorded with `restart_info`, after the clients have logged back in.
+pub fn restore(mut eng: EngineRef, members: Vec<(ClientId, u16, bool)>) {
+    let mut groups = HashMap::new();
+    for (cid, index, leader) in members {
+        if index == 0 || eng.world().get_client(cid).is_none() {
+            continue;
+        }
+        let group = groups.entry(index).or_insert_with(|| (None, Vec::new()));
+        if leader {
+            group.0 = Some(cid);
+        }
+        group.1.push(cid);
+    }
+
+    for (_, (leader, members)) in groups {
+        if members.len() < 2 {
+            continue;
+        }
+        let leader = leader.unwrap_or(members[0]);
+        let id = eng.extra_mut().parties.create(leader, members);
+        send_roster(&eng, id);
+    }
+    schedule_markers(eng);
+}
+
+
+/// Start sending teammate positions, if there are any parties and the updates aren't already
+/// running.
+fn schedule_markers(mut eng: EngineRef) {
+    if eng.extra().parties.marker_timer.is_some() || eng.extra().parties.parties.len() == 0 {
+        return;
+    }
+    let when = eng.now() + MARKER_INTERVAL;
+    let cookie = eng.timer_mut().schedule(when, |eng| send_markers(eng));
+    eng.extra_mut().parties.marker_timer = Some(cookie);
+}
+
+/// Send each party member the positions of its teammates, relative to its camera.
+fn send_markers(mut eng: EngineRef) {
+    eng.extra_mut().parties.marker_timer = None;
+
+    let now = eng.now();
+    for (_, party) in eng.extra().parties.iter() {
+        let positions = party.members.iter().filter_map(|&cid| {
+            let c = unwrap_or!(eng.world().get_client(cid), return None);
+            let e = unwrap_or!(c.pawn(), return None);
+            Some((cid, e.stable_plane_id(), e.pos(now)))
+        }).collect::<Vec<_>>();
+
+        for &cid in &party.members {
+            let (stable_pid, motion) =
+                unwrap_or!(logic::camera::focus(eng.world(), eng.extra(), cid), continue);
+            let center = motion.pos(now);
+            let markers = positions.iter()
+                                   .filter(|&&(other, _, _)| other != cid)
+                                   .map(|&(other, other_pid, pos)| {
+                                       (other, other_pid == stable_pid, pos - center)
+                                   })
+                                   .collect::<Vec<_>>();
+            eng.messages().send_client(cid, ClientResponse::PartyMarkers(markers));
+        }
+    }
+
+    schedule_markers(eng);
+}
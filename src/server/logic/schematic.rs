@@ -0,0 +1,391 @@
+//! Schematics: blueprints of a region of terrain and the structures in it.
+//!
+//! A schematic records blocks and structure templates by name, with positions relative to the
+//! region's minimum corner, so it can be pasted anywhere and survives changes to the numeric IDs
+//! in the game data.  Structures can optionally carry the contents of their attached inventories,
+//! along with the names the scripts use for them.  Item instances are not captured.
+//!
+//! Pasting can rotate the schematic in 90 degree steps.  Rotation works on names: each path
+//! component of a block or template name made up only of the letters `n`, `s`, `w` and `e` is
+//! treated as a set of directions and rotated along with the schematic, so `fence/corner/ne`
+//! becomes `fence/corner/se` after one clockwise turn.  Names without a rotated variant are left
+//! as they are for blocks, and cause the paste to fail for structures.
+//!
+//! Schematics are saved as JSON in the `save/schematics` directory.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use rustc_serialize::json;
+
+use libphysics::CHUNK_SIZE;
+use types::*;
+use util::StrResult;
+
+use engine::split::EngineRef;
+use logic;
+use world::{self, World, InventoryAttachment};
+use world::object::*;
+
+
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Schematic {
+    pub size: (i32, i32, i32),
+    /// Names of the block types that appear in `blocks`.
+    pub block_names: Vec<String>,
+    /// Each block in the region, as an index into `block_names`, in `Region::index` order.
+    pub blocks: Vec<u16>,
+    pub structures: Vec<SchematicStructure>,
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct SchematicStructure {
+    /// Position of the structure's minimum corner, relative to the region's minimum corner.
+    pub pos: (i32, i32, i32),
+    pub template: String,
+    pub inventories: Vec<SchematicInventory>,
+}
+
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct SchematicInventory {
+    /// Name the scripts use for the inventory on its structure, such as `contents`.
+    pub name: String,
+    /// Contents of the inventory, as (item name, count) pairs.
+    pub contents: Vec<(String, u16)>,
+}
+
+fn to_v3((x, y, z): (i32, i32, i32)) -> V3 {
+    V3::new(x, y, z)
+}
+
+fn from_v3(v: V3) -> (i32, i32, i32) {
+    (v.x, v.y, v.z)
+}
+
+
+/// Find the structures that lie entirely inside `region`.
+pub fn region_structures(w: &World, pid: PlaneId, region: Region) -> Vec<StructureId> {
+    let mut sids = Vec::new();
+    let mut seen = HashSet::new();
+    for cpos in region.reduce().div_round_signed(CHUNK_SIZE).points() {
+        for s in w.chunk_structures(pid, cpos) {
+            if !seen.insert(s.id()) {
+                continue;
+            }
+            let bounds = s.bounds();
+            if bounds.intersect(region) == bounds {
+                sids.push(s.id());
+            }
+        }
+    }
+    sids
+}
+
+/// Record the blocks and structures in `region`.  Only structures that lie entirely inside the
+/// region are included.  If `inventory_names` is given, the contents of each structure inventory
+/// listed there are recorded under its name.  Inventories missing from the map are left out.
+pub fn capture(w: &World,
+               pid: PlaneId,
+               region: Region,
+               inventory_names: Option<&HashMap<InventoryId, String>>) -> StrResult<Schematic> {
+    if region.is_empty() {
+        fail!("region is empty");
+    }
+    if region.volume() > logic::terrain::MAX_EDIT_VOLUME {
+        fail!("region is too large");
+    }
+    if region.min.z < 0 || region.max.z > CHUNK_SIZE {
+        fail!("region is outside the plane's height range");
+    }
+
+    let data = w.data();
+    let p = unwrap!(w.get_plane(pid));
+    let chunks = region.reduce().div_round_signed(CHUNK_SIZE);
+    for cpos in chunks.points() {
+        if p.get_terrain_chunk(cpos).is_none() {
+            fail!("region includes chunks that are not loaded");
+        }
+    }
+
+    let mut block_names = Vec::new();
+    let mut palette = HashMap::new();
+    let mut blocks = Vec::with_capacity(region.volume() as usize);
+    for pos in region.points() {
+        let cpos = pos.reduce().div_floor(scalar(CHUNK_SIZE));
+        let tc = p.terrain_chunk(cpos);
+        let block_id = tc.blocks()[tc.bounds().index(pos)];
+        let idx = *palette.entry(block_id).or_insert_with(|| {
+            block_names.push(data.block_data.name(block_id).to_owned());
+            block_names.len() as u16 - 1
+        });
+        blocks.push(idx);
+    }
+
+    let mut structures = Vec::new();
+    for sid in region_structures(w, pid, region) {
+        let s = w.structure(sid);
+
+        let mut inventories = Vec::new();
+        if let Some(names) = inventory_names {
+            for i in s.child_inventories() {
+                let name = match names.get(&i.id()) {
+                    Some(x) => x.clone(),
+                    None => continue,
+                };
+                let mut contents = i.contents().iter()
+                                    .filter(|&(_, &count)| count > 0)
+                                    .map(|(&item_id, &count)| {
+                                        (data.item_data.name(item_id).to_owned(), count)
+                                    })
+                                    .collect::<Vec<_>>();
+                contents.sort();
+                inventories.push(SchematicInventory {
+                    name: name,
+                    contents: contents,
+                });
+            }
+            // `child_inventories` has no fixed order.
+            inventories.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        structures.push(SchematicStructure {
+            pos: from_v3(s.bounds().min - region.min),
+            template: s.template().name.clone(),
+            inventories: inventories,
+        });
+    }
+
+    Ok(Schematic {
+        size: from_v3(region.size()),
+        block_names: block_names,
+        blocks: blocks,
+        structures: structures,
+    })
+}
+
+
+/// Paste `schem` with its minimum corner at `pos`, after rotating it `rotation` quarter turns
+/// clockwise.  The target area must not contain any structures.  If any part of the paste fails,
+/// the terrain is restored and no structures or inventories are left behind.  Returns the new
+/// structures, each with its restored inventories and their names.
+pub fn paste<'d, WF>(wf: &mut WF,
+                     pid: PlaneId,
+                     pos: V3,
+                     schem: &Schematic,
+                     rotation: u8) -> StrResult<Vec<(StructureId, Vec<(String, InventoryId)>)>>
+        where WF: world::Fragment<'d> {
+    let rotation = rotation % 4;
+    let src_size = to_v3(schem.size);
+    let src_region = Region::new(scalar(0), src_size);
+    if schem.blocks.len() != src_region.volume() as usize {
+        fail!("schematic block data has the wrong size");
+    }
+    let dest_region = Region::new(pos, pos + rotate_size(src_size, rotation));
+
+    // Resolve all names before changing anything.
+    let mut new_blocks = vec![0; schem.blocks.len()];
+    let mut structures = Vec::with_capacity(schem.structures.len());
+    {
+        let data = wf.world().data();
+
+        let mut palette = Vec::with_capacity(schem.block_names.len());
+        for name in &schem.block_names {
+            let rotated = rotate_name(name, rotation);
+            let block_id = match data.block_data.find_id(&rotated) {
+                Some(x) => x,
+                None => unwrap!(data.block_data.find_id(name)),
+            };
+            palette.push(block_id);
+        }
+        for p in src_region.points() {
+            let idx = schem.blocks[src_region.index(p)] as usize;
+            let block_id = *unwrap!(palette.get(idx));
+            let dest = pos + rotate_box(p, scalar(1), src_size, rotation);
+            new_blocks[dest_region.index(dest)] = block_id;
+        }
+
+        for ss in &schem.structures {
+            let old_tid = unwrap!(data.structure_templates.find_id(&ss.template));
+            let old_size = data.structure_templates.template(old_tid).size;
+            let tid = unwrap!(data.structure_templates.find_id(&rotate_name(&ss.template,
+                                                                            rotation)));
+            let t = data.structure_templates.template(tid);
+            let dest = pos + rotate_box(to_v3(ss.pos), old_size, src_size, rotation);
+
+            let mut inventories = Vec::with_capacity(ss.inventories.len());
+            for si in &ss.inventories {
+                let mut items = Vec::with_capacity(si.contents.len());
+                for &(ref name, count) in &si.contents {
+                    items.push((unwrap!(data.item_data.find_id(name)), count));
+                }
+                inventories.push((&si.name, items));
+            }
+
+            structures.push((t.layer, dest, tid, inventories));
+        }
+        // Lower layers must be placed first, since higher layers may rest on them.
+        structures.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let w = wf.world();
+        for cpos in dest_region.reduce().div_round_signed(CHUNK_SIZE).points() {
+            if w.chunk_structures(pid, cpos).any(|s| s.bounds().overlaps(dest_region)) {
+                fail!("target area is not clear of structures");
+            }
+        }
+    }
+
+    let mut old_blocks = Vec::with_capacity(new_blocks.len());
+    try!(logic::terrain::edit_blocks(wf, pid, dest_region, |p, old| {
+        old_blocks.push(old);
+        Some(new_blocks[dest_region.index(p)])
+    }));
+
+    let mut created = Vec::with_capacity(structures.len());
+    for &(_, dest, tid, ref inventories) in &structures {
+        if let Err(e) = paste_structure(wf, pid, dest, tid, inventories, &mut created) {
+            // Destroying the structures also destroys the inventories attached to them.
+            for &(sid, _) in &created {
+                warn_on_err!(wf.destroy_structure(sid));
+            }
+            warn_on_err!(logic::terrain::edit_blocks(wf, pid, dest_region, |p, _| {
+                Some(old_blocks[dest_region.index(p)])
+            }));
+            return Err(e);
+        }
+    }
+
+    Ok(created)
+}
+
+/// Create one pasted structure and its inventories, adding it to `created` as soon as it exists
+/// so the caller can clean it up if a later step fails.
+fn paste_structure<'d, WF>(wf: &mut WF,
+                           pid: PlaneId,
+                           pos: V3,
+                           tid: TemplateId,
+                           inventories: &[(&String, Vec<(ItemId, u16)>)],
+                           created: &mut Vec<(StructureId, Vec<(String, InventoryId)>)>)
+                           -> StrResult<()>
+        where WF: world::Fragment<'d> {
+    let sid = try!(wf.create_structure(pid, pos, tid)).id();
+    created.push((sid, Vec::with_capacity(inventories.len())));
+
+    for &(name, ref items) in inventories {
+        let iid = try!(wf.create_inventory()).id();
+        // Attach before filling, so the inventory goes away with the structure on failure.
+        let attached = wf.inventory_mut(iid).set_attachment(InventoryAttachment::Structure(sid));
+        if let Err(e) = attached {
+            warn_on_err!(wf.destroy_inventory(iid));
+            return Err(e);
+        }
+        created.last_mut().unwrap().1.push((name.clone(), iid));
+
+        let mut i = wf.inventory_mut(iid);
+        for &(item_id, count) in items {
+            try!(i.update(item_id, count as i32));
+        }
+    }
+    Ok(())
+}
+
+
+fn rotate_size(size: V3, rotation: u8) -> V3 {
+    if rotation % 2 == 0 {
+        size
+    } else {
+        V3::new(size.y, size.x, size.z)
+    }
+}
+
+/// Compute the new minimum corner of a box at `pos` with size `box_size`, inside a region of size
+/// `size`, after rotating the region `rotation` quarter turns clockwise.
+fn rotate_box(pos: V3, box_size: V3, size: V3, rotation: u8) -> V3 {
+    let mut pos = pos;
+    let mut box_size = box_size;
+    let mut size = size;
+    for _ in 0 .. rotation {
+        pos = V3::new(size.y - (pos.y + box_size.y), pos.x, pos.z);
+        box_size = rotate_size(box_size, 1);
+        size = rotate_size(size, 1);
+    }
+    pos
+}
+
+const DIRECTIONS: &'static str = "nswe";
+
+fn rotate_dir(c: char) -> char {
+    match c {
+        'n' => 'e',
+        'e' => 's',
+        's' => 'w',
+        'w' => 'n',
+        _ => c,
+    }
+}
+
+/// Check whether a name component is a direction suffix: a single direction, or a corner like
+/// `ne`.
+fn is_direction(part: &str) -> bool {
+    match part {
+        "n" | "s" | "e" | "w" | "ne" | "nw" | "se" | "sw" => true,
+        _ => false,
+    }
+}
+
+/// Rotate the direction suffixes of `name` by `rotation` quarter turns clockwise.  Only
+/// components after the first are rotated, so `tee/n` and `corner/ne/in` have direction
+/// variants, but a template named `n` or `house/new` is left alone.
+fn rotate_name(name: &str, rotation: u8) -> String {
+    let parts = name.split('/').enumerate().map(|(i, part)| {
+        if i == 0 || !is_direction(part) {
+            return part.to_owned();
+        }
+
+        let mut dirs = part.chars().collect::<Vec<_>>();
+        for _ in 0 .. rotation {
+            for c in &mut dirs {
+                *c = rotate_dir(*c);
+            }
+        }
+        // Put the directions back in canonical order.
+        DIRECTIONS.chars().filter(|c| dirs.contains(c)).collect::<String>()
+    }).collect::<Vec<_>>();
+    parts.join("/")
+}
+
+
+pub fn save(eng: EngineRef,
+            name: &str,
+            pid: PlaneId,
+            region: Region,
+            inventory_names: Option<&HashMap<InventoryId, String>>) -> StrResult<()> {
+    let schem = try!(capture(eng.world(), pid, region, inventory_names));
+    let encoded = unwrap!(json::encode(&schem).ok());
+    let mut file = eng.storage().create_schematic_file(name);
+    if let Err(e) = file.write_all(encoded.as_bytes()) {
+        warn!("error writing schematic {}: {}", name, e);
+        fail!("failed to write schematic file");
+    }
+    Ok(())
+}
+
+pub fn load(eng: &EngineRef, name: &str) -> StrResult<Schematic> {
+    let mut file = unwrap!(eng.storage().open_schematic_file(name),
+                           "no schematic with that name");
+    let mut s = String::new();
+    if let Err(e) = file.read_to_string(&mut s) {
+        warn!("error reading schematic {}: {}", name, e);
+        fail!("failed to read schematic file");
+    }
+    let schem = unwrap!(json::decode(&s).ok(), "schematic file is invalid");
+    Ok(schem)
+}
+
+pub fn paste_saved(mut eng: EngineRef,
+                   name: &str,
+                   pid: PlaneId,
+                   pos: V3,
+                   rotation: u8) -> StrResult<Vec<(StructureId, Vec<(String, InventoryId)>)>> {
+    let schem = try!(load(&eng, name));
+    paste(&mut eng.as_world_fragment(), pid, pos, &schem, rotation)
+}
+
@@ -0,0 +1,164 @@
+//! Bulk terrain editing.
+//!
+//! Each edit is computed in full before anything is changed, so an edit that would break a
+//! structure's support fails without modifying any blocks.  Once the edit is applied, each changed
+//! block gets an `on_block_change`, and each affected chunk gets a single
+//! `on_terrain_chunk_update`, which also refreshes the terrain cache.
+
+use std::collections::HashSet;
+
+use libphysics::CHUNK_SIZE;
+use types::*;
+
+use world::{self, World, Hooks};
+use world::object::*;
+
+
+/// Maximum number of blocks a single edit can cover.
+pub const MAX_EDIT_VOLUME: i32 = 64 * 64 * CHUNK_SIZE;
+
+
+/// Replace each block in `region` with the result of `f(pos, old_block)`, or leave it unchanged
+/// if `f` returns `None`.  Returns the number of blocks that changed.
+pub fn edit_blocks<'d, WF, F>(wf: &mut WF,
+                              pid: PlaneId,
+                              region: Region,
+                              mut f: F) -> world::OpResult<usize>
+        where WF: world::Fragment<'d>,
+              F: FnMut(V3, BlockId) -> Option<BlockId> {
+    if region.is_empty() {
+        return Ok(0);
+    }
+    if region.volume() > MAX_EDIT_VOLUME {
+        fail!("region is too large");
+    }
+    if region.min.z < 0 || region.max.z > CHUNK_SIZE {
+        fail!("region is outside the plane's height range");
+    }
+
+    let chunks = region.reduce().div_round_signed(CHUNK_SIZE);
+    let mut updates = Vec::with_capacity(region.volume() as usize);
+    {
+        let w = wf.world();
+        let p = unwrap!(w.get_plane(pid));
+        for cpos in chunks.points() {
+            if p.get_terrain_chunk(cpos).is_none() {
+                fail!("region includes chunks that are not loaded");
+            }
+        }
+
+        for pos in region.points() {
+            let cpos = pos.reduce().div_floor(scalar(CHUNK_SIZE));
+            let tc = p.terrain_chunk(cpos);
+            let old = tc.blocks()[tc.bounds().index(pos)];
+            updates.push(f(pos, old).and_then(|new| if new != old { Some(new) } else { None }));
+        }
+
+        try!(check_structure_support(w, pid, region, &updates));
+    }
+
+    let mut changed_chunks = HashSet::new();
+    let mut changes = Vec::new();
+    {
+        let mut p = wf.plane_mut(pid);
+        for pos in region.points() {
+            let block_id = unwrap_or!(updates[region.index(pos)], continue);
+            let cpos = pos.reduce().div_floor(scalar(CHUNK_SIZE));
+            let mut tc = p.terrain_chunk_mut(cpos);
+            let idx = tc.bounds().index(pos);
+            changes.push((pos, tc.blocks()[idx], block_id));
+            tc.blocks_mut()[idx] = block_id;
+            changed_chunks.insert(cpos);
+        }
+    }
+
+    for &(pos, old, new) in &changes {
+        wf.with_hooks(|h| h.on_block_change(pid, pos, old, new));
+    }
+
+    for cpos in changed_chunks {
+        let tcid = wf.world().plane(pid).terrain_chunk(cpos).id();
+        wf.with_hooks(|h| h.on_terrain_chunk_update(tcid));
+    }
+
+    Ok(changes.len())
+}
+
+/// Check that every structure touching `region` will still have room and a floor after the
+/// blocks in `updates` are applied.  A structure can rest on a terrain floor or on another
+/// structure in a lower layer.
+fn check_structure_support(w: &World,
+                           pid: PlaneId,
+                           region: Region,
+                           updates: &[Option<BlockId>]) -> world::OpResult<()> {
+    let bd = &w.data().block_data;
+    let mut seen = HashSet::new();
+
+    for cpos in region.reduce().div_round_signed(CHUNK_SIZE).points() {
+        for s in w.chunk_structures(pid, cpos) {
+            if !seen.insert(s.id()) {
+                continue;
+            }
+
+            let bounds = s.bounds();
+            let layer = s.template().layer;
+            for pos in bounds.intersect(region).points() {
+                let block_id = unwrap_or!(updates[region.index(pos)], continue);
+                let shape = bd.shape(block_id);
+
+                if pos.z != bounds.min.z {
+                    if shape != Shape::Empty {
+                        fail!("new terrain would overlap a structure");
+                    }
+                    continue;
+                }
+
+                match shape {
+                    Shape::Floor => {},
+                    Shape::Empty => {
+                        if layer > 0 && !has_support(w, pid, pos, layer) {
+                            fail!("removing the floor would leave a structure floating");
+                        }
+                    },
+                    _ => fail!("new terrain would overlap a structure"),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn has_support(w: &World, pid: PlaneId, pos: V3, layer: u8) -> bool {
+    let cpos = pos.reduce().div_floor(scalar(CHUNK_SIZE));
+    w.chunk_structures(pid, cpos)
+     .any(|s| s.template().layer < layer && s.bounds().contains(pos))
+}
+
+
+pub fn set_block<'d, WF>(wf: &mut WF,
+                         pid: PlaneId,
+                         pos: V3,
+                         block_id: BlockId) -> world::OpResult<bool>
+        where WF: world::Fragment<'d> {
+    let region = Region::new(pos, pos + scalar(1));
+    let count = try!(edit_blocks(wf, pid, region, |_, _| Some(block_id)));
+    Ok(count > 0)
+}
+
+pub fn fill<'d, WF>(wf: &mut WF,
+                    pid: PlaneId,
+                    region: Region,
+                    block_id: BlockId) -> world::OpResult<usize>
+        where WF: world::Fragment<'d> {
+    edit_blocks(wf, pid, region, |_, _| Some(block_id))
+}
+
+pub fn replace<'d, WF>(wf: &mut WF,
+                       pid: PlaneId,
+                       region: Region,
+                       from: BlockId,
+                       to: BlockId) -> world::OpResult<usize>
+        where WF: world::Fragment<'d> {
+    edit_blocks(wf, pid, region, |_, old| if old == from { Some(to) } else { None })
+}
@@ -0,0 +1,384 @@
+//! Player-to-player trading.
+//!
+//! Each side of a trade session gets an offer inventory, attached to that side's client.  Players
+//! move items between their main inventory and their offer, then lock the offer once it's final.
+//! When both offers are locked, each side can confirm the trade.  Once both sides have confirmed,
+//! the two offers are delivered to the other side's main inventory in a single step: either both
+//! transfers happen, or neither does.  A cancelled session returns each offer to its owner.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use libphysics::TILE_SIZE;
+use types::*;
+use util::StrResult;
+
+use engine::split::EngineRef;
+use messages::{ClientResponse, Dialog, TradeAction, TradeState};
+use timer;
+use world::{self, InventoryAttachment};
+use world::object::*;
+use vision;
+
+
+/// Maximum distance (in pixels, along each axis) between the pawns of two trading players.
+const TRADE_RANGE: i32 = 8 * TILE_SIZE;
+
+/// How often to check that the players in a session are still within range.
+const RANGE_CHECK_INTERVAL: Time = 1000;
+
+
+struct Side {
+    cid: ClientId,
+    main_iid: InventoryId,
+    offer_iid: InventoryId,
+    state: TradeState,
+}
+
+struct Session {
+    sides: [Side; 2],
+    range_timer: timer::Cookie,
+}
+
+pub struct Trades {
+    /// Pending invitations.  Maps each invited client to the inviting client and the inviter's
+    /// main inventory.
+    invites: HashMap<ClientId, (ClientId, InventoryId)>,
+    sessions: HashMap<u32, Session>,
+    client_session: HashMap<ClientId, u32>,
+    next_id: u32,
+}
+
+impl Trades {
+    pub fn new() -> Trades {
+        Trades {
+            invites: HashMap::new(),
+            sessions: HashMap::new(),
+            client_session: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Find the session that uses `iid` as an offer inventory, if any.  Returns the session and
+    /// the index of the side that owns the offer.
+    fn find_offer(&self, iid: InventoryId) -> Option<(u32, usize)> {
+        for (&id, session) in self.sessions.iter() {
+            for (idx, side) in session.sides.iter().enumerate() {
+                if side.offer_iid == iid {
+                    return Some((id, idx));
+                }
+            }
+        }
+        None
+    }
+
+    pub fn is_offer(&self, iid: InventoryId) -> bool {
+        self.find_offer(iid).is_some()
+    }
+}
+
+
+fn send_message(eng: &mut EngineRef, cid: ClientId, msg: String) {
+    eng.messages_mut().send_client(cid, ClientResponse::ChatUpdate(format!("***\t{}", msg)));
+}
+
+fn check_range(eng: &EngineRef, cid1: ClientId, cid2: ClientId) -> StrResult<()> {
+    let now = eng.now();
+    let world = eng.world();
+    let pawn1 = unwrap!(unwrap!(world.get_client(cid1)).pawn());
+    let pawn2 = unwrap!(unwrap!(world.get_client(cid2)).pawn());
+    if pawn1.plane_id() != pawn2.plane_id() ||
+       (pawn1.pos(now) - pawn2.pos(now)).abs().max() > TRADE_RANGE {
+        fail!("too far away to trade");
+    }
+    Ok(())
+}
+
+/// Handle a trade request from `cid` (whose items come from `main_iid`) to `other_cid`.  If
+/// `other_cid` has already invited `cid` to trade, this starts a session.  Otherwise, it invites
+/// `other_cid` to trade with `cid`.
+pub fn request(mut eng: EngineRef,
+               cid: ClientId,
+               main_iid: InventoryId,
+               other_cid: ClientId) -> StrResult<()> {
+    if cid == other_cid {
+        fail!("can't trade with yourself");
+    }
+    unwrap!(eng.world().get_inventory(main_iid));
+    let other_name = unwrap!(eng.world().get_client(other_cid)).name().to_owned();
+    let name = unwrap!(eng.world().get_client(cid)).name().to_owned();
+    if eng.extra().trades.client_session.contains_key(&cid) {
+        fail!("you are already trading");
+    }
+    if eng.extra().trades.client_session.contains_key(&other_cid) {
+        fail!("{} is already trading", other_name);
+    }
+    try!(check_range(&eng, cid, other_cid));
+
+    let accepted = match eng.extra().trades.invites.get(&cid) {
+        Some(&(inviter, inviter_iid)) if inviter == other_cid => Some(inviter_iid),
+        _ => None,
+    };
+
+    if let Some(other_main_iid) = accepted {
+        eng.extra_mut().trades.invites.remove(&cid);
+        unwrap!(eng.world().get_inventory(other_main_iid),
+                "the other player's inventory is gone");
+        // `commit` checks each side's delivery on its own, which is only valid if they go to
+        // different inventories.
+        if other_main_iid == main_iid {
+            fail!("can't trade using the same inventory as {}", other_name);
+        }
+        start_session(eng, (other_cid, other_main_iid), (cid, main_iid))
+    } else {
+        eng.extra_mut().trades.invites.insert(other_cid, (cid, main_iid));
+        send_message(&mut eng, other_cid,
+                     format!("{} wants to trade.  Use /trade {} to accept.", name, name));
+        send_message(&mut eng, cid, format!("Sent trade request to {}", other_name));
+        Ok(())
+    }
+}
+
+fn start_session(mut eng: EngineRef,
+                 (cid1, main_iid1): (ClientId, InventoryId),
+                 (cid2, main_iid2): (ClientId, InventoryId)) -> StrResult<()> {
+    let offer_iid1 = try!(create_offer(&mut eng, cid1));
+    let offer_iid2 = try!(create_offer(&mut eng, cid2));
+
+    let id = eng.extra().trades.next_id;
+    eng.extra_mut().trades.next_id += 1;
+    let range_timer = schedule_range_check(&mut eng, id);
+
+    {
+        let trades = &mut eng.extra_mut().trades;
+        trades.sessions.insert(id, Session {
+            sides: [
+                Side { cid: cid1, main_iid: main_iid1, offer_iid: offer_iid1,
+                       state: TradeState::Open },
+                Side { cid: cid2, main_iid: main_iid2, offer_iid: offer_iid2,
+                       state: TradeState::Open },
+            ],
+            range_timer: range_timer,
+        });
+        trades.client_session.insert(cid1, id);
+        trades.client_session.insert(cid2, id);
+        // Neither client can accept any other invitations while the session is open.
+        trades.invites.remove(&cid1);
+        trades.invites.remove(&cid2);
+    }
+
+    for &(cid, main_iid, own_iid, other_iid) in &[(cid1, main_iid1, offer_iid1, offer_iid2),
+                                                   (cid2, main_iid2, offer_iid2, offer_iid1)] {
+        let dialog = Dialog::Trade(main_iid, own_iid, other_iid);
+        eng.messages_mut().send_client(cid, ClientResponse::OpenDialog(dialog));
+        for &iid in &[main_iid, own_iid, other_iid] {
+            vision::Fragment::subscribe_inventory(&mut eng.as_vision_fragment(), cid, iid);
+        }
+    }
+    send_status(&mut eng, id);
+    Ok(())
+}
+
+fn create_offer(eng: &mut EngineRef, cid: ClientId) -> StrResult<InventoryId> {
+    let mut wf = eng.as_world_fragment();
+    let mut i = try!(world::Fragment::create_inventory(&mut wf));
+    try!(i.set_attachment(InventoryAttachment::Client(cid)));
+    Ok(i.id())
+}
+
+fn schedule_range_check(eng: &mut EngineRef, id: u32) -> timer::Cookie {
+    let when = eng.now() + RANGE_CHECK_INTERVAL;
+    eng.timer_mut().schedule(when, move |eng| check_session_range(eng, id))
+}
+
+fn check_session_range(mut eng: EngineRef, id: u32) {
+    let (cid1, cid2) = {
+        let session = unwrap_or!(eng.extra().trades.sessions.get(&id));
+        (session.sides[0].cid, session.sides[1].cid)
+    };
+
+    if let Err(e) = check_range(&eng, cid1, cid2) {
+        cancel(eng, id, e.description());
+    } else {
+        let cookie = schedule_range_check(&mut eng, id);
+        // OK: the session was found above.
+        eng.extra_mut().trades.sessions.get_mut(&id).unwrap().range_timer = cookie;
+    }
+}
+
+fn send_status(eng: &mut EngineRef, id: u32) {
+    let states = {
+        let session = unwrap_or!(eng.extra().trades.sessions.get(&id));
+        [(session.sides[0].cid, session.sides[0].state, session.sides[1].state),
+         (session.sides[1].cid, session.sides[1].state, session.sides[0].state)]
+    };
+    for &(cid, own, other) in &states {
+        eng.messages_mut().send_client(cid, ClientResponse::TradeStatus(own, other));
+    }
+}
+
+
+/// Handle a change to the trade state requested by `cid`.
+pub fn action(mut eng: EngineRef, cid: ClientId, action: TradeAction) -> StrResult<()> {
+    let id = *unwrap!(eng.extra().trades.client_session.get(&cid), "you are not trading");
+
+    if action == TradeAction::Cancel {
+        let name = eng.world().client(cid).name().to_owned();
+        cancel(eng, id, &format!("{} cancelled the trade", name));
+        return Ok(());
+    }
+
+    let (idx, other_cid) = {
+        let session = &eng.extra().trades.sessions[&id];
+        let idx = if session.sides[0].cid == cid { 0 } else { 1 };
+        (idx, session.sides[1 - idx].cid)
+    };
+    if action != TradeAction::Unlock {
+        try!(check_range(&eng, cid, other_cid));
+    }
+
+    let commit_ready = {
+        let session = eng.extra_mut().trades.sessions.get_mut(&id).unwrap();
+        match action {
+            TradeAction::Cancel => unreachable!(),
+            TradeAction::Lock => {
+                if session.sides[idx].state != TradeState::Open {
+                    fail!("your offer is already locked");
+                }
+                session.sides[idx].state = TradeState::Locked;
+            },
+            TradeAction::Unlock => {
+                // Changing an offer invalidates both confirmations.
+                session.sides[idx].state = TradeState::Open;
+                if session.sides[1 - idx].state == TradeState::Confirmed {
+                    session.sides[1 - idx].state = TradeState::Locked;
+                }
+            },
+            TradeAction::Confirm => {
+                if session.sides.iter().any(|s| s.state == TradeState::Open) {
+                    fail!("both offers must be locked before confirming");
+                }
+                session.sides[idx].state = TradeState::Confirmed;
+            },
+        }
+        session.sides.iter().all(|s| s.state == TradeState::Confirmed)
+    };
+
+    if commit_ready {
+        if let Err(e) = commit(&mut eng, id) {
+            // Leave both offers in place, so the players can adjust them and try again.
+            {
+                let session = eng.extra_mut().trades.sessions.get_mut(&id).unwrap();
+                for side in session.sides.iter_mut() {
+                    side.state = TradeState::Locked;
+                }
+            }
+            send_status(&mut eng, id);
+            return Err(e);
+        }
+        close(eng, id, "Trade complete");
+    } else {
+        send_status(&mut eng, id);
+    }
+    Ok(())
+}
+
+/// Deliver each offer to the other side's main inventory.  Either both transfers succeed, or
+/// neither happens.  The two main inventories are always distinct (see `request`).
+fn commit(eng: &mut EngineRef, id: u32) -> StrResult<()> {
+    let (a, b) = {
+        let session = &eng.extra().trades.sessions[&id];
+        let a = &session.sides[0];
+        let b = &session.sides[1];
+        ((a.offer_iid, b.main_iid), (b.offer_iid, a.main_iid))
+    };
+
+    {
+        let world = eng.world();
+        let item_data = &world.data().item_data;
+        for &(from_iid, to_iid) in &[a, b] {
+            let from = unwrap!(world.get_inventory(from_iid));
+            let to = unwrap!(world.get_inventory(to_iid));
+            try!(to.check_space_for_contents(item_data, &from));
+        }
+    }
+
+    // Both transfers were checked above, and nothing else can touch the inventories in between,
+    // so the limits can't get in the way now.
+    let mut wf = eng.as_world_fragment();
+    // OK: inventory IDs have already been checked.
+    world::Fragment::inventory_mut(&mut wf, a.0).transfer_contents(a.1, true).unwrap();
+    world::Fragment::inventory_mut(&mut wf, b.0).transfer_contents(b.1, true).unwrap();
+    Ok(())
+}
+
+/// Cancel a session, returning each offer to its owner.
+fn cancel(mut eng: EngineRef, id: u32, reason: &str) {
+    let offers = {
+        let session = unwrap_or!(eng.extra().trades.sessions.get(&id));
+        [(session.sides[0].offer_iid, session.sides[0].main_iid),
+         (session.sides[1].offer_iid, session.sides[1].main_iid)]
+    };
+    for &(offer_iid, main_iid) in &offers {
+        // Ignore limits: the items came from the main inventory in the first place.
+        let mut wf = eng.as_world_fragment();
+        if let Some(mut i) = world::Fragment::get_inventory_mut(&mut wf, offer_iid) {
+            warn_on_err!(i.transfer_contents(main_iid, true));
+        }
+    }
+    close(eng, id, reason);
+}
+
+/// Tear down a session, whose offers should be empty by now.
+fn close(mut eng: EngineRef, id: u32, msg: &str) {
+    let session = unwrap_or!(eng.extra_mut().trades.sessions.remove(&id));
+    eng.timer_mut().cancel(session.range_timer);
+
+    for side in session.sides.iter() {
+        eng.extra_mut().trades.client_session.remove(&side.cid);
+        eng.messages_mut().send_client(side.cid, ClientResponse::TradeClosed);
+        send_message(&mut eng, side.cid, msg.to_owned());
+    }
+    for side in session.sides.iter() {
+        if eng.world().get_inventory(side.offer_iid).is_some() {
+            warn_on_err!(world::Fragment::destroy_inventory(&mut eng.as_world_fragment(),
+                                                            side.offer_iid));
+        }
+    }
+}
+
+/// Cancel any session or invitations involving `cid`.  Called when the client logs out.
+pub fn cancel_client(mut eng: EngineRef, cid: ClientId) {
+    let stale = eng.extra().trades.invites.iter()
+                   .filter(|&(&invitee, &(inviter, _))| invitee == cid || inviter == cid)
+                   .map(|(&invitee, _)| invitee)
+                   .collect::<Vec<_>>();
+    for invitee in stale {
+        eng.extra_mut().trades.invites.remove(&invitee);
+    }
+
+    let id = unwrap_or!(eng.extra().trades.client_session.get(&cid).map(|&id| id));
+    let name = eng.world().client(cid).name().to_owned();
+    cancel(eng, id, &format!("{} left the game", name));
+}
+
+/// Check whether items can be moved between `from_iid` and `to_iid` by `cid`.  Each player can
+/// modify only their own offer, and only while it's unlocked.
+pub fn check_move(eng: &EngineRef,
+                  cid: ClientId,
+                  from_iid: InventoryId,
+                  to_iid: InventoryId) -> StrResult<()> {
+    let trades = &eng.extra().trades;
+    for &iid in &[from_iid, to_iid] {
+        if let Some((id, idx)) = trades.find_offer(iid) {
+            let side = &trades.sessions[&id].sides[idx];
+            if side.cid != cid {
+                fail!("that is not your offer");
+            }
+            if side.state != TradeState::Open {
+                fail!("your offer is locked");
+            }
+        }
+    }
+    Ok(())
+}
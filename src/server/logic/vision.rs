@@ -1,10 +1,13 @@
 use std::borrow::ToOwned;
 
 use types::*;
+use util::IntrusiveStableId;
 
 use engine::glue::*;
+use logic;
 use messages::ClientResponse;
 use world;
+use world::PlaneProps;
 use world::object::*;
 use vision;
 
@@ -81,19 +84,36 @@ impl<'a, 'd> vision::Hooks for VisionHooks<'a, 'd> {
                        cid: ClientId,
                        _: PlaneId,
                        pid: PlaneId) {
-        // TODO: super hack.  add a flags field to the plane or something.
-        let is_dark = match self.world().get_plane(pid) {
-            Some(p) => p.name() != "Everfree Forest",
-            None => true,
+        self.on_plane_props_change(cid, pid);
+
+        let stable_pid = match self.world().get_plane(pid) {
+            Some(p) => p.get_stable_id(),
+            None => STABLE_PLANE_LIMBO.unwrap(),
+        };
+        let now = self.now();
+        let msg = logic::clock::cycle_message(&self.extra().clock, stable_pid, now);
+        self.messages().send_client(cid, msg);
+    }
+
+    fn on_plane_props_change(&mut self, cid: ClientId, pid: PlaneId) {
+        let props = match self.world().get_plane(pid) {
+            Some(p) => *p.props(),
+            None => PlaneProps::new(),
         };
-        self.messages().send_client(cid, ClientResponse::PlaneFlags(is_dark as u32));
+        self.messages().send_client(cid, ClientResponse::PlaneProps(props));
     }
 
 
     fn on_structure_appear(&mut self, cid: ClientId, sid: StructureId) {
-        let s = self.world().structure(sid);
-        self.messages().send_client(cid, ClientResponse::StructureAppear(
-                sid, s.template_id(), s.pos()));
+        let crafting = {
+            let s = self.world().structure(sid);
+            self.messages().send_client(cid, ClientResponse::StructureAppear(
+                    sid, s.template_id(), s.pos()));
+            s.crafting_jobs().len() > 0
+        };
+        if crafting {
+            self.on_structure_crafting_change(cid, sid);
+        }
     }
 
     fn on_structure_disappear(&mut self, cid: ClientId, sid: StructureId) {
@@ -105,21 +125,40 @@ impl<'a, 'd> vision::Hooks for VisionHooks<'a, 'd> {
         self.messages().send_client(cid, ClientResponse::StructureReplace(sid, s.template_id()));
     }
 
+    fn on_structure_crafting_change(&mut self, cid: ClientId, sid: StructureId) {
+        let now = self.now();
+        let jobs = {
+            let s = self.world().structure(sid);
+            s.crafting_jobs().iter().map(|j| {
+                let remaining = if j.end_time > now { j.end_time - now } else { 0 };
+                (j.recipe_id, j.count, remaining as u32, (j.end_time - j.start_time) as u32)
+            }).collect()
+        };
+        self.messages().send_client(cid, ClientResponse::StructureCrafting(sid, jobs));
+    }
+
 
     fn on_inventory_appear(&mut self, cid: ClientId, iid: InventoryId) {
         let i = self.world().inventory(iid);
 
-        let updates = i.contents().iter().map(|(&item, &count)| (item, 0, count)).collect();
-        self.messages().send_client(cid, ClientResponse::InventoryUpdate(iid, updates));
+        let slots = i.limits().slots;
+        let updates = i.item_counts().into_iter().map(|(item, count)| (item, 0, count)).collect();
+        self.messages().send_client(cid, ClientResponse::InventoryUpdate(iid, slots, updates));
     }
 
     fn on_inventory_update(&mut self,
                            cid: ClientId,
                            iid: InventoryId,
                            item_id: ItemId,
-                           old_count: u8,
-                           new_count: u8) {
+                           old_count: u16,
+                           new_count: u16) {
+        let slots = self.world().inventory(iid).limits().slots;
         let update = vec![(item_id, old_count, new_count)];
-        self.messages().send_client(cid, ClientResponse::InventoryUpdate(iid, update));
+        self.messages().send_client(cid, ClientResponse::InventoryUpdate(iid, slots, update));
+    }
+
+    fn on_inventory_limits_change(&mut self, cid: ClientId, iid: InventoryId) {
+        let slots = self.world().inventory(iid).limits().slots;
+        self.messages().send_client(cid, ClientResponse::InventoryUpdate(iid, slots, Vec::new()));
     }
 }
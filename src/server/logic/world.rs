@@ -2,22 +2,24 @@ use std::iter;
 use libphysics::{CHUNK_SIZE, TILE_SIZE};
 
 use types::*;
-use util::SmallSet;
+use util::{SmallSet, SmallVec};
 use util::StrResult;
 
 use data::StructureTemplate;
 use engine::glue::*;
 use engine::split::{Open, EngineRef};
 use logic;
+use logic::camera::Camera;
+use logic::events::WorldEvent;
 use messages::{ClientResponse, SyncKind};
 use physics;
 use world::{self, World, Entity, Structure};
 use world::object::*;
-use vision::{self, vision_region};
+use vision;
 
 
 macro_rules! impl_world_Hooks {
-    ($WorldHooks:ident, $as_vision_fragment:ident) => {
+    ($WorldHooks:ident, $as_vision_fragment:ident, $record_changes:expr) => {
 
 impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
     // We should never get client callbacks in the HiddenWorldHooks variant.
@@ -47,6 +49,7 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
             (tc.plane_id(), tc.chunk_pos())
         };
         vision::Fragment::add_terrain_chunk(&mut self.$as_vision_fragment(), tcid, pid, cpos);
+        self.queue_world_event(WorldEvent::TerrainChunkCreate(pid, cpos));
 
         let Open { world, cache, .. } = (**self).open();
         warn_on_err!(cache.add_chunk(world, pid, cpos));
@@ -70,6 +73,14 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         cache.update_region(world, pid, bounds);
     }
 
+    fn on_block_change(&mut self, pid: PlaneId, pos: V3, old_block: BlockId, new_block: BlockId) {
+        if self.is_journaling() {
+            let change = logic::journal::block_change(&mut self.as_hidden_world_fragment(),
+                                                      pid, pos, old_block, new_block);
+            self.record_journal(change);
+        }
+    }
+
 
     fn on_entity_create(&mut self, eid: EntityId) {
         let (plane, area, end_time) = {
@@ -89,6 +100,15 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
     fn on_entity_destroy(&mut self, eid: EntityId) {
         self.script_mut().cb_entity_destroyed(eid);
         vision::Fragment::remove_entity(&mut self.$as_vision_fragment(), eid);
+
+        // Cameras following the entity go back to their pawns.  Changing the view loads and
+        // unloads chunks, so it can't happen in the middle of a world operation.
+        let now = self.now();
+        for cid in logic::camera::release_entity(self.extra_mut(), eid) {
+            self.timer_mut().schedule(now, move |eng| {
+                warn_on_err!(logic::camera::follow_pawn(eng, cid));
+            });
+        }
     }
 
     fn on_entity_motion_change(&mut self, eid: EntityId) {
@@ -102,6 +122,7 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         vision::Fragment::set_entity_area(&mut self.$as_vision_fragment(), eid, plane, area);
         self.schedule_physics_update(eid, end_time);
         self.schedule_view_update(eid);
+        self.queue_world_event(WorldEvent::EntityMotion(eid));
     }
 
     fn on_entity_appearance_change(&mut self, eid: EntityId) {
@@ -120,6 +141,14 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
             (s.plane_id(), structure_area(s))
         };
         vision::Fragment::add_structure(&mut self.$as_vision_fragment(), sid, pid, area);
+        // Jobs may have finished while the structure was unloaded.
+        self.schedule_crafting_update(sid, true);
+        self.queue_world_event(WorldEvent::StructureCreate(sid));
+        if self.is_journaling() {
+            let change = logic::journal::structure_create(&mut self.as_hidden_world_fragment(),
+                                                          sid);
+            self.record_journal(change);
+        }
 
         let Open { world, cache, .. } = (**self).open();
         let s = world.structure(sid);
@@ -129,8 +158,12 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
     fn on_structure_destroy(&mut self,
                             sid: StructureId,
                             old_pid: PlaneId,
+                            old_template: TemplateId,
                             old_bounds: Region) {
         vision::Fragment::remove_structure(&mut self.$as_vision_fragment(), sid);
+        if let Some(cookie) = self.extra_mut().structure_crafting_timer.remove(&sid) {
+            self.timer_mut().cancel(cookie);
+        }
 
         {
             let Open { world, cache, .. } = (**self).open();
@@ -138,11 +171,18 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         }
 
         self.script_mut().cb_structure_destroyed(sid);
+        self.queue_world_event(WorldEvent::StructureDestroy(sid, old_pid, old_bounds));
+        if self.is_journaling() {
+            let change = logic::journal::structure_destroy(&mut self.as_hidden_world_fragment(),
+                                                           old_pid, old_template, old_bounds);
+            self.record_journal(change);
+        }
     }
 
     fn on_structure_replace(&mut self,
                             sid: StructureId,
                             pid: PlaneId,
+                            old_template: TemplateId,
                             old_bounds: Region) {
         {
             let Open { world, cache, .. } = (**self).open();
@@ -155,12 +195,23 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         };
         vision::Fragment::set_structure_area(&mut self.$as_vision_fragment(), sid, pid, area);
         vision::Fragment::change_structure_template(&mut self.$as_vision_fragment(), sid);
+        self.queue_world_event(WorldEvent::StructureReplace(sid));
+        if self.is_journaling() {
+            let change = logic::journal::structure_replace(&mut self.as_hidden_world_fragment(),
+                                                           sid, old_template);
+            self.record_journal(change);
+        }
 
         let Open { world, cache, .. } = (**self).open();
         let s = world.structure(sid);
         cache.update_region(world, pid, old_bounds.join(s.bounds()));
     }
 
+    fn on_structure_crafting_change(&mut self, sid: StructureId) {
+        vision::Fragment::update_structure_crafting(&mut self.$as_vision_fragment(), sid);
+        self.schedule_crafting_update(sid, false);
+    }
+
     fn check_structure_placement(&self,
                                  template: &StructureTemplate,
                                  pid: PlaneId,
@@ -196,21 +247,102 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
     // No lifecycle callbacks for inventories, because Vision doesn't care what inventories exist,
     // only what inventories are actually subscribed to.
 
-    fn on_inventory_destroy(&mut self, iid: InventoryId) {
+    fn on_inventory_destroy(&mut self,
+                            iid: InventoryId,
+                            stable_iid: Option<Stable<InventoryId>>) {
         self.script_mut().cb_inventory_destroyed(iid);
+
+        // Inventories are also destroyed when they are unloaded.  A logging-out client's
+        // inventories are unloaded without reporting a stable ID, and chunks are unloaded
+        // through the hidden variant.  Only a real destruction means that crafting jobs
+        // delivering to this inventory can never finish.
+        if let Some(stable_iid) = stable_iid {
+            if $record_changes {
+                let sids = logic::items::drop_crafting_jobs(&mut self.as_hidden_world_fragment(),
+                                                            stable_iid);
+                for sid in sids {
+                    vision::Fragment::update_structure_crafting(&mut self.$as_vision_fragment(),
+                                                                sid);
+                }
+            }
+        }
     }
 
     fn on_inventory_update(&mut self,
                            iid: InventoryId,
                            item_id: ItemId,
-                           old_count: u8,
-                           new_count: u8) {
+                           old_count: u16,
+                           new_count: u16) {
         vision::Fragment::update_inventory(&mut self.$as_vision_fragment(),
                                            iid, item_id, old_count, new_count);
+        self.queue_world_event(WorldEvent::InventoryUpdate(iid, item_id, old_count, new_count));
+        if self.is_journaling() {
+            let change = logic::journal::inventory_update(&mut self.as_hidden_world_fragment(),
+                                                          iid, item_id, old_count, new_count);
+            self.record_journal(change);
+        }
+    }
+
+    fn on_inventory_limits_change(&mut self, iid: InventoryId) {
+        vision::Fragment::update_inventory_limits(&mut self.$as_vision_fragment(), iid);
+    }
+
+    fn on_plane_props_change(&mut self, pid: PlaneId) {
+        vision::Fragment::update_plane(&mut self.$as_vision_fragment(), pid);
     }
 }
 
 impl<'a, 'd> $WorldHooks<'a, 'd> {
+    /// Queue an event for delivery to scripts.  Changes made through the hidden variant (loading
+    /// and unloading of chunks) are not reported.
+    fn queue_world_event(&mut self, evt: WorldEvent) {
+        if !$record_changes {
+            return;
+        }
+        if self.extra_mut().world_events.push(evt) {
+            let now = self.now();
+            self.timer_mut().schedule(now, |eng| logic::events::dispatch(eng));
+        }
+    }
+
+    /// Check whether changes should be recorded in the undo journal.  Only changes made on behalf
+    /// of a client are recorded, and never changes made through the hidden variant.
+    fn is_journaling(&self) -> bool {
+        $record_changes && self.extra().journal.is_recording()
+    }
+
+    fn record_journal(&mut self, change: Option<logic::journal::Change>) {
+        let change = unwrap_or!(change);
+        let now = self.now();
+        if self.extra_mut().journal.record(now, change) {
+            self.timer_mut().schedule(now, |eng| logic::journal::flush(eng));
+        }
+    }
+
+    /// Schedule a timer to deliver the next crafting job at `sid` when it finishes.  Jobs that
+    /// have already finished are skipped unless `include_finished` is set, since they are only
+    /// waiting for their output inventory to be loaded.
+    pub fn schedule_crafting_update(&mut self, sid: StructureId, include_finished: bool) {
+        let now = self.now();
+        let when = {
+            let s = unwrap_or!(self.world().get_structure(sid));
+            s.crafting_jobs().iter()
+             .map(|j| j.end_time)
+             .filter(|&t| include_finished || t > now)
+             .min()
+        };
+
+        if let Some(cookie) = self.extra_mut().structure_crafting_timer.remove(&sid) {
+            self.timer_mut().cancel(cookie);
+        }
+        if let Some(when) = when {
+            let cookie = self.timer_mut().schedule(when, move |eng| {
+                warn_on_err!(logic::items::finish_crafting(eng, sid));
+            });
+            self.extra_mut().structure_crafting_timer.insert(sid, cookie);
+        }
+    }
+
     fn schedule_physics_update(&mut self, eid: EntityId, when: Time) {
         if let Some(cookie) = self.extra_mut().entity_physics_update_timer.remove(&eid) {
             self.timer_mut().cancel(cookie);
@@ -219,26 +351,45 @@ impl<'a, 'd> $WorldHooks<'a, 'd> {
         self.extra_mut().entity_physics_update_timer.insert(eid, cookie);
     }
 
+    /// Schedule view updates for every client whose camera follows entity `eid`.
     pub fn schedule_view_update(&mut self, eid: EntityId) {
-        let now = self.now();
-        let cid;
-        let when = {
+        let mut cids = SmallVec::new();
+        let motion = {
             let e = unwrap_or!(self.world().get_entity(eid));
-            let c = unwrap_or!(e.pawn_owner());
-            cid = c.id();
+            if let Some(c) = e.pawn_owner() {
+                if let Camera::Pawn = logic::camera::camera(self.extra(), c.id()) {
+                    cids.push(c.id());
+                }
+            }
+            e.motion().clone()
+        };
+        for (&cid, camera) in self.extra().client_camera.iter() {
+            match *camera {
+                Camera::Entity(target) if target == eid => cids.push(cid),
+                _ => {},
+            }
+        }
+
+        for &cid in cids.as_slice().iter() {
+            self.schedule_client_view_update(cid, &motion);
+        }
+    }
 
+    /// Schedule a view update for a client whose camera is following motion `m`.
+    pub fn schedule_client_view_update(&mut self, cid: ClientId, m: &world::Motion) {
+        let now = self.now();
+        let when = {
             // If the client is not registered with the vision system, do nothing.
-            let old_area = unwrap_or!(self.vision().client_view_area(c.id()));
-            let new_area = vision_region(e.pos(now));
+            let old_view = unwrap_or!(self.vision().client_view(cid));
+            let new_view = logic::client::view_shape(self.extra(), cid).view(m.pos(now));
 
-            if old_area != new_area {
+            if old_view != new_view {
                 // Simple case: If the vision area needs to change immediately, schedule the update
                 // to happen as soon as possible.
                 Some(now)
             } else {
-                // Complex case: Figure out when the pawn will move into a new chunk, and schedule
-                // the update to happen at that time.
-                let m = e.motion();
+                // Complex case: Figure out when the camera will move into a new chunk, and
+                // schedule the update to happen at that time.
                 let start = m.pos(now);
                 let delta = m.end_pos - start;
                 let dur = (m.end_time() - now) as i32;
@@ -279,8 +430,8 @@ impl<'a, 'd> $WorldHooks<'a, 'd> {
 }
 
 
-impl_world_Hooks!(WorldHooks, as_vision_fragment);
-impl_world_Hooks!(HiddenWorldHooks, as_hidden_vision_fragment);
+impl_world_Hooks!(WorldHooks, as_vision_fragment, true);
+impl_world_Hooks!(HiddenWorldHooks, as_hidden_vision_fragment, false);
 
 
 pub fn entity_area(e: ObjectRef<Entity>) -> SmallSet<V2> {
@@ -428,6 +579,12 @@ fn teleport_entity_internal(mut wf: WorldFragment,
     use world::Fragment;
     let now = wf.now();
 
+    if let Some(stable_pid) = stable_pid {
+        if wf.extra().instances.is_retired(stable_pid) {
+            fail!("the destination plane no longer exists");
+        }
+    }
+
     {
         let e = unwrap!(wf.world().get_entity(eid));
         let cid = e.pawn_owner().map(|c| c.id());
@@ -476,3 +633,13 @@ pub fn teleport_entity_stable_plane(wf: WorldFragment,
                                     pos: V3) -> StrResult<()> {
     teleport_entity_internal(wf, eid, None, Some(stable_pid), pos)
 }
+
+/// Change which clients can see an entity.  If the entity is a pawn, its owner can always see it.
+/// Visibility is not saved, so it resets to `Public` when the entity is unloaded.
+pub fn set_entity_visibility(mut eng: EngineRef,
+                             eid: EntityId,
+                             visibility: vision::Visibility) -> StrResult<()> {
+    let owner = unwrap!(eng.world().get_entity(eid)).pawn_owner().map(|c| c.id());
+    vision::Fragment::set_entity_visibility(&mut eng.as_vision_fragment(), eid, visibility, owner);
+    Ok(())
+}
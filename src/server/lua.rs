@@ -3,6 +3,8 @@
 // rustc also complains about lua_SomeType typedefs.
 #![allow(non_camel_case_types)]
 
+use std::collections::HashMap;
+use std::ffi::CStr;
 use std::marker::PhantomData;
 use std::mem;
 use std::path::Path;
@@ -12,6 +14,7 @@ use std::str;
 
 use libc;
 use libc::{c_void, c_int, c_char, size_t};
+use time;
 
 use self::ffi::{lua_State, lua_Integer, lua_Number};
 
@@ -25,12 +28,36 @@ mod ffi {
     pub type lua_Integer = ptrdiff_t;
     pub type lua_Number = c_double;
     pub type lua_Reader = extern "C" fn(*mut lua_State, data: *mut c_void, size: *mut size_t) -> *const c_char;
+    pub type lua_Hook = extern "C" fn(*mut lua_State, *mut lua_Debug);
+
+    pub const LUA_IDSIZE: usize = 60;
+
+    #[repr(C)]
+    pub struct lua_Debug {
+        pub event: c_int,
+        pub name: *const c_char,
+        pub namewhat: *const c_char,
+        pub what: *const c_char,
+        pub source: *const c_char,
+        pub currentline: c_int,
+        pub nups: c_int,
+        pub linedefined: c_int,
+        pub lastlinedefined: c_int,
+        pub short_src: [c_char; LUA_IDSIZE],
+        i_ci: c_int,
+    }
+
+    pub const LUA_MASKCOUNT: c_int = 1 << 3;
 
     #[link(name = "lua5.1")]
     extern "C" {
         pub fn lua_newstate(f: lua_Alloc, ud: *mut c_void) -> *mut lua_State;
         pub fn luaL_newstate() -> *mut lua_State;
         pub fn lua_close(L: *mut lua_State);
+        pub fn lua_atpanic(L: *mut lua_State, panicf: lua_CFunction) -> lua_CFunction;
+        pub fn lua_getallocf(L: *mut lua_State, ud: *mut *mut c_void) -> lua_Alloc;
+        pub fn lua_sethook(L: *mut lua_State, f: lua_Hook, mask: c_int, count: c_int) -> c_int;
+        pub fn lua_getinfo(L: *mut lua_State, what: *const c_char, ar: *mut lua_Debug) -> c_int;
 
         pub fn lua_load(L: *mut lua_State, reader: lua_Reader, data: *mut c_void, chunkname: *const c_char);
         pub fn lua_pcall(L: *mut lua_State, nargs: c_int, nresults: c_int, errfunc: c_int) -> c_int;
@@ -51,6 +78,7 @@ mod ffi {
 
         pub fn lua_toboolean(L: *mut lua_State, index: c_int) -> c_int;
         pub fn lua_tointeger(L: *mut lua_State, index: c_int) -> lua_Integer;
+        pub fn lua_tocfunction(L: *mut lua_State, index: c_int) -> Option<lua_CFunction>;
         pub fn lua_tolstring(L: *mut lua_State, index: c_int, len: *mut size_t) -> *const c_char;
         pub fn lua_tonumber(L: *mut lua_State, index: c_int) -> lua_Number;
         pub fn lua_topointer(L: *mut lua_State, index: c_int) -> *mut c_void;
@@ -71,7 +99,7 @@ mod ffi {
         pub fn lua_rawseti(L: *mut lua_State, index: c_int, n: c_int);
         pub fn lua_next(L: *mut lua_State, index: c_int) -> c_int;
 
-        pub fn lua_getmetatable(L: *mut lua_State, index: c_int);
+        pub fn lua_getmetatable(L: *mut lua_State, index: c_int) -> c_int;
         pub fn lua_setmetatable(L: *mut lua_State, index: c_int);
 
         pub fn luaL_ref(L: *mut lua_State, t: c_int) -> c_int;
@@ -87,17 +115,37 @@ mod ffi {
 #[unsafe_no_drop_flag]
 pub struct OwnedLuaState {
     L: *mut lua_State,
+    limits: *mut Limits,
 }
 
 impl OwnedLuaState {
     pub fn new() -> LuaResult<'static, OwnedLuaState> {
-        let L = unsafe { ffi::luaL_newstate() };
+        OwnedLuaState::with_memory_limit(0)
+    }
+
+    /// Create a new Lua state whose allocations are capped at `limit` bytes in total.  Allocations
+    /// beyond the limit fail, which Lua reports as an `ErrMem` error.  A `limit` of zero means no
+    /// limit.
+    pub fn with_memory_limit(limit: usize) -> LuaResult<'static, OwnedLuaState> {
+        let limits: *mut Limits = unsafe { mem::transmute(Box::new(Limits {
+            mem_used: 0,
+            mem_limit: limit,
+            budget: None,
+            profile: None,
+        })) };
+        let L = unsafe { ffi::lua_newstate(lua_alloc, limits as *mut c_void) };
 
         if L.is_null() {
+            drop(unsafe { mem::transmute::<_, Box<Limits>>(limits) });
             Err((ErrorType::ErrMem, "failed to allocate memory"))
         } else {
+            unsafe {
+                ffi::lua_atpanic(L, lua_panic);
+                ffi::lua_sethook(L, lua_count_hook, ffi::LUA_MASKCOUNT, HOOK_INTERVAL as c_int);
+            }
             Ok(OwnedLuaState {
                 L: L,
+                limits: limits,
             })
         }
     }
@@ -115,21 +163,144 @@ impl Drop for OwnedLuaState {
 
         unsafe { ffi::lua_close(self.L) };
         self.L = ptr::null_mut();
+        // The allocator's state must outlive the Lua state.
+        drop(unsafe { mem::transmute::<_, Box<Limits>>(self.limits) });
+        self.limits = ptr::null_mut();
+    }
+}
+
+/// Resource accounting for a Lua state.  The allocator and the count hook both find this through
+/// the allocator's userdata pointer.
+struct Limits {
+    mem_used: usize,
+    mem_limit: usize,
+    /// Number of VM instructions left to run before the current callback is aborted, or `None` if
+    /// no budget is active.
+    budget: Option<u32>,
+    /// Profiling data, or `None` if profiling is turned off.
+    profile: Option<Box<Profile>>,
+}
+
+/// Timing statistics for one kind of call.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CallStats {
+    pub count: u64,
+    pub total_ns: u64,
+    pub max_ns: u64,
+}
+
+impl CallStats {
+    fn record(&mut self, ns: u64) {
+        self.count += 1;
+        self.total_ns += ns;
+        if ns > self.max_ns {
+            self.max_ns = ns;
+        }
     }
 }
 
-fn lua_alloc(_userdata: *mut c_void,
-             ptr: *mut c_void,
-             _old_size: size_t,
-             new_size: size_t) -> *mut c_void {
+/// Profiling data for a Lua state.  Callbacks and Rust functions are timed on every call.  Time
+/// spent in Lua functions is sampled by the count hook: the time since the previous sample is
+/// charged to whichever function is running when the hook fires.
+#[derive(Default)]
+pub struct Profile {
+    /// Time spent in each callback, by callback name.
+    pub callbacks: HashMap<String, CallStats>,
+    /// Time spent in each Rust function called from Lua, by function address.
+    pub ffi: HashMap<usize, CallStats>,
+    /// Sampled time spent in each Lua function, by "source:line".  Each sample counts as one call.
+    pub functions: HashMap<String, CallStats>,
+    /// Time of the previous sample, or `None` if no callback is running.
+    last_sample: Option<u64>,
+}
+
+/// How many VM instructions run between calls to the count hook.
+const HOOK_INTERVAL: u32 = 1000;
+
+extern "C" fn lua_alloc(userdata: *mut c_void,
+                        ptr: *mut c_void,
+                        old_size: size_t,
+                        new_size: size_t) -> *mut c_void {
+    let limits = unsafe { &mut *(userdata as *mut Limits) };
+    // NB: old_size is 0 when ptr is null (a new allocation).
+    let old_size = if ptr.is_null() { 0 } else { old_size as usize };
+
     if new_size == 0 {
         unsafe { libc::free(ptr) };
+        limits.mem_used -= old_size;
         ptr::null_mut()
     } else {
-        // NB: ptr is guaranteed to be null when requesting a new allocation (i.e., when old_size
-        // is 0).
-        unsafe { libc::realloc(ptr, new_size) }
+        let new_size = new_size as usize;
+        // Lua requires that shrinking a block never fails, so only growth is checked.
+        if new_size > old_size && limits.mem_limit != 0 &&
+           limits.mem_used - old_size + new_size > limits.mem_limit {
+            return ptr::null_mut();
+        }
+        let new_ptr = unsafe { libc::realloc(ptr, new_size as size_t) };
+        if !new_ptr.is_null() {
+            limits.mem_used = limits.mem_used - old_size + new_size;
+        }
+        new_ptr
+    }
+}
+
+/// Get the `Limits` of a state created by `OwnedLuaState`.  Returns `None` for states that use a
+/// different allocator.
+unsafe fn get_limits<'a>(L: *mut lua_State) -> Option<&'a mut Limits> {
+    let mut userdata = ptr::null_mut();
+    let f = ffi::lua_getallocf(L, &mut userdata);
+    if f as usize != lua_alloc as usize || userdata.is_null() {
+        return None;
     }
+    Some(&mut *(userdata as *mut Limits))
+}
+
+extern "C" fn lua_count_hook(L: *mut lua_State, ar: *mut ffi::lua_Debug) {
+    let exceeded = {
+        let limits = unwrap_or!(unsafe { get_limits(L) }, return);
+        if let Some(ref mut profile) = limits.profile {
+            unsafe { sample_function(L, ar, profile) };
+        }
+        match limits.budget {
+            None => false,
+            Some(left) if left > HOOK_INTERVAL => {
+                limits.budget = Some(left - HOOK_INTERVAL);
+                false
+            },
+            Some(_) => {
+                // Disable the budget, so that the error handler can still run.
+                limits.budget = None;
+                true
+            },
+        }
+    };
+
+    if exceeded {
+        unsafe {
+            let mut lua = LuaState::new(L);
+            lua.push_string("script ran for too long (instruction budget exceeded)");
+            lua.error();
+        }
+    }
+}
+
+unsafe fn sample_function(L: *mut lua_State, ar: *mut ffi::lua_Debug, profile: &mut Profile) {
+    let now = time::precise_time_ns();
+    let last = unwrap_or!(profile.last_sample, return);
+    profile.last_sample = Some(now);
+
+    if ffi::lua_getinfo(L, b"S\0".as_ptr() as *const c_char, ar) == 0 {
+        return;
+    }
+    let src = CStr::from_ptr((*ar).short_src.as_ptr()).to_string_lossy();
+    let key = format!("{}:{}", src, (*ar).linedefined);
+    profile.functions.entry(key).or_insert_with(CallStats::default).record(now - last);
+}
+
+extern "C" fn lua_panic(L: *mut lua_State) -> c_int {
+    let lua = unsafe { LuaState::new(L) };
+    error!("unprotected error in lua: {}", lua.to_string(-1).unwrap_or("(no message)"));
+    unsafe { libc::abort() }
 }
 
 
@@ -353,6 +524,12 @@ impl<'a> LuaState<'a> {
         unsafe { ffi::lua_tonumber(self.L, index) as f64 }
     }
 
+    /// Get the address of the C or Rust function at `index`, or `None` if the value is not a C
+    /// function.
+    pub fn to_function_addr(&self, index: c_int) -> Option<usize> {
+        unsafe { ffi::lua_tocfunction(self.L, index) }.map(|f| f as usize)
+    }
+
     pub fn to_string<'b>(&'b self, index: c_int) -> Option<&'b str> {
         let v = match self.to_bytes(index) {
             Some(v) => v,
@@ -423,8 +600,10 @@ impl<'a> LuaState<'a> {
         }
     }
 
-    pub fn get_metatable(&mut self, index: c_int) {
-        unsafe { ffi::lua_getmetatable(self.L, index) };
+    /// Push the metatable of the value at `index`.  Returns `false`, and pushes nothing, if the
+    /// value has no metatable.
+    pub fn get_metatable(&mut self, index: c_int) -> bool {
+        unsafe { ffi::lua_getmetatable(self.L, index) != 0 }
     }
 
     pub fn set_metatable(&mut self, index: c_int) {
@@ -466,6 +645,100 @@ impl<'a> LuaState<'a> {
         make_result(self, code)
     }
 
+    /// Limit the number of VM instructions that can run before an error is raised.  Returns
+    /// `false` without changing anything if a budget is already active, so that callbacks nested
+    /// inside another callback share the outer callback's budget.
+    pub fn begin_budget(&mut self, budget: u32) -> bool {
+        let limits = unwrap_or!(unsafe { get_limits(self.L) }, return false);
+        if limits.budget.is_some() {
+            return false;
+        }
+        limits.budget = Some(budget);
+        true
+    }
+
+    pub fn end_budget(&mut self) {
+        if let Some(limits) = unsafe { get_limits(self.L) } {
+            limits.budget = None;
+        }
+    }
+
+    // Profiling
+
+    pub fn start_profiling(&mut self) {
+        if let Some(limits) = unsafe { get_limits(self.L) } {
+            if limits.profile.is_none() {
+                limits.profile = Some(Box::new(Profile::default()));
+            }
+        }
+    }
+
+    pub fn stop_profiling(&mut self) {
+        if let Some(limits) = unsafe { get_limits(self.L) } {
+            limits.profile = None;
+        }
+    }
+
+    /// Discard all profiling data collected so far, without turning profiling on or off.
+    pub fn reset_profile(&mut self) {
+        if let Some(profile) = self.profile_mut() {
+            *profile = Profile {
+                last_sample: profile.last_sample,
+                .. Profile::default()
+            };
+        }
+    }
+
+    pub fn profile(&mut self) -> Option<&Profile> {
+        self.profile_mut().map(|p| &*p)
+    }
+
+    fn profile_mut(&mut self) -> Option<&mut Profile> {
+        let limits = unwrap_or!(unsafe { get_limits(self.L) }, return None);
+        limits.profile.as_mut().map(|p| &mut **p)
+    }
+
+    /// Get the start time of a timed call, or `None` if profiling is turned off.
+    pub fn profile_begin(&mut self) -> Option<u64> {
+        if self.profile_mut().is_some() {
+            Some(time::precise_time_ns())
+        } else {
+            None
+        }
+    }
+
+    /// Get the start time of a callback, or `None` if profiling is turned off.  This also starts
+    /// sampling of Lua functions, if it isn't running already.
+    pub fn profile_callback_begin(&mut self) -> Option<u64> {
+        let profile = unwrap_or!(self.profile_mut(), return None);
+        let now = time::precise_time_ns();
+        if profile.last_sample.is_none() {
+            profile.last_sample = Some(now);
+        }
+        Some(now)
+    }
+
+    /// Record a call to the callback `name`, which started at `start`.  Sampling stops at the end
+    /// of the `outermost` callback, so that time spent outside of scripts is not counted.
+    pub fn profile_callback_end(&mut self, name: &str, start: Option<u64>, outermost: bool) {
+        let profile = unwrap_or!(self.profile_mut(), return);
+        let start = unwrap_or!(start, return);
+        let now = time::precise_time_ns();
+        profile.callbacks.entry(name.to_owned()).or_insert_with(CallStats::default)
+               .record(now - start);
+        if outermost {
+            profile.last_sample = None;
+        }
+    }
+
+    /// Record a call to the Rust function at address `addr`, which started at `start`.
+    pub fn profile_ffi(&mut self, addr: usize, start: Option<u64>) {
+        let start = unwrap_or!(start, return);
+        let profile = unwrap_or!(self.profile_mut(), return);
+        let now = time::precise_time_ns();
+        profile.ffi.entry(addr).or_insert_with(CallStats::default).record(now - start);
+    }
+
     // Miscellaneous
 
     pub fn open_libs(&mut self) {
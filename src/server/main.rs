@@ -60,6 +60,7 @@ mod lua;
 mod script;
 mod world;
 
+mod audit;
 mod auth;
 mod messages;
 mod physics;
@@ -129,6 +129,7 @@ impl ClientInfo {
                       end.y as u16,
                       end.z as u16),
             end_time: (m.start_time + m.duration as Time).to_local(),
+            arc: m.arc,
         }
     }
 
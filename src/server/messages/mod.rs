@@ -11,7 +11,7 @@ use libphysics::TILE_SIZE;
 use auth::Secret;
 use input::InputBits;
 use msg::{Request, Response, InitData, ExtraArg};
-use world::Motion;
+use world::{Motion, PlaneProps};
 
 use self::clients::Clients;
 
@@ -39,10 +39,12 @@ pub enum ControlEvent {
     ReplCommand(u16, String),
     Shutdown,
     Restart(bool, bool),
+    ReloadScripts,
 }
 
 pub enum WireEvent {
-    Login(String, Secret),
+    /// Name, secret, and screen size in pixels, or zero if the client didn't report it.
+    Login(String, Secret, V2),
     Register(String, Secret, u32),
     BadRequest,
 }
@@ -58,6 +60,8 @@ pub enum ClientEvent {
     UseItem(Time, ItemId, Option<ExtraArg>),
     UseAbility(Time, ItemId, Option<ExtraArg>),
 
+    TradeAction(TradeAction),
+
     BadRequest,
 }
 
@@ -96,12 +100,27 @@ pub enum ClientResponse {
     StructureAppear(StructureId, TemplateId, V3),
     StructureGone(StructureId),
     StructureReplace(StructureId, TemplateId),
+    /// Crafting jobs queued at a station: recipe, batch count, milliseconds remaining, and total
+    /// milliseconds for each job.
+    StructureCrafting(StructureId, Vec<(RecipeId, u16, u32, u32)>),
 
     //InventoryAppear(InventoryId, Vec<(ItemId, u8)>),
-    InventoryUpdate(InventoryId, Vec<(ItemId, u8, u8)>),
+    InventoryUpdate(InventoryId, u16, Vec<(ItemId, u16, u16)>),
     //InventoryGone(InventoryId),
     
-    PlaneFlags(u32),
+    /// Properties of the client's current plane that affect how it is displayed.
+    PlaneProps(PlaneProps),
+    /// Current time, milliseconds since the start of the day, and length of a day for the
+    /// client's current plane.
+    DayNightCycle(Time, u32, u32),
+    /// Entity the client should center its display on, or `None` to follow the motion instead.
+    CameraUpdate(Option<EntityId>, Motion),
+    /// Members of the client's party: client, whether it is the leader, and name.  Empty when the
+    /// client is not in a party.
+    PartyUpdate(Vec<(ClientId, bool, String)>),
+    /// Positions of the client's teammates, relative to the center of its view: client, whether
+    /// it is on the same plane, and offset.
+    PartyMarkers(Vec<(ClientId, bool, V3)>),
     SyncStatus(SyncKind),
 
     GetInteractArgs(u32, ExtraArg),
@@ -109,6 +128,9 @@ pub enum ClientResponse {
     GetUseAbilityArgs(ItemId, u32, ExtraArg),
 
     OpenDialog(Dialog),
+    /// Current trade state of the client's own side and the other side.
+    TradeStatus(TradeState, TradeState),
+    TradeClosed,
     MainInventory(InventoryId),
     AbilityInventory(InventoryId),
     ChatUpdate(String),
@@ -120,6 +142,26 @@ pub enum Dialog {
     Inventory(InventoryId),
     Container(InventoryId, InventoryId),
     Crafting(TemplateId, StructureId, InventoryId),
+    /// Trade window: the client's main inventory, their own offer, and the other side's offer.
+    Trade(InventoryId, InventoryId, InventoryId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeState {
+    /// The offer can still be changed.
+    Open,
+    /// The offer is final, but the trade is not yet accepted.
+    Locked,
+    /// The trade is accepted and will go through once the other side also confirms.
+    Confirmed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeAction {
+    Cancel,
+    Lock,
+    Unlock,
+    Confirm,
 }
 
 
@@ -237,6 +279,8 @@ impl Messages {
                 Some(Event::Control(ControlEvent::Shutdown)),
             Request::Restart(server, client) =>
                 Some(Event::Control(ControlEvent::Restart(server, client))),
+            Request::ReloadScripts =>
+                Some(Event::Control(ControlEvent::ReloadScripts)),
 
             _ => {
                 warn!("bad control request: {:?}", req);
@@ -252,7 +296,11 @@ impl Messages {
                 None
             },
             Request::Login(name, secret) =>
-                Some(Event::Wire(wire_id, WireEvent::Login(name, secret))),
+                Some(Event::Wire(wire_id, WireEvent::Login(name, secret, scalar(0)))),
+            Request::LoginWithScreen(name, secret, width, height) => {
+                let screen = V2::new(width as i32, height as i32);
+                Some(Event::Wire(wire_id, WireEvent::Login(name, secret, screen)))
+            },
             Request::Register(name, secret, appearance) =>
                 Some(Event::Wire(wire_id, WireEvent::Register(name, secret, appearance))),
             _ => {
@@ -337,6 +385,18 @@ impl Messages {
             },
 
 
+            Request::TradeAction(action) => {
+                let action = match action {
+                    0 => TradeAction::Cancel,
+                    1 => TradeAction::Lock,
+                    2 => TradeAction::Unlock,
+                    3 => TradeAction::Confirm,
+                    _ => fail!("bad trade action: {}", action),
+                };
+                Ok(Some(ClientEvent::TradeAction(action)))
+            },
+
+
             _ => fail!("bad request: {:?}", req),
         }
     }
@@ -425,13 +485,50 @@ impl Messages {
                 self.send_raw(wire_id, Response::StructureReplace(sid, template_id));
             },
 
+            ClientResponse::StructureCrafting(sid, jobs) => {
+                self.send_raw(wire_id, Response::StructureCrafting(sid, jobs));
+            },
+
 
-            ClientResponse::InventoryUpdate(iid, update) =>
-                self.send_raw(wire_id, Response::InventoryUpdate(iid, update)),
+            ClientResponse::InventoryUpdate(iid, slots, update) =>
+                self.send_raw(wire_id, Response::InventoryUpdate(iid, slots, update)),
 
 
-            ClientResponse::PlaneFlags(flags) =>
-                self.send_raw(wire_id, Response::PlaneFlags(flags)),
+            ClientResponse::PlaneProps(props) => {
+                let flags = (props.day_night as u8) |
+                            ((props.build_allowed as u8) << 1) |
+                            ((props.pvp as u8) << 2);
+                self.send_raw(wire_id, Response::PlaneProps(props.lighting as u8,
+                                                            flags,
+                                                            props.ambient_color));
+            },
+
+            ClientResponse::CameraUpdate(opt_eid, motion) => {
+                let eid = opt_eid.unwrap_or(EntityId(-1_i32 as u32));
+                let wire_motion = client.local_motion(motion);
+                self.send_raw(wire_id, Response::CameraUpdate(eid, wire_motion));
+            },
+
+            ClientResponse::PartyUpdate(members) => {
+                let members = members.into_iter()
+                                     .map(|(cid, leader, name)| (cid.unwrap(), leader as u8, name))
+                                     .collect();
+                self.send_raw(wire_id, Response::PartyUpdate(members));
+            },
+
+            ClientResponse::PartyMarkers(markers) => {
+                let markers = markers.into_iter()
+                                     .map(|(cid, same_plane, offset)| {
+                                         (cid.unwrap(), same_plane as u8, offset.x, offset.y)
+                                     })
+                                     .collect();
+                self.send_raw(wire_id, Response::PartyMarkers(markers));
+            },
+
+            ClientResponse::DayNightCycle(time, cycle_base, cycle_ms) =>
+                self.send_raw(wire_id, Response::DayNightCycle(time.to_local(),
+                                                               cycle_base,
+                                                               cycle_ms)),
 
             ClientResponse::SyncStatus(kind) => {
                 let arg = match kind {
@@ -463,9 +560,27 @@ impl Messages {
                                                                             iid2.unwrap()])),
                     Dialog::Crafting(template_id, sid, iid) =>
                         self.send_raw(wire_id, Response::OpenCrafting(template_id, sid, iid)),
+                    Dialog::Trade(main_iid, own_iid, other_iid) =>
+                        self.send_raw(wire_id, Response::OpenDialog(2, vec![main_iid.unwrap(),
+                                                                            own_iid.unwrap(),
+                                                                            other_iid.unwrap()])),
+                }
+            },
+
+            ClientResponse::TradeStatus(own, other) => {
+                fn state_code(state: TradeState) -> u8 {
+                    match state {
+                        TradeState::Open => 0,
+                        TradeState::Locked => 1,
+                        TradeState::Confirmed => 2,
+                    }
                 }
+                self.send_raw(wire_id, Response::TradeStatus(state_code(own), state_code(other)))
             },
 
+            ClientResponse::TradeClosed =>
+                self.send_raw(wire_id, Response::TradeClosed),
+
             ClientResponse::MainInventory(iid) =>
                 self.send_raw(wire_id, Response::MainInventory(iid)),
 
@@ -59,6 +59,8 @@ mod op {
         InteractWithArgs = 0x0010,
         UseItemWithArgs = 0x0011,
         UseAbilityWithArgs = 0x0012,
+        TradeAction = 0x0013,
+        LoginWithScreen = 0x0014,
 
         // Deprecated requests
         GetTerrain = 0x0001,
@@ -84,12 +86,19 @@ mod op {
         StructureGone = 0x8010,
         MainInventory = 0x8011,
         AbilityInventory = 0x8012,
-        PlaneFlags = 0x8013,
+        PlaneProps = 0x8013,
         GetInteractArgs = 0x8014,
         GetUseItemArgs = 0x8015,
         GetUseAbilityArgs = 0x8016,
         SyncStatus = 0x8017,
         StructureReplace = 0x8018,
+        StructureCrafting = 0x8019,
+        TradeStatus = 0x801a,
+        TradeClosed = 0x801b,
+        DayNightCycle = 0x801c,
+        CameraUpdate = 0x801d,
+        PartyUpdate = 0x801e,
+        PartyMarkers = 0x801f,
 
         // Deprecated responses
         PlayerMotion = 0x8002,
@@ -104,6 +113,7 @@ mod op {
         RestartServer = 0xff06,
         RestartClient = 0xff07,
         RestartBoth = 0xff08,
+        ReloadScripts = 0xff09,
     }
 }
 
@@ -130,6 +140,8 @@ pub enum Request {
     InteractWithArgs(LocalTime, ExtraArg),
     UseItemWithArgs(LocalTime, ItemId, ExtraArg),
     UseAbilityWithArgs(LocalTime, ItemId, ExtraArg),
+    TradeAction(u8),
+    LoginWithScreen(String, [u32; 4], u16, u16),
 
     // Control messages
     AddClient(WireId),
@@ -137,6 +149,7 @@ pub enum Request {
     ReplCommand(u16, String),
     Shutdown,
     Restart(bool, bool),
+    ReloadScripts,
 
     // Server-internal messages
     BadMessage(Opcode),
@@ -212,6 +225,15 @@ impl Request {
                 let (a, b, c) = try!(wr.read());
                 UseAbilityWithArgs(a, b, c)
             },
+            op::TradeAction => {
+                let a = try!(wr.read());
+                TradeAction(a)
+            },
+            op::LoginWithScreen => {
+                // Shuffle order since the String must be last on the wire
+                let (b, c, d, a) = try!(wr.read());
+                LoginWithScreen(a, b, c, d)
+            },
 
             op::AddClient => {
                 let a = try!(wr.read());
@@ -237,6 +259,9 @@ impl Request {
             op::RestartBoth => {
                 Restart(true, true)
             },
+            op::ReloadScripts => {
+                ReloadScripts
+            },
             _ => BadMessage(opcode),
         };
 
@@ -259,7 +284,7 @@ pub enum Response {
     KickReason(String),
     UnloadChunk(u16),
     OpenDialog(u32, Vec<u32>),
-    InventoryUpdate(InventoryId, Vec<(ItemId, u8, u8)>),
+    InventoryUpdate(InventoryId, u16, Vec<(ItemId, u16, u16)>),
     OpenCrafting(TemplateId, StructureId, InventoryId),
     ChatUpdate(String),
     EntityAppear(EntityId, u32, String),
@@ -269,12 +294,19 @@ pub enum Response {
     StructureGone(StructureId),
     MainInventory(InventoryId),
     AbilityInventory(InventoryId),
-    PlaneFlags(u32),
+    PlaneProps(u8, u8, (u8, u8, u8)),
     GetInteractArgs(u32, ExtraArg),
     GetUseItemArgs(ItemId, u32, ExtraArg),
     GetUseAbilityArgs(ItemId, u32, ExtraArg),
     SyncStatus(u8),
     StructureReplace(StructureId, TemplateId),
+    StructureCrafting(StructureId, Vec<(RecipeId, u16, u32, u32)>),
+    TradeStatus(u8, u8),
+    TradeClosed,
+    DayNightCycle(LocalTime, u32, u32),
+    CameraUpdate(EntityId, Motion),
+    PartyUpdate(Vec<(u16, u8, String)>),
+    PartyMarkers(Vec<(u16, u8, i32, i32)>),
 
     ClientRemoved(WireId),
     ReplResult(u16, String),
@@ -299,8 +331,8 @@ impl Response {
                 ww.write_msg(id, (op::UnloadChunk, idx)),
             OpenDialog(dialog_id, ref params) =>
                 ww.write_msg(id, (op::OpenDialog, dialog_id, params)),
-            InventoryUpdate(inventory_id, ref changes) =>
-                ww.write_msg(id, (op::InventoryUpdate, inventory_id, changes)),
+            InventoryUpdate(inventory_id, slots, ref changes) =>
+                ww.write_msg(id, (op::InventoryUpdate, inventory_id, slots, changes)),
             OpenCrafting(station_type, station_id, inventory_id) =>
                 ww.write_msg(id, (op::OpenCrafting, station_type, station_id, inventory_id)),
             ChatUpdate(ref msg) =>
@@ -319,8 +351,8 @@ impl Response {
                 ww.write_msg(id, (op::MainInventory, iid)),
             AbilityInventory(iid) =>
                 ww.write_msg(id, (op::AbilityInventory, iid)),
-            PlaneFlags(flags) =>
-                ww.write_msg(id, (op::PlaneFlags, flags)),
+            PlaneProps(lighting, flags, color) =>
+                ww.write_msg(id, (op::PlaneProps, lighting, flags, color)),
             GetInteractArgs(dialog_id, ref args) =>
                 ww.write_msg(id, (op::GetInteractArgs, dialog_id, args)),
             GetUseItemArgs(item_id, dialog_id, ref args) =>
@@ -331,6 +363,20 @@ impl Response {
                 ww.write_msg(id, (op::SyncStatus, kind)),
             StructureReplace(sid, template_id) =>
                 ww.write_msg(id, (op::StructureReplace, sid, template_id)),
+            StructureCrafting(sid, ref jobs) =>
+                ww.write_msg(id, (op::StructureCrafting, sid, jobs)),
+            TradeStatus(mine, theirs) =>
+                ww.write_msg(id, (op::TradeStatus, mine, theirs)),
+            TradeClosed =>
+                ww.write_msg(id, op::TradeClosed),
+            DayNightCycle(now, cycle_base, cycle_ms) =>
+                ww.write_msg(id, (op::DayNightCycle, now, cycle_base, cycle_ms)),
+            CameraUpdate(eid, ref motion) =>
+                ww.write_msg(id, (op::CameraUpdate, eid, motion)),
+            PartyUpdate(ref members) =>
+                ww.write_msg(id, (op::PartyUpdate, members)),
+            PartyMarkers(ref markers) =>
+                ww.write_msg(id, (op::PartyMarkers, markers)),
 
             ClientRemoved(wire_id) =>
                 ww.write_msg(id, (op::ClientRemoved, wire_id)),
@@ -363,17 +409,19 @@ pub struct Motion {
     pub start_time: LocalTime,
     pub end_pos: (u16, u16, u16),
     pub end_time: LocalTime,
+    pub arc: u16,
 }
 
 impl wire::ReadFrom for Motion {
     fn read_from<R: Read>(r: &mut WireReader<R>) -> io::Result<Motion> {
-        let (a, b, c, d): ((u16, u16, u16), LocalTime, (u16, u16, u16), LocalTime) =
+        let (a, b, c, d, e): ((u16, u16, u16), LocalTime, (u16, u16, u16), LocalTime, u16) =
                             try!(wire::ReadFrom::read_from(r));
         Ok(Motion {
             start_pos: a,
             start_time: b,
             end_pos: c,
             end_time: d,
+            arc: e,
         })
     }
 }
@@ -384,6 +432,7 @@ impl wire::WriteTo for Motion {
         try!(self.start_time.write_to(w));
         try!(self.end_pos.write_to(w));
         try!(self.end_time.write_to(w));
+        try!(self.arc.write_to(w));
         Ok(())
     }
 
@@ -391,7 +440,8 @@ impl wire::WriteTo for Motion {
         self.start_pos.size() +
         self.start_time.size() +
         self.end_pos.size() +
-        self.end_time.size()
+        self.end_time.size() +
+        self.arc.size()
     }
 
     fn size_is_fixed() -> bool { true }
@@ -1,6 +1,9 @@
 //! Interface to the physics engine.  The physics engine itself lives in a separate library,
 //! `libphysics`, so that it can be compiled to asm.js for use on the client.  This system just
 //! provides the glue to connect the physics engine to entities and the rest of the `World`.
+use std::cmp;
+use std::u16;
+
 use libphysics::{self, ShapeSource};
 use libphysics::{CHUNK_SIZE, CHUNK_BITS, CHUNK_MASK, TILE_SIZE};
 
@@ -78,6 +81,12 @@ pub trait Fragment<'d> {
         let motion = try!(self.with_cache(|_sys, cache, world| -> StrResult<_> {
             let e = unwrap!(world.get_entity(eid));
 
+            // Don't interrupt a jump or fall that's still in progress.  The new target velocity
+            // takes effect when the entity lands, which triggers another update.
+            if e.motion().airborne(now) {
+                return Ok(None);
+            }
+
             // Run the physics calculation
 
             // TODO: hardcoded constant based on entity size
@@ -85,20 +94,27 @@ pub trait Fragment<'d> {
             let velocity = e.target_velocity();
             let size = V3::new(32, 32, 64);
 
-            let chunk_px = CHUNK_SIZE * TILE_SIZE;
-            let base_chunk = start_pos.div_floor(scalar(chunk_px)) - scalar::<V2>(3).extend(0);
-            let base_tile = base_chunk * scalar(CHUNK_SIZE);
-            let base_px = base_tile * scalar(TILE_SIZE);
+            let (source, base_px) = chunks_source(cache, e.plane_id(), start_pos);
+
+            // Entities with nothing underneath them fall before doing anything else.
+            if let Some((end_pos, dur, arc)) =
+                    libphysics::fall(&source, start_pos - base_px, size) {
+                return Ok(Some(airborne_motion(now, start_pos, end_pos + base_px, dur, arc)));
+            }
 
-            let source = ChunksSource {
-                cache: cache,
-                base_tile: base_tile,
-                plane: e.plane_id(),
-            };
             let (mut end_pos, mut dur) =
                 libphysics::collide(&source, start_pos - base_px, size, velocity);
             end_pos = end_pos + base_px;
 
+            // If the entity can't make any progress, it might be standing at the edge of a
+            // ledge.  In that case, walk off the edge and drop to the ground below.
+            if end_pos == start_pos && velocity != scalar(0) {
+                if let Some((end_pos, dur, arc)) =
+                        libphysics::step_off(&source, start_pos - base_px, size, velocity) {
+                    return Ok(Some(airborne_motion(now, start_pos, end_pos + base_px, dur, arc)));
+                }
+            }
+
             // NB: keep this in sync with client/js/physics.js  computeForecast
             if dur > DURATION_MAX as i32 {
                 let offset = end_pos - start_pos;
@@ -108,14 +124,20 @@ pub trait Fragment<'d> {
                 dur = DURATION_MAX as i32;
             }
 
-            Ok(Motion {
+            Ok(Some(Motion {
                 start_time: now,
                 duration: dur as Duration,
                 start_pos: start_pos,
                 end_pos: end_pos,
-            })
+                arc: 0,
+            }))
         }));
 
+        let motion = match motion {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+
         self.with_world(|wf| {
             let data = wf.world().data();
             let mut e = wf.entity_mut(eid);
@@ -135,12 +157,7 @@ pub trait Fragment<'d> {
 
             const ANIM_DIR_COUNT: AnimId = 8;
             static SPEED_NAME_MAP: [&'static str; 4] = ["stand", "walk", "", "run"];
-            let idx = (3 * (facing.x + 1) + (facing.y + 1)) as usize;
-            let anim_dir = [5, 4, 3, 6, 0, 2, 7, 0, 1][idx];
-            let anim_name = format!("pony/{}-{}",
-                                    SPEED_NAME_MAP[speed as usize],
-                                    anim_dir);
-            let anim = data.animations.get_id(&anim_name);
+            let anim = pony_anim(data, SPEED_NAME_MAP[speed as usize], facing);
 
             e.set_anim(anim);
             e.set_facing(facing);
@@ -148,4 +165,77 @@ pub trait Fragment<'d> {
         });
         Ok(())
     }
+
+    fn jump(&mut self, now: Time, eid: EntityId) -> StrResult<()> {
+        use world::Fragment;
+
+        let motion = try!(self.with_cache(|_sys, cache, world| -> StrResult<_> {
+            let e = unwrap!(world.get_entity(eid));
+
+            // No double jumps, and no jumping while falling.
+            if e.motion().airborne(now) {
+                return Ok(None);
+            }
+
+            // TODO: hardcoded constant based on entity size
+            let start_pos = e.pos(now);
+            let velocity = e.target_velocity();
+            let size = V3::new(32, 32, 64);
+
+            let (source, base_px) = chunks_source(cache, e.plane_id(), start_pos);
+            if libphysics::fall(&source, start_pos - base_px, size).is_some() {
+                // Can't jump without something to jump off of.
+                return Ok(None);
+            }
+
+            let (end_pos, dur, arc) =
+                libphysics::jump(&source, start_pos - base_px, size, velocity);
+            Ok(Some(airborne_motion(now, start_pos, end_pos + base_px, dur, arc)))
+        }));
+
+        if let Some(motion) = motion {
+            self.with_world(|wf| {
+                let data = wf.world().data();
+                let mut e = wf.entity_mut(eid);
+                // There is no dedicated jump animation, so leap using the running one.
+                let anim = pony_anim(data, "run", e.facing());
+                e.set_anim(anim);
+                e.set_motion(motion);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Get the pony animation `speed_name` (`stand`, `walk`, or `run`) for the direction `facing`.
+fn pony_anim(data: &Data, speed_name: &str, facing: V3) -> AnimId {
+    let idx = (3 * (facing.x + 1) + (facing.y + 1)) as usize;
+    let anim_dir = [5, 4, 3, 6, 0, 2, 7, 0, 1][idx];
+    let anim_name = format!("pony/{}-{}", speed_name, anim_dir);
+    data.animations.get_id(&anim_name)
+}
+
+fn chunks_source<'a>(cache: &'a TerrainCache, plane: PlaneId, pos: V3) -> (ChunksSource<'a>, V3) {
+    let chunk_px = CHUNK_SIZE * TILE_SIZE;
+    let base_chunk = pos.div_floor(scalar(chunk_px)) - scalar::<V2>(3).extend(0);
+    let base_tile = base_chunk * scalar(CHUNK_SIZE);
+    let base_px = base_tile * scalar(TILE_SIZE);
+
+    let source = ChunksSource {
+        cache: cache,
+        base_tile: base_tile,
+        plane: plane,
+    };
+    (source, base_px)
+}
+
+fn airborne_motion(now: Time, start_pos: V3, end_pos: V3, dur: i32, arc: i32) -> Motion {
+    // Jumps and falls are short, so `dur` and `arc` should always fit in a u16.
+    Motion {
+        start_time: now,
+        duration: cmp::min(dur, DURATION_MAX as i32 - 1) as Duration,
+        start_pos: start_pos,
+        end_pos: end_pos,
+        arc: cmp::min(arc, u16::MAX as i32) as u16,
+    }
 }
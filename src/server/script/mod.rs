@@ -1,5 +1,5 @@
 use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr;
 use libc::c_int;
 use rand::XorShiftRng;
@@ -10,6 +10,7 @@ use util::{StringError, StringResult};
 use engine;
 use engine::glue::WorldFragment;
 use engine::split::EngineRef;
+use logic::events::WorldEvent;
 use messages;
 use msg;
 use terrain_gen;
@@ -17,7 +18,7 @@ use timer;
 use world;
 use world::object::*;
 
-use lua::{OwnedLuaState, LuaState};
+use lua::{OwnedLuaState, LuaState, ValueType};
 use lua::{GLOBALS_INDEX, REGISTRY_INDEX};
 
 pub use self::save::{WriteHooks, ReadHooks};
@@ -27,9 +28,12 @@ use self::traits::ToLua;
 use self::traits::{MetatableKey, metatable_key};
 
 
+#[macro_use] mod signature;
 #[macro_use] mod traits;
 mod userdata;
 mod save;
+mod reload;
+mod profile;
 
 
 const FFI_CALLBACKS_KEY: &'static str = "outpost_ffi_callbacks";
@@ -37,63 +41,77 @@ const FFI_LIB_NAME: &'static str = "outpost_ffi";
 
 const BOOTSTRAP_FILE: &'static str = "bootstrap.lua";
 
+/// Total memory available to scripts, in bytes.
+const MEMORY_LIMIT: usize = 512 * 1024 * 1024;
+
+/// Number of VM instructions a single callback may run before it's aborted.
+const CALLBACK_INSTRUCTION_BUDGET: u32 = 10_000_000;
+
+/// Registry key of the message handler used when running callbacks.  It's set by
+/// `core/sandbox.lua` and adds the failing mod's name and a traceback to error messages.
+const ERROR_HANDLER_KEY: &'static str = "outpost_callback_error_handler";
+
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Nil;
 
 pub struct ScriptEngine {
     owned_lua: OwnedLuaState,
+    script_dir: PathBuf,
 }
 
 impl ScriptEngine {
     pub fn new(script_dir: &Path) -> ScriptEngine {
-        // OwnedLuaState::new() should return Err only on out-of-memory.
-        let mut owned_lua = OwnedLuaState::new().unwrap();
-
-        {
-            let mut lua = owned_lua.get();
-            lua.open_libs();
-
-            // Put an empty table into _LOADED.bootstrap, to prevent "require('bootstrap')" from
-            // causing problems.
-            lua.get_field(REGISTRY_INDEX, "_LOADED");
-            lua.push_string("bootstrap");
-            lua.push_table();
-            lua.set_table(-3);
-            lua.pop(1);
-
-            // Set package.path to the script directory.
-            lua.get_field(GLOBALS_INDEX, "package");
-            lua.push_string("path");
-            lua.push_string(&*format!("{}/?.lua",
-                                      script_dir.to_str().expect("invalid utf-8 in path")));
-            lua.set_table(-3);
-            lua.pop(1);
-
-            // Set up the `outpost_ffi` library.
-            build_ffi_lib(&mut lua);
-
-            // Stack: outpost_ffi
-            lua.get_field(REGISTRY_INDEX, "_LOADED");
-            lua.copy(-2);
-            // Stack: outpost_ffi, _LOADED, outpost_ffi
-            lua.set_field(-2, FFI_LIB_NAME);
-            lua.pop(1);
-
-            // Stack: outpost_ffi
-            lua.set_field(GLOBALS_INDEX, FFI_LIB_NAME);
-
-
-            // Finally, actually run the startup script.
-            lua.load_file(&script_dir.join(BOOTSTRAP_FILE)).unwrap();
-            lua.pcall(0, 0, 0).unwrap();
-        }
+        let owned_lua = match load_scripts(script_dir) {
+            Ok(x) => x,
+            Err(e) => panic!("failed to load scripts: {}", e.msg),
+        };
 
         ScriptEngine {
             owned_lua: owned_lua,
+            script_dir: script_dir.to_owned(),
         }
     }
 
+    /// Replace the running Lua state with a new one, built by running the startup script again.
+    /// The `extra` data of all loaded objects is copied into the new state, and the load hooks
+    /// are run so scripts can re-arm their persistent timers.  If the new scripts fail to load,
+    /// the old state is left running.
+    pub fn reload(eng: &mut engine::Engine) -> StringResult<()> {
+        let mut new_lua = try!(load_scripts(&eng.script.script_dir));
+        try!(reload::copy_extras(&mut eng.script.owned_lua.get(),
+                                 &mut new_lua.get(),
+                                 &eng.world));
+
+        // Timers scheduled by the old state would otherwise fire into the new one.
+        warn_on_err!(ScriptEngine::with_engine(eng, |lua| {
+            run_callback(lua, "outpost_callback_cancel_all_timers", ())
+        }));
+        eng.script.owned_lua = new_lua;
+        eng.extra.world_events.clear_subscribers();
+        ScriptEngine::cb_world_event_sync(eng);
+
+        let eids = eng.world.entities().map(|e| e.id().unwrap()).collect::<Vec<_>>();
+        let pids = eng.world.planes().map(|p| p.id().unwrap()).collect::<Vec<_>>();
+        let sids = eng.world.structures()
+                      .filter(|s| s.flags().contains(world::flags::S_HAS_SAVE_HOOKS))
+                      .map(|s| s.id().unwrap())
+                      .collect::<Vec<_>>();
+        ScriptEngine::with_engine(eng, |lua| {
+            warn_on_err!(run_callback(lua, "outpost_callback_world_load", ()));
+            for &eid in &eids {
+                warn_on_err!(run_callback(lua, "outpost_callback_entity_load", eid));
+            }
+            for &pid in &pids {
+                warn_on_err!(run_callback(lua, "outpost_callback_plane_load", pid));
+            }
+            for &sid in &sids {
+                warn_on_err!(run_callback(lua, "outpost_callback_structure_load", sid));
+            }
+        });
+        Ok(())
+    }
+
     fn with_context<F, R, E>(&mut self,
                              ptr: *mut E,
                              f: F) -> R
@@ -136,6 +154,27 @@ impl ScriptEngine {
         })
     }
 
+    /// Pass a chat message through the script chat filters.  Returns the message to send, which
+    /// the filters may have rewritten, or `None` if they dropped it.
+    pub fn cb_chat_filter(eng: &mut engine::Engine,
+                          cid: ClientId,
+                          channel: &str,
+                          msg: &str) -> StringResult<Option<String>> {
+        ScriptEngine::with_engine(eng, |lua| {
+            run_callback_with(lua,
+                              "outpost_callback_chat_filter",
+                              (userdata::world::Client { id: cid }, channel, msg),
+                              1,
+                              |lua| {
+                                  if lua.type_of(-1) == ValueType::String {
+                                      lua.to_string(-1).map(|s| s.to_owned())
+                                  } else {
+                                      None
+                                  }
+                              })
+        })
+    }
+
     pub fn cb_login(eng: &mut engine::Engine, cid: ClientId) -> StringResult<()> {
         ScriptEngine::with_engine(eng, |lua| {
             run_callback(lua,
@@ -201,13 +240,63 @@ impl ScriptEngine {
     }
 
 
+    /// Ask scripts to report their world event subscriptions.  Called once the server is ready
+    /// to receive them, after loading the world or replacing the script state.
+    pub fn cb_world_event_sync(eng: &mut engine::Engine) {
+        warn_on_err!(ScriptEngine::with_engine(eng, |lua| {
+            run_callback(lua, "outpost_callback_world_event_sync", ())
+        }));
+    }
+
+    pub fn cb_world_events(eng: &mut engine::Engine, events: &[WorldEvent]) {
+        use self::userdata::world::{Entity, Inventory, Plane, Structure};
+        ScriptEngine::with_engine(eng, |lua| {
+            let key = "outpost_callback_world_event";
+            for evt in events {
+                let result = match *evt {
+                    WorldEvent::StructureCreate(sid) =>
+                        run_callback(lua, key, ("structure_create", Structure { id: sid })),
+                    WorldEvent::StructureDestroy(sid, pid, bounds) =>
+                        run_callback(lua, key, ("structure_destroy",
+                                                sid.unwrap(),
+                                                Plane { id: pid },
+                                                bounds.min,
+                                                bounds.max)),
+                    WorldEvent::StructureReplace(sid) =>
+                        run_callback(lua, key, ("structure_replace", Structure { id: sid })),
+                    WorldEvent::InventoryUpdate(iid, item_id, old_count, new_count) =>
+                        run_callback(lua, key, ("inventory_update",
+                                                Inventory { id: iid },
+                                                item_id,
+                                                old_count,
+                                                new_count)),
+                    WorldEvent::EntityMotion(eid) =>
+                        run_callback(lua, key, ("entity_motion", Entity { id: eid })),
+                    WorldEvent::TerrainChunkCreate(pid, cpos) =>
+                        run_callback(lua, key, ("terrain_chunk_create", Plane { id: pid }, cpos)),
+                    WorldEvent::Dawn(day) =>
+                        run_callback(lua, key, ("dawn", day)),
+                    WorldEvent::Dusk(day) =>
+                        run_callback(lua, key, ("dusk", day)),
+                };
+                warn_on_err!(result);
+            }
+        })
+    }
+
+
     pub fn cb_eval(eng: &mut engine::Engine,
                    code: &str) -> Result<String, String> {
         ScriptEngine::with_engine(eng, |lua| {
             lua.get_field(REGISTRY_INDEX, "outpost_callback_eval");
             userdata::world::World.to_lua(lua);
             code.to_lua(lua);
-            try!(lua.pcall(2, 1, 0).map_err(|(e,s)| format!("{:?}: {}", e, s)));
+            let started_budget = lua.begin_budget(CALLBACK_INSTRUCTION_BUDGET);
+            let result = lua.pcall(2, 1, 0).map_err(|(e,s)| format!("{:?}: {}", e, s));
+            if started_budget {
+                lua.end_budget();
+            }
+            try!(result);
             let result = lua.to_string(-1).unwrap_or("(bad result)");
             Ok(result.to_owned())
         })
@@ -257,10 +346,97 @@ impl ScriptEngine {
 
 
 fn run_callback<A: ToLua>(lua: &mut LuaState, key: &str, args: A) -> StringResult<()> {
+    run_callback_with(lua, key, args, 0, |_| ())
+}
+
+/// Like `run_callback`, but keeps `num_results` results from the callback, and passes them to `f`
+/// while they're still on the stack.
+fn run_callback_with<A, F, R>(lua: &mut LuaState,
+                              key: &str,
+                              args: A,
+                              num_results: c_int,
+                              f: F) -> StringResult<R>
+        where A: ToLua,
+              F: FnOnce(&mut LuaState) -> R {
+    let base = lua.top_index();
+    lua.get_field(REGISTRY_INDEX, ERROR_HANDLER_KEY);
+    let handler_index = if lua.type_of(-1) == ValueType::Function { base + 1 } else { 0 };
+
     lua.get_field(REGISTRY_INDEX, key);
     let arg_count = pack_count(lua, args);
-    lua.pcall(arg_count, 0, 0)
-       .map_err(|(e, s)| StringError { msg: format!("{:?}: {}", e, s) })
+
+    let started_budget = lua.begin_budget(CALLBACK_INSTRUCTION_BUDGET);
+    let start = lua.profile_callback_begin();
+    let result = lua.pcall(arg_count, num_results, handler_index)
+                    .map_err(|(e, s)| StringError {
+                        msg: format!("callback {}: {:?}: {}", callback_name(key), e, s),
+                    });
+    lua.profile_callback_end(callback_name(key), start, started_budget);
+    if started_budget {
+        lua.end_budget();
+    }
+    let result = result.map(|()| f(lua));
+
+    // Pop the error handler, along with the error message if there is one.
+    let count = lua.top_index() - base;
+    lua.pop(count);
+    result
+}
+
+fn callback_name(key: &str) -> &str {
+    if key.starts_with("outpost_callback_") {
+        &key["outpost_callback_".len()..]
+    } else {
+        key
+    }
+}
+
+fn load_scripts(script_dir: &Path) -> StringResult<OwnedLuaState> {
+    // OwnedLuaState::with_memory_limit() should return Err only on out-of-memory.
+    let mut owned_lua = OwnedLuaState::with_memory_limit(MEMORY_LIMIT).unwrap();
+
+    {
+        let mut lua = owned_lua.get();
+        lua.open_libs();
+
+        // Put an empty table into _LOADED.bootstrap, to prevent "require('bootstrap')" from
+        // causing problems.
+        lua.get_field(REGISTRY_INDEX, "_LOADED");
+        lua.push_string("bootstrap");
+        lua.push_table();
+        lua.set_table(-3);
+        lua.pop(1);
+
+        // Set package.path to the script directory.
+        lua.get_field(GLOBALS_INDEX, "package");
+        lua.push_string("path");
+        lua.push_string(&*format!("{}/?.lua",
+                                  script_dir.to_str().expect("invalid utf-8 in path")));
+        lua.set_table(-3);
+        lua.pop(1);
+
+        // Set up the `outpost_ffi` library.
+        build_ffi_lib(&mut lua);
+
+        // Stack: outpost_ffi
+        lua.get_field(REGISTRY_INDEX, "_LOADED");
+        lua.copy(-2);
+        // Stack: outpost_ffi, _LOADED, outpost_ffi
+        lua.set_field(-2, FFI_LIB_NAME);
+        lua.pop(1);
+
+        // Stack: outpost_ffi
+        lua.set_field(GLOBALS_INDEX, FFI_LIB_NAME);
+
+
+        // Finally, actually run the startup script.
+        try!(lua.load_file(&script_dir.join(BOOTSTRAP_FILE))
+                .map_err(|(e, s)| StringError { msg: format!("{:?}: {}", e, s) }));
+        try!(lua.pcall(0, 0, 0)
+                .map_err(|(e, s)| StringError { msg: format!("{:?}: {}", e, s) }));
+    }
+
+    Ok(owned_lua)
 }
 
 fn build_ffi_lib(lua: &mut LuaState) {
@@ -271,6 +447,12 @@ fn build_ffi_lib(lua: &mut LuaState) {
 
     build_callbacks_table(lua);
     lua.set_field(-2, "callbacks");
+
+    signature::push_signatures(lua);
+    lua.set_field(-2, "signatures");
+
+    profile::build_profiler_table(lua);
+    lua.set_field(-2, "profiler");
 }
 
 // NB: assumes the idxs are negative
@@ -279,6 +461,7 @@ fn build_type_table<U: Userdata>(lua: &mut LuaState) {
 
     lua.push_table();
     // Stack: type, table
+    signature::set_section(lua, "table");
     <U as Userdata>::populate_table(lua);
 
     lua.push_table();
@@ -287,6 +470,7 @@ fn build_type_table<U: Userdata>(lua: &mut LuaState) {
     // with `ud:method()` syntax.  But we also let the Userdata impl override this behavior.
     lua.copy(-2);
     lua.set_field(-2, "__index");
+    signature::set_section(lua, "metatable");
     <U as Userdata>::populate_metatable(lua);
 
     // Stack: type, table, metatable
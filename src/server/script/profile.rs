@@ -0,0 +1,140 @@
+//! Script profiler.  `outpost_ffi.profiler` lets scripts turn profiling on and off, discard the
+//! data collected so far, and get a report of the most expensive callbacks, Rust functions, and
+//! Lua functions.  The data itself is collected by `LuaState` (see `lua::Profile`).
+use std::collections::HashMap;
+use libc::c_int;
+
+use lua::{LuaState, CallStats, ValueType};
+use lua::REGISTRY_INDEX;
+
+use super::FFI_LIB_NAME;
+
+
+/// Maximum number of entries to show in each section of the report.
+const REPORT_ENTRIES: usize = 15;
+
+pub fn build_profiler_table(lua: &mut LuaState) {
+    lua.push_table();
+
+    lua.push_rust_function(profiler_start);
+    lua.set_field(-2, "start");
+    lua.push_rust_function(profiler_stop);
+    lua.set_field(-2, "stop");
+    lua.push_rust_function(profiler_reset);
+    lua.set_field(-2, "reset");
+    lua.push_rust_function(profiler_is_running);
+    lua.set_field(-2, "is_running");
+    lua.push_rust_function(profiler_report);
+    lua.set_field(-2, "report");
+}
+
+fn profiler_start(mut lua: LuaState) -> c_int {
+    lua.start_profiling();
+    0
+}
+
+fn profiler_stop(mut lua: LuaState) -> c_int {
+    lua.stop_profiling();
+    0
+}
+
+fn profiler_reset(mut lua: LuaState) -> c_int {
+    lua.reset_profile();
+    0
+}
+
+fn profiler_is_running(mut lua: LuaState) -> c_int {
+    let running = lua.profile().is_some();
+    lua.push_boolean(running);
+    1
+}
+
+fn profiler_report(mut lua: LuaState) -> c_int {
+    let names = ffi_function_names(&mut lua);
+    let report = match lua.profile() {
+        Some(profile) => {
+            let mut lines = Vec::new();
+
+            lines.push("callbacks:".to_owned());
+            report_section(&mut lines, "calls",
+                           profile.callbacks.iter().map(|(k, v)| (k.clone(), v)));
+
+            lines.push("rust functions:".to_owned());
+            report_section(&mut lines, "calls", profile.ffi.iter().map(|(&addr, v)| {
+                let name = match names.get(&addr) {
+                    Some(name) => name.clone(),
+                    None => format!("<function {:x}>", addr),
+                };
+                (name, v)
+            }));
+
+            lines.push("lua functions (sampled):".to_owned());
+            report_section(&mut lines, "samples",
+                           profile.functions.iter().map(|(k, v)| (k.clone(), v)));
+
+            lines.join("\n")
+        },
+        None => "profiler is not running".to_owned(),
+    };
+    lua.push_string(&report);
+    1
+}
+
+fn report_section<'a, I>(lines: &mut Vec<String>, unit: &str, iter: I)
+        where I: Iterator<Item=(String, &'a CallStats)> {
+    let mut entries = iter.collect::<Vec<_>>();
+    if entries.len() == 0 {
+        lines.push("  (none)".to_owned());
+        return;
+    }
+
+    entries.sort_by(|a, b| b.1.total_ns.cmp(&a.1.total_ns));
+    for &(ref name, stats) in entries.iter().take(REPORT_ENTRIES) {
+        lines.push(format!("  {}: {} {}, {:.3} ms total, {:.3} ms max",
+                           name,
+                           stats.count,
+                           unit,
+                           stats.total_ns as f64 / 1e6,
+                           stats.max_ns as f64 / 1e6));
+    }
+    if entries.len() > REPORT_ENTRIES {
+        lines.push(format!("  ({} more)", entries.len() - REPORT_ENTRIES));
+    }
+}
+
+/// Build a map from the address of each Rust function in `outpost_ffi.types` to its name, in the
+/// form "Type.function".
+fn ffi_function_names(lua: &mut LuaState) -> HashMap<usize, String> {
+    let mut names = HashMap::new();
+
+    lua.get_field(REGISTRY_INDEX, "_LOADED");
+    lua.get_field(-1, FFI_LIB_NAME);
+    lua.get_field(-1, "types");
+    // Stack: _LOADED, outpost_ffi, types
+    if lua.type_of(-1) != ValueType::Table {
+        lua.pop(3);
+        return names;
+    }
+
+    lua.push_nil();
+    while lua.next_entry(-2) {
+        // Stack: ..., types, type_name, type
+        let type_name = lua.to_string(-2).unwrap_or("?").to_owned();
+        lua.get_field(-1, "table");
+        if lua.type_of(-1) == ValueType::Table {
+            lua.push_nil();
+            while lua.next_entry(-2) {
+                // Stack: ..., type, table, fn_name, fn
+                if let Some(addr) = lua.to_function_addr(-1) {
+                    let fn_name = lua.to_string(-2).unwrap_or("?").to_owned();
+                    names.insert(addr, format!("{}.{}", type_name, fn_name));
+                }
+                lua.pop(1);
+            }
+        }
+        lua.pop(2);
+    }
+
+    lua.pop(3);
+    names
+}
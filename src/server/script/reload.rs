@@ -0,0 +1,211 @@
+//! Support for replacing the running Lua state with a freshly loaded one.  Script-visible state
+//! that must survive the reload (the `extra` data attached to world objects) is copied value by
+//! value from the old state into the new one.
+
+use libc::c_int;
+
+use types::*;
+use util::{StringError, StringResult};
+
+use lua::{LuaState, ValueType, REGISTRY_INDEX};
+use world::World;
+use world::object::*;
+
+use super::traits::{ToLua, Userdata, metatable_key};
+use super::userdata;
+
+
+/// Maximum nesting depth of tables in copied values.  Deeper nesting most likely indicates a
+/// reference cycle, which can't be copied.
+const MAX_DEPTH: u32 = 100;
+
+fn lua_error(what: &str, (e, s): (::lua::ErrorType, &str)) -> StringError {
+    StringError { msg: format!("{}: {:?}: {}", what, e, s) }
+}
+
+
+pub fn copy_extras(old: &mut LuaState, new: &mut LuaState, w: &World) -> StringResult<()> {
+    try!(copy_extra(old, new, "world", ()));
+    for c in w.clients() {
+        try!(copy_extra(old, new, "client", c.id().unwrap()));
+    }
+    for e in w.entities() {
+        try!(copy_extra(old, new, "entity", e.id().unwrap()));
+    }
+    for i in w.inventories() {
+        try!(copy_extra(old, new, "inventory", i.id().unwrap()));
+    }
+    for p in w.planes() {
+        try!(copy_extra(old, new, "plane", p.id().unwrap()));
+    }
+    for s in w.structures() {
+        try!(copy_extra(old, new, "structure", s.id().unwrap()));
+    }
+    Ok(())
+}
+
+fn copy_extra<T: ToLua + Copy>(old: &mut LuaState,
+                               new: &mut LuaState,
+                               kind: &str,
+                               id: T) -> StringResult<()> {
+    let getter = format!("outpost_callback_get_{}_extra", kind);
+    old.get_field(REGISTRY_INDEX, &*getter);
+    id.to_lua(old);
+    try!(old.pcall(<T as ToLua>::count(), 1, 0).map_err(|e| lua_error(&*getter, e)));
+
+    if old.type_of(-1) == ValueType::Nil {
+        old.pop(1);
+        return Ok(());
+    }
+
+    let setter = format!("outpost_callback_set_{}_extra", kind);
+    let base = new.top_index();
+    new.get_field(REGISTRY_INDEX, &*setter);
+    id.to_lua(new);
+    let result = copy_value(old, new, -1, 0);
+    old.pop(1);
+    if let Err(e) = result {
+        let count = new.top_index() - base;
+        new.pop(count);
+        return Err(e);
+    }
+
+    try!(new.pcall(<T as ToLua>::count() + 1, 0, 0).map_err(|e| lua_error(&*setter, e)));
+    Ok(())
+}
+
+
+/// Push onto `new` a copy of the value at `index` in `old`.  Values that can't be saved in
+/// `extra` data are replaced with `nil`.
+fn copy_value(old: &mut LuaState,
+              new: &mut LuaState,
+              index: c_int,
+              depth: u32) -> StringResult<()> {
+    match old.type_of(index) {
+        ValueType::Nil => new.push_nil(),
+        ValueType::Boolean => new.push_boolean(old.to_boolean(index)),
+        ValueType::Number => new.push_number(old.to_number(index)),
+        ValueType::String => new.push_string(unwrap!(old.to_string(index))),
+        ValueType::Table => try!(copy_table(old, new, index, depth)),
+        ValueType::Userdata => copy_userdata(old, new, index),
+        ty => {
+            warn!("don't know how to copy {:?}", ty);
+            new.push_nil();
+        },
+    }
+    Ok(())
+}
+
+fn copy_table(old: &mut LuaState,
+              new: &mut LuaState,
+              index: c_int,
+              depth: u32) -> StringResult<()> {
+    if depth >= MAX_DEPTH {
+        fail!("tables are nested too deeply (is there a cycle?)");
+    }
+
+    let index = old.abs_index(index);
+    new.push_table();
+
+    old.push_nil();
+    while old.next_entry(index) {
+        if let Err(e) = copy_value(old, new, -2, depth + 1) {
+            old.pop(2);
+            return Err(e);
+        }
+        if new.type_of(-1) == ValueType::Nil {
+            // The key couldn't be copied.  Skip this entry.
+            new.pop(1);
+            old.pop(1);
+            continue;
+        }
+        if let Err(e) = copy_value(old, new, -1, depth + 1) {
+            old.pop(2);
+            return Err(e);
+        }
+        new.set_table(-3);
+        old.pop(1);
+    }
+
+    Ok(())
+}
+
+fn copy_userdata(old: &mut LuaState, new: &mut LuaState, index: c_int) {
+    let index = old.abs_index(index);
+
+    if !old.get_metatable(index) {
+        warn!("don't know how to copy userdata with no metatable");
+        new.push_nil();
+        return;
+    }
+    fn get_userdata_opt<'a, U: Userdata>(lua: &'a mut LuaState, index: c_int) -> Option<&'a U> {
+        lua.get_field(REGISTRY_INDEX, metatable_key::<U>());
+        if lua.raw_equal(-1, -2) {
+            lua.pop(2);
+            unsafe { lua.to_userdata(index) }
+        } else {
+            lua.pop(1);
+            None
+        }
+    }
+
+    if let Some(_) = get_userdata_opt::<userdata::world::World>(old, index) {
+        userdata::world::World.to_lua(new);
+        return;
+    }
+
+    if let Some(c) = get_userdata_opt::<userdata::world::Client>(old, index) {
+        userdata::world::Client { id: c.id }.to_lua(new);
+        return;
+    }
+    if let Some(e) = get_userdata_opt::<userdata::world::Entity>(old, index) {
+        userdata::world::Entity { id: e.id }.to_lua(new);
+        return;
+    }
+    if let Some(i) = get_userdata_opt::<userdata::world::Inventory>(old, index) {
+        userdata::world::Inventory { id: i.id }.to_lua(new);
+        return;
+    }
+    if let Some(p) = get_userdata_opt::<userdata::world::Plane>(old, index) {
+        userdata::world::Plane { id: p.id }.to_lua(new);
+        return;
+    }
+    if let Some(s) = get_userdata_opt::<userdata::world::Structure>(old, index) {
+        userdata::world::Structure { id: s.id }.to_lua(new);
+        return;
+    }
+
+    if let Some(c) = get_userdata_opt::<userdata::world::StableClient>(old, index) {
+        userdata::world::StableClient { id: Stable::new(c.id.val) }.to_lua(new);
+        return;
+    }
+    if let Some(e) = get_userdata_opt::<userdata::world::StableEntity>(old, index) {
+        userdata::world::StableEntity { id: Stable::new(e.id.val) }.to_lua(new);
+        return;
+    }
+    if let Some(i) = get_userdata_opt::<userdata::world::StableInventory>(old, index) {
+        userdata::world::StableInventory { id: Stable::new(i.id.val) }.to_lua(new);
+        return;
+    }
+    if let Some(p) = get_userdata_opt::<userdata::world::StablePlane>(old, index) {
+        userdata::world::StablePlane { id: Stable::new(p.id.val) }.to_lua(new);
+        return;
+    }
+    if let Some(s) = get_userdata_opt::<userdata::world::StableStructure>(old, index) {
+        userdata::world::StableStructure { id: Stable::new(s.id.val) }.to_lua(new);
+        return;
+    }
+
+    if let Some(v) = get_userdata_opt::<V3>(old, index) {
+        V3::new(v.x, v.y, v.z).to_lua(new);
+        return;
+    }
+    if let Some(t) = get_userdata_opt::<userdata::timer::TimeU>(old, index) {
+        userdata::timer::TimeU { t: t.t }.to_lua(new);
+        return;
+    }
+
+    warn!("don't know how to copy unrecognized userdata");
+    old.pop(1);
+    new.push_nil();
+}
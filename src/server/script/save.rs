@@ -416,6 +416,7 @@ impl<'a, 'd> save::ReadHooks for ReadHooks<'a, 'd> {
         try!(self.read_extra(reader, |lua| {
             lua.get_field(REGISTRY_INDEX, "outpost_callback_set_world_extra");
         }));
+        try!(self.call_load_hook("outpost_callback_world_load", ()));
         Ok(())
     }
 
@@ -434,6 +435,7 @@ impl<'a, 'd> save::ReadHooks for ReadHooks<'a, 'd> {
         try!(self.read_extra(reader, |lua| {
             push_setter_and_id(lua, "outpost_callback_set_entity_extra", eid.unwrap())
         }));
+        try!(self.call_load_hook("outpost_callback_entity_load", eid.unwrap()));
         Ok(())
     }
 
@@ -452,6 +454,7 @@ impl<'a, 'd> save::ReadHooks for ReadHooks<'a, 'd> {
         try!(self.read_extra(reader, |lua| {
             push_setter_and_id(lua, "outpost_callback_set_plane_extra", pid.unwrap())
         }));
+        try!(self.call_load_hook("outpost_callback_plane_load", pid.unwrap()));
         Ok(())
     }
 
@@ -546,7 +549,7 @@ impl<'a, 'd> ReadHooks<'a, 'd> {
         self.script_mut().with_context(ptr, |lua| -> Result<()> {
             lua.get_field(REGISTRY_INDEX, func);
             id.to_lua(lua);
-            try!(lua.pcall(1, 0, 0));
+            try!(lua.pcall(<T as ToLua>::count(), 0, 0));
             Ok(())
         })
     }
@@ -0,0 +1,194 @@
+//! Signatures of the Rust functions exposed to Lua.  `lua_table_fns2!` builds a `Signature` for
+//! each function it defines.  Argument checking uses it to produce readable error messages, and
+//! `build_type_table` collects all of them into `outpost_ffi.signatures`, so scripts (and the
+//! REPL) can inspect the API.  `util/gen_lua_api.py` reads that table from a running server's REPL
+//! to produce documentation and editor stubs.
+use libc::c_int;
+
+use lua::{LuaState, ValueType};
+use lua::REGISTRY_INDEX;
+
+
+/// Registry key of the table where signatures are collected while the `outpost_ffi` types are
+/// being built.  The table maps type names to a `table` and a `metatable` entry, matching the
+/// `outpost_ffi.types` layout, each holding signatures keyed by function name.
+const SIGNATURES_KEY: &'static str = "outpost_ffi_signatures";
+
+/// Registry key holding which of the type's tables `record` is currently filling in.
+const SECTION_KEY: &'static str = "outpost_ffi_signatures_section";
+
+pub struct Signature {
+    pub name: &'static str,
+    /// The engine context the function requires: "full", "partial", or "none".
+    pub context: &'static str,
+    pub args: &'static [Arg],
+    /// The Rust return type, or "_" if it's inferred.
+    pub ret: &'static str,
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub ty: &'static str,
+}
+
+impl Signature {
+    /// Get the name of the argument at stack index `index`.
+    pub fn arg_name(&self, index: c_int) -> Option<&'static str> {
+        if index < 1 {
+            return None;
+        }
+        self.args.get(index as usize - 1).map(|a| a.name)
+    }
+
+    /// Format the signature as it would appear in the Rust source, minus the context argument.
+    pub fn usage(&self) -> String {
+        let args = self.args.iter()
+                       .map(|a| format!("{}: {}", a.name, a.ty))
+                       .collect::<Vec<_>>();
+        format!("{}({}) -> {}", self.name, args.join(", "), self.ret)
+    }
+}
+
+macro_rules! lua_fn_signature {
+    ($name:ident,
+     (!$mode:ident $ctx:ident: $ctx_ty:ty, $($arg:ident: $arg_ty:ty),*),
+     $ret_ty:ty) => {
+        $crate::script::signature::Signature {
+            name: stringify!($name),
+            context: stringify!($mode),
+            args: &[$($crate::script::signature::Arg {
+                name: stringify!($arg),
+                ty: stringify!($arg_ty),
+            }),*],
+            ret: stringify!($ret_ty),
+        }
+    };
+
+    ($name:ident,
+     ($($arg:ident: $arg_ty:ty),*),
+     $ret_ty:ty) => {
+        $crate::script::signature::Signature {
+            name: stringify!($name),
+            context: "none",
+            args: &[$($crate::script::signature::Arg {
+                name: stringify!($arg),
+                ty: stringify!($arg_ty),
+            }),*],
+            ret: stringify!($ret_ty),
+        }
+    };
+}
+
+
+/// Set which of a type's tables the following calls to `record` describe: "table" for the
+/// functions in its method table, or "metatable" for those in its metatable.
+pub fn set_section(lua: &mut LuaState, section: &'static str) {
+    lua.push_string(section);
+    lua.set_field(REGISTRY_INDEX, SECTION_KEY);
+}
+
+/// Record `sig` as a function of the type `type_name`, in the section chosen by `set_section`.
+pub fn record(lua: &mut LuaState, type_name: &str, sig: &Signature) {
+    lua.get_field(REGISTRY_INDEX, SECTION_KEY);
+    let section = lua.to_string(-1).unwrap_or("table").to_owned();
+    lua.pop(1);
+
+    lua.get_field(REGISTRY_INDEX, SIGNATURES_KEY);
+    if lua.type_of(-1) != ValueType::Table {
+        lua.pop(1);
+        lua.push_table();
+        lua.copy(-1);
+        lua.set_field(REGISTRY_INDEX, SIGNATURES_KEY);
+    }
+
+    push_field_table(lua, type_name);
+    push_field_table(lua, &section);
+
+    // Stack: signatures, type_sigs, section_sigs
+    lua.push_table();
+    lua.push_string(sig.context);
+    lua.set_field(-2, "context");
+    lua.push_string(sig.ret);
+    lua.set_field(-2, "returns");
+
+    lua.push_table();
+    for (i, arg) in sig.args.iter().enumerate() {
+        lua.push_table();
+        lua.push_string(arg.name);
+        lua.set_field(-2, "name");
+        lua.push_string(arg.ty);
+        lua.set_field(-2, "type");
+        lua.push_integer(i as isize + 1);
+        lua.insert(-2);
+        lua.set_table(-3);
+    }
+    lua.set_field(-2, "args");
+
+    // Stack: signatures, type_sigs, section_sigs, sig
+    lua.set_field(-2, sig.name);
+    lua.pop(3);
+}
+
+/// Push the field `key` of the table on top of the stack, setting it to a new table first if it
+/// isn't one already.
+fn push_field_table(lua: &mut LuaState, key: &str) {
+    lua.get_field(-1, key);
+    if lua.type_of(-1) != ValueType::Table {
+        lua.pop(1);
+        lua.push_table();
+        lua.copy(-1);
+        lua.set_field(-3, key);
+    }
+}
+
+/// Push the table of all recorded signatures.
+pub fn push_signatures(lua: &mut LuaState) {
+    lua.get_field(REGISTRY_INDEX, SIGNATURES_KEY);
+    if lua.type_of(-1) != ValueType::Table {
+        lua.pop(1);
+        lua.push_table();
+    }
+}
+
+
+fn value_type_name(ty: ValueType) -> &'static str {
+    match ty {
+        ValueType::Nil => "nil",
+        ValueType::Boolean => "boolean",
+        ValueType::LightUserdata => "light userdata",
+        ValueType::Number => "number",
+        ValueType::String => "string",
+        ValueType::Table => "table",
+        ValueType::Function => "function",
+        ValueType::Userdata => "userdata",
+        ValueType::Thread => "thread",
+        ValueType::Unknown => "no value",
+    }
+}
+
+/// Raise an error for an argument of the wrong type.
+pub unsafe fn arg_error(lua: &mut LuaState,
+                        index: c_int,
+                        sig: &Signature,
+                        expected: &str) -> ! {
+    let actual = value_type_name(lua.type_of(index));
+    let msg = match sig.arg_name(index) {
+        Some(arg) => format!("bad argument #{} ({}) to '{}' ({} expected, got {})",
+                             index, arg, sig.name, expected, actual),
+        None => format!("bad argument #{} to '{}' ({} expected, got {})",
+                        index, sig.name, expected, actual),
+    };
+    lua.push_string(&msg);
+    lua.error();
+}
+
+/// Raise an error for a call with the wrong number of arguments.
+pub unsafe fn count_error(lua: &mut LuaState,
+                          sig: &Signature,
+                          actual: c_int,
+                          expected: c_int) -> ! {
+    let msg = format!("wrong number of arguments to '{}' ({} expected, got {}); usage: {}",
+                      sig.name, expected, actual, sig.usage());
+    lua.push_string(&msg);
+    lua.error();
+}
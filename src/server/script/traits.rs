@@ -7,6 +7,7 @@ use types::*;
 use util::StrResult;
 
 use super::Nil;
+use super::signature::{Signature, count_error};
 
 
 /// Trait for obtaining a string representation of the name of a type.  The Lua interface code uses
@@ -97,25 +98,21 @@ pub fn create_userdata<U: Userdata>(lua: &mut LuaState, u: U) {
 
 /// Types that can be read from the Lua stack.
 pub trait FromLua<'a> {
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str);
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature);
     unsafe fn from_lua(lua: &'a LuaState, index: c_int) -> Self;
     fn count() -> c_int { 1 }
 }
 
 macro_rules! type_error {
     ($lua:expr, $index:expr, $func:expr, $ty_name:expr) => {{
-        $lua.push_string(&*format!("bad argument {} to '{}' ({} expected)",
-                                   $index,
-                                   $func,
-                                   $ty_name));
-        $lua.error();
+        $crate::script::signature::arg_error($lua, $index, $func, $ty_name);
     }};
 }
 
 macro_rules! int_from_lua_impl {
     ($ty:ty) => {
         impl<'a> FromLua<'a> for $ty {
-            unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+            unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
                 if lua.type_of(index) != ValueType::Number {
                     type_error!(lua, index, func, "number");
                 }
@@ -136,7 +133,7 @@ int_from_lua_impl!(i16);
 int_from_lua_impl!(i32);
 
 impl<'a> FromLua<'a> for bool {
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
         if lua.type_of(index) != ValueType::Boolean {
             type_error!(lua, index, func, "boolean");
         }
@@ -148,7 +145,7 @@ impl<'a> FromLua<'a> for bool {
 }
 
 impl<'a> FromLua<'a> for Nil {
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
         if lua.type_of(index) != ValueType::Nil {
             type_error!(lua, index, func, "nil");
         }
@@ -160,7 +157,7 @@ impl<'a> FromLua<'a> for Nil {
 }
 
 impl<'a> FromLua<'a> for &'a str {
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
         if lua.type_of(index) != ValueType::String {
             type_error!(lua, index, func, "string");
         }
@@ -172,7 +169,7 @@ impl<'a> FromLua<'a> for &'a str {
 }
 
 impl<'a> FromLua<'a> for String {
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
         <&str as FromLua>::check(lua, index, func)
     }
 
@@ -190,7 +187,7 @@ pub fn is_userdata<U: Userdata>(lua: &mut LuaState, index: c_int) -> bool {
 }
 
 impl<'a, U: Userdata> FromLua<'a> for &'a U {
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
         if !is_userdata::<U>(lua, index) {
             type_error!(lua, index, func, type_name::<U>());
         }
@@ -204,7 +201,9 @@ impl<'a, U: Userdata> FromLua<'a> for &'a U {
 macro_rules! impl_fromlua_copy {
     ($U:ty) => {
         impl<'a> $crate::script::traits::FromLua<'a> for $U {
-            unsafe fn check(lua: &mut $crate::lua::LuaState, index: ::libc::c_int, func: &'static str) {
+            unsafe fn check(lua: &mut $crate::lua::LuaState,
+                            index: ::libc::c_int,
+                            func: &'static $crate::script::signature::Signature) {
                 <&'a $U as $crate::script::traits::FromLua>::check(lua, index, func);
             }
 
@@ -220,7 +219,7 @@ macro_rules! tuple_from_lua_impl {
     ($count:expr, $($ty:ident)*) => {
         #[allow(unused_variables, unused_mut, unused_attributes, non_snake_case)]
         impl<'a, $($ty: FromLua<'a>),*> FromLua<'a> for ($($ty,)*) {
-            unsafe fn check(lua: &mut LuaState, mut index: c_int, func: &'static str) {
+            unsafe fn check(lua: &mut LuaState, mut index: c_int, func: &'static Signature) {
                 $(
                     <$ty as FromLua>::check(lua, index, func);
                     index += <$ty as FromLua>::count();
@@ -252,11 +251,12 @@ tuple_from_lua_impl!(2, A B);
 tuple_from_lua_impl!(3, A B C);
 tuple_from_lua_impl!(4, A B C D);
 tuple_from_lua_impl!(5, A B C D E);
+tuple_from_lua_impl!(6, A B C D E F);
 
 macro_rules! newtype_from_lua_impl {
     ($ty:ident, $inner_ty:ty) => {
         impl<'a> FromLua<'a> for $ty {
-            unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+            unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
                 <$inner_ty as FromLua>::check(lua, index, func)
             }
 
@@ -276,28 +276,28 @@ newtype_from_lua_impl!(ClientId, u16);
 newtype_from_lua_impl!(EntityId, u32);
 newtype_from_lua_impl!(StructureId, u32);
 newtype_from_lua_impl!(InventoryId, u32);
+newtype_from_lua_impl!(PlaneId, u32);
 
-pub unsafe fn check_args<'a, T: FromLua<'a>>(lua: &mut LuaState, func: &'static str) {
+pub unsafe fn check_args<'a, T: FromLua<'a>>(lua: &mut LuaState,
+                                             func: &'static Signature) {
     let actual = lua.top_index();
     let expected = <T as FromLua>::count();
     if actual != expected {
-        lua.push_string(&*format!("wrong number of arguments for '{}' ({} expected)",
-                                  func,
-                                  expected));
-        lua.error();
+        count_error(lua, func, actual, expected);
     }
 
     <T as FromLua>::check(lua, 1, func);
 }
 
-pub unsafe fn unpack_args<'a, T: FromLua<'a>>(lua: &'a mut LuaState, func: &'static str) -> T {
+pub unsafe fn unpack_args<'a, T: FromLua<'a>>(lua: &'a mut LuaState,
+                                              func: &'static Signature) -> T {
     check_args::<T>(lua, func);
     let x = <T as FromLua>::from_lua(lua, 1);
     x
 }
 
 pub unsafe fn unpack_args_count<'a, T: FromLua<'a>>(lua: &'a mut LuaState,
-                                                    func: &'static str) -> (T, c_int) {
+                                                    func: &'static Signature) -> (T, c_int) {
     let x = unpack_args(lua, func);
     (x, <T as FromLua>::count())
 }
@@ -390,6 +390,18 @@ impl<T: ToLua> ToLua for Option<T> {
     fn count() -> c_int { <T as ToLua>::count() }
 }
 
+/// Vecs are converted to array-like tables.  Each element must produce exactly one Lua value.
+impl<T: ToLua> ToLua for Vec<T> {
+    fn to_lua(self, lua: &mut LuaState) {
+        lua.push_table();
+        for (i, x) in self.into_iter().enumerate() {
+            lua.push_integer(i as isize + 1);
+            x.to_lua(lua);
+            lua.set_table(-3);
+        }
+    }
+}
+
 impl<T: ToLua> ToLua for StrResult<T> {
     fn to_lua(self, lua: &mut LuaState) {
         match self {
@@ -6,12 +6,13 @@ use util::StrResult;
 
 use lua::{LuaState, ValueType};
 use msg;
+use script::signature::Signature;
 use script::traits::{FromLua, ToLua, Userdata};
 use script::userdata::OptWrapper;
 
 
 impl<'a> FromLua<'a> for msg::SimpleArg {
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
         let ty = lua.type_of(index);
         if ty != ValueType::Number && ty != ValueType::String {
             type_error!(lua, index, func, "number or string");
@@ -5,6 +5,7 @@ use util::StrResult;
 
 use lua::LuaState;
 use script::build_type_table;
+use script::signature::Signature;
 use script::traits::{TypeName, FromLua, Userdata};
 
 
@@ -26,16 +27,17 @@ macro_rules! mk_block {
 
 macro_rules! lua_fn_raw {
     // TODO: support functions that take only the context and no other args
-    ($name:ident,
+    ($name:ident, $sig:expr,
      (!partial $ctx:ident: $ctx_ty:ty, $($arg:ident: $arg_ty:ty),*),
      $ret_ty:ty,
      $body:expr) => {
         #[allow(unused_mut)]
         fn $name(mut lua: $crate::lua::LuaState) -> ::libc::c_int {
+            let start = lua.profile_begin();
             let (result, count): ($ret_ty, ::libc::c_int) = {
                 let ctx = unsafe { $crate::script::PartialContext::from_lua(&mut lua) };
                 let (args, count): (_, ::libc::c_int) = unsafe {
-                    $crate::script::traits::unpack_args_count(&mut lua, stringify!($name))
+                    $crate::script::traits::unpack_args_count(&mut lua, $sig)
                 };
                 // Use a closure to prevent $body from abusing the context reference, which will
                 // likely be inferred as 'static.
@@ -43,20 +45,23 @@ macro_rules! lua_fn_raw {
                 (f(ctx, args), count)
             };
             lua.pop(count);
-            $crate::script::traits::pack_count(&mut lua, result)
+            let n = $crate::script::traits::pack_count(&mut lua, result);
+            lua.profile_ffi($name as usize, start);
+            n
         }
     };
 
-    ($name:ident,
+    ($name:ident, $sig:expr,
      (!full $ctx:ident: $ctx_ty:ty, $($arg:ident: $arg_ty:ty),*),
      $ret_ty:ty,
      $body:expr) => {
         #[allow(unused_mut)]
         fn $name(mut lua: $crate::lua::LuaState) -> ::libc::c_int {
+            let start = lua.profile_begin();
             let result: $ret_ty = {
                 unsafe { <$ctx_ty as $crate::script::FullContext>::check(&mut lua) };
                 let (args, count): (_, ::libc::c_int) = unsafe {
-                    $crate::script::traits::unpack_args_count(&mut lua, stringify!($name))
+                    $crate::script::traits::unpack_args_count(&mut lua, $sig)
                 };
                 // Clear the stack in case of reentrant calls to the script engine.
                 lua.pop(count);
@@ -64,25 +69,30 @@ macro_rules! lua_fn_raw {
                 let f = |mut $ctx: $ctx_ty, ($($arg,)*): ($($arg_ty,)*)| $body;
                 f(ctx, args)
             };
-            $crate::script::traits::pack_count(&mut lua, result)
+            let n = $crate::script::traits::pack_count(&mut lua, result);
+            lua.profile_ffi($name as usize, start);
+            n
         }
     };
 
-    ($name:ident,
+    ($name:ident, $sig:expr,
      ($($arg:ident: $arg_ty:ty),*),
      $ret_ty:ty,
      $body:expr) => {
         #[allow(unused_mut)]
         fn $name(mut lua: $crate::lua::LuaState) -> ::libc::c_int {
+            let start = lua.profile_begin();
             let (result, count): ($ret_ty, ::libc::c_int) = {
                 let (args, count): (_, ::libc::c_int) = unsafe {
-                    $crate::script::traits::unpack_args_count(&mut lua, stringify!($name))
+                    $crate::script::traits::unpack_args_count(&mut lua, $sig)
                 };
                 let f = |($($arg,)*): ($($arg_ty,)*)| $body;
                 (f(args), count)
             };
             lua.pop(count);
-            $crate::script::traits::pack_count(&mut lua, result)
+            let n = $crate::script::traits::pack_count(&mut lua, result);
+            lua.profile_ffi($name as usize, start);
+            n
         }
     };
 }
@@ -95,10 +105,14 @@ macro_rules! lua_table_fns2 {
                 //$($arg_name:ident : $arg_ty:ty),*
         )*
     ) => {{
-        $(
-            lua_fn_raw!($name, ( $($a)* ), $ret_ty, mk_block!({ $($b)* } {}));
+        $({
+            static SIGNATURE: $crate::script::signature::Signature =
+                lua_fn_signature!($name, ( $($a)* ), $ret_ty);
+            lua_fn_raw!($name, &SIGNATURE, ( $($a)* ), $ret_ty, mk_block!({ $($b)* } {}));
             insert_function!($lua, $idx, stringify!($name), $name);
-        )*
+            $crate::script::signature::record(
+                $lua, <Self as $crate::script::traits::TypeName>::type_name(), &SIGNATURE);
+        })*
     }};
 }
 
@@ -189,7 +203,7 @@ pub struct TakeOptWrapper<T>(Option<T>);
 
 impl<'a, T: 'a> FromLua<'a> for TakeOptWrapper<T>
         where OptWrapper<T>: Userdata{
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
         <&'a OptWrapper<T> as FromLua>::check(lua, index, func);
     }
 
@@ -6,6 +6,7 @@ use util::StrResult;
 
 use lua::{LuaState, ValueType};
 use script::ScriptEngine;
+use script::signature::Signature;
 use script::traits::{TypeName, MetatableKey, FromLua, ToLua, Userdata, is_userdata};
 use script::userdata::OptWrapper;
 use timer;
@@ -17,7 +18,7 @@ enum TimeOrNumber {
 }
 
 impl<'a> FromLua<'a> for TimeOrNumber {
-    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static str) {
+    unsafe fn check(lua: &mut LuaState, index: c_int, func: &'static Signature) {
         if lua.type_of(index) != ValueType::Number && !is_userdata::<TimeU>(lua, index) {
             type_error!(lua, index, func, "number or Time");
         }
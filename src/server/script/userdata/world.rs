@@ -1,21 +1,32 @@
 use std::borrow::ToOwned;
+use std::collections::{HashMap, HashSet};
 
 use libphysics::CHUNK_SIZE;
 
 use types::*;
+use util::IntrusiveStableId;
 use util::StrResult;
 
+use audit;
 use engine::Engine;
 use engine::glue::WorldFragment;
 use logic;
+use logic::chat;
+use logic::clock;
+use logic::instance;
+use logic::mail;
+use logic::party;
 use lua::LuaState;
 use messages::ClientResponse;
 use msg;
 use script::traits::Userdata;
 use script::userdata::TakeOptWrapper;
 use script::userdata::extra_arg::ExtraArg;
+use script::userdata::timer::TimeU;
+use vision::Visibility;
 use world;
 use world::Fragment;
+use world::{LightingMode, PlaneProps};
 use world::object::*;
 
 
@@ -139,6 +150,145 @@ impl Userdata for World {
                              id: InventoryId) -> Option<Inventory> {
                 w.get_inventory(id).map(|_| Inventory { id: id })
             }
+
+            fn get_plane(!partial w: &world::World,
+                         _w: World,
+                         id: PlaneId) -> Option<Plane> {
+                w.get_plane(id).map(|_| Plane { id: id })
+            }
+
+            fn time_of_day(!full eng: &mut Engine, _w: World) -> u32 {
+                eng.extra.clock.world.tick(eng.now)
+            }
+
+            fn is_day(!full eng: &mut Engine, _w: World) -> bool {
+                eng.extra.clock.world.is_day(eng.now)
+            }
+
+            fn next_dawn(!full eng: &mut Engine, _w: World) -> TimeU {
+                TimeU { t: eng.extra.clock.world.next_tick(eng.now, clock::DAWN_TICK) }
+            }
+
+            fn next_dusk(!full eng: &mut Engine, _w: World) -> TimeU {
+                TimeU { t: eng.extra.clock.world.next_tick(eng.now, clock::DUSK_TICK) }
+            }
+
+            fn set_event_subscribers(!full eng: &mut Engine,
+                                     _w: World,
+                                     name: &str,
+                                     count: u32) -> () {
+                eng.extra.world_events.set_subscribers(name, count);
+            }
+
+            fn day(!full eng: &mut Engine, _w: World) -> u32 {
+                eng.extra.clock.world.day(eng.now) as u32
+            }
+
+            fn season(!full eng: &mut Engine, _w: World) -> &'static str {
+                clock::SEASONS[eng.extra.clock.season(eng.now)]
+            }
+
+            fn day_length(!full eng: &mut Engine, _w: World) -> u32 {
+                eng.extra.clock.world.day_ms
+            }
+
+            fn set_day_length(!full eng: &mut Engine, _w: World, ms: u32) -> StrResult<()> {
+                clock::set_day_length(eng.as_ref(), ms)
+            }
+
+            fn season_length(!full eng: &mut Engine, _w: World) -> u32 {
+                eng.extra.clock.season_days
+            }
+
+            fn set_season_length(!full eng: &mut Engine,
+                                 _w: World,
+                                 days: u32) -> StrResult<()> {
+                clock::set_season_length(eng.as_ref(), days)
+            }
+
+            fn client_count(!full eng: &mut Engine, _w: World) -> u32 {
+                eng.messages.clients_len() as u32
+            }
+
+            fn mute(!full eng: &mut Engine, _w: World, name: &str, ms: u32) -> StrResult<()> {
+                chat::mute(eng.as_ref(), name, ms)
+            }
+
+            fn unmute(!full eng: &mut Engine, _w: World, name: &str) -> StrResult<()> {
+                chat::unmute(eng.as_ref(), name)
+            }
+
+            fn mute_expiry(!full eng: &mut Engine, _w: World, name: &str) -> Option<TimeU> {
+                eng.extra.chat.mute_expiry(name, eng.now).map(|t| TimeU { t: t })
+            }
+
+            fn audit_log(!full eng: &mut Engine,
+                         _w: World,
+                         name: &str,
+                         since_secs: u32,
+                         limit: u32) -> StrResult<Vec<String>> {
+                let name = if name.len() > 0 { Some(name) } else { None };
+                logic::audit::query(eng.as_ref(), name, since_secs as Time * 1000, limit)
+            }
+
+            fn create_instance(!full eng: &mut Engine,
+                               _w: World,
+                               template: Plane,
+                               name: String,
+                               ttl_secs: u32,
+                               return_plane: StablePlane,
+                               return_pos: V3) -> StrResult<StablePlane> {
+                let props = *unwrap!(eng.world.get_plane(template.id)).props();
+                instance::create(eng.as_ref(), name, props, ttl_secs as Time * 1000,
+                                 return_plane.id, return_pos)
+                    .map(|id| StablePlane { id: id })
+            }
+
+            fn create_generated_instance(!full eng: &mut Engine,
+                                         _w: World,
+                                         generator: &str,
+                                         name: String,
+                                         ttl_secs: u32,
+                                         return_plane: StablePlane,
+                                         return_pos: V3) -> StrResult<StablePlane> {
+                let mut props = PlaneProps::new();
+                props.generator = unwrap!(GeneratorKind::from_name(generator),
+                                          "unknown terrain generator");
+                instance::create(eng.as_ref(), name, props, ttl_secs as Time * 1000,
+                                 return_plane.id, return_pos)
+                    .map(|id| StablePlane { id: id })
+            }
+
+            fn close_instance(!full eng: &mut Engine,
+                              _w: World,
+                              p: StablePlane) -> StrResult<()> {
+                instance::close(eng.as_ref(), p.id)
+            }
+
+            fn is_instance(!full eng: &mut Engine, _w: World, p: StablePlane) -> bool {
+                eng.extra.instances.is_instance(p.id)
+            }
+
+            fn plane_exists(!full eng: &mut Engine, _w: World, p: StablePlane) -> bool {
+                !eng.extra.instances.is_retired(p.id)
+            }
+
+            fn send_mail(!full eng: &mut Engine,
+                         _w: World,
+                         from: &str,
+                         to: &str,
+                         body: &str) -> StrResult<()> {
+                mail::send(eng.as_ref(), from, to, body, None)
+            }
+
+            fn send_mail_items(!full eng: &mut Engine,
+                               _w: World,
+                               from: &str,
+                               to: &str,
+                               body: &str,
+                               i: Inventory) -> StrResult<()> {
+                mail::send(eng.as_ref(), from, to, body, Some(i.id))
+            }
         }
     }
 }
@@ -209,6 +359,13 @@ impl Userdata for Client {
                 logic::items::open_crafting(eng.as_ref(), c.id, s.id, i.id)
             }
 
+            fn trade(!full eng: &mut Engine,
+                     c: Client,
+                     other: Client,
+                     main_inv: Inventory) -> StrResult<()> {
+                logic::trade::request(eng.as_ref(), c.id, main_inv.id, other.id)
+            }
+
             fn set_main_inventories(!full eng: &mut Engine,
                                     c: Client,
                                     item_inv: Inventory,
@@ -219,6 +376,172 @@ impl Userdata for Client {
                                                    ability_inv.id)
             }
 
+            fn camera_mode(!full eng: &mut Engine, c: Client) -> Option<&'static str> {
+                use logic::camera::Camera;
+                unwrap_or!(eng.world.get_client(c.id), return None);
+                let mode = match logic::camera::camera(&eng.extra, c.id) {
+                    Camera::Pawn => "pawn",
+                    Camera::Entity(_) => "entity",
+                    Camera::Free(..) => "free",
+                };
+                Some(mode)
+            }
+
+            fn camera_target(!full eng: &mut Engine, c: Client) -> Option<Entity> {
+                use logic::camera::Camera;
+                match logic::camera::camera(&eng.extra, c.id) {
+                    Camera::Entity(eid) => Some(Entity { id: eid }),
+                    _ => None,
+                }
+            }
+
+            fn follow_pawn(!full eng: &mut Engine, c: Client) -> StrResult<()> {
+                logic::camera::follow_pawn(eng.as_ref(), c.id)
+            }
+
+            fn follow_entity(!full eng: &mut Engine, c: Client, e: Entity) -> StrResult<()> {
+                logic::camera::follow_entity(eng.as_ref(), c.id, e.id)
+            }
+
+            fn set_camera_pos(!full eng: &mut Engine,
+                              c: Client,
+                              p: Plane,
+                              pos: V3) -> StrResult<()> {
+                logic::camera::set_free(eng.as_ref(), c.id, p.id, pos)
+            }
+
+            fn chat_channel(!full eng: &mut Engine, c: Client) -> Option<&'static str> {
+                unwrap_or!(eng.world.get_client(c.id), return None);
+                Some(eng.extra.chat.channel(c.id).name())
+            }
+
+            fn set_chat_channel(!full eng: &mut Engine, c: Client, name: &str) -> StrResult<()> {
+                let channel = unwrap!(chat::Channel::from_name(name), "no such channel");
+                chat::set_channel(eng.as_ref(), c.id, channel)
+            }
+
+            fn chat(!full eng: &mut Engine, c: Client, channel: &str, msg: &str) -> StrResult<()> {
+                let channel = unwrap!(chat::Channel::from_name(channel), "no such channel");
+                unwrap!(eng.world.get_client(c.id));
+                chat::send(eng.as_ref(), c.id, channel, msg);
+                Ok(())
+            }
+
+            fn whisper(!full eng: &mut Engine,
+                       c: Client,
+                       name: &str,
+                       msg: &str) -> StrResult<()> {
+                unwrap!(eng.world.get_client(c.id));
+                chat::whisper(eng.as_ref(), c.id, name, msg);
+                Ok(())
+            }
+
+            fn ignore(!full eng: &mut Engine, c: Client, name: &str) -> StrResult<()> {
+                chat::ignore(eng.as_ref(), c.id, name)
+            }
+
+            fn unignore(!full eng: &mut Engine, c: Client, name: &str) -> StrResult<()> {
+                chat::unignore(eng.as_ref(), c.id, name)
+            }
+
+            fn ignored(!full eng: &mut Engine, c: Client) -> Vec<String> {
+                match eng.world.get_client(c.id) {
+                    Some(c) => eng.extra.chat.ignored(c.name()),
+                    None => Vec::new(),
+                }
+            }
+
+            fn record_command(!full eng: &mut Engine, c: Client, msg: &str) -> StrResult<()> {
+                let name = unwrap!(eng.world.get_client(c.id)).name().to_owned();
+                eng.audit.record(audit::Kind::Command, &name, msg);
+                Ok(())
+            }
+
+            fn is_chat_staff(!full eng: &mut Engine, c: Client) -> bool {
+                eng.extra.chat.is_staff(c.id)
+            }
+
+            fn set_chat_staff(!full eng: &mut Engine, c: Client, staff: bool) -> StrResult<()> {
+                unwrap!(eng.world.get_client(c.id));
+                eng.extra.chat.set_staff(c.id, staff);
+                Ok(())
+            }
+
+            fn party_invite(!full eng: &mut Engine, c: Client, other: Client) -> StrResult<()> {
+                party::invite(eng.as_ref(), c.id, other.id)
+            }
+
+            fn party_accept(!full eng: &mut Engine, c: Client) -> StrResult<()> {
+                party::accept(eng.as_ref(), c.id)
+            }
+
+            fn party_decline(!full eng: &mut Engine, c: Client) -> StrResult<()> {
+                party::decline(eng.as_ref(), c.id)
+            }
+
+            fn party_leave(!full eng: &mut Engine, c: Client) -> StrResult<()> {
+                party::leave(eng.as_ref(), c.id)
+            }
+
+            fn party_set_leader(!full eng: &mut Engine,
+                                c: Client,
+                                other: Client) -> StrResult<()> {
+                party::set_leader(eng.as_ref(), c.id, other.id)
+            }
+
+            fn party_members(!full eng: &mut Engine, c: Client) -> Vec<Client> {
+                match eng.extra.parties.client_party(c.id) {
+                    Some(p) => p.members.iter().map(|&id| Client { id: id }).collect(),
+                    None => Vec::new(),
+                }
+            }
+
+            fn party_leader(!full eng: &mut Engine, c: Client) -> Option<Client> {
+                eng.extra.parties.client_party(c.id).map(|p| Client { id: p.leader })
+            }
+
+            fn party_instance(!full eng: &mut Engine,
+                              c: Client,
+                              key: &str) -> Option<StablePlane> {
+                party::instance(&eng.extra, c.id, key).map(|id| StablePlane { id: id })
+            }
+
+            fn set_party_instance(!full eng: &mut Engine,
+                                  c: Client,
+                                  key: &str,
+                                  p: StablePlane) -> StrResult<()> {
+                party::set_instance(eng.as_ref(), c.id, key, p.id)
+            }
+
+            fn mailbox(!full eng: &mut Engine, c: Client) -> Option<Inventory> {
+                eng.extra.mail.mailbox(c.id).map(|iid| Inventory { id: iid })
+            }
+
+            fn send_mail(!full eng: &mut Engine, c: Client, to: &str, body: &str) -> StrResult<()> {
+                let name = unwrap!(eng.world.get_client(c.id)).name().to_owned();
+                mail::send(eng.as_ref(), &name, to, body, None)
+            }
+
+            fn send_mail_items(!full eng: &mut Engine,
+                               c: Client,
+                               to: &str,
+                               body: &str,
+                               i: Inventory) -> StrResult<()> {
+                let name = unwrap!(eng.world.get_client(c.id)).name().to_owned();
+                mail::send(eng.as_ref(), &name, to, body, Some(i.id))
+            }
+
+            fn mail(!full eng: &mut Engine, c: Client) -> StrResult<Vec<String>> {
+                let name = unwrap!(eng.world.get_client(c.id)).name().to_owned();
+                mail::list(eng.as_ref(), &name)
+            }
+
+            fn delete_mail(!full eng: &mut Engine, c: Client, index: u32) -> StrResult<()> {
+                let name = unwrap!(eng.world.get_client(c.id)).name().to_owned();
+                let index = unwrap!(index.checked_sub(1), "no such letter");
+                mail::delete(eng.as_ref(), &name, index as usize)
+            }
+
             fn send_message_raw(!full eng: &mut Engine,
                                 c: Client,
                                 msg: String) -> StrResult<()> {
@@ -372,6 +695,51 @@ impl Userdata for Entity {
                 try!(e.set_attachment(EntityAttachment::Client(c.id)));
                 Ok(())
             }
+
+
+            fn visibility(!full eng: &mut Engine, e: Entity) -> Option<&'static str> {
+                eng.vision.entity_visibility(e.id).map(|v| match *v {
+                    Visibility::Public => "public",
+                    Visibility::Hidden => "hidden",
+                    Visibility::Clients(_) => "clients",
+                    Visibility::Area(_) => "area",
+                })
+            }
+
+            fn show(!full eng: &mut Engine, e: Entity) -> StrResult<()> {
+                logic::world::set_entity_visibility(eng.as_ref(), e.id, Visibility::Public)
+            }
+
+            fn hide(!full eng: &mut Engine, e: Entity) -> StrResult<()> {
+                logic::world::set_entity_visibility(eng.as_ref(), e.id, Visibility::Hidden)
+            }
+
+            fn show_to(!full eng: &mut Engine, e: Entity, c: Client) -> StrResult<()> {
+                let mut cids = match eng.vision.entity_visibility(e.id) {
+                    Some(&Visibility::Clients(ref cids)) => cids.clone(),
+                    _ => HashSet::new(),
+                };
+                cids.insert(c.id);
+                logic::world::set_entity_visibility(eng.as_ref(), e.id, Visibility::Clients(cids))
+            }
+
+            fn hide_from(!full eng: &mut Engine, e: Entity, c: Client) -> StrResult<()> {
+                let mut cids = match eng.vision.entity_visibility(e.id) {
+                    Some(&Visibility::Clients(ref cids)) => cids.clone(),
+                    _ => fail!("entity is not limited to a list of clients"),
+                };
+                cids.remove(&c.id);
+                logic::world::set_entity_visibility(eng.as_ref(), e.id, Visibility::Clients(cids))
+            }
+
+            fn show_in_area(!full eng: &mut Engine,
+                            e: Entity,
+                            min: V3,
+                            max: V3) -> StrResult<()> {
+                // The area is given in block coordinates, but visibility is checked per chunk.
+                let area = Region::new(min.reduce(), max.reduce()).div_round_signed(CHUNK_SIZE);
+                logic::world::set_entity_visibility(eng.as_ref(), e.id, Visibility::Area(area))
+            }
         }
     }
 }
@@ -388,7 +756,7 @@ impl_fromlua_copy!(Inventory);
 
 impl Userdata for Inventory {
     fn populate_table(lua: &mut LuaState) {
-        use world::InventoryAttachment;
+        use world::{InventoryAttachment, InventoryLimits};
 
         lua_table_fns2! {
             lua, -1,
@@ -405,7 +773,7 @@ impl Userdata for Inventory {
                 wf.destroy_inventory(i.id)
             }
 
-            fn count(!partial w: &world::World, i: Inventory, name: &str) -> StrResult<u8> {
+            fn count(!partial w: &world::World, i: Inventory, name: &str) -> StrResult<u16> {
                 let i = unwrap!(w.get_inventory(i.id));
                 i.count_by_name(name)
             }
@@ -413,11 +781,118 @@ impl Userdata for Inventory {
             fn update(!full wf: WorldFragment,
                       i: Inventory,
                       name: String,
-                      adjust: i16) -> StrResult<u8> {
+                      adjust: i32) -> StrResult<u16> {
                 let mut i = unwrap!(wf.get_inventory_mut(i.id));
                 i.update_by_name(&name, adjust)
             }
 
+            fn space_for(!partial w: &world::World, i: Inventory, name: &str) -> StrResult<u16> {
+                let i = unwrap!(w.get_inventory(i.id));
+                let item_id = unwrap!(w.data().item_data.find_id(name));
+                Ok(i.space_for(item_id))
+            }
+
+            fn check_space(!partial w: &world::World,
+                           i: Inventory,
+                           name: &str,
+                           count: u16) -> StrResult<()> {
+                let i = unwrap!(w.get_inventory(i.id));
+                let item_id = unwrap!(w.data().item_data.find_id(name));
+                i.check_space(item_id, count)
+            }
+
+            fn limits(!partial w: &world::World, i: Inventory) -> StrResult<(u16, u32, u32)> {
+                let i = unwrap!(w.get_inventory(i.id));
+                let limits = i.limits();
+                Ok((limits.slots, limits.weight, limits.volume))
+            }
+
+            fn set_limits(!full wf: WorldFragment,
+                          i: Inventory,
+                          slots: u16,
+                          weight: u32,
+                          volume: u32) -> StrResult<()> {
+                let mut i = unwrap!(wf.get_inventory_mut(i.id));
+                i.set_limits(InventoryLimits {
+                    slots: slots,
+                    weight: weight,
+                    volume: volume,
+                });
+                Ok(())
+            }
+
+            fn create_instance(!full wf: WorldFragment,
+                               i: Inventory,
+                               name: &str) -> StrResult<u32> {
+                if wf.extra().mail.mailbox_owner(i.id).is_some() {
+                    fail!("can't put unique items in a mailbox");
+                }
+                let mut i = unwrap!(wf.get_inventory_mut(i.id));
+                i.create_instance_by_name(name)
+            }
+
+            fn destroy_instance(!full wf: WorldFragment,
+                                i: Inventory,
+                                key: u32) -> StrResult<()> {
+                let mut i = unwrap!(wf.get_inventory_mut(i.id));
+                i.destroy_instance(key)
+            }
+
+            fn move_instance(!full wf: WorldFragment,
+                             i: Inventory,
+                             key: u32,
+                             to: Inventory) -> StrResult<u32> {
+                if wf.extra().mail.mailbox_owner(to.id).is_some() {
+                    fail!("can't put unique items in a mailbox");
+                }
+                let mut i = unwrap!(wf.get_inventory_mut(i.id));
+                i.move_instance(key, to.id)
+            }
+
+            fn find_instance(!partial w: &world::World,
+                             i: Inventory,
+                             name: &str) -> StrResult<Option<u32>> {
+                let i = unwrap!(w.get_inventory(i.id));
+                i.find_instance_by_name(name)
+            }
+
+            fn instance_item(!partial w: &world::World,
+                             i: Inventory,
+                             key: u32) -> StrResult<String> {
+                let i = unwrap!(w.get_inventory(i.id));
+                let inst = unwrap!(i.instance(key), "no such item instance");
+                Ok(w.data().item_data.name(inst.item_id).to_owned())
+            }
+
+            fn get_meta(!partial w: &world::World,
+                        i: Inventory,
+                        key: u32,
+                        name: &str) -> StrResult<Option<ExtraArg>> {
+                let i = unwrap!(w.get_inventory(i.id));
+                let inst = unwrap!(i.instance(key), "no such item instance");
+                Ok(inst.meta.get(name).map(|x| ExtraArg::new(x.clone())))
+            }
+
+            fn set_meta(!full wf: WorldFragment,
+                        i: Inventory,
+                        key: u32,
+                        name: String,
+                        value: TakeOptWrapper<msg::ExtraArg>) -> StrResult<()> {
+                let value = unwrap!(value.0);
+                let mut i = unwrap!(wf.get_inventory_mut(i.id));
+                try!(i.set_instance_meta(key, name, Some(value)));
+                Ok(())
+            }
+
+            fn clear_meta(!full wf: WorldFragment,
+                          i: Inventory,
+                          key: u32,
+                          name: String) -> StrResult<()> {
+                let mut i = unwrap!(wf.get_inventory_mut(i.id));
+                try!(i.set_instance_meta(key, name, None));
+                Ok(())
+            }
+
             fn attach_to_world(!full wf: WorldFragment,
                                i: Inventory) -> StrResult<()> {
                 let mut i = unwrap!(wf.get_inventory_mut(i.id));
@@ -480,6 +955,137 @@ impl Userdata for Plane {
                  .map(|p| p.name().to_owned())
             }
 
+            fn lighting(!partial w: &world::World, p: Plane) -> Option<&'static str> {
+                w.get_plane(p.id)
+                 .map(|p| p.props().lighting.name())
+            }
+
+            fn set_lighting(!full wf: WorldFragment,
+                            p: Plane,
+                            mode: &str) -> StrResult<()> {
+                let lighting = unwrap!(LightingMode::from_name(mode),
+                                       "unknown lighting mode");
+                update_props(&mut wf, p.id, |props| props.lighting = lighting)
+            }
+
+            fn ambient_color(!partial w: &world::World, p: Plane) -> Option<(u8, u8, u8)> {
+                w.get_plane(p.id)
+                 .map(|p| p.props().ambient_color)
+            }
+
+            fn set_ambient_color(!full wf: WorldFragment,
+                                 p: Plane,
+                                 r: u8,
+                                 g: u8,
+                                 b: u8) -> StrResult<()> {
+                update_props(&mut wf, p.id, |props| props.ambient_color = (r, g, b))
+            }
+
+            fn day_night(!partial w: &world::World, p: Plane) -> Option<bool> {
+                w.get_plane(p.id)
+                 .map(|p| p.props().day_night)
+            }
+
+            fn set_day_night(!full wf: WorldFragment,
+                             p: Plane,
+                             enabled: bool) -> StrResult<()> {
+                update_props(&mut wf, p.id, |props| props.day_night = enabled)
+            }
+
+            fn build_allowed(!partial w: &world::World, p: Plane) -> Option<bool> {
+                w.get_plane(p.id)
+                 .map(|p| p.props().build_allowed)
+            }
+
+            fn set_build_allowed(!full wf: WorldFragment,
+                                 p: Plane,
+                                 allowed: bool) -> StrResult<()> {
+                update_props(&mut wf, p.id, |props| props.build_allowed = allowed)
+            }
+
+            fn pvp(!partial w: &world::World, p: Plane) -> Option<bool> {
+                w.get_plane(p.id)
+                 .map(|p| p.props().pvp)
+            }
+
+            fn set_pvp(!full wf: WorldFragment,
+                       p: Plane,
+                       enabled: bool) -> StrResult<()> {
+                update_props(&mut wf, p.id, |props| props.pvp = enabled)
+            }
+
+            fn spawn_point(!partial w: &world::World, p: Plane) -> Option<V3> {
+                w.get_plane(p.id)
+                 .and_then(|p| p.props().spawn_point)
+            }
+
+            fn set_spawn_point(!full wf: WorldFragment,
+                               p: Plane,
+                               pos: V3) -> StrResult<()> {
+                update_props(&mut wf, p.id, |props| props.spawn_point = Some(pos))
+            }
+
+            fn clear_spawn_point(!full wf: WorldFragment, p: Plane) -> StrResult<()> {
+                update_props(&mut wf, p.id, |props| props.spawn_point = None)
+            }
+
+            fn generator(!partial w: &world::World, p: Plane) -> Option<&'static str> {
+                w.get_plane(p.id)
+                 .map(|p| p.props().generator.name())
+            }
+
+            fn set_generator(!full wf: WorldFragment,
+                             p: Plane,
+                             kind: &str) -> StrResult<()> {
+                let generator = unwrap!(GeneratorKind::from_name(kind),
+                                        "unknown terrain generator");
+                update_props(&mut wf, p.id, |props| props.generator = generator)
+            }
+
+            fn seed(!partial w: &world::World, p: Plane) -> Option<u32> {
+                w.get_plane(p.id)
+                 .map(|p| p.props().seed)
+            }
+
+            fn set_seed(!full wf: WorldFragment,
+                        p: Plane,
+                        seed: u32) -> StrResult<()> {
+                update_props(&mut wf, p.id, |props| props.seed = seed)
+            }
+
+            fn time_of_day(!full eng: &mut Engine, p: Plane) -> StrResult<u32> {
+                let cycle = try!(plane_cycle(eng, p.id));
+                Ok(cycle.tick(eng.now))
+            }
+
+            fn is_day(!full eng: &mut Engine, p: Plane) -> StrResult<bool> {
+                let cycle = try!(plane_cycle(eng, p.id));
+                Ok(cycle.is_day(eng.now))
+            }
+
+            fn next_dawn(!full eng: &mut Engine, p: Plane) -> StrResult<TimeU> {
+                let cycle = try!(plane_cycle(eng, p.id));
+                Ok(TimeU { t: cycle.next_tick(eng.now, clock::DAWN_TICK) })
+            }
+
+            fn next_dusk(!full eng: &mut Engine, p: Plane) -> StrResult<TimeU> {
+                let cycle = try!(plane_cycle(eng, p.id));
+                Ok(TimeU { t: cycle.next_tick(eng.now, clock::DUSK_TICK) })
+            }
+
+            fn day_length(!full eng: &mut Engine, p: Plane) -> StrResult<u32> {
+                let cycle = try!(plane_cycle(eng, p.id));
+                Ok(cycle.day_ms)
+            }
+
+            fn set_day_length(!full eng: &mut Engine, p: Plane, ms: u32) -> StrResult<()> {
+                clock::set_plane_day_length(eng.as_ref(), p.id, Some(ms))
+            }
+
+            fn clear_day_length(!full eng: &mut Engine, p: Plane) -> StrResult<()> {
+                clock::set_plane_day_length(eng.as_ref(), p.id, None)
+            }
+
             fn set_interior(!full wf: WorldFragment,
                             plane: Plane,
                             pos: V3,
@@ -512,8 +1118,145 @@ impl Userdata for Plane {
                 let block_id = tc.blocks()[idx];
                 Some(w.data().block_data.name(block_id).to_owned())
             }
+
+            fn set_block(!full wf: WorldFragment,
+                         plane: Plane,
+                         pos: V3,
+                         name: String) -> StrResult<bool> {
+                let block_id = unwrap!(wf.data().block_data.find_id(&name));
+                logic::terrain::set_block(&mut wf, plane.id, pos, block_id)
+            }
+
+            fn fill(!full wf: WorldFragment,
+                    plane: Plane,
+                    min: V3,
+                    max: V3,
+                    name: String) -> StrResult<u32> {
+                let block_id = unwrap!(wf.data().block_data.find_id(&name));
+                let count = try!(logic::terrain::fill(&mut wf, plane.id,
+                                                      Region::new(min, max), block_id));
+                Ok(count as u32)
+            }
+
+            fn replace(!full wf: WorldFragment,
+                       plane: Plane,
+                       min: V3,
+                       max: V3,
+                       from: String,
+                       to: String) -> StrResult<u32> {
+                let from_id = unwrap!(wf.data().block_data.find_id(&from));
+                let to_id = unwrap!(wf.data().block_data.find_id(&to));
+                let count = try!(logic::terrain::replace(&mut wf, plane.id,
+                                                         Region::new(min, max), from_id, to_id));
+                Ok(count as u32)
+            }
+
+            fn structures_in_region(!partial w: &world::World,
+                                    plane: Plane,
+                                    min: V3,
+                                    max: V3) -> Vec<Structure> {
+                logic::schematic::region_structures(w, plane.id, Region::new(min, max))
+                    .into_iter().map(|sid| Structure { id: sid }).collect()
+            }
+
+            fn save_schematic(!full eng: &mut Engine,
+                              plane: Plane,
+                              name: String,
+                              min: V3,
+                              max: V3,
+                              with_items: bool,
+                              inventory_names: TakeOptWrapper<msg::ExtraArg>) -> StrResult<()> {
+                // `inventory_names` maps inventory IDs to the names the scripts use for them.
+                let names = if with_items {
+                    Some(try!(inventory_name_map(unwrap!(inventory_names.0))))
+                } else {
+                    None
+                };
+                logic::schematic::save(eng.as_ref(), &name, plane.id,
+                                       Region::new(min, max), names.as_ref())
+            }
+
+            fn paste_schematic(!full eng: &mut Engine,
+                               plane: Plane,
+                               name: String,
+                               pos: V3,
+                               rotation: u8)
+                               -> StrResult<(Vec<Structure>,
+                                             Vec<Vec<String>>,
+                                             Vec<Vec<Inventory>>)> {
+                // Returns the new structures, along with the names and IDs of each structure's
+                // restored inventories.
+                let pasted = try!(logic::schematic::paste_saved(eng.as_ref(), &name, plane.id,
+                                                                pos, rotation));
+                let mut structures = Vec::with_capacity(pasted.len());
+                let mut names = Vec::with_capacity(pasted.len());
+                let mut inventories = Vec::with_capacity(pasted.len());
+                for (sid, invs) in pasted {
+                    structures.push(Structure { id: sid });
+                    names.push(invs.iter().map(|&(ref name, _)| name.clone()).collect());
+                    inventories.push(invs.iter().map(|&(_, iid)| Inventory { id: iid }).collect());
+                }
+                Ok((structures, names, inventories))
+            }
+
+            fn rollback(!full eng: &mut Engine,
+                        plane: Plane,
+                        actor: String,
+                        min: V3,
+                        max: V3,
+                        since: u32,
+                        until: u32) -> StrResult<u32> {
+                // `since` and `until` are given in seconds before the current time.
+                let now = eng.now;
+                let count = try!(logic::journal::rollback(eng.as_ref(), &actor, plane.id,
+                                                          Region::new(min, max),
+                                                          now - since as Time * 1000,
+                                                          now - until as Time * 1000));
+                Ok(count as u32)
+            }
+
+            fn change_history(!full eng: &mut Engine,
+                              plane: Plane,
+                              pos: V3) -> StrResult<Vec<String>> {
+                logic::journal::history(eng.as_ref(), plane.id, pos)
+            }
+        }
+    }
+}
+
+
+/// Get the day/night cycle that applies to plane `pid`.
+fn plane_cycle(eng: &Engine, pid: PlaneId) -> StrResult<clock::Cycle> {
+    let stable_pid = unwrap!(eng.world.get_plane(pid)).get_stable_id();
+    Ok(*eng.extra.clock.cycle(stable_pid))
+}
+
+/// Convert a map from inventory IDs to names, as passed in from Lua.
+fn inventory_name_map(arg: msg::ExtraArg) -> StrResult<HashMap<InventoryId, String>> {
+    let map = match arg {
+        msg::ExtraArg::Map(m) => m,
+        _ => fail!("expected a map of inventory names"),
+    };
+    let mut names = HashMap::with_capacity(map.len());
+    for (k, v) in map {
+        match (k, v) {
+            (msg::SimpleArg::Int(id), msg::ExtraArg::Str(name)) => {
+                names.insert(InventoryId(id as u32), name);
+            },
+            _ => fail!("expected inventory IDs mapped to names"),
         }
     }
+    Ok(names)
+}
+
+/// Apply `f` to the properties of plane `pid`.
+fn update_props<F>(wf: &mut WorldFragment, pid: PlaneId, f: F) -> StrResult<()>
+        where F: FnOnce(&mut PlaneProps) {
+    let mut p = unwrap!(wf.get_plane_mut(pid));
+    let mut props = *p.props();
+    f(&mut props);
+    p.set_props(props);
+    Ok(())
 }
 
 
@@ -572,6 +1315,12 @@ impl Userdata for Structure {
                  .map(|t| t.name.clone())
             }
 
+            fn child_inventories(!partial w: &world::World, s: Structure) -> Vec<Inventory> {
+                w.get_structure(s.id)
+                 .map(|s| s.child_inventories().map(|i| Inventory { id: i.id() }).collect())
+                 .unwrap_or_else(Vec::new)
+            }
+
             fn layer(!partial w: &world::World, s: Structure) -> Option<u8> {
                 w.get_structure(s.id)
                  .map(|s| s.template_id())
@@ -66,11 +66,22 @@ pub trait Fragment<'d> {
     fn generate(&mut self,
                 pid: PlaneId,
                 cpos: V2) -> StrResult<TerrainChunkId> {
-        let stable_pid = self.with_world(|wf| wf.plane_mut(pid).stable_id());
-        self.terrain_gen_mut().send.send(worker::Command::Generate(stable_pid, cpos)).unwrap();
+        let (stable_pid, kind, seed) = self.with_world(|wf| {
+            let mut p = wf.plane_mut(pid);
+            let props = *p.props();
+            (p.stable_id(), props.generator, props.seed)
+        });
+        let cmd = worker::Command::Generate(stable_pid, cpos, kind, seed);
+        self.terrain_gen_mut().send.send(cmd).unwrap();
         self.with_world(move |wf| { wf.create_terrain_chunk(pid, cpos).map(|tc| tc.id()) })
     }
 
+    /// Discard the generator's summaries for a plane that is being destroyed.
+    fn forget_plane(&mut self, stable_pid: Stable<PlaneId>) {
+        let cmd = worker::Command::ForgetPlane(stable_pid);
+        self.terrain_gen_mut().send.send(cmd).unwrap();
+    }
+
     fn process(&mut self, evt: TerrainGenEvent) {
         let (stable_pid, cpos, gc) = evt;
         self.with_world(move |wf| {
@@ -17,6 +17,7 @@ use std::collections::{HashMap, HashSet, VecMap};
 use std::mem;
 
 use libphysics::{CHUNK_SIZE, TILE_SIZE};
+use libphysics::v3::RegionPoints;
 
 use types::*;
 use util::{multimap_insert, multimap_remove};
@@ -25,14 +26,134 @@ use util::OptionIterExt;
 use util::SmallSet;
 
 
-pub const VIEW_SIZE: V2 = V2 { x: 5, y: 6 };
-pub const VIEW_ANCHOR: V2 = V2 { x: 2, y: 2 };
+/// View size for clients that don't report their screen size.
+pub const DEFAULT_VIEW_SIZE: V2 = V2 { x: 5, y: 6 };
 
-pub fn vision_region(pos: V3) -> Region<V2> {
-    let center = pos.reduce().div_floor(scalar(CHUNK_SIZE * TILE_SIZE));
+const CHUNK_PX: i32 = CHUNK_SIZE * TILE_SIZE;
 
-    let base = center - VIEW_ANCHOR;
-    Region::new(base, base + VIEW_SIZE)
+/// The size of a client's view, in chunks.  Each client can have a different shape, depending on
+/// the size of its screen.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ViewShape {
+    /// Size of the area in which terrain and structures are visible.
+    pub size: V2,
+    /// Position of the pawn's chunk within that area.
+    pub anchor: V2,
+    /// Entities are also visible within this many chunks of the pawn's chunk, so that fast-moving
+    /// entities appear before they reach the edge of the loaded terrain.
+    pub entity_radius: i32,
+}
+
+impl ViewShape {
+    pub fn new(size: V2, entity_radius: i32) -> ViewShape {
+        ViewShape {
+            size: size,
+            // Leave more room below the pawn than above, since structures extend upward.
+            anchor: V2::new(size.x / 2, (size.y - 1) / 2),
+            entity_radius: entity_radius,
+        }
+    }
+
+    /// Get the view size needed to fill a screen `px` pixels in size.  This includes a margin of
+    /// one chunk on each side, plus an extra row below for tall structures, so a 1024x1024 screen
+    /// gets the `DEFAULT_VIEW_SIZE`.
+    pub fn size_for_screen(px: V2) -> V2 {
+        let chunks = (px + scalar(CHUNK_PX - 1)).div_floor(scalar(CHUNK_PX));
+        chunks + V2::new(3, 4)
+    }
+
+    pub fn view(&self, pos: V3) -> View {
+        let center = pos.reduce().div_floor(scalar(CHUNK_PX));
+        let base = center - self.anchor;
+        View {
+            terrain: Region::new(base, base + self.size),
+            center: center,
+            entity_radius: self.entity_radius,
+        }
+    }
+}
+
+/// The set of chunks visible to a client.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct View {
+    /// Chunks whose terrain, structures, and entities are visible.
+    pub terrain: Region<V2>,
+    /// Additional chunks whose entities are visible: all those within `entity_radius` of `center`.
+    /// A negative radius means no additional chunks.
+    pub center: V2,
+    pub entity_radius: i32,
+}
+
+impl View {
+    pub fn empty() -> View {
+        View {
+            terrain: Region::empty(),
+            center: scalar(0),
+            entity_radius: -1,
+        }
+    }
+
+    pub fn contains_entity_chunk(&self, p: V2) -> bool {
+        if self.terrain.contains(p) {
+            return true;
+        }
+        if self.entity_radius < 0 {
+            return false;
+        }
+        let d = p - self.center;
+        // Compare against `(r + 1/2)^2`, which gives rounder circles for small radii.
+        let r = self.entity_radius;
+        d.x * d.x + d.y * d.y <= r * r + r
+    }
+
+    /// Iterate over the chunks whose entities are visible.
+    pub fn entity_points(&self) -> EntityPoints {
+        let bounds =
+            if self.entity_radius < 0 {
+                self.terrain
+            } else {
+                let r = self.entity_radius;
+                let circle = Region::new(self.center - scalar(r), self.center + scalar(r + 1));
+                if self.terrain.is_empty() { circle } else { self.terrain.join(circle) }
+            };
+        EntityPoints {
+            iter: bounds.points(),
+            view: *self,
+        }
+    }
+}
+
+pub struct EntityPoints {
+    iter: RegionPoints<V2>,
+    view: View,
+}
+
+impl Iterator for EntityPoints {
+    type Item = V2;
+
+    fn next(&mut self) -> Option<V2> {
+        while let Some(p) = self.iter.next() {
+            if self.view.contains_entity_chunk(p) {
+                return Some(p);
+            }
+        }
+        None
+    }
+}
+
+
+/// Which clients can see an entity, among those whose view includes the entity's chunks.  An
+/// entity that is a client's pawn is always visible to that client.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Visibility {
+    /// Visible to every client.
+    Public,
+    /// Visible to no client, except its owner.
+    Hidden,
+    /// Visible only to the listed clients.
+    Clients(HashSet<ClientId>),
+    /// Visible only to clients whose pawn is inside the region, in chunk coordinates.
+    Area(Region<V2>),
 }
 
 
@@ -41,11 +162,17 @@ type ViewerId = ClientId;
 pub struct Vision {
     viewers: VecMap<Viewer>,
     viewers_by_pos: HashMap<(PlaneId, V2), HashSet<ViewerId>>,
+    // Viewers can see entities in more chunks than terrain and structures, so they are indexed
+    // separately.
+    entity_viewers_by_pos: HashMap<(PlaneId, V2), HashSet<ViewerId>>,
 
     entities: VecMap<Entity>,
     terrain_chunks: VecMap<TerrainChunk>,
     structures: VecMap<Structure>,
 
+    /// Entities with a visibility other than `Public`.
+    filtered_entities: HashSet<EntityId>,
+
     // NB: PLANE_LIMBO gets special treatment so that it always appears empty, no matter what is
     // actually present.  This is done by skipping insertions into x_by_pos when the PlaneId is
     // PLANE_LIMBO.  Thus, none of the x_by_pos maps should ever have PLANE_LIMBO in theer keys.
@@ -59,7 +186,7 @@ pub struct Vision {
 
 struct Viewer {
     plane: PlaneId,
-    view: Region<V2>,
+    view: View,
 
     visible_entities: RefcountedMap<EntityId, ()>,
     visible_terrain_chunks: RefcountedMap<TerrainChunkId, ()>,
@@ -70,7 +197,11 @@ struct Viewer {
 struct Entity {
     plane: PlaneId,
     area: SmallSet<V2>,
+    /// Viewers that have been notified of this entity.  This includes only viewers whose view
+    /// includes the entity and that are permitted to see it by its `visibility`.
     viewers: HashSet<ViewerId>,
+    visibility: Visibility,
+    owner: Option<ClientId>,
 }
 
 struct TerrainChunk {
@@ -97,6 +228,7 @@ pub trait Hooks {
                        cid: ClientId,
                        old_pid: PlaneId,
                        new_pid: PlaneId) {}
+    fn on_plane_props_change(&mut self, cid: ClientId, pid: PlaneId) {}
 
     fn on_terrain_chunk_appear(&mut self,
                                cid: ClientId,
@@ -114,6 +246,7 @@ pub trait Hooks {
     fn on_structure_appear(&mut self, cid: ClientId, sid: StructureId) {}
     fn on_structure_disappear(&mut self, cid: ClientId, sid: StructureId) {}
     fn on_structure_template_change(&mut self, cid: ClientId, sid: StructureId) {}
+    fn on_structure_crafting_change(&mut self, cid: ClientId, sid: StructureId) {}
 
     fn on_inventory_appear(&mut self, cid: ClientId, iid: InventoryId) {}
     fn on_inventory_disappear(&mut self, cid: ClientId, iid: InventoryId) {}
@@ -121,8 +254,9 @@ pub trait Hooks {
                            cid: ClientId,
                            iid: InventoryId,
                            item_id: ItemId,
-                           old_count: u8,
-                           new_count: u8) {}
+                           old_count: u16,
+                           new_count: u16) {}
+    fn on_inventory_limits_change(&mut self, cid: ClientId, iid: InventoryId) {}
 }
 
 pub struct NoHooks;
@@ -133,11 +267,14 @@ impl Vision {
         Vision {
             viewers: VecMap::new(),
             viewers_by_pos: HashMap::new(),
+            entity_viewers_by_pos: HashMap::new(),
 
             entities: VecMap::new(),
             terrain_chunks: VecMap::new(),
             structures: VecMap::new(),
 
+            filtered_entities: HashSet::new(),
+
             entities_by_pos: HashMap::new(),
             terrain_chunks_by_pos: HashMap::new(),
             structures_by_pos: HashMap::new(),
@@ -151,7 +288,7 @@ impl Vision {
     pub fn add_client<H>(&mut self,
                          cid: ClientId,
                          plane: PlaneId,
-                         view: Region<V2>,
+                         view: View,
                          h: &mut H)
             where H: Hooks {
         trace!("{:?} created", cid);
@@ -164,19 +301,31 @@ impl Vision {
                             h: &mut H)
             where H: Hooks {
         trace!("{:?} destroyed", cid);
-        self.set_client_view(cid, PLANE_LIMBO, Region::empty(), h);
+        self.set_client_view(cid, PLANE_LIMBO, View::empty(), h);
         self.viewers.remove(&(cid.unwrap() as usize));
+
+        // Client IDs are reused, so make sure a future client with the same ID doesn't inherit
+        // this one's permissions.
+        for &eid in self.filtered_entities.iter() {
+            let entity = &mut self.entities[eid.unwrap() as usize];
+            if entity.owner == Some(cid) {
+                entity.owner = None;
+            }
+            if let Visibility::Clients(ref mut cids) = entity.visibility {
+                cids.remove(&cid);
+            }
+        }
     }
 
     // This code is carefully arranged to produce events in the proper order.  Specifically, when a
     // single update produces both "gone" and "appear" events, all "gone" events should appear
     // before all "appear" events.  This avoids giving an inconsistent view, in which (for example)
-    // two structures more than `VIEW_SIZE` distance apart are visible at the same time.
+    // two structures farther apart than the view size are visible at the same time.
 
     pub fn set_client_view<H>(&mut self,
                               cid: ClientId,
                               new_plane: PlaneId,
-                              new_view: Region<V2>,
+                              new_view: View,
                               h: &mut H)
             where H: Hooks {
         let raw_cid = cid.unwrap() as usize;
@@ -189,17 +338,28 @@ impl Vision {
         let terrain_chunks = &mut self.terrain_chunks;
         let structures = &mut self.structures;
 
-        for p in old_view.points().filter(|&p| !new_view.contains(p) || plane_change) {
+        for p in old_view.entity_points()
+                         .filter(|&p| !new_view.contains_entity_chunk(p) || plane_change) {
             let pos = (old_plane, p);
 
             for &eid in self.entities_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
                 viewer.visible_entities.release(eid, |()| {
-                    trace!("{:?} moved: --{:?}", cid, eid);
-                    h.on_entity_disappear(cid, eid);
-                    entities[eid.unwrap() as usize].viewers.remove(&cid);
+                    let entity = &mut entities[eid.unwrap() as usize];
+                    if entity.viewers.contains(&cid) {
+                        trace!("{:?} moved: --{:?}", cid, eid);
+                        h.on_entity_disappear(cid, eid);
+                        entity.viewers.remove(&cid);
+                    }
                 });
             }
 
+            multimap_remove(&mut self.entity_viewers_by_pos, pos, cid);
+        }
+
+        let (old_terrain, new_terrain) = (old_view.terrain, new_view.terrain);
+        for p in old_terrain.points().filter(|&p| !new_terrain.contains(p) || plane_change) {
+            let pos = (old_plane, p);
+
             for &tcid in self.terrain_chunks_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
                 viewer.visible_terrain_chunks.release(tcid, |()| {
                     trace!("{:?} moved: --{:?}", cid, tcid);
@@ -224,17 +384,9 @@ impl Vision {
             h.on_plane_change(cid, old_plane, new_plane);
         }
 
-        for p in new_view.points().filter(|&p| !old_view.contains(p) || plane_change) {
+        for p in new_terrain.points().filter(|&p| !old_terrain.contains(p) || plane_change) {
             let pos = (new_plane, p);
 
-            for &eid in self.entities_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
-                viewer.visible_entities.retain(eid, || {
-                    trace!("{:?} moved: ++{:?}", cid, eid);
-                    h.on_entity_appear(cid, eid);
-                    entities[eid.unwrap() as usize].viewers.insert(cid);
-                });
-            }
-
             for &tcid in self.terrain_chunks_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
                 viewer.visible_terrain_chunks.retain(tcid, || {
                     trace!("{:?} moved: ++{:?}", cid, tcid);
@@ -256,6 +408,48 @@ impl Vision {
                 multimap_insert(&mut self.viewers_by_pos, pos, cid);
             }
         }
+
+        for p in new_view.entity_points()
+                         .filter(|&p| !old_view.contains_entity_chunk(p) || plane_change) {
+            let pos = (new_plane, p);
+
+            for &eid in self.entities_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
+                viewer.visible_entities.retain(eid, || {
+                    let entity = &mut entities[eid.unwrap() as usize];
+                    if entity.can_see(cid, new_view.center) {
+                        trace!("{:?} moved: ++{:?}", cid, eid);
+                        h.on_entity_appear(cid, eid);
+                        entity.viewers.insert(cid);
+                    }
+                });
+            }
+
+            if new_plane != PLANE_LIMBO {
+                multimap_insert(&mut self.entity_viewers_by_pos, pos, cid);
+            }
+        }
+
+        // Entities limited to an area may need to appear or disappear even though they are still
+        // within the client's view.
+        if new_view.center != old_view.center && !plane_change {
+            for &eid in self.filtered_entities.iter() {
+                if viewer.visible_entities.get(&eid).is_none() {
+                    continue;
+                }
+                let entity = &mut entities[eid.unwrap() as usize];
+                let old = entity.viewers.contains(&cid);
+                let new = entity.can_see(cid, new_view.center);
+                if old && !new {
+                    trace!("{:?} filtered: --{:?}", cid, eid);
+                    h.on_entity_disappear(cid, eid);
+                    entity.viewers.remove(&cid);
+                } else if new && !old {
+                    trace!("{:?} filtered: ++{:?}", cid, eid);
+                    h.on_entity_appear(cid, eid);
+                    entity.viewers.insert(cid);
+                }
+            }
+        }
     }
 
     pub fn client_view_plane(&self, cid: ClientId) -> Option<PlaneId> {
@@ -263,6 +457,10 @@ impl Vision {
     }
 
     pub fn client_view_area(&self, cid: ClientId) -> Option<Region<V2>> {
+        self.viewers.get(&(cid.unwrap() as usize)).map(|c| c.view.terrain)
+    }
+
+    pub fn client_view(&self, cid: ClientId) -> Option<View> {
         self.viewers.get(&(cid.unwrap() as usize)).map(|c| c.view)
     }
 
@@ -285,6 +483,7 @@ impl Vision {
         trace!("{:?} destroyed", eid);
         self.set_entity_area(eid, PLANE_LIMBO, SmallSet::new(), h);
         self.entities.remove(&(eid.unwrap() as usize));
+        self.filtered_entities.remove(&eid);
     }
 
     pub fn set_entity_area<H>(&mut self,
@@ -313,11 +512,15 @@ impl Vision {
         //    refoucnt is positive the whole way through.
         for &p in new_area.iter().filter(|&p| !old_area.contains(p) || plane_change) {
             let pos = (new_plane, p);
-            for &cid in self.viewers_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
-                self.viewers[cid.unwrap() as usize].visible_entities.retain(eid, || {
-                    trace!("{:?} moved: ++{:?}", eid, cid);
-                    h.on_entity_appear(cid, eid);
-                    entity.viewers.insert(cid);
+            for &cid in self.entity_viewers_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
+                let viewer = &mut self.viewers[cid.unwrap() as usize];
+                let center = viewer.view.center;
+                viewer.visible_entities.retain(eid, || {
+                    if entity.can_see(cid, center) {
+                        trace!("{:?} moved: ++{:?}", eid, cid);
+                        h.on_entity_appear(cid, eid);
+                        entity.viewers.insert(cid);
+                    }
                 });
             }
             if new_plane != PLANE_LIMBO {
@@ -327,11 +530,13 @@ impl Vision {
 
         for &p in old_area.iter().filter(|&p| !new_area.contains(p) || plane_change) {
             let pos = (old_plane, p);
-            for &cid in self.viewers_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
+            for &cid in self.entity_viewers_by_pos.get(&pos).map(|x| x.iter()).unwrap_iter() {
                 self.viewers[cid.unwrap() as usize].visible_entities.release(eid, |()| {
-                    trace!("{:?} moved: --{:?}", eid, cid);
-                    h.on_entity_disappear(cid, eid);
-                    entity.viewers.remove(&cid);
+                    if entity.viewers.contains(&cid) {
+                        trace!("{:?} moved: --{:?}", eid, cid);
+                        h.on_entity_disappear(cid, eid);
+                        entity.viewers.remove(&cid);
+                    }
                 });
             }
             multimap_remove(&mut self.entities_by_pos, pos, eid);
@@ -357,6 +562,47 @@ impl Vision {
         }
     }
 
+    pub fn set_entity_visibility<H>(&mut self,
+                                    eid: EntityId,
+                                    visibility: Visibility,
+                                    owner: Option<ClientId>,
+                                    h: &mut H)
+            where H: Hooks {
+        let entity = unwrap_or!(self.entities.get_mut(&(eid.unwrap() as usize)));
+        if visibility == Visibility::Public {
+            self.filtered_entities.remove(&eid);
+        } else {
+            self.filtered_entities.insert(eid);
+        }
+        entity.visibility = visibility;
+        entity.owner = owner;
+
+        // Send all "gone" events before any "appear" events.
+        for (raw_cid, viewer) in self.viewers.iter() {
+            let cid = ClientId(raw_cid as u16);
+            if entity.viewers.contains(&cid) && !entity.can_see(cid, viewer.view.center) {
+                trace!("{:?} filtered: --{:?}", eid, cid);
+                h.on_entity_disappear(cid, eid);
+                entity.viewers.remove(&cid);
+            }
+        }
+
+        for (raw_cid, viewer) in self.viewers.iter() {
+            let cid = ClientId(raw_cid as u16);
+            if viewer.visible_entities.get(&eid).is_some() &&
+               !entity.viewers.contains(&cid) &&
+               entity.can_see(cid, viewer.view.center) {
+                trace!("{:?} filtered: ++{:?}", eid, cid);
+                h.on_entity_appear(cid, eid);
+                entity.viewers.insert(cid);
+            }
+        }
+    }
+
+    pub fn entity_visibility(&self, eid: EntityId) -> Option<&Visibility> {
+        self.entities.get(&(eid.unwrap() as usize)).map(|e| &e.visibility)
+    }
+
 
     pub fn add_terrain_chunk<H>(&mut self,
                                 tcid: TerrainChunkId,
@@ -493,6 +739,16 @@ impl Vision {
         }
     }
 
+    pub fn update_structure_crafting<H>(&mut self,
+                                        sid: StructureId,
+                                        h: &mut H)
+            where H: Hooks {
+        let structure = unwrap_or!(self.structures.get(&(sid.unwrap() as usize)));
+        for &cid in structure.viewers.iter() {
+            h.on_structure_crafting_change(cid, sid);
+        }
+    }
+
 
     pub fn subscribe_inventory<H>(&mut self,
                                   cid: ClientId,
@@ -525,8 +781,8 @@ impl Vision {
     pub fn update_inventory<H>(&mut self,
                                iid: InventoryId,
                                item_id: ItemId,
-                               old_count: u8,
-                               new_count: u8,
+                               old_count: u16,
+                               new_count: u16,
                                h: &mut H)
             where H: Hooks {
         let cids = unwrap_or!(self.inventory_viewers.get(&iid));
@@ -534,13 +790,34 @@ impl Vision {
             h.on_inventory_update(cid, iid, item_id, old_count, new_count);
         }
     }
+
+    pub fn update_inventory_limits<H>(&mut self,
+                                      iid: InventoryId,
+                                      h: &mut H)
+            where H: Hooks {
+        let cids = unwrap_or!(self.inventory_viewers.get(&iid));
+        for &cid in cids.iter() {
+            h.on_inventory_limits_change(cid, iid);
+        }
+    }
+
+    pub fn update_plane<H>(&mut self,
+                           pid: PlaneId,
+                           h: &mut H)
+            where H: Hooks {
+        for (raw_cid, viewer) in self.viewers.iter() {
+            if viewer.plane == pid {
+                h.on_plane_props_change(ClientId(raw_cid as u16), pid);
+            }
+        }
+    }
 }
 
 impl Viewer {
     fn new() -> Viewer {
         Viewer {
             plane: PLANE_LIMBO,
-            view: Region::empty(),
+            view: View::empty(),
             visible_entities: RefcountedMap::new(),
             visible_terrain_chunks: RefcountedMap::new(),
             visible_structures: RefcountedMap::new(),
@@ -555,6 +832,22 @@ impl Entity {
             plane: PLANE_LIMBO,
             area: SmallSet::new(),
             viewers: HashSet::new(),
+            visibility: Visibility::Public,
+            owner: None,
+        }
+    }
+
+    /// Check whether the entity's visibility allows a client to see it, assuming the client's view
+    /// includes the entity.  `center` is the chunk containing the client's pawn.
+    fn can_see(&self, cid: ClientId, center: V2) -> bool {
+        if self.owner == Some(cid) {
+            return true;
+        }
+        match self.visibility {
+            Visibility::Public => true,
+            Visibility::Hidden => false,
+            Visibility::Clients(ref cids) => cids.contains(&cid),
+            Visibility::Area(region) => region.contains(center),
         }
     }
 }
@@ -599,14 +892,15 @@ macro_rules! gen_Fragment {
 }
 
 gen_Fragment! {
-    fn add_client(cid: ClientId, plane: PlaneId, view: Region<V2>);
+    fn add_client(cid: ClientId, plane: PlaneId, view: View);
     fn remove_client(cid: ClientId);
-    fn set_client_view(cid: ClientId, plane: PlaneId, view: Region<V2>);
+    fn set_client_view(cid: ClientId, plane: PlaneId, view: View);
 
     fn add_entity(eid: EntityId, plane: PlaneId, area: SmallSet<V2>);
     fn remove_entity(eid: EntityId);
     fn set_entity_area(eid: EntityId, plane: PlaneId, area: SmallSet<V2>);
     fn update_entity_appearance(eid: EntityId);
+    fn set_entity_visibility(eid: EntityId, visibility: Visibility, owner: Option<ClientId>);
 
     fn add_terrain_chunk(tcid: TerrainChunkId, plane: PlaneId, cpos: V2);
     fn remove_terrain_chunk(tcid: TerrainChunkId);
@@ -616,12 +910,16 @@ gen_Fragment! {
     fn remove_structure(sid: StructureId);
     fn set_structure_area(sid: StructureId, new_plane: PlaneId, new_area: SmallSet<V2>);
     fn change_structure_template(sid: StructureId);
+    fn update_structure_crafting(sid: StructureId);
 
     fn subscribe_inventory(cid: ClientId, iid: InventoryId);
     fn unsubscribe_inventory(cid: ClientId, iid: InventoryId);
     fn update_inventory(iid: InventoryId,
                         item_id: ItemId,
-                        old_count: u8,
-                        new_count: u8);
+                        old_count: u16,
+                        new_count: u16);
+    fn update_inventory_limits(iid: InventoryId);
+
+    fn update_plane(pid: PlaneId);
 }
 
@@ -80,6 +80,10 @@ pub trait Fragment<'d>: Sized {
         self.with_hooks(|h| h.on_structure_create(sid));
         Ok(ObjectRefMut::new(self, sid))
     }
+
+    fn unload_client(&mut self, cid: ClientId) -> OpResult<()> {
+        ops::client::unload(self, cid)
+    }
 }
 
     }
@@ -19,23 +19,41 @@ pub trait Hooks {
     fn on_entity_plane_change(&mut self, eid: EntityId) {}
 
     fn on_inventory_create(&mut self, iid: InventoryId) {}
-    fn on_inventory_destroy(&mut self, iid: InventoryId) {}
+    fn on_inventory_destroy(&mut self,
+                            iid: InventoryId,
+                            stable_iid: Option<Stable<InventoryId>>) {}
     fn on_inventory_update(&mut self,
                            iid: InventoryId,
                            item_id: ItemId,
-                           old_count: u8,
-                           new_count: u8) {}
+                           old_count: u16,
+                           new_count: u16) {}
+    fn on_inventory_limits_change(&mut self, iid: InventoryId) {}
 
     fn on_plane_create(&mut self, pid: PlaneId) {}
     fn on_plane_destroy(&mut self, pid: PlaneId) {}
+    fn on_plane_props_change(&mut self, pid: PlaneId) {}
 
     fn on_terrain_chunk_create(&mut self, tcid: TerrainChunkId) {}
     fn on_terrain_chunk_destroy(&mut self, tcid: TerrainChunkId, plane_id: PlaneId, cpos: V2) {}
     fn on_terrain_chunk_update(&mut self, tcid: TerrainChunkId) {}
+    fn on_block_change(&mut self,
+                       plane_id: PlaneId,
+                       pos: V3,
+                       old_block: BlockId,
+                       new_block: BlockId) {}
 
     fn on_structure_create(&mut self, sid: StructureId) {}
-    fn on_structure_destroy(&mut self, sid: StructureId, plane_id: PlaneId, old_bounds: Region) {}
-    fn on_structure_replace(&mut self, sid: StructureId, plane_id: PlaneId, old_bounds: Region) {}
+    fn on_structure_destroy(&mut self,
+                            sid: StructureId,
+                            plane_id: PlaneId,
+                            old_template: TemplateId,
+                            old_bounds: Region) {}
+    fn on_structure_replace(&mut self,
+                            sid: StructureId,
+                            plane_id: PlaneId,
+                            old_template: TemplateId,
+                            old_bounds: Region) {}
+    fn on_structure_crafting_change(&mut self, sid: StructureId) {}
 
     fn check_structure_placement(&self,
                                  template: &StructureTemplate,
@@ -13,6 +13,11 @@ pub use self::types::{
     EntityAttachment,
     StructureAttachment,
     InventoryAttachment,
+    InventoryLimits,
+    ItemInstance,
+    LightingMode,
+    PlaneProps,
+    CraftingJob,
     Motion,
 };
 pub use self::world::{EntitiesById, StructuresById, InventoriesById};
@@ -103,7 +108,11 @@ pub struct Entity {
 impl_IntrusiveStableId!(Entity, stable_id);
 
 pub struct Inventory {
-    contents: HashMap<ItemId, u8>,
+    contents: HashMap<ItemId, u16>,
+    /// Unique items with metadata, indexed by a key that is unique within this inventory.
+    instances: HashMap<u32, ItemInstance>,
+    next_instance: u32,
+    limits: InventoryLimits,
 
     stable_id: StableId,
     attachment: InventoryAttachment,
@@ -112,6 +121,7 @@ impl_IntrusiveStableId!(Inventory, stable_id);
 
 pub struct Plane {
     name: String,
+    props: PlaneProps,
 
     /// *Invariant*: If the same `cpos` is in both maps, then `saved_chunks[cpos]` is the stable ID
     /// of the chunk with ID `loaded_chunks[cpos]`.
@@ -144,6 +154,8 @@ pub struct Structure {
     flags: StructureFlags,
     attachment: StructureAttachment,
     child_inventories: HashSet<InventoryId>,
+    /// Timed crafting jobs, in the order they were queued.
+    crafting: Vec<CraftingJob>,
 }
 impl_IntrusiveStableId!(Structure, stable_id);
 
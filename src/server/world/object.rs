@@ -3,15 +3,16 @@ use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 
 use libphysics::CHUNK_SIZE;
+use msg::ExtraArg;
 use types::*;
 
 use data::StructureTemplate;
 use world::World;
 use world::{Client, Entity, Inventory, Plane, TerrainChunk, Structure};
 use world::{EntitiesById, StructuresById, InventoriesById};
-use world::{EntityAttachment, StructureAttachment, InventoryAttachment};
+use world::{EntityAttachment, StructureAttachment, InventoryAttachment, InventoryLimits};
 use world::{TerrainChunkFlags, StructureFlags};
-use world::Motion;
+use world::{Motion, ItemInstance, CraftingJob, PlaneProps};
 use world::fragment::Fragment;
 use world::hooks::Hooks;
 use world::ops::{self, OpResult};
@@ -322,10 +323,23 @@ impl<'a, 'd, F: Fragment<'d>> EntityRefMut<'d, F> for ObjectRefMut<'a, 'd, Entit
 
 
 pub trait InventoryRef<'d>: ObjectRefBase<'d, Inventory> {
-    fn count_by_name(&self, name: &str) -> OpResult<u8> {
+    fn count_by_name(&self, name: &str) -> OpResult<u16> {
         let item_id = unwrap!(self.world().data().item_data.find_id(name));
         Ok(self.obj().count(item_id))
     }
+
+    fn space_for(&self, item_id: ItemId) -> u16 {
+        self.obj().space_for(&self.world().data().item_data, item_id)
+    }
+
+    fn check_space(&self, item_id: ItemId, count: u16) -> OpResult<()> {
+        self.obj().check_space(&self.world().data().item_data, item_id, count)
+    }
+
+    fn find_instance_by_name(&self, name: &str) -> OpResult<Option<u32>> {
+        let item_id = unwrap!(self.world().data().item_data.find_id(name));
+        Ok(self.obj().find_instance(item_id))
+    }
 }
 impl<'a, 'd> InventoryRef<'d> for ObjectRef<'a, 'd, Inventory> { }
 impl<'a, 'd, F: Fragment<'d>> InventoryRef<'d> for ObjectRefMut<'a, 'd, Inventory, F> { }
@@ -336,15 +350,56 @@ pub trait InventoryRefMut<'d, F: Fragment<'d>>: ObjectRefMutBase<'d, Inventory,
         self.world_mut().inventories.pin(iid)
     }
 
-    fn update(&mut self, item_id: ItemId, adjust: i16) -> u8 {
+    fn update(&mut self, item_id: ItemId, adjust: i32) -> OpResult<u16> {
         let iid = self.id();
-        // OK: self.id() is always a valid InventoryId
-        ops::inventory::update(self.fragment_mut(), iid, item_id, adjust).unwrap()
+        ops::inventory::update(self.fragment_mut(), iid, item_id, adjust)
+    }
+
+    fn update_by_name(&mut self, name: &str, adjust: i32) -> OpResult<u16> {
+        let item_id = unwrap!(self.world().data().item_data.find_id(name));
+        self.update(item_id, adjust)
     }
 
-    fn update_by_name(&mut self, name: &str, adjust: i16) -> OpResult<u8> {
+    fn create_instance(&mut self, item_id: ItemId) -> OpResult<u32> {
+        let iid = self.id();
+        ops::inventory::insert_instance(self.fragment_mut(), iid, ItemInstance::new(item_id))
+    }
+
+    fn create_instance_by_name(&mut self, name: &str) -> OpResult<u32> {
         let item_id = unwrap!(self.world().data().item_data.find_id(name));
-        Ok(self.update(item_id, adjust))
+        self.create_instance(item_id)
+    }
+
+    fn destroy_instance(&mut self, key: u32) -> OpResult<()> {
+        let iid = self.id();
+        try!(ops::inventory::remove_instance(self.fragment_mut(), iid, key));
+        Ok(())
+    }
+
+    fn move_instance(&mut self, key: u32, to_iid: InventoryId) -> OpResult<u32> {
+        let iid = self.id();
+        ops::inventory::move_instance(self.fragment_mut(), iid, to_iid, key)
+    }
+
+    /// Move all items, including instances, into another inventory.  See
+    /// `ops::inventory::transfer_contents`.
+    fn transfer_contents(&mut self, to_iid: InventoryId, force: bool) -> OpResult<()> {
+        let iid = self.id();
+        ops::inventory::transfer_contents(self.fragment_mut(), iid, to_iid, force)
+    }
+
+    fn set_instance_meta(&mut self,
+                         key: u32,
+                         name: String,
+                         value: Option<ExtraArg>) -> OpResult<Option<ExtraArg>> {
+        let iid = self.id();
+        ops::inventory::set_instance_meta(self.fragment_mut(), iid, key, name, value)
+    }
+
+    fn set_limits(&mut self, limits: InventoryLimits) {
+        let iid = self.id();
+        // OK: self.id() is always a valid InventoryId
+        ops::inventory::set_limits(self.fragment_mut(), iid, limits).unwrap()
     }
 
     fn set_attachment(&mut self, attach: InventoryAttachment) -> OpResult<InventoryAttachment> {
@@ -397,6 +452,12 @@ pub trait PlaneRefMut<'d, F: Fragment<'d>>: ObjectRefMutBase<'d, Plane, F> {
     fn save_terrain_chunk(&mut self, cpos: V2) -> Stable<TerrainChunkId> {
         self.try_save_terrain_chunk(cpos).expect("no TerrainChunk at given pos")
     }
+
+    fn set_props(&mut self, props: PlaneProps) {
+        let pid = self.id();
+        // OK: self.id() is always a valid PlaneId
+        ops::plane::set_props(self.fragment_mut(), pid, props).unwrap()
+    }
 }
 impl<'a, 'd, F: Fragment<'d>> PlaneRefMut<'d, F> for ObjectRefMut<'a, 'd, Plane, F> { }
 
@@ -509,5 +570,16 @@ pub trait StructureRefMut<'d, F: Fragment<'d>>: ObjectRefMutBase<'d, Structure,
         let sid = self.id();
         ops::structure::attach(self.fragment_mut(), sid, attach)
     }
+
+    fn push_crafting_job(&mut self, job: CraftingJob) {
+        let sid = self.id();
+        // OK: self.id() is always a valid StructureId
+        ops::structure::push_crafting_job(self.fragment_mut(), sid, job).unwrap()
+    }
+
+    fn remove_crafting_job(&mut self, idx: usize) -> OpResult<CraftingJob> {
+        let sid = self.id();
+        ops::structure::remove_crafting_job(self.fragment_mut(), sid, idx)
+    }
 }
 impl<'a, 'd, F: Fragment<'d>> StructureRefMut<'d, F> for ObjectRefMut<'a, 'd, Structure, F> { }
@@ -47,16 +47,39 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> ClientId
 pub fn destroy<'d, F>(f: &mut F,
                       cid: ClientId) -> OpResult<()>
         where F: Fragment<'d> {
+    destroy_impl(f, cid, false)
+}
+
+/// Remove a client that has been saved and will be loaded again on its next login, along with
+/// its child entities and inventories.
+pub fn unload<'d, F>(f: &mut F,
+                     cid: ClientId) -> OpResult<()>
+        where F: Fragment<'d> {
+    destroy_impl(f, cid, true)
+}
+
+fn destroy_impl<'d, F>(f: &mut F,
+                       cid: ClientId,
+                       unload: bool) -> OpResult<()>
+        where F: Fragment<'d> {
     let c = unwrap!(f.world_mut().clients.remove(cid));
     // Further lookup failures indicate an invariant violation.
 
     for &eid in c.child_entities.iter() {
         // TODO: do we really want .unwrap() here?
-        ops::entity::destroy(f, eid).unwrap();
+        if unload {
+            ops::entity::unload(f, eid).unwrap();
+        } else {
+            ops::entity::destroy(f, eid).unwrap();
+        }
     }
 
     for &iid in c.child_inventories.iter() {
-        ops::inventory::destroy(f, iid).unwrap();
+        if unload {
+            ops::inventory::unload(f, iid).unwrap();
+        } else {
+            ops::inventory::destroy(f, iid).unwrap();
+        }
     }
 
     f.with_hooks(|h| h.on_client_destroy(cid));
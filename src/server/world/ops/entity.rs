@@ -87,6 +87,21 @@ pub fn pre_fini<'d, F>(f: &mut F,
 pub fn destroy<'d, F>(f: &mut F,
                       eid: EntityId) -> OpResult<()>
         where F: Fragment<'d> {
+    destroy_impl(f, eid, false)
+}
+
+/// Remove an entity that has been saved and will be loaded again later, along with its child
+/// inventories.
+pub fn unload<'d, F>(f: &mut F,
+                     eid: EntityId) -> OpResult<()>
+        where F: Fragment<'d> {
+    destroy_impl(f, eid, true)
+}
+
+fn destroy_impl<'d, F>(f: &mut F,
+                       eid: EntityId,
+                       unload: bool) -> OpResult<()>
+        where F: Fragment<'d> {
     use world::EntityAttachment::*;
     pre_fini(f, eid);
     let e = unwrap!(f.world_mut().entities.remove(eid));
@@ -110,7 +125,11 @@ pub fn destroy<'d, F>(f: &mut F,
     }
 
     for &iid in e.child_inventories.iter() {
-        ops::inventory::destroy(f, iid).unwrap();
+        if unload {
+            ops::inventory::unload(f, iid).unwrap();
+        } else {
+            ops::inventory::destroy(f, iid).unwrap();
+        }
     }
 
     f.with_hooks(|h| h.on_entity_destroy(eid));
@@ -1,9 +1,12 @@
 use std::collections::HashMap;
 use std::mem::replace;
+use std::u16;
 
 use types::*;
 
-use world::{Inventory, InventoryAttachment};
+use msg::ExtraArg;
+
+use world::{Inventory, InventoryAttachment, InventoryLimits, ItemInstance};
 use world::{Fragment, Hooks};
 use world::ops::OpResult;
 
@@ -19,6 +22,9 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> InventoryId
         where F: Fragment<'d> {
     let iid = f.world_mut().inventories.insert(Inventory {
         contents: HashMap::new(),
+        instances: HashMap::new(),
+        next_instance: 0,
+        limits: InventoryLimits::unlimited(),
 
         stable_id: NO_STABLE_ID,
         attachment: InventoryAttachment::World,
@@ -29,8 +35,25 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> InventoryId
 pub fn destroy<'d, F>(f: &mut F,
                       iid: InventoryId) -> OpResult<()>
         where F: Fragment<'d> {
+    destroy_impl(f, iid, false)
+}
+
+/// Remove an inventory that has been saved and will be loaded again later.  Unlike `destroy`,
+/// the hooks are not given its stable ID, since the inventory still exists.
+pub fn unload<'d, F>(f: &mut F,
+                     iid: InventoryId) -> OpResult<()>
+        where F: Fragment<'d> {
+    destroy_impl(f, iid, true)
+}
+
+fn destroy_impl<'d, F>(f: &mut F,
+                       iid: InventoryId,
+                       unload: bool) -> OpResult<()>
+        where F: Fragment<'d> {
     use world::InventoryAttachment::*;
     let i = unwrap!(f.world_mut().inventories.remove(iid));
+    let stable_iid =
+        if !unload && i.stable_id != NO_STABLE_ID { Some(Stable::new(i.stable_id)) } else { None };
 
     match i.attachment {
         World => {},
@@ -51,7 +74,7 @@ pub fn destroy<'d, F>(f: &mut F,
         },
     }
 
-    f.with_hooks(|h| h.on_inventory_destroy(iid));
+    f.with_hooks(|h| h.on_inventory_destroy(iid, stable_iid));
     Ok(())
 }
 
@@ -105,49 +128,205 @@ pub fn attach<'d, F>(f: &mut F,
     Ok(old_attach)
 }
 
-/// Fails only if `iid` is not valid.
+/// Adjust the count of plain `item_id` items by `adjust`.  Instances of `item_id` are never
+/// affected.  Returns the new total count, including instances.  Fails without changing anything
+/// if the inventory doesn't have `-adjust` plain items to remove, or doesn't have room for
+/// `adjust` more.
 pub fn update<'d, F>(f: &mut F,
                      iid: InventoryId,
                      item_id: ItemId,
-                     adjust: i16) -> OpResult<u8>
+                     adjust: i32) -> OpResult<u16>
         where F: Fragment<'d> {
-    use std::collections::hash_map::Entry::*;
+    let (old_value, new_value, instance_count) = {
+        let w = f.world_mut();
+        let data = w.data();
+        let i = unwrap!(w.inventories.get_mut(iid));
 
-    let (old_value, new_value) = {
-        let i = unwrap!(f.world_mut().inventories.get_mut(iid));
+        let old_value = i.stack_count(item_id);
+        if adjust < 0 && old_value as i32 + adjust < 0 {
+            fail!("not enough {} in inventory", data.item_data.name(item_id));
+        }
+        if adjust > 0 {
+            if adjust > u16::MAX as i32 {
+                fail!("inventory can't hold any more of that item");
+            }
+            try!(i.check_space(&data.item_data, item_id, adjust as u16));
+        }
+        let new_value = (old_value as i32 + adjust) as u16;
 
-        match i.contents.entry(item_id) {
-            Vacant(e) => {
-                let new_value = update_item_count(0, adjust);
-                e.insert(new_value);
-                (0, new_value)
-            },
-            Occupied(mut e) => {
-                let old_value = *e.get();
-                let new_value = update_item_count(old_value, adjust);
-                if new_value == 0 {
-                    e.remove();
-                } else {
-                    e.insert(new_value);
-                }
-                (old_value, new_value)
-            },
+        if new_value == 0 {
+            i.contents.remove(&item_id);
+        } else {
+            i.contents.insert(item_id, new_value);
         }
+        (old_value, new_value, i.instance_count(item_id))
+    };
+
+    if old_value != new_value {
+        f.with_hooks(|h| h.on_inventory_update(iid,
+                                               item_id,
+                                               old_value + instance_count,
+                                               new_value + instance_count));
+    }
+
+    Ok(new_value + instance_count)
+}
+
+/// Set the capacity limits of the inventory.  Items already in the inventory are not affected,
+/// even if they exceed the new limits.
+pub fn set_limits<'d, F>(f: &mut F,
+                         iid: InventoryId,
+                         limits: InventoryLimits) -> OpResult<()>
+        where F: Fragment<'d> {
+    {
+        let i = unwrap!(f.world_mut().inventories.get_mut(iid));
+        if i.limits == limits {
+            return Ok(());
+        }
+        i.limits = limits;
+    }
+
+    f.with_hooks(|h| h.on_inventory_limits_change(iid));
+    Ok(())
+}
+
+/// Add an item instance to the inventory.  Unlike `update`, this fails if the inventory has no
+/// room for the instance.  Returns the instance's key within the inventory.
+pub fn insert_instance<'d, F>(f: &mut F,
+                              iid: InventoryId,
+                              inst: ItemInstance) -> OpResult<u32>
+        where F: Fragment<'d> {
+    let item_id = inst.item_id;
+    let (key, old_count) = {
+        let w = f.world_mut();
+        let data = w.data();
+        let i = unwrap!(w.inventories.get_mut(iid));
+        try!(i.check_instance_space(&data.item_data, item_id));
+
+        let old_count = i.count(item_id);
+        let key = i.next_instance;
+        i.next_instance += 1;
+        i.instances.insert(key, inst);
+        (key, old_count)
+    };
+
+    f.with_hooks(|h| h.on_inventory_update(iid, item_id, old_count, old_count + 1));
+    Ok(key)
+}
+
+pub fn remove_instance<'d, F>(f: &mut F,
+                              iid: InventoryId,
+                              key: u32) -> OpResult<ItemInstance>
+        where F: Fragment<'d> {
+    let (inst, old_count) = {
+        let i = unwrap!(f.world_mut().inventories.get_mut(iid));
+        let inst = unwrap!(i.instances.remove(&key), "no such item instance");
+        let old_count = i.count(inst.item_id) + 1;
+        (inst, old_count)
     };
 
-    f.with_hooks(|h| h.on_inventory_update(iid, item_id, old_value, new_value));
+    let item_id = inst.item_id;
+    f.with_hooks(|h| h.on_inventory_update(iid, item_id, old_count, old_count - 1));
+    Ok(inst)
+}
 
-    Ok(new_value)
+/// Move an item instance from one inventory to another, keeping its metadata.  Returns the
+/// instance's key within the new inventory.
+pub fn move_instance<'d, F>(f: &mut F,
+                            from_iid: InventoryId,
+                            to_iid: InventoryId,
+                            key: u32) -> OpResult<u32>
+        where F: Fragment<'d> {
+    {
+        let w = f.world();
+        let from = unwrap!(w.inventories.get(from_iid));
+        let to = unwrap!(w.inventories.get(to_iid));
+        let inst = unwrap!(from.instances.get(&key), "no such item instance");
+        if from_iid == to_iid {
+            return Ok(key);
+        }
+        try!(to.check_instance_space(&w.data().item_data, inst.item_id));
+    }
+
+    let inst = try!(remove_instance(f, from_iid, key));
+    insert_instance(f, to_iid, inst)
 }
 
-fn update_item_count(old: u8, adjust: i16) -> u8 {
-    use std::u8;
-    let sum = old as i16 + adjust;
-    if sum < u8::MIN as i16 {
-        u8::MIN
-    } else if sum > u8::MAX as i16 {
-        u8::MAX
-    } else {
-        sum as u8
+/// Set or clear (if `value` is `None`) a metadata field of an item instance.  Returns the old
+/// value of the field.
+pub fn set_instance_meta<'d, F>(f: &mut F,
+                                iid: InventoryId,
+                                key: u32,
+                                name: String,
+                                value: Option<ExtraArg>) -> OpResult<Option<ExtraArg>>
+        where F: Fragment<'d> {
+    let i = unwrap!(f.world_mut().inventories.get_mut(iid));
+    let inst = unwrap!(i.instances.get_mut(&key), "no such item instance");
+    let old = match value {
+        Some(value) => inst.meta.insert(name, value),
+        None => inst.meta.remove(&name),
+    };
+    Ok(old)
+}
+
+/// Move the entire contents of one inventory, including item instances, into another.  Fails
+/// without moving anything if the destination doesn't have room for all of it, unless `force` is
+/// set, in which case the destination's limits are ignored.
+pub fn transfer_contents<'d, F>(f: &mut F,
+                                from_iid: InventoryId,
+                                to_iid: InventoryId,
+                                force: bool) -> OpResult<()>
+        where F: Fragment<'d> {
+    if from_iid == to_iid {
+        return Ok(());
     }
+
+    let (contents, instances) = {
+        let w = f.world_mut();
+        {
+            let from = unwrap!(w.inventories.get(from_iid));
+            let to = unwrap!(w.inventories.get(to_iid));
+            if !force {
+                try!(to.check_space_for_contents(&w.data().item_data, from));
+            }
+        }
+        let from = &mut w.inventories[from_iid];
+        (replace(&mut from.contents, HashMap::new()),
+         replace(&mut from.instances, HashMap::new()))
+    };
+
+    let mut moved = HashMap::new();
+    for (&item_id, &count) in contents.iter() {
+        *moved.entry(item_id).or_insert(0) += count;
+    }
+    for inst in instances.values() {
+        *moved.entry(inst.item_id).or_insert(0) += 1;
+    }
+
+    let changes = {
+        // OK: `to_iid` was checked above.
+        let i = &mut f.world_mut().inventories[to_iid];
+        let old_counts = moved.keys().map(|&item_id| (item_id, i.count(item_id)))
+                              .collect::<Vec<_>>();
+        for (item_id, count) in contents.into_iter() {
+            let c = i.contents.entry(item_id).or_insert(0);
+            *c = c.saturating_add(count);
+        }
+        for (_, inst) in instances.into_iter() {
+            let key = i.next_instance;
+            i.next_instance += 1;
+            i.instances.insert(key, inst);
+        }
+        old_counts.into_iter()
+                  .map(|(item_id, old)| (item_id, old, i.count(item_id)))
+                  .collect::<Vec<_>>()
+    };
+
+    for (&item_id, &count) in moved.iter() {
+        f.with_hooks(|h| h.on_inventory_update(from_iid, item_id, count, 0));
+    }
+    for (item_id, old, new) in changes.into_iter() {
+        f.with_hooks(|h| h.on_inventory_update(to_iid, item_id, old, new));
+    }
+    Ok(())
 }
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use types::*;
 
-use world::Plane;
+use world::{Plane, PlaneProps};
 use world::{Fragment, Hooks};
 use world::ops::{self, OpResult};
 
@@ -11,6 +11,7 @@ pub fn create<'d, F>(f: &mut F, name: String) -> OpResult<PlaneId>
         where F: Fragment<'d> {
     let p = Plane {
         name: name,
+        props: PlaneProps::new(),
 
         loaded_chunks: HashMap::new(),
         saved_chunks: HashMap::new(),
@@ -28,6 +29,7 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> PlaneId
         where F: Fragment<'d> {
     let pid = f.world_mut().planes.insert(Plane {
         name: String::new(),
+        props: PlaneProps::new(),
 
         loaded_chunks: HashMap::new(),
         saved_chunks: HashMap::new(),
@@ -37,6 +39,23 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> PlaneId
     pid
 }
 
+/// Change the properties of the plane.
+pub fn set_props<'d, F>(f: &mut F,
+                        pid: PlaneId,
+                        props: PlaneProps) -> OpResult<()>
+        where F: Fragment<'d> {
+    {
+        let p = unwrap!(f.world_mut().planes.get_mut(pid));
+        if p.props == props {
+            return Ok(());
+        }
+        p.props = props;
+    }
+
+    f.with_hooks(|h| h.on_plane_props_change(pid));
+    Ok(())
+}
+
 pub fn post_init<'d, F>(f: &mut F,
                         pid: PlaneId)
         where F: Fragment<'d> {
@@ -4,7 +4,7 @@ use libphysics::CHUNK_SIZE;
 use types::*;
 use util::{multimap_insert, multimap_remove};
 
-use world::{Structure, StructureAttachment, StructureFlags};
+use world::{Structure, StructureAttachment, StructureFlags, CraftingJob};
 use world::{Fragment, Hooks};
 use world::ops::{self, OpResult};
 
@@ -34,6 +34,7 @@ pub fn create<'d, F>(f: &mut F,
         flags: StructureFlags::empty(),
         attachment: StructureAttachment::Plane,
         child_inventories: HashSet::new(),
+        crafting: Vec::new(),
     };
 
     let sid = unwrap!(f.world_mut().structures.insert(s));
@@ -53,6 +54,7 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> StructureId
         flags: StructureFlags::empty(),
         attachment: StructureAttachment::Plane,
         child_inventories: HashSet::new(),
+        crafting: Vec::new(),
     }).unwrap();     // Shouldn't fail when stable_id == NO_STABLE_ID
     sid
 }
@@ -116,7 +118,7 @@ pub fn destroy<'d, F>(f: &mut F,
         ops::inventory::destroy(f, iid).unwrap();
     }
 
-    f.with_hooks(|h| h.on_structure_destroy(sid, s.plane, bounds));
+    f.with_hooks(|h| h.on_structure_destroy(sid, s.plane, s.template, bounds));
     Ok(())
 }
 
@@ -170,12 +172,12 @@ pub fn replace<'d, F>(f: &mut F,
                       sid: StructureId,
                       new_tid: TemplateId) -> OpResult<()>
         where F: Fragment<'d> {
-    let (pid, pos, old_t, new_t) = {
+    let (pid, pos, old_tid, old_t, new_t) = {
         let w = f.world();
         let s = unwrap!(w.structures.get(sid));
         let old_t = unwrap!(w.data.structure_templates.get_template(s.template));
         let new_t = unwrap!(w.data.structure_templates.get_template(new_tid));
-        (s.plane, s.pos, old_t, new_t)
+        (s.plane, s.pos, s.template, old_t, new_t)
     };
 
     let old_bounds = Region::new(pos, pos + old_t.size);
@@ -200,7 +202,7 @@ pub fn replace<'d, F>(f: &mut F,
         s.template = new_tid;
     }
 
-    f.with_hooks(|h| h.on_structure_replace(sid, pid, old_bounds));
+    f.with_hooks(|h| h.on_structure_replace(sid, pid, old_tid, old_bounds));
     Ok(())
 }
 
@@ -223,3 +225,30 @@ fn remove_from_lookup(lookup: &mut HashMap<(PlaneId, V2), HashSet<StructureId>>,
         multimap_remove(lookup, (pid, chunk_pos), sid);
     }
 }
+
+pub fn push_crafting_job<'d, F>(f: &mut F,
+                                sid: StructureId,
+                                job: CraftingJob) -> OpResult<()>
+        where F: Fragment<'d> {
+    {
+        let s = unwrap!(f.world_mut().structures.get_mut(sid));
+        s.crafting.push(job);
+    }
+    f.with_hooks(|h| h.on_structure_crafting_change(sid));
+    Ok(())
+}
+
+pub fn remove_crafting_job<'d, F>(f: &mut F,
+                                  sid: StructureId,
+                                  idx: usize) -> OpResult<CraftingJob>
+        where F: Fragment<'d> {
+    let job = {
+        let s = unwrap!(f.world_mut().structures.get_mut(sid));
+        if idx >= s.crafting.len() {
+            fail!("no such crafting job");
+        }
+        s.crafting.remove(idx)
+    };
+    f.with_hooks(|h| h.on_structure_crafting_change(sid));
+    Ok(job)
+}
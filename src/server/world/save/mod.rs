@@ -62,7 +62,7 @@ impl ToAnyId for StructureId {
 }
 
 
-const CURRENT_VERSION: u32 = 5;
+const CURRENT_VERSION: u32 = 9;
 
 
 fn padding(len: usize) -> usize {
@@ -9,9 +9,11 @@ use libphysics::CHUNK_SIZE;
 use types::*;
 
 use data::Data;
+use msg::{ExtraArg, SimpleArg};
 use util::Convert;
 use world;
-use world::{EntityAttachment, StructureAttachment, InventoryAttachment};
+use world::{EntityAttachment, StructureAttachment, InventoryAttachment, InventoryLimits};
+use world::{ItemInstance, CraftingJob, LightingMode, PlaneProps};
 use world::{TerrainChunkFlags, StructureFlags};
 use world::object::*;
 use world::ops;
@@ -87,7 +89,8 @@ impl<R: io::Read> ObjectReader<R> {
 
     fn read_file_header(&mut self) -> Result<()> {
         let version: u32 = try!(self.r.read());
-        if version != CURRENT_VERSION && version != 3 {
+        if version != CURRENT_VERSION && version != 8 && version != 7 && version != 6 &&
+           version != 5 && version != 3 {
             fail!("file version does not match current version");
         }
         self.file_version = version;
@@ -194,6 +197,9 @@ impl<R: io::Read> ObjectReader<R> {
                 e.motion.end_pos = end_pos;
                 e.motion.start_time = start_time;
                 e.motion.duration = duration;
+                if self.file_version > 5 {
+                    e.motion.arc = try!(self.r.read());
+                }
 
                 e.anim = anim;
                 e.facing = facing;
@@ -223,31 +229,72 @@ impl<R: io::Read> ObjectReader<R> {
         Ok(eid)
     }
 
-    fn read_inventory<'d, F: Fragment<'d>>(&mut self, f: &mut F) -> Result<InventoryId> {
+    /// Read an item ID and count, as written by `ObjectWriter::write_item_id`.
+    fn read_item_id(&mut self, data: &Data) -> Result<(ItemId, u16)> {
         use std::collections::hash_map::Entry::*;
+
+        let (old_item_id, count, name_len): (u16, u16, u8) =
+            if self.file_version > 5 {
+                try!(self.r.read())
+            } else {
+                let (id, count, len): (u16, u8, u8) = try!(self.r.read());
+                (id, count as u16, len)
+            };
+        let item_id = match self.item_map.entry(old_item_id) {
+            Occupied(e) => *e.get(),
+            Vacant(e) => {
+                let name = try!(self.r.read_str_bytes(unwrap!(name_len.to_usize())));
+                let new_id = unwrap!(data.item_data.find_id(&*name));
+                e.insert(new_id);
+                new_id
+            },
+        };
+        Ok((item_id, count))
+    }
+
+    fn read_inventory<'d, F: Fragment<'d>>(&mut self, f: &mut F) -> Result<InventoryId> {
         let (iid, stable_id) = try!(self.read_object_header(f));
 
         try!(f.with_world(|wf| -> Result<_> {
             let w = world::Fragment::world_mut(wf);
             try!(w.inventories.set_stable_id(iid, stable_id));
 
+            let data = w.data;
             let i = &mut w.inventories[iid];
 
-            let contents_count = try!(self.r.read_count());
-            for _ in 0..contents_count {
-                let (old_item_id, count, name_len): (u16, u8, u8) = try!(self.r.read());
-                let item_id = match self.item_map.entry(old_item_id) {
-                    Occupied(e) => *e.get(),
-                    Vacant(e) => {
-                        let name = try!(self.r.read_str_bytes(unwrap!(name_len.to_usize())));
-                        let new_id = unwrap!(w.data.item_data.find_id(&*name));
-                        e.insert(new_id);
-                        new_id
-                    },
+            if self.file_version > 5 {
+                let (slots, weight, volume) = try!(self.r.read());
+                i.limits = InventoryLimits {
+                    slots: slots,
+                    weight: weight,
+                    volume: volume,
                 };
+            }
 
+            let contents_count = try!(self.r.read_count());
+            for _ in 0..contents_count {
+                let (item_id, count) = try!(self.read_item_id(data));
                 i.contents.insert(item_id, count);
             }
+
+            if self.file_version > 6 {
+                i.next_instance = try!(self.r.read());
+                let instance_count = try!(self.r.read_count());
+                for _ in 0..instance_count {
+                    let key = try!(self.r.read());
+                    let (item_id, _) = try!(self.read_item_id(data));
+                    let mut inst = ItemInstance::new(item_id);
+
+                    let meta_count = try!(self.r.read_count());
+                    for _ in 0..meta_count {
+                        let name = try!(self.r.read_str());
+                        let value = try!(read_extra_arg(&mut self.r));
+                        inst.meta.insert(name, value);
+                    }
+
+                    i.instances.insert(key, inst);
+                }
+            }
             Ok(())
         }));
 
@@ -268,6 +315,26 @@ impl<R: io::Read> ObjectReader<R> {
 
                 p.name = try!(self.r.read_str());
 
+                if self.file_version > 8 {
+                    let (lighting, generator, flags, _): (u8, u8, u8, u8) = try!(self.r.read());
+                    let (r, g, b, _): (u8, u8, u8, u8) = try!(self.r.read());
+                    let seed = try!(self.r.read());
+                    let spawn_point = try!(self.r.read());
+                    p.props = PlaneProps {
+                        lighting: unwrap!(LightingMode::from_primitive(lighting)),
+                        ambient_color: (r, g, b),
+                        day_night: flags & (1 << 0) != 0,
+                        build_allowed: flags & (1 << 1) != 0,
+                        pvp: flags & (1 << 2) != 0,
+                        spawn_point: if flags & (1 << 3) != 0 { Some(spawn_point) } else { None },
+                        generator: unwrap!(GeneratorKind::from_primitive(generator)),
+                        seed: seed,
+                    };
+                } else if stable_id == STABLE_PLANE_FOREST.unwrap() {
+                    // Older versions picked the forest's settings based on its name.
+                    p.props = PlaneProps::forest();
+                }
+
                 let chunks_count = try!(self.r.read_count());
                 for _ in 0..chunks_count {
                     let (cpos, stable_tcid) = try!(self.r.read());
@@ -376,6 +443,22 @@ impl<R: io::Read> ObjectReader<R> {
                     s.flags = StructureFlags::from_bits_truncate(try!(self.r.read()));
                 }
 
+                if self.file_version > 7 {
+                    let job_count = try!(self.r.read_count());
+                    for _ in 0..job_count {
+                        let recipe_name = try!(self.r.read_str());
+                        let (count, inventory, start_time, end_time) = try!(self.r.read());
+                        let recipe_id = unwrap!(w.data.recipes.find_id(&recipe_name));
+                        s.crafting.push(CraftingJob {
+                            recipe_id: recipe_id,
+                            count: count,
+                            inventory: Stable::new(inventory),
+                            start_time: start_time,
+                            end_time: end_time,
+                        });
+                    }
+                }
+
                 s.flags
             };
             try!(ops::structure::post_init(wf, sid));
@@ -547,3 +630,39 @@ fn unwrap_warn<T, E: error::Error>(r: result::Result<T, E>) {
                         error::Error::description(&e)),
     }
 }
+
+fn read_simple_arg<R: Reader>(r: &mut R) -> Result<SimpleArg> {
+    let tag: u8 = try!(r.read());
+    match tag {
+        0 => Ok(SimpleArg::Int(try!(r.read()))),
+        1 => Ok(SimpleArg::Str(try!(r.read_str()))),
+        _ => fail!("bad SimpleArg tag"),
+    }
+}
+
+fn read_extra_arg<R: Reader>(r: &mut R) -> Result<ExtraArg> {
+    let tag: u8 = try!(r.read());
+    match tag {
+        0 => Ok(ExtraArg::Int(try!(r.read()))),
+        1 => Ok(ExtraArg::Str(try!(r.read_str()))),
+        2 => {
+            let len = try!(r.read_count());
+            let mut l = Vec::with_capacity(len);
+            for _ in 0..len {
+                l.push(try!(read_extra_arg(r)));
+            }
+            Ok(ExtraArg::List(l))
+        },
+        3 => {
+            let len = try!(r.read_count());
+            let mut m = HashMap::with_capacity(len);
+            for _ in 0..len {
+                let k = try!(read_simple_arg(r));
+                let v = try!(read_extra_arg(r));
+                m.insert(k, v);
+            }
+            Ok(ExtraArg::Map(m))
+        },
+        _ => fail!("bad ExtraArg tag"),
+    }
+}
@@ -7,6 +7,7 @@ use libphysics::CHUNK_SIZE;
 use types::*;
 
 use data::Data;
+use msg::{ExtraArg, SimpleArg};
 use util::Convert;
 use util::IntrusiveStableId;
 use world::{World, Client, Entity, Inventory, Plane, TerrainChunk, Structure};
@@ -91,6 +92,24 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
     }
 
 
+    /// Write an item ID along with a count.  The item's name is included the first time each ID
+    /// appears in the file.
+    fn write_item_id(&mut self, data: &Data, item_id: ItemId, count: u16) -> Result<()> {
+        if !self.seen_items.contains(&item_id) {
+            self.seen_items.insert(item_id);
+            let name = data.item_data.name(item_id);
+            try!(self.w.write((item_id,
+                               count,
+                               unwrap!(name.len().to_u8()))));
+            try!(self.w.write_str_bytes(name));
+        } else {
+            try!(self.w.write((item_id,
+                               count,
+                               0_u8)));
+        }
+        Ok(())
+    }
+
     fn write_client(&mut self, c: &ObjectRef<Client>) -> Result<()> {
         try!(self.write_object_header(c));
 
@@ -127,6 +146,7 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
                            e.facing,
                            e.target_velocity,
                            e.appearance)));
+        try!(self.w.write(m.arc));
 
         try!(self.hooks.post_write_entity(&mut self.w, e));
 
@@ -144,19 +164,22 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
         let data = i.world().data();
 
         // Body
+        try!(self.w.write((i.limits.slots, i.limits.weight, i.limits.volume)));
+
         try!(self.w.write_count(i.contents.len()));
         for (&item_id, &count) in i.contents.iter() {
-            if !self.seen_items.contains(&item_id) {
-                self.seen_items.insert(item_id);
-                let name = data.item_data.name(item_id);
-                try!(self.w.write((item_id,
-                                   count,
-                                   unwrap!(name.len().to_u8()))));
-                try!(self.w.write_str_bytes(name));
-            } else {
-                try!(self.w.write((item_id,
-                                   count,
-                                   0_u8)));
+            try!(self.write_item_id(data, item_id, count));
+        }
+
+        try!(self.w.write(i.next_instance));
+        try!(self.w.write_count(i.instances.len()));
+        for (&key, inst) in i.instances.iter() {
+            try!(self.w.write(key));
+            try!(self.write_item_id(data, inst.item_id, 1));
+            try!(self.w.write_count(inst.meta.len()));
+            for (name, value) in inst.meta.iter() {
+                try!(self.w.write_str(name));
+                try!(write_extra_arg(&mut self.w, value));
             }
         }
 
@@ -171,6 +194,17 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
         // Body
         try!(self.w.write_str(p.name()));
 
+        let props = p.props();
+        let flags = (props.day_night as u8) |
+                    ((props.build_allowed as u8) << 1) |
+                    ((props.pvp as u8) << 2) |
+                    ((props.spawn_point.is_some() as u8) << 3);
+        try!(self.w.write((props.lighting as u8, props.generator as u8, flags, 0_u8)));
+        let (r, g, b) = props.ambient_color;
+        try!(self.w.write((r, g, b, 0_u8)));
+        try!(self.w.write(props.seed));
+        try!(self.w.write(props.spawn_point.unwrap_or(scalar(0))));
+
         try!(self.w.write_count(p.saved_chunks.len()));
         for (&cpos, &stable_tcid) in p.saved_chunks.iter() {
             try!(self.w.write((cpos, stable_tcid.unwrap())));
@@ -231,6 +265,16 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
 
         try!(self.w.write(s.flags.bits()));
 
+        try!(self.w.write_count(s.crafting.len()));
+        for job in s.crafting.iter() {
+            let recipe = s.world().data().recipes.recipe(job.recipe_id);
+            try!(self.w.write_str(&recipe.name));
+            try!(self.w.write((job.count,
+                               job.inventory.unwrap(),
+                               job.start_time,
+                               job.end_time)));
+        }
+
         try!(self.hooks.post_write_structure(&mut self.w, s));
 
         // Children
@@ -317,3 +361,40 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
 pub struct NoWriteHooks;
 
 impl WriteHooks for NoWriteHooks { }
+
+fn write_simple_arg<W: Writer>(w: &mut W, arg: &SimpleArg) -> Result<()> {
+    match *arg {
+        SimpleArg::Int(i) => w.write((0_u8, i)),
+        SimpleArg::Str(ref s) => {
+            try!(w.write(1_u8));
+            w.write_str(s)
+        },
+    }
+}
+
+fn write_extra_arg<W: Writer>(w: &mut W, arg: &ExtraArg) -> Result<()> {
+    match *arg {
+        ExtraArg::Int(i) => w.write((0_u8, i)),
+        ExtraArg::Str(ref s) => {
+            try!(w.write(1_u8));
+            w.write_str(s)
+        },
+        ExtraArg::List(ref l) => {
+            try!(w.write(2_u8));
+            try!(w.write_count(l.len()));
+            for x in l.iter() {
+                try!(write_extra_arg(w, x));
+            }
+            Ok(())
+        },
+        ExtraArg::Map(ref m) => {
+            try!(w.write(3_u8));
+            try!(w.write_count(m.len()));
+            for (k, v) in m.iter() {
+                try!(write_simple_arg(w, k));
+                try!(write_extra_arg(w, v));
+            }
+            Ok(())
+        },
+    }
+}
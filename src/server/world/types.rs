@@ -1,8 +1,13 @@
 use std::collections::HashMap;
 
+use libphysics;
+
 use types::*;
+use util::StrResult;
 
+use data::ItemData;
 use input::InputBits;
+use msg::ExtraArg;
 
 pub use super::World;
 pub use super::{Client, Entity, Inventory, Plane, TerrainChunk, Structure};
@@ -100,18 +105,349 @@ impl super::Entity {
     }
 }
 
+/// Capacity limits for an inventory.  A limit of zero means there is no limit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InventoryLimits {
+    /// Number of slots.  Each slot holds up to `ItemData::stack_size` items of a single kind.
+    pub slots: u16,
+    /// Maximum total weight of the contents.
+    pub weight: u32,
+    /// Maximum total volume of the contents.
+    pub volume: u32,
+}
+
+impl InventoryLimits {
+    pub fn unlimited() -> InventoryLimits {
+        InventoryLimits {
+            slots: 0,
+            weight: 0,
+            volume: 0,
+        }
+    }
+}
+
+/// A unique item with its own metadata.  Unlike plain items, which are stored only as a count,
+/// each instance occupies a slot of its own and never stacks.
+#[derive(Clone, Debug)]
+pub struct ItemInstance {
+    pub item_id: ItemId,
+    pub meta: HashMap<String, ExtraArg>,
+}
+
+impl ItemInstance {
+    pub fn new(item_id: ItemId) -> ItemInstance {
+        ItemInstance {
+            item_id: item_id,
+            meta: HashMap::new(),
+        }
+    }
+}
+
 impl super::Inventory {
-    pub fn count(&self, item_id: ItemId) -> u8 {
+    /// Total number of `item_id` items, including both plain items and instances.
+    pub fn count(&self, item_id: ItemId) -> u16 {
+        self.stack_count(item_id) + self.instance_count(item_id)
+    }
+
+    /// Number of plain (stackable) `item_id` items.
+    pub fn stack_count(&self, item_id: ItemId) -> u16 {
         self.contents.get(&item_id).map_or(0, |&x| x)
     }
 
-    pub fn contents(&self) -> &HashMap<ItemId, u8> {
+    pub fn instance_count(&self, item_id: ItemId) -> u16 {
+        self.instances.values().filter(|inst| inst.item_id == item_id).count() as u16
+    }
+
+    pub fn contents(&self) -> &HashMap<ItemId, u16> {
         &self.contents
     }
 
+    /// Total counts of all items, including both plain items and instances.
+    pub fn item_counts(&self) -> HashMap<ItemId, u16> {
+        let mut counts = self.contents.clone();
+        for inst in self.instances.values() {
+            *counts.entry(inst.item_id).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub fn instances(&self) -> &HashMap<u32, ItemInstance> {
+        &self.instances
+    }
+
+    pub fn instance(&self, key: u32) -> Option<&ItemInstance> {
+        self.instances.get(&key)
+    }
+
+    /// Split a request to move `count` `item_id` items into the number of plain items and the
+    /// keys of the instances to move.  Plain items go first, then instances in key order.
+    pub fn split_move(&self, item_id: ItemId, count: u16) -> (u16, Vec<u32>) {
+        use std::cmp;
+
+        let plain = cmp::min(self.stack_count(item_id), count);
+        let mut keys = self.instances.iter()
+                           .filter(|&(_, inst)| inst.item_id == item_id)
+                           .map(|(&key, _)| key)
+                           .collect::<Vec<_>>();
+        keys.sort();
+        keys.truncate((count - plain) as usize);
+        (plain, keys)
+    }
+
+    /// Number of batches, up to `count`, that the plain items in this inventory can supply for
+    /// a recipe with the given `inputs`.  Instances are never consumed by crafting.
+    pub fn batches_available(&self, inputs: &HashMap<ItemId, u8>, count: u16) -> u16 {
+        use std::cmp;
+
+        inputs.iter()
+              .map(|(&item_id, &num_required)| self.stack_count(item_id) / num_required as u16)
+              .fold(count, |a, b| cmp::min(a, b))
+    }
+
+    /// Find the instance of `item_id` with the lowest key, if there is one.
+    pub fn find_instance(&self, item_id: ItemId) -> Option<u32> {
+        self.instances.iter()
+            .filter(|&(_, inst)| inst.item_id == item_id)
+            .map(|(&key, _)| key)
+            .min()
+    }
+
+    pub fn limits(&self) -> InventoryLimits {
+        self.limits
+    }
+
     pub fn attachment(&self) -> InventoryAttachment {
         self.attachment
     }
+
+    /// Number of slots occupied by the current contents.  Each kind of plain item occupies as
+    /// many slots as it takes to hold all of that item at its stack size, and each instance
+    /// occupies one slot.
+    pub fn used_slots(&self, item_data: &ItemData) -> u32 {
+        self.contents.iter()
+            .map(|(&item_id, &count)| slots_for(item_data, item_id, count as u32))
+            .fold(self.instances.len() as u32, |a, b| a + b)
+    }
+
+    pub fn total_weight(&self, item_data: &ItemData) -> u32 {
+        let instance_weight = self.instances.values()
+            .map(|inst| item_data.weight(inst.item_id))
+            .fold(0, |a, b| a + b);
+        self.contents.iter()
+            .map(|(&item_id, &count)| item_data.weight(item_id) * count as u32)
+            .fold(instance_weight, |a, b| a + b)
+    }
+
+    pub fn total_volume(&self, item_data: &ItemData) -> u32 {
+        let instance_volume = self.instances.values()
+            .map(|inst| item_data.volume(inst.item_id))
+            .fold(0, |a, b| a + b);
+        self.contents.iter()
+            .map(|(&item_id, &count)| item_data.volume(item_id) * count as u32)
+            .fold(instance_volume, |a, b| a + b)
+    }
+
+    /// Number of additional plain `item_id` items that can be added without exceeding any of
+    /// the inventory's limits.
+    pub fn space_for(&self, item_data: &ItemData, item_id: ItemId) -> u16 {
+        use std::cmp;
+        use std::u16;
+
+        let mut space = u16::MAX as u32 - self.count(item_id) as u32;
+
+        if self.limits.slots != 0 {
+            let count = self.stack_count(item_id) as u32;
+            let stack_size = item_data.stack_size(item_id) as u32;
+            let other_slots = self.used_slots(item_data) -
+                              slots_for(item_data, item_id, count);
+            let free_slots = (self.limits.slots as u32).saturating_sub(other_slots);
+            space = cmp::min(space, (free_slots * stack_size).saturating_sub(count));
+        }
+
+        let weight = item_data.weight(item_id);
+        if self.limits.weight != 0 && weight != 0 {
+            let free = self.limits.weight.saturating_sub(self.total_weight(item_data));
+            space = cmp::min(space, free / weight);
+        }
+
+        let volume = item_data.volume(item_id);
+        if self.limits.volume != 0 && volume != 0 {
+            let free = self.limits.volume.saturating_sub(self.total_volume(item_data));
+            space = cmp::min(space, free / volume);
+        }
+
+        space as u16
+    }
+
+    /// Check that `count` more plain `item_id` items fit in the inventory.  On failure, the error
+    /// describes which limit would be exceeded.
+    pub fn check_space(&self, item_data: &ItemData, item_id: ItemId, count: u16) -> StrResult<()> {
+        let old_count = self.stack_count(item_id) as u32;
+        let new_count = old_count + count as u32;
+        let slots = self.used_slots(item_data) -
+                    slots_for(item_data, item_id, old_count) +
+                    slots_for(item_data, item_id, new_count);
+        self.check_limits(item_data, item_id, count as u32, slots)
+    }
+
+    /// Check that one more `item_id` instance fits in the inventory.
+    pub fn check_instance_space(&self, item_data: &ItemData, item_id: ItemId) -> StrResult<()> {
+        let slots = self.used_slots(item_data) + 1;
+        self.check_limits(item_data, item_id, 1, slots)
+    }
+
+    /// Check that the entire contents of `other`, both plain items and instances, fit in this
+    /// inventory at the same time.
+    pub fn check_space_for_contents(&self,
+                                    item_data: &ItemData,
+                                    other: &super::Inventory) -> StrResult<()> {
+        use std::u16;
+
+        let mut slots = self.used_slots(item_data) + other.instances.len() as u32;
+        for (&item_id, &count) in other.contents.iter() {
+            let old_count = self.stack_count(item_id) as u32;
+            slots = slots - slots_for(item_data, item_id, old_count) +
+                    slots_for(item_data, item_id, old_count + count as u32);
+        }
+        for (item_id, count) in other.item_counts() {
+            if self.count(item_id) as u32 + count as u32 > u16::MAX as u32 {
+                fail!("inventory can't hold any more of that item");
+            }
+        }
+
+        if self.limits.slots != 0 && slots > self.limits.slots as u32 {
+            fail!("not enough free slots in inventory");
+        }
+
+        if self.limits.weight != 0 &&
+           self.total_weight(item_data) + other.total_weight(item_data) > self.limits.weight {
+            fail!("inventory weight limit exceeded");
+        }
+
+        if self.limits.volume != 0 &&
+           self.total_volume(item_data) + other.total_volume(item_data) > self.limits.volume {
+            fail!("not enough room in inventory");
+        }
+
+        Ok(())
+    }
+
+    /// Check the limits for adding `count` more `item_id` items, bringing the number of used
+    /// slots up to `slots`.
+    fn check_limits(&self,
+                    item_data: &ItemData,
+                    item_id: ItemId,
+                    count: u32,
+                    slots: u32) -> StrResult<()> {
+        use std::u16;
+
+        if self.count(item_id) as u32 + count > u16::MAX as u32 {
+            fail!("inventory can't hold any more of that item");
+        }
+
+        if self.limits.slots != 0 && slots > self.limits.slots as u32 {
+            fail!("not enough free slots in inventory");
+        }
+
+        if self.limits.weight != 0 {
+            let weight = self.total_weight(item_data) + item_data.weight(item_id) * count;
+            if weight > self.limits.weight {
+                fail!("inventory weight limit exceeded");
+            }
+        }
+
+        if self.limits.volume != 0 {
+            let volume = self.total_volume(item_data) + item_data.volume(item_id) * count;
+            if volume > self.limits.volume {
+                fail!("not enough room in inventory");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn slots_for(item_data: &ItemData, item_id: ItemId, count: u32) -> u32 {
+    let stack_size = item_data.stack_size(item_id) as u32;
+    (count + stack_size - 1) / stack_size
+}
+
+/// How a plane is lit on the client.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightingMode {
+    /// Lit by the day/night cycle, if it's enabled, or by a fixed ambient color otherwise.
+    Ambient = 0,
+    /// Completely dark.  Only light sources are visible.
+    Dark = 1,
+}
+
+impl LightingMode {
+    pub fn from_primitive(i: u8) -> Option<LightingMode> {
+        match i {
+            0 => Some(LightingMode::Ambient),
+            1 => Some(LightingMode::Dark),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<LightingMode> {
+        match s {
+            "ambient" => Some(LightingMode::Ambient),
+            "dark" => Some(LightingMode::Dark),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LightingMode::Ambient => "ambient",
+            LightingMode::Dark => "dark",
+        }
+    }
+}
+
+/// Properties and rules of a plane.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlaneProps {
+    pub lighting: LightingMode,
+    /// Light color used for `LightingMode::Ambient` when the day/night cycle is disabled.
+    pub ambient_color: (u8, u8, u8),
+    pub day_night: bool,
+    /// Whether players may build (place and remove structures and blocks) in this plane.
+    pub build_allowed: bool,
+    pub pvp: bool,
+    /// Where new and respawning players appear.  `None` means the plane has no spawn point.
+    pub spawn_point: Option<V3>,
+    pub generator: GeneratorKind,
+    /// Terrain generator seed.  Zero means the plane uses the server-wide generator state.
+    pub seed: u32,
+}
+
+impl PlaneProps {
+    /// Properties of an ordinary underground plane (the default for newly created planes).
+    pub fn new() -> PlaneProps {
+        PlaneProps {
+            lighting: LightingMode::Dark,
+            ambient_color: (255, 255, 255),
+            day_night: false,
+            build_allowed: true,
+            pvp: false,
+            spawn_point: None,
+            generator: GeneratorKind::Dungeon,
+            seed: 0,
+        }
+    }
+
+    /// Properties of the main overworld plane.
+    pub fn forest() -> PlaneProps {
+        PlaneProps {
+            lighting: LightingMode::Ambient,
+            day_night: true,
+            spawn_point: Some(V3::new(32, 32, 0)),
+            generator: GeneratorKind::Forest,
+            .. PlaneProps::new()
+        }
+    }
 }
 
 impl super::Plane {
@@ -119,6 +455,10 @@ impl super::Plane {
         &self.name
     }
 
+    pub fn props(&self) -> &PlaneProps {
+        &self.props
+    }
+
     pub fn get_terrain_chunk_id(&self, cpos: V2) -> Option<TerrainChunkId> {
         self.loaded_chunks.get(&cpos).map(|&x| x)
     }
@@ -134,6 +474,14 @@ impl super::Plane {
     pub fn saved_terrain_chunk_id(&self, cpos: V2) -> Stable<TerrainChunkId> {
         self.get_saved_terrain_chunk_id(cpos).expect("no TerrainChunk at given pos")
     }
+
+    pub fn saved_terrain_chunk_ids(&self) -> Vec<Stable<TerrainChunkId>> {
+        self.saved_chunks.values().cloned().collect()
+    }
+
+    pub fn loaded_terrain_chunk_count(&self) -> usize {
+        self.loaded_chunks.len()
+    }
 }
 
 impl super::TerrainChunk {
@@ -170,6 +518,23 @@ impl super::Structure {
     pub fn attachment(&self) -> StructureAttachment {
         self.attachment
     }
+
+    pub fn crafting_jobs(&self) -> &[CraftingJob] {
+        &self.crafting
+    }
+}
+
+/// A timed crafting operation queued at a station structure.
+#[derive(Clone, Copy, Debug)]
+pub struct CraftingJob {
+    pub recipe_id: RecipeId,
+    /// Number of batches to produce.  The inputs for all batches are consumed when the job is
+    /// queued.
+    pub count: u16,
+    /// Inventory that receives the outputs when the job finishes.
+    pub inventory: Stable<InventoryId>,
+    pub start_time: Time,
+    pub end_time: Time,
 }
 
 
@@ -181,6 +546,9 @@ pub struct Motion {
     pub duration: Duration,
     pub start_pos: V3,
     pub end_pos: V3,
+    /// Vertical arc applied on top of the straight-line path, for jumps and falls.  See
+    /// `libphysics::arc_offset`.
+    pub arc: u16,
 }
 
 impl Motion {
@@ -190,6 +558,7 @@ impl Motion {
             duration: 0,
             start_pos: pos,
             end_pos: pos,
+            arc: 0,
         }
     }
 
@@ -199,6 +568,7 @@ impl Motion {
             duration: -1_i64 as Duration,
             start_pos: pos,
             end_pos: pos,
+            arc: 0,
         }
     }
 
@@ -212,11 +582,19 @@ impl Motion {
             } else {
                 let offset = (self.end_pos - self.start_pos) *
                         scalar(delta as i32) / scalar(self.duration as i32);
-                self.start_pos + offset
+                let lift = libphysics::arc_offset(self.arc as i32,
+                                                  delta as i32,
+                                                  self.duration as i32);
+                self.start_pos + offset + V3::new(0, 0, lift)
             }
         }
     }
 
+    /// Check if the entity is in the middle of a jump or fall at time `now`.
+    pub fn airborne(&self, now: Time) -> bool {
+        self.arc != 0 && now < self.end_time()
+    }
+
     pub fn end_time(&self) -> Time {
         self.start_time + self.duration as Time
     }
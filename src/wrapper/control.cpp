@@ -56,6 +56,8 @@ void control::handle_command(size_t id,
         owner.handle_control_command(opcode::OP_RESTART_CLIENT);
     } else if (s == "restart_both") {
         owner.handle_control_command(opcode::OP_RESTART_BOTH);
+    } else if (s == "reload_scripts") {
+        owner.handle_control_command(opcode::OP_RELOAD_SCRIPTS);
     } else {
         cerr << "unknown control command" << endl;
     }
@@ -0,0 +1,253 @@
+"""Generate a description of the `outpost_ffi` Lua API from a running server.
+
+Connects to the server's REPL, reads the `outpost_ffi.signatures` table that
+the server builds from the signatures of its Rust functions, and writes two
+files:
+
+ - A JSON file listing, for each userdata type, the signature of every function
+   in its method table and metatable: argument names and types, return type,
+   and the engine context the function requires.  Rust types are given as
+   recorded by the server, along with the equivalent Lua type.
+
+ - A Lua file containing annotation stubs (in the `---@class` / `---@param`
+   format understood by most Lua editors), for mod authors.
+
+Usage: gen_lua_api.py <repl socket> <json out> <stubs out>
+
+The REPL socket is normally `repl` in the server's directory.
+"""
+import json
+import re
+import socket
+import sys
+
+
+### Reading signatures
+
+# Lua code that encodes `outpost_ffi.signatures` as JSON.  The REPL prints the
+# returned string.
+DUMP_SIGNATURES = r'''
+local function encode(x)
+    local t = type(x)
+    if t == 'string' then
+        return '"' .. x:gsub('[%c"\\]', function(c)
+            return string.format('\\u%04x', c:byte())
+        end) .. '"'
+    elseif t == 'number' or t == 'boolean' then
+        return tostring(x)
+    elseif t == 'table' then
+        local parts = {}
+        if #x > 0 then
+            for _, v in ipairs(x) do
+                parts[#parts + 1] = encode(v)
+            end
+            return '[' .. table.concat(parts, ',') .. ']'
+        end
+        for k, v in pairs(x) do
+            parts[#parts + 1] = encode(tostring(k)) .. ':' .. encode(v)
+        end
+        return '{' .. table.concat(parts, ',') .. '}'
+    else
+        return 'null'
+    end
+end
+return encode(require('outpost_ffi').signatures)
+'''
+
+PROMPT = b'\n >>> '
+
+def read_signatures(repl_path):
+    """Evaluate `DUMP_SIGNATURES` on the server's REPL and decode the
+    result."""
+    s = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    s.connect(repl_path)
+    try:
+        # The REPL treats lines between `{` and `}` as a single command.
+        s.sendall(b'{\n' + DUMP_SIGNATURES.strip().encode('utf-8') + b'\n}\n')
+        buf = b''
+        while not buf.endswith(PROMPT):
+            chunk = s.recv(65536)
+            if len(chunk) == 0:
+                raise IOError('REPL connection closed before the reply was complete')
+            buf += chunk
+    finally:
+        s.close()
+
+    reply = buf[:-len(PROMPT)].decode('utf-8')
+    if reply.startswith('error'):
+        raise ValueError('REPL reported an %s' % reply)
+    return json.loads(reply)
+
+
+### Lua types
+
+INTEGER_TYPES = {
+    'u8', 'u16', 'u32', 'u64', 'usize', 'i8', 'i16', 'i32', 'i64', 'isize',
+    'ClientId', 'EntityId', 'InventoryId', 'PlaneId', 'StructureId',
+    'AnimId', 'BlockId', 'ItemId', 'RecipeId', 'TileId', 'TemplateId',
+    'StableId', 'Time', 'Duration', 'Coord',
+}
+
+SPECIAL_TYPES = {
+    'bool': 'boolean',
+    'str': 'string',
+    'String': 'string',
+    'f32': 'number',
+    'f64': 'number',
+    'Nil': 'nil',
+    'SimpleArg': 'integer|string',
+    'TimeOrNumber': 'integer|Time',
+    '_': 'any',
+}
+
+def split_top_level(text, sep=','):
+    """Split `text` at occurrences of `sep` that are not nested inside any
+    brackets (including angle brackets)."""
+    parts = []
+    depth = 0
+    cur = []
+    for c in text:
+        if c in '([{<':
+            depth += 1
+        elif c in ')]}>':
+            depth -= 1
+        if c == sep and depth == 0:
+            parts.append(''.join(cur))
+            cur = []
+        else:
+            cur.append(c)
+    parts.append(''.join(cur))
+    return [p.strip() for p in parts if p.strip() != '']
+
+def parse_type(ty):
+    """Parse a Rust type into (name, [args]), ignoring references, lifetimes,
+    and module paths.  Tuples have the name '()'."""
+    ty = re.sub(r"'\w+", '', ty)
+    ty = re.sub(r'\s*::\s*', '::', ty)
+    ty = ty.replace('&', ' ').replace('mut ', ' ').strip()
+    if ty.startswith('('):
+        return ('()', [parse_type(t) for t in split_top_level(ty[1:-1])])
+    m = re.match(r'([\w:]+)\s*(?:<(.*)>)?$', ty)
+    name = m.group(1).split('::')[-1]
+    args = [parse_type(t) for t in split_top_level(m.group(2) or '')]
+    return (name, args)
+
+def lua_type(ty):
+    """Convert a parsed Rust type to a single Lua type."""
+    name, args = ty
+    if name in INTEGER_TYPES:
+        return 'integer'
+    elif name in SPECIAL_TYPES:
+        return SPECIAL_TYPES[name]
+    elif name == 'Option':
+        return optional(lua_type(args[0]))
+    elif name == 'Vec':
+        return lua_type(args[0]) + '[]'
+    elif name in ('TakeOptWrapper', 'OptWrapper'):
+        return lua_type(args[0])
+    elif name == '()' and len(args) == 0:
+        return 'nil'
+    else:
+        return name
+
+def optional(t):
+    return t if t in ('any', 'nil') or t.endswith('|nil') else t + '|nil'
+
+def lua_returns(ty):
+    """Convert a parsed Rust return type to a list of Lua return types,
+    following the `ToLua` impls in script/traits.rs."""
+    name, args = ty
+    if name == '()':
+        return [r for a in args for r in lua_returns(a)]
+    elif name == 'Option':
+        return [optional(t) for t in lua_returns(args[0])]
+    elif name == 'StrResult':
+        return [optional(t) for t in lua_returns(args[0])] + ['string|nil']
+    else:
+        return [lua_type(ty)]
+
+
+### Output
+
+def build_api(signatures):
+    api = {}
+    for ty_name, ty in sorted(signatures.items()):
+        out = {}
+        for which, section in (('methods', 'table'), ('metamethods', 'metatable')):
+            out[which] = []
+            for fn_name, f in sorted(ty.get(section, {}).items()):
+                # Empty tables come back as objects rather than lists.
+                args = f['args'] if isinstance(f['args'], list) else []
+                out[which].append({
+                    'name': fn_name,
+                    'context': f['context'],
+                    'args': [{
+                        'name': a['name'],
+                        'rust_type': a['type'],
+                        'type': lua_type(parse_type(a['type'])),
+                    } for a in args],
+                    'rust_returns': f['returns'],
+                    'returns': lua_returns(parse_type(f['returns'])),
+                })
+        api[ty_name] = out
+    return api
+
+LUA_KEYWORDS = {
+    'and', 'break', 'do', 'else', 'elseif', 'end', 'false', 'for', 'function',
+    'if', 'in', 'local', 'nil', 'not', 'or', 'repeat', 'return', 'then',
+    'true', 'until', 'while',
+}
+
+def emit_stubs(api, f):
+    f.write('---@meta\n')
+    f.write('-- Generated by util/gen_lua_api.py from outpost_ffi.signatures.\n')
+    f.write('-- Do not edit.\n')
+
+    for ty_name, ty in sorted(api.items()):
+        f.write('\n\n---@class %s\n' % ty_name)
+        f.write('local %s = {}\n' % ty_name)
+
+        for which in ('methods', 'metamethods'):
+            for fn in ty[which]:
+                f.write('\n')
+                if which == 'metamethods':
+                    f.write('-- (metamethod)\n')
+                if fn['context'] != 'none':
+                    f.write('-- Requires %s engine context.\n' % fn['context'])
+                f.write('-- Rust: %s(%s) -> %s\n' % (
+                    fn['name'],
+                    ', '.join('%s: %s' % (a['name'], a['rust_type']) for a in fn['args']),
+                    fn['rust_returns']))
+                # Arguments of type `()` don't take up any Lua values.
+                args = [a for a in fn['args'] if a['rust_type'] != '()']
+                arg_names = [a['name'] + '_' if a['name'] in LUA_KEYWORDS else a['name']
+                        for a in args]
+                for a, arg_name in zip(args, arg_names):
+                    f.write('---@param %s %s\n' % (arg_name, a['type']))
+                for r in fn['returns']:
+                    f.write('---@return %s\n' % r)
+                f.write('function %s.%s(%s) end\n' %
+                        (ty_name, fn['name'], ', '.join(arg_names)))
+
+    f.write('\n\n---@class outpost_ffi\n')
+    f.write('---@field types table<string, {table: table, metatable: table}>\n')
+    f.write('---@field callbacks table<string, function>\n')
+    f.write('---@field signatures table<string, {table: table, metatable: table}>\n')
+    f.write('---@field profiler table\n')
+    f.write('outpost_ffi = {}\n')
+
+def main(repl_path, json_out, stubs_out):
+    api = build_api(read_signatures(repl_path))
+
+    with open(json_out, 'w') as f:
+        json.dump(api, f, indent=2, sort_keys=True)
+        f.write('\n')
+
+    with open(stubs_out, 'w') as f:
+        emit_stubs(api, f)
+
+if __name__ == '__main__':
+    if len(sys.argv) != 4:
+        sys.stderr.write('usage: %s <repl socket> <json out> <stubs out>\n' % sys.argv[0])
+        sys.exit(1)
+    main(*sys.argv[1:])